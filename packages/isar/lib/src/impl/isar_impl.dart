@@ -414,7 +414,7 @@ class _IsarImpl extends Isar {
   void verify() {
     getTxn(
       (isarPtr, txnPtr) =>
-          IsarCore.b.isar_verify(isarPtr, txnPtr).checkNoError(),
+          IsarCore.b.isar_verify(isarPtr, txnPtr, true).checkNoError(),
     );
   }
 }
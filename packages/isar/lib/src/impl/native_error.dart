@@ -24,6 +24,13 @@ extension on int {
         throw EncryptionError();
       case ERROR_DB_FULL:
         throw DatabaseFullError();
+      case ERROR_UNIQUE_VIOLATION:
+        final length = IsarCore.b.isar_get_unique_violation_index(
+          IsarCore.stringPtrPtr,
+        );
+        final index = utf8.decode(IsarCore.stringPtr.asU8List(length));
+        final existingId = IsarCore.b.isar_get_unique_violation_id();
+        throw UniqueViolationError(index, existingId);
       default:
         final length = IsarCore.b.isar_get_error(IsarCore.stringPtrPtr);
         final ptr = IsarCore.stringPtr;
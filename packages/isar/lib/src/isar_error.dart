@@ -94,6 +94,27 @@ class DatabaseFullError extends IsarError {
       'specifying a CompactCondition when opening Isar.';
 }
 
+/// An object has the same unique index key as an existing object and the
+/// index does not replace conflicting objects.
+class UniqueViolationError extends IsarError {
+  /// @nodoc
+  @protected
+  UniqueViolationError(this.index, this.existingId);
+
+  /// The name of the violated index.
+  final String index;
+
+  /// The id of the existing object.
+  final int existingId;
+
+  @override
+  String get name => 'UniqueViolationError';
+
+  @override
+  String get message =>
+      'Unique index $index is violated by the existing object $existingId.';
+}
+
 /// Isar has not been initialized correctly.
 class IsarNotReadyError extends IsarError {
   /// @nodoc
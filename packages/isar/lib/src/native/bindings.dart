@@ -57,6 +57,30 @@ class IsarCoreBindings {
       _isar_get_errorPtr
           .asFunction<int Function(ffi.Pointer<ffi.Pointer<ffi.Uint8>>)>();
 
+  int isar_get_unique_violation_index(
+    ffi.Pointer<ffi.Pointer<ffi.Uint8>> value,
+  ) {
+    return _isar_get_unique_violation_index(value);
+  }
+
+  late final _isar_get_unique_violation_indexPtr = _lookup<
+    ffi.NativeFunction<ffi.Uint32 Function(ffi.Pointer<ffi.Pointer<ffi.Uint8>>)>
+  >('isar_get_unique_violation_index');
+  late final _isar_get_unique_violation_index =
+      _isar_get_unique_violation_indexPtr
+          .asFunction<int Function(ffi.Pointer<ffi.Pointer<ffi.Uint8>>)>();
+
+  int isar_get_unique_violation_id() {
+    return _isar_get_unique_violation_id();
+  }
+
+  late final _isar_get_unique_violation_idPtr =
+      _lookup<ffi.NativeFunction<IsarI64 Function()>>(
+        'isar_get_unique_violation_id',
+      );
+  late final _isar_get_unique_violation_id =
+      _isar_get_unique_violation_idPtr.asFunction<int Function()>();
+
   ffi.Pointer<CIsarReader> isar_cursor_next(
     ffi.Pointer<CIsarCursor> cursor,
     int id,
@@ -1018,19 +1042,31 @@ class IsarCoreBindings {
             int Function(ffi.Pointer<CIsarInstance>, ffi.Pointer<CString>)
           >();
 
-  int isar_verify(ffi.Pointer<CIsarInstance> isar, ffi.Pointer<CIsarTxn> txn) {
-    return _isar_verify(isar, txn);
+  int isar_verify(
+    ffi.Pointer<CIsarInstance> isar,
+    ffi.Pointer<CIsarTxn> txn,
+    bool deep,
+  ) {
+    return _isar_verify(isar, txn, deep);
   }
 
   late final _isar_verifyPtr = _lookup<
     ffi.NativeFunction<
-      ffi.Uint8 Function(ffi.Pointer<CIsarInstance>, ffi.Pointer<CIsarTxn>)
+      ffi.Uint8 Function(
+        ffi.Pointer<CIsarInstance>,
+        ffi.Pointer<CIsarTxn>,
+        ffi.Bool,
+      )
     >
   >('isar_verify');
   late final _isar_verify =
       _isar_verifyPtr
           .asFunction<
-            int Function(ffi.Pointer<CIsarInstance>, ffi.Pointer<CIsarTxn>)
+            int Function(
+              ffi.Pointer<CIsarInstance>,
+              ffi.Pointer<CIsarTxn>,
+              bool,
+            )
           >();
 
   int isar_close(ffi.Pointer<CIsarInstance> isar, bool delete_) {
@@ -2157,6 +2193,8 @@ const int ERROR_ENCRYPTION = 6;
 
 const int ERROR_DB_FULL = 7;
 
+const int ERROR_UNIQUE_VIOLATION = 8;
+
 const int AGGREGATION_COUNT = 0;
 
 const int AGGREGATION_IS_EMPTY = 1;
@@ -22,6 +22,14 @@ extension IsarBindingsX on JSIsar {
   @ffi.Native<ffi.Uint32 Function(ffi.Pointer<ffi.Pointer<ffi.Uint8>>)>()
   external int isar_get_error(ffi.Pointer<ffi.Pointer<ffi.Uint8>> value);
 
+  @ffi.Native<ffi.Uint32 Function(ffi.Pointer<ffi.Pointer<ffi.Uint8>>)>()
+  external int isar_get_unique_violation_index(
+    ffi.Pointer<ffi.Pointer<ffi.Uint8>> value,
+  );
+
+  @ffi.Native<IsarI64 Function()>()
+  external int isar_get_unique_violation_id();
+
   @ffi.Native<
     ffi.Pointer<CIsarReader> Function(
       ffi.Pointer<CIsarCursor>,
@@ -451,11 +459,16 @@ extension IsarBindingsX on JSIsar {
   );
 
   @ffi.Native<
-    ffi.Uint8 Function(ffi.Pointer<CIsarInstance>, ffi.Pointer<CIsarTxn>)
+    ffi.Uint8 Function(
+      ffi.Pointer<CIsarInstance>,
+      ffi.Pointer<CIsarTxn>,
+      ffi.Bool,
+    )
   >()
   external int isar_verify(
     ffi.Pointer<CIsarInstance> isar,
     ffi.Pointer<CIsarTxn> txn,
+    bool deep,
   );
 
   @ffi.Native<ffi.Uint8 Function(ffi.Pointer<CIsarInstance>, ffi.Bool)>()
@@ -1011,6 +1024,8 @@ const int ERROR_ENCRYPTION = 6;
 
 const int ERROR_DB_FULL = 7;
 
+const int ERROR_UNIQUE_VIOLATION = 8;
+
 const int AGGREGATION_COUNT = 0;
 
 const int AGGREGATION_IS_EMPTY = 1;
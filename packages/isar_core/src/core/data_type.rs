@@ -28,6 +28,20 @@ impl DataType {
         self.element_type().is_some()
     }
 
+    /// Whether values of this type can be represented by an `IsarValue`.
+    pub const fn is_scalar(&self) -> bool {
+        matches!(
+            self,
+            DataType::Bool
+                | DataType::Byte
+                | DataType::Int
+                | DataType::Float
+                | DataType::Long
+                | DataType::Double
+                | DataType::String
+        )
+    }
+
     pub const fn element_type(&self) -> Option<DataType> {
         match self {
             DataType::BoolList => Some(DataType::Bool),
@@ -81,6 +95,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_is_scalar() {
+        assert!(DataType::Bool.is_scalar());
+        assert!(DataType::Long.is_scalar());
+        assert!(DataType::String.is_scalar());
+        assert!(!DataType::Object.is_scalar());
+        assert!(!DataType::Json.is_scalar());
+        assert!(!DataType::IntList.is_scalar());
+    }
+
     #[test]
     fn test_element_type() {
         // Test non-list types return None
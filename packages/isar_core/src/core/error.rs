@@ -64,11 +64,24 @@ pub enum IsarError {
     #[snafu(display("The database is full."))]
     DbFull {},
 
-    DbCorrupted {},
+    #[snafu(display("Database corrupted: {}", message))]
+    DbCorrupted {
+        message: String,
+    },
 
     #[snafu(display("DbError ({}): {}", code, message))]
     DbError {
         code: i32,
         message: String,
     },
+
+    #[snafu(display(
+        "Unique index {} is violated by the existing object {}.",
+        index,
+        existing_id
+    ))]
+    UniqueViolation {
+        index: String,
+        existing_id: i64,
+    },
 }
@@ -1,10 +1,13 @@
+use crate::core::error::{IsarError, Result};
 use crate::core::value::IsarValue;
+use serde::{Deserialize, Serialize};
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 pub enum Filter {
     Condition(FilterCondition),
     Json(FilterJson),
     Embedded(FilterEmbedded),
+    Parameter(FilterParameter),
     And(Vec<Filter>),
     Or(Vec<Filter>),
     Not(Box<Filter>),
@@ -45,6 +48,22 @@ impl Filter {
         Filter::Embedded(FilterEmbedded::new(property_index, filter))
     }
 
+    /// A condition whose values are bound after the query has been built. A query with
+    /// parameters cannot be run until all of its parameters are bound.
+    pub fn new_parameter(
+        parameter_index: u16,
+        property_index: u16,
+        condition_type: ConditionType,
+        case_sensitive: bool,
+    ) -> Self {
+        Filter::Parameter(FilterParameter {
+            parameter_index,
+            property_index,
+            condition_type,
+            case_sensitive,
+        })
+    }
+
     pub fn new_and(filters: Vec<Filter>) -> Self {
         Filter::And(filters)
     }
@@ -56,9 +75,57 @@ impl Filter {
     pub fn new_not(filter: Filter) -> Self {
         Filter::Not(Box::new(filter))
     }
+
+    pub fn has_parameters(&self) -> bool {
+        match self {
+            Filter::Parameter(_) => true,
+            Filter::Embedded(embedded) => embedded.filter.has_parameters(),
+            Filter::And(filters) | Filter::Or(filters) => {
+                filters.iter().any(|filter| filter.has_parameters())
+            }
+            Filter::Not(filter) => filter.has_parameters(),
+            Filter::Condition(_) | Filter::Json(_) => false,
+        }
+    }
+
+    /// Replaces the parameters with conditions using the values of the parameters. Fails if
+    /// there are no values for a parameter.
+    pub fn bind(&self, parameters: &[Vec<Option<IsarValue>>]) -> Result<Filter> {
+        let filter = match self {
+            Filter::Parameter(parameter) => {
+                let values = parameters
+                    .get(parameter.parameter_index as usize)
+                    .ok_or(IsarError::IllegalArgument {})?;
+                Filter::new_condition(
+                    parameter.property_index,
+                    parameter.condition_type,
+                    values.clone(),
+                    parameter.case_sensitive,
+                )
+            }
+            Filter::Embedded(embedded) => {
+                Filter::new_embedded(embedded.property_index, embedded.filter.bind(parameters)?)
+            }
+            Filter::And(filters) => Filter::And(
+                filters
+                    .iter()
+                    .map(|f| f.bind(parameters))
+                    .collect::<Result<_>>()?,
+            ),
+            Filter::Or(filters) => Filter::Or(
+                filters
+                    .iter()
+                    .map(|f| f.bind(parameters))
+                    .collect::<Result<_>>()?,
+            ),
+            Filter::Not(filter) => Filter::new_not(filter.bind(parameters)?),
+            Filter::Condition(_) | Filter::Json(_) => self.clone(),
+        };
+        Ok(filter)
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
 pub enum ConditionType {
     IsNull,
     Equal,
@@ -73,7 +140,8 @@ pub enum ConditionType {
     StringMatches,
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct FilterCondition {
     pub property_index: u16,
     pub condition_type: ConditionType,
@@ -97,7 +165,8 @@ impl FilterCondition {
     }
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct FilterJson {
     pub property_index: u16,
     pub path: Vec<String>,
@@ -141,7 +210,8 @@ impl FilterJson {
     }
 }
 
-#[derive(PartialEq, Clone, Debug)]
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct FilterEmbedded {
     pub property_index: u16,
     pub filter: Box<Filter>,
@@ -155,3 +225,65 @@ impl FilterEmbedded {
         }
     }
 }
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterParameter {
+    pub parameter_index: u16,
+    pub property_index: u16,
+    pub condition_type: ConditionType,
+    pub case_sensitive: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bind() {
+        let filter = Filter::new_and(vec![
+            Filter::new_parameter(1, 2, ConditionType::Between, true),
+            Filter::new_not(Filter::new_embedded(
+                3,
+                Filter::new_parameter(0, 1, ConditionType::StringContains, false),
+            )),
+            Filter::new_condition(1, ConditionType::IsNull, vec![], false),
+        ]);
+        assert!(filter.has_parameters());
+
+        let bound = filter
+            .bind(&[
+                vec![Some(IsarValue::String("a".to_string()))],
+                vec![Some(IsarValue::Integer(1)), None],
+            ])
+            .unwrap();
+        assert!(!bound.has_parameters());
+        assert_eq!(
+            bound,
+            Filter::new_and(vec![
+                Filter::new_condition(
+                    2,
+                    ConditionType::Between,
+                    vec![Some(IsarValue::Integer(1)), None],
+                    true
+                ),
+                Filter::new_not(Filter::new_embedded(
+                    3,
+                    Filter::new_condition(
+                        1,
+                        ConditionType::StringContains,
+                        vec![Some(IsarValue::String("a".to_string()))],
+                        false
+                    ),
+                )),
+                Filter::new_condition(1, ConditionType::IsNull, vec![], false),
+            ])
+        );
+
+        // Test missing parameter
+        assert!(matches!(
+            filter.bind(&[vec![]]),
+            Err(IsarError::IllegalArgument {})
+        ));
+    }
+}
@@ -49,7 +49,9 @@ pub fn matches_json(
     }
 }
 
-fn extract_value<'a>(json: &'a Value, path: &[String]) -> Option<&'a Value> {
+/// Returns the value at the path or `None` if the path traverses a value that is neither an
+/// object nor an array. Missing keys are null.
+pub fn extract_value<'a>(json: &'a Value, path: &[String]) -> Option<&'a Value> {
     let mut value = json;
     for key in path.iter() {
         match value {
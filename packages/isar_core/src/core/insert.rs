@@ -4,7 +4,9 @@ use crate::core::error::Result;
 pub trait IsarInsert<'a>: IsarWriter<'a> + Sized {
     type Txn;
 
-    fn save(&mut self, id: i64) -> Result<()>;
+    /// Saves the object and returns its id or `None` if the object was ignored because of a
+    /// unique index. Upserts return the id of the merged object.
+    fn save(&mut self, id: i64) -> Result<Option<i64>>;
 
     fn finish(self) -> Result<Self::Txn>;
 }
@@ -3,6 +3,7 @@ use super::de::IsarJsonImportVisitor;
 use super::error::{IsarError, Result};
 use super::insert::IsarInsert;
 use super::query_builder::IsarQueryBuilder;
+use super::query_plan::QueryPlan;
 use super::reader::IsarReader;
 use super::schema::IsarSchema;
 use super::value::IsarValue;
@@ -75,6 +76,16 @@ pub trait IsarInstance: Sized {
     fn insert(&self, txn: Self::Txn, collection_index: u16, count: u32)
     -> Result<Self::Insert<'_>>;
 
+    /// Like [`insert`](Self::insert) but objects with the same key in the given unique index
+    /// keep the id of the existing object and only overwrite the written properties.
+    fn upsert(
+        &self,
+        txn: Self::Txn,
+        collection_index: u16,
+        index_name: &str,
+        count: u32,
+    ) -> Result<Self::Insert<'_>>;
+
     fn update(
         &self,
         txn: &Self::Txn,
@@ -106,6 +117,44 @@ pub trait IsarInstance: Sized {
         limit: Option<u32>,
     ) -> Result<Self::QueryCursor<'a>>;
 
+    /// Binds the values of the filter parameters of the query. The values of a parameter are
+    /// used like the values of a condition so `Between` requires a lower and an upper value.
+    /// Fails if the query has no parameters or if a parameter has no values. Queries with
+    /// parameters fail with [`IsarError::IllegalArgument`] until they are bound. Pages of a
+    /// query use the values bound when the page was created and cannot be bound.
+    fn query_bind(
+        &self,
+        query: &mut Self::Query,
+        parameters: &[Vec<Option<IsarValue>>],
+    ) -> Result<()>;
+
+    /// Returns a cursor that only reads the id and the given properties of the matching
+    /// objects. All other properties are read as null.
+    fn query_properties_cursor<'a>(
+        &'a self,
+        txn: &'a Self::Txn,
+        query: &'a Self::Query,
+        properties: &[u16],
+        offset: Option<u32>,
+        limit: Option<u32>,
+    ) -> Result<Self::QueryCursor<'a>>;
+
+    /// Returns a query for the objects after the page that ended with the given token or for
+    /// the first page if there is no token. Pages use the sort of the query with ties ordered
+    /// by id in the direction of the last sort property and are read using
+    /// [`query_cursor`](Self::query_cursor) with a limit. Tokens are only accepted by the query
+    /// that created them and its pages. Binding other parameter values creates a new query.
+    fn query_page(&self, query: &Self::Query, token: Option<&str>) -> Result<Self::Query>;
+
+    /// Returns the token of a page that ends with the object with the given id or `None` if
+    /// the object does not exist.
+    fn query_page_token(
+        &self,
+        txn: &Self::Txn,
+        query: &Self::Query,
+        id: i64,
+    ) -> Result<Option<String>>;
+
     fn query_aggregate(
         &self,
         txn: &Self::Txn,
@@ -114,6 +163,27 @@ pub trait IsarInstance: Sized {
         property_index: Option<u16>,
     ) -> Result<Option<IsarValue>>;
 
+    /// Evaluates multiple aggregations with a single iteration of the query. Returns the results
+    /// in the order of the aggregations.
+    fn query_aggregate_batch(
+        &self,
+        txn: &Self::Txn,
+        query: &Self::Query,
+        aggregations: &[(Aggregation, Option<u16>)],
+    ) -> Result<Vec<Option<IsarValue>>>;
+
+    /// Groups the objects of the query by the values of the given properties and aggregates
+    /// every group. Returns the values of the group properties and the result of each group in
+    /// no particular order. At least one scalar property is required to group objects.
+    fn query_aggregate_groups(
+        &self,
+        txn: &Self::Txn,
+        query: &Self::Query,
+        group_by: &[(u16, bool)],
+        aggregation: Aggregation,
+        property_index: Option<u16>,
+    ) -> Result<Vec<AggregationGroup>>;
+
     fn query_update(
         &self,
         txn: &Self::Txn,
@@ -131,6 +201,9 @@ pub trait IsarInstance: Sized {
         limit: Option<u32>,
     ) -> Result<u32>;
 
+    /// Returns how the query is executed. Used to diagnose slow queries.
+    fn explain(&self, txn: &Self::Txn, query: &Self::Query) -> Result<QueryPlan>;
+
     fn import_json<'a, T: Deserializer<'a>>(
         &self,
         txn: Self::Txn,
@@ -164,11 +237,25 @@ pub trait IsarInstance: Sized {
 
     fn copy(&self, path: &str) -> Result<()>;
 
-    fn verify(&self, txn: &Self::Txn) -> Result<()>;
+    fn verify(&self, txn: &Self::Txn, deep: bool) -> Result<()>;
+
+    /// Regenerates the index from the objects. Objects are never deleted so duplicates in a
+    /// unique index fail with [`IsarError::UniqueViolation`].
+    fn rebuild_index(&self, txn: &Self::Txn, collection_index: u16, index_name: &str)
+    -> Result<()>;
+
+    fn rebuild_indexes(&self, txn: &Self::Txn) -> Result<()>;
 
     fn close(instance: Self::Instance, delete: bool) -> bool;
 }
 
+/// The result of an aggregation for one group of objects.
+#[derive(Clone, PartialEq, Debug)]
+pub struct AggregationGroup {
+    pub values: Vec<Option<IsarValue>>,
+    pub result: Option<IsarValue>,
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum Aggregation {
     Count,
@@ -177,4 +264,57 @@ pub enum Aggregation {
     Max,
     Sum,
     Average,
+    CountDistinct,
+    Median,
+    /// The percentile between 0 and 1 interpolated between the closest values.
+    Percentile(Percentile),
+    /// The sample standard deviation.
+    StdDev,
+    /// The sample variance.
+    Variance,
+    /// The number of elements of a list property that are not null.
+    ElementCount,
+    /// The length of the shortest list.
+    MinLength,
+    /// The length of the longest list.
+    MaxLength,
+    /// The average length of the lists.
+    AverageLength,
+}
+
+/// A percentile between 0 and 1. NaN is rejected so percentiles are totally ordered.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Percentile(f64);
+
+impl Percentile {
+    pub fn new(percentile: f64) -> Result<Self> {
+        if (0.0..=1.0).contains(&percentile) {
+            Ok(Percentile(percentile))
+        } else {
+            Err(IsarError::IllegalArgument {})
+        }
+    }
+
+    pub fn value(&self) -> f64 {
+        self.0
+    }
+}
+
+impl Eq for Percentile {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile_new() {
+        assert_eq!(Percentile::new(0.0).unwrap().value(), 0.0);
+        assert_eq!(Percentile::new(1.0).unwrap().value(), 1.0);
+        for percentile in [-0.1, 1.1, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                Percentile::new(percentile),
+                Err(IsarError::IllegalArgument {})
+            ));
+        }
+    }
 }
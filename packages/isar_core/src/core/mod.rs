@@ -7,10 +7,13 @@ pub mod filter;
 pub mod filter_json;
 pub mod insert;
 pub mod instance;
+pub mod page_token;
 pub mod query_builder;
+pub mod query_plan;
 pub mod reader;
 pub mod schema;
 mod ser;
+pub(crate) mod statistics;
 pub mod value;
 pub mod watcher;
 pub mod writer;
@@ -0,0 +1,123 @@
+use super::error::{IsarError, Result};
+use super::value::IsarValue;
+use base64::{Engine, engine::general_purpose};
+use serde::{Deserialize, Serialize};
+
+/// Position of the last object of a page. Contains the values of the sort properties and the
+/// id of the object which breaks ties.
+#[derive(PartialEq, Clone, Debug)]
+pub struct PageToken {
+    pub values: Vec<Option<IsarValue>>,
+    pub id: i64,
+    /// Fingerprint of the query that created the token.
+    pub query: u64,
+}
+
+/// Serialized form of a value. Reals are stored as bits because JSON has no infinity.
+#[derive(Serialize, Deserialize)]
+enum TokenValue {
+    Bool(bool),
+    Integer(i64),
+    Real(u64),
+    String(String),
+}
+
+impl PageToken {
+    pub fn new(values: Vec<Option<IsarValue>>, id: i64, query: u64) -> Self {
+        Self { values, id, query }
+    }
+
+    /// Encodes the token as an opaque url safe string.
+    pub fn encode(&self) -> String {
+        let values = self
+            .values
+            .iter()
+            .map(|value| {
+                value.as_ref().map(|value| match value {
+                    IsarValue::Bool(value) => TokenValue::Bool(*value),
+                    IsarValue::Integer(value) => TokenValue::Integer(*value),
+                    IsarValue::Real(value) => TokenValue::Real(value.to_bits()),
+                    IsarValue::String(value) => TokenValue::String(value.clone()),
+                })
+            })
+            .collect::<Vec<_>>();
+        let json = serde_json::to_vec(&(values, self.id, self.query)).unwrap();
+        general_purpose::URL_SAFE_NO_PAD.encode(json)
+    }
+
+    /// Decodes a token of the query with the given fingerprint and number of sort properties.
+    /// Tokens of other queries are rejected.
+    pub fn decode(token: &str, properties: usize, query: u64) -> Result<Self> {
+        let (values, id, token_query): (Vec<Option<TokenValue>>, i64, u64) =
+            general_purpose::URL_SAFE_NO_PAD
+                .decode(token)
+                .ok()
+                .and_then(|json| serde_json::from_slice(&json).ok())
+                .ok_or(IsarError::IllegalArgument {})?;
+        if values.len() != properties || token_query != query {
+            return Err(IsarError::IllegalArgument {});
+        }
+        let values = values
+            .into_iter()
+            .map(|value| {
+                value.map(|value| match value {
+                    TokenValue::Bool(value) => IsarValue::Bool(value),
+                    TokenValue::Integer(value) => IsarValue::Integer(value),
+                    TokenValue::Real(bits) => IsarValue::Real(f64::from_bits(bits)),
+                    TokenValue::String(value) => IsarValue::String(value),
+                })
+            })
+            .collect();
+        Ok(PageToken { values, id, query })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let token = PageToken::new(
+            vec![
+                Some(IsarValue::Integer(-5)),
+                None,
+                Some(IsarValue::String("a/b+c".to_string())),
+                Some(IsarValue::Real(1.5)),
+                Some(IsarValue::Real(f64::NEG_INFINITY)),
+                Some(IsarValue::Bool(true)),
+            ],
+            i64::MIN,
+            u64::MAX,
+        );
+        let encoded = token.encode();
+        assert!(
+            encoded
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        );
+        assert_eq!(PageToken::decode(&encoded, 6, u64::MAX).unwrap(), token);
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        let token = PageToken::new(vec![Some(IsarValue::Integer(1))], 2, 3).encode();
+        assert!(PageToken::decode(&token, 1, 3).is_ok());
+        assert!(matches!(
+            PageToken::decode(&token, 2, 3),
+            Err(IsarError::IllegalArgument {})
+        ));
+        assert!(matches!(
+            PageToken::decode(&token, 1, 4),
+            Err(IsarError::IllegalArgument {})
+        ));
+        assert!(matches!(
+            PageToken::decode("not a token", 0, 3),
+            Err(IsarError::IllegalArgument {})
+        ));
+        assert!(matches!(
+            PageToken::decode("", 0, 3),
+            Err(IsarError::IllegalArgument {})
+        ));
+    }
+}
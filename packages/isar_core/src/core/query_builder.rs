@@ -1,6 +1,8 @@
+use super::error::Result;
 use super::filter::Filter;
+use super::value::IsarValue;
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Sort {
     Asc,
     Desc,
@@ -15,5 +17,22 @@ pub trait IsarQueryBuilder {
 
     fn add_distinct(&mut self, property_index: u16, case_sensitive: bool);
 
+    /// Restricts the query to objects with an id between `lower` and `upper` (inclusive).
+    /// Multiple ranges are combined and the filter is applied to every object in the ranges.
+    fn add_id_range(&mut self, lower: i64, upper: i64);
+
+    /// Restricts the query to objects in the given range of the index. `lower` and `upper`
+    /// contain values for a prefix of the index properties. Hashed indexes only support
+    /// lookups of a value for every index property. Keys longer than about 1 KB are only
+    /// compared by their first bytes so the range may contain additional objects.
+    fn add_index_range(
+        &mut self,
+        index_name: &str,
+        lower: Vec<Option<IsarValue>>,
+        upper: Vec<Option<IsarValue>>,
+        include_lower: bool,
+        include_upper: bool,
+    ) -> Result<()>;
+
     fn build(self) -> Self::Query;
 }
@@ -0,0 +1,28 @@
+use serde::Serialize;
+
+/// Describes how a query is executed.
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct QueryPlan {
+    /// The id and index ranges that are scanned in this order.
+    pub ranges: Vec<QueryPlanRange>,
+    /// The conditions that are checked for every scanned object or `None` if all scanned
+    /// objects match.
+    pub filter: Option<String>,
+    /// Whether the results are sorted in memory instead of being read in sort order.
+    #[serde(rename = "sortInMemory")]
+    pub sort_in_memory: bool,
+    /// Estimated number of objects read from the ranges. SQLite estimates the rows using the
+    /// statistics collected by `ANALYZE` and reports 0 rows for collections without them.
+    #[serde(rename = "estimatedRows")]
+    pub estimated_rows: u64,
+    /// The steps of the plan as reported by the storage engine.
+    pub details: Vec<String>,
+}
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct QueryPlanRange {
+    /// Name of the index or `None` for the ids.
+    pub index: Option<String>,
+    /// Bounds of the range in the format of the storage engine. Empty for full scans.
+    pub bounds: String,
+}
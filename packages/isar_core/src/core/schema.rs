@@ -1,7 +1,10 @@
 use super::error::Result;
+use super::filter::{ConditionType, Filter};
+use super::value::IsarValue;
 use super::{data_type::DataType, error::IsarError};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
 
 #[derive(Serialize, Deserialize, Clone, Hash, Debug, PartialEq, Eq)]
 pub struct IsarSchema {
@@ -98,10 +101,18 @@ impl IsarSchema {
         for old_index in &old_collection.indexes {
             let index = self.indexes.iter().find(|i| &i.name == &old_index.name);
             if let Some(index) = index {
-                let property_dropped = index.properties.iter().any(|p| drop_properties.contains(p));
+                let property_dropped = index.properties.iter().any(|p| {
+                    let name = p.split('.').next().unwrap();
+                    drop_properties.iter().any(|d| d == name)
+                });
                 if index.unique != old_index.unique
                     || &index.properties != &old_index.properties
                     || index.hash != old_index.hash
+                    || index.case_sensitive != old_index.case_sensitive
+                    || index.json_type != old_index.json_type
+                    || index.on_conflict != old_index.on_conflict
+                    // filters reference properties by position so they are compared by the
+                    // backends
                     || property_dropped
                 {
                     add_indexes.push(index);
@@ -174,29 +185,82 @@ impl IsarSchema {
             }
 
             for index_property in &index.properties {
-                let property = self
-                    .properties
-                    .iter()
-                    .find(|p| p.name.as_deref() == Some(&index_property));
+                let property = self.find_index_property(index_property, collections);
                 if property.is_none() {
                     return schema_error("Index property does not exist");
                 }
                 let property = property.unwrap();
 
-                if property.data_type == DataType::Float || property.data_type == DataType::Double {
-                    return schema_error("Float properties cannot be indexed.");
-                } else if property.data_type == DataType::Object {
+                // list indexes contain an entry for each element
+                let data_type = property
+                    .data_type
+                    .element_type()
+                    .unwrap_or(property.data_type);
+                if data_type == DataType::Object {
                     return schema_error("Object properties cannot be indexed.");
-                } else if property.data_type == DataType::Json {
+                } else if data_type == DataType::Json && index.json_type.is_none() {
                     return schema_error("JSON properties cannot be indexed.");
-                } else if property.data_type.is_list() {
-                    return schema_error("List properties cannot be indexed.");
-                } else if property.data_type == DataType::String
-                    && !index.hash
-                    && index.properties.last() != Some(&index_property)
+                } else if data_type != DataType::Json && index.json_type.is_some() {
+                    return schema_error("JSON path indexes must reference a JSON property.");
+                } else if property.data_type.is_list() && index_property.contains('.') {
+                    return schema_error("List properties of embedded objects cannot be indexed.");
+                } else if property.data_type.is_list()
+                    && (index.properties.len() > 1 || index.unique)
                 {
                     return schema_error(
-                        "Only the last property of a non-hashed index can be a String.",
+                        "List properties can only be indexed by non-unique single property indexes.",
+                    );
+                }
+            }
+
+            if let Some(json_type) = index.json_type {
+                let path = index.properties[0].split_once('.').map(|(_, path)| path);
+                if index.properties.len() != 1 || path.is_none() {
+                    return schema_error("JSON path indexes must have exactly one path.");
+                } else if !matches!(
+                    json_type,
+                    DataType::Bool | DataType::Long | DataType::Double | DataType::String
+                ) {
+                    return schema_error(
+                        "JSON path indexes only support Bool, Long, Double and String values.",
+                    );
+                } else if index.hash {
+                    return schema_error("JSON path indexes cannot be hashed.");
+                }
+                let valid_path = path.unwrap().split('.').all(|key| {
+                    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                });
+                if !valid_path {
+                    return schema_error("Invalid JSON index path.");
+                }
+            }
+
+            if !index.unique && index.on_conflict != ConflictPolicy::Replace {
+                return schema_error("Only unique indexes can have a conflict policy.");
+            }
+
+            if let Some(filter) = &index.filter {
+                let has_list = index
+                    .properties
+                    .iter()
+                    .filter_map(|p| self.find_index_property(p, collections))
+                    .any(|p| p.data_type.is_list());
+                if has_list {
+                    return schema_error("List indexes cannot have a filter.");
+                }
+                self.verify_index_filter(filter)?;
+            }
+
+            if !index.case_sensitive {
+                let has_string = index.json_type == Some(DataType::String)
+                    || index
+                        .properties
+                        .iter()
+                        .filter_map(|p| self.find_index_property(p, collections))
+                        .any(|p| matches!(p.data_type, DataType::String | DataType::StringList));
+                if !has_string {
+                    return schema_error(
+                        "Only indexes containing strings can be case-insensitive.",
                     );
                 }
             }
@@ -204,6 +268,98 @@ impl IsarSchema {
 
         Ok(())
     }
+
+    /// Index filters only support the conditions that SQLite and the native backend evaluate
+    /// the same way.
+    fn verify_index_filter(&self, filter: &Filter) -> Result<()> {
+        match filter {
+            Filter::Condition(condition) => {
+                let property = (condition.property_index as usize)
+                    .checked_sub(1)
+                    .and_then(|i| self.properties.get(i));
+                let Some(property) = property else {
+                    return schema_error(
+                        "Index filters only support properties of the collection.",
+                    );
+                };
+                let supported_type = matches!(
+                    property.data_type,
+                    DataType::Bool
+                        | DataType::Byte
+                        | DataType::Int
+                        | DataType::Float
+                        | DataType::Long
+                        | DataType::Double
+                        | DataType::String
+                );
+                let supported_condition = matches!(
+                    condition.condition_type,
+                    ConditionType::IsNull
+                        | ConditionType::Equal
+                        | ConditionType::Greater
+                        | ConditionType::GreaterOrEqual
+                        | ConditionType::Less
+                        | ConditionType::LessOrEqual
+                        | ConditionType::Between
+                );
+                if !supported_type || !supported_condition {
+                    return schema_error("Unsupported index filter condition.");
+                }
+                let matches_type = condition.values.iter().flatten().all(|value| {
+                    match (property.data_type, value) {
+                        (DataType::Bool, IsarValue::Bool(_)) => true,
+                        (
+                            DataType::Byte | DataType::Int | DataType::Long,
+                            IsarValue::Integer(_),
+                        ) => true,
+                        (DataType::Float | DataType::Double, IsarValue::Integer(_)) => true,
+                        (DataType::Float | DataType::Double, IsarValue::Real(value)) => {
+                            value.is_finite()
+                        }
+                        (DataType::String, IsarValue::String(_)) => condition.case_sensitive,
+                        _ => false,
+                    }
+                });
+                if !matches_type {
+                    return schema_error("Index filter values must match the property type.");
+                }
+                Ok(())
+            }
+            Filter::And(filters) | Filter::Or(filters) if !filters.is_empty() => filters
+                .iter()
+                .try_for_each(|filter| self.verify_index_filter(filter)),
+            _ => schema_error("Index filters only support conditions combined by AND and OR."),
+        }
+    }
+
+    /// Finds the property of an index. Properties of embedded objects are referenced using
+    /// dotted paths like `address.zip`. Paths into JSON properties like `payload.user.age`
+    /// return the JSON property.
+    pub fn find_index_property<'a>(
+        &'a self,
+        path: &str,
+        collections: &'a [IsarSchema],
+    ) -> Option<&'a PropertySchema> {
+        let mut schema = self;
+        let mut names = path.split('.').peekable();
+        loop {
+            let name = names.next()?;
+            let property = schema
+                .properties
+                .iter()
+                .find(|p| p.name.as_deref() == Some(name))?;
+            let json_path = property.data_type == DataType::Json && std::ptr::eq(schema, self);
+            if names.peek().is_none() || json_path {
+                return Some(property);
+            } else if property.data_type != DataType::Object {
+                return None;
+            }
+            let target = property.collection.as_ref()?;
+            schema = collections
+                .iter()
+                .find(|c| &c.name == target && c.embedded)?;
+        }
+    }
 }
 
 fn schema_error<T>(msg: &str) -> Result<T> {
@@ -216,6 +372,7 @@ fn verify_name(name: &str) -> Result<()> {
     if name.is_empty() {
         schema_error("Empty names are not allowed.")
     } else if name.starts_with('_') {
+        // reserved for internal tables like the ones of list indexes
         schema_error("Names must not begin with an underscore.")
     } else if name.starts_with("sqlite_") {
         schema_error("Names must not begin with 'sqlite_'.")
@@ -244,23 +401,107 @@ impl PropertySchema {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct IndexSchema {
     pub name: String,
     pub properties: Vec<String>,
     pub unique: bool,
     pub hash: bool,
+    /// Case-insensitive indexes store lowercase strings.
+    #[serde(rename = "caseSensitive", default = "default_case_sensitive")]
+    pub case_sensitive: bool,
+    /// The type of the values of a JSON path index. Values of other types are indexed as null.
+    #[serde(rename = "jsonType", default, skip_serializing_if = "Option::is_none")]
+    pub json_type: Option<DataType>,
+    /// Only objects matching the filter are indexed. The conditions reference the properties by
+    /// their position in the schema.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter>,
+    /// How unique conflicts with other objects are resolved.
+    #[serde(rename = "onConflict", default)]
+    pub on_conflict: ConflictPolicy,
+}
+
+// Filter values may be floats so `Eq` and `Hash` cannot be derived. The filter is not hashed
+// which keeps the hash consistent with `PartialEq`.
+impl Eq for IndexSchema {}
+
+impl Hash for IndexSchema {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.properties.hash(state);
+        self.unique.hash(state);
+        self.hash.hash(state);
+        self.case_sensitive.hash(state);
+        self.json_type.hash(state);
+        self.on_conflict.hash(state);
+    }
+}
+
+/// Decides what happens when an object has the same unique index key as another object.
+/// Migrations and index rebuilds never delete objects so they fail with
+/// `IsarError::UniqueViolation` on duplicates regardless of the policy.
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictPolicy {
+    /// The other object is deleted.
+    #[default]
+    Replace,
+    /// The write fails with `IsarError::UniqueViolation`.
+    Fail,
+    /// The object is not written.
+    Ignore,
 }
 
 impl IndexSchema {
-    pub fn new(name: &str, properties: Vec<&str>, unique: bool, hash: bool) -> IndexSchema {
+    pub fn new(
+        name: &str,
+        properties: Vec<&str>,
+        unique: bool,
+        hash: bool,
+        case_sensitive: bool,
+    ) -> IndexSchema {
         IndexSchema {
             name: name.to_string(),
             properties: properties.iter().map(|p| p.to_string()).collect(),
             unique,
             hash,
+            case_sensitive,
+            json_type: None,
+            filter: None,
+            on_conflict: ConflictPolicy::Replace,
         }
     }
+
+    /// Creates a partial index that only contains the objects matching the filter.
+    pub fn with_filter(mut self, filter: Filter) -> IndexSchema {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Sets how conflicts of a unique index with other objects are resolved.
+    pub fn with_conflict(mut self, on_conflict: ConflictPolicy) -> IndexSchema {
+        self.on_conflict = on_conflict;
+        self
+    }
+
+    /// Creates an index on the value at a path of a JSON property like `payload.user.age`.
+    pub fn new_json(
+        name: &str,
+        path: &str,
+        json_type: DataType,
+        unique: bool,
+        case_sensitive: bool,
+    ) -> IndexSchema {
+        IndexSchema {
+            json_type: Some(json_type),
+            ..IndexSchema::new(name, vec![path], unique, false, case_sensitive)
+        }
+    }
+}
+
+fn default_case_sensitive() -> bool {
+    true
 }
 
 #[cfg(test)]
@@ -294,6 +535,7 @@ mod test {
                 "",
                 "sqlite_sequence",
                 "_internal",
+                "_isar_conflict",
             ];
 
             for name in invalid_names {
@@ -316,7 +558,7 @@ mod test {
                 "test",
                 None,
                 vec![PropertySchema::new("prop1", DataType::Int, None)],
-                vec![IndexSchema::new("index", vec!["prop1"], false, false)],
+                vec![IndexSchema::new("index", vec!["prop1"], false, false, true)],
                 true, // embedded
             );
             assert!(schema.verify(&[]).is_err());
@@ -351,6 +593,93 @@ mod test {
             )]);
             assert!(schema.verify(&[embedded]).is_err());
         }
+
+        #[test]
+        fn test_embedded_property_index_validation() {
+            let embedded = IsarSchema::new(
+                "address",
+                None,
+                vec![
+                    PropertySchema::new("zip", DataType::Int, None),
+                    PropertySchema::new("tags", DataType::StringList, None),
+                ],
+                vec![],
+                true,
+            );
+            let props = vec![
+                PropertySchema::new("address", DataType::Object, Some("address")),
+                PropertySchema::new("count", DataType::Int, None),
+            ];
+            let schema_with_index = |properties: Vec<&str>| {
+                let index = IndexSchema::new("index", properties, false, false, true);
+                index_schema(props.clone(), vec![index])
+            };
+
+            // Valid: property of an embedded object
+            let schema = schema_with_index(vec!["address.zip"]);
+            assert!(schema.verify(&[embedded.clone()]).is_ok());
+
+            // Valid: composite with a property of the collection
+            let schema = schema_with_index(vec!["address.zip", "count"]);
+            assert!(schema.verify(&[embedded.clone()]).is_ok());
+
+            // Invalid: unknown property of the embedded object
+            let schema = schema_with_index(vec!["address.street"]);
+            assert!(schema.verify(&[embedded.clone()]).is_err());
+
+            // Invalid: path does not start with an object
+            let schema = schema_with_index(vec!["count.zip"]);
+            assert!(schema.verify(&[embedded.clone()]).is_err());
+
+            // Invalid: list properties of embedded objects
+            let schema = schema_with_index(vec!["address.tags"]);
+            assert!(schema.verify(&[embedded.clone()]).is_err());
+
+            // Invalid: the object itself
+            let schema = schema_with_index(vec!["address"]);
+            assert!(schema.verify(&[embedded]).is_err());
+        }
+        #[test]
+        fn test_json_index_validation() {
+            let props = vec![
+                PropertySchema::new("json", DataType::Json, None),
+                PropertySchema::new("count", DataType::Int, None),
+            ];
+            let schema_with_index = |index: IndexSchema| index_schema(props.clone(), vec![index]);
+
+            // Valid: path of a JSON property
+            let index = IndexSchema::new_json("index", "json.a.b_1", DataType::Long, false, true);
+            assert!(schema_with_index(index).verify(&[]).is_ok());
+
+            // Valid: case-insensitive string values
+            let index = IndexSchema::new_json("index", "json.a", DataType::String, false, false);
+            assert!(schema_with_index(index).verify(&[]).is_ok());
+
+            // Invalid: the JSON property itself
+            let index = IndexSchema::new("index", vec!["json"], false, false, true);
+            assert!(schema_with_index(index).verify(&[]).is_err());
+
+            // Invalid: not a JSON property
+            let index = IndexSchema::new_json("index", "count.a", DataType::Long, false, true);
+            assert!(schema_with_index(index).verify(&[]).is_err());
+
+            // Invalid: unsupported value type
+            let index = IndexSchema::new_json("index", "json.a", DataType::Int, false, true);
+            assert!(schema_with_index(index).verify(&[]).is_err());
+
+            // Invalid: path segments with special characters
+            let index = IndexSchema::new_json("index", "json.a'b", DataType::Long, false, true);
+            assert!(schema_with_index(index).verify(&[]).is_err());
+
+            // Invalid: case-insensitive non-string values
+            let index = IndexSchema::new_json("index", "json.a", DataType::Long, false, false);
+            assert!(schema_with_index(index).verify(&[]).is_err());
+
+            // Invalid: hashed
+            let mut index = IndexSchema::new_json("index", "json.a", DataType::Long, false, true);
+            index.hash = true;
+            assert!(schema_with_index(index).verify(&[]).is_err());
+        }
     }
 
     mod property_validation {
@@ -399,9 +728,9 @@ mod test {
                 ],
                 vec![
                     // Valid: string at end of non-hashed index
-                    IndexSchema::new("index1", vec!["int1", "bool1", "str1"], false, false),
+                    IndexSchema::new("index1", vec!["int1", "bool1", "str1"], false, false, true),
                     // Valid: hashed index with any property order
-                    IndexSchema::new("index2", vec!["int1", "str1", "bool1"], false, true),
+                    IndexSchema::new("index2", vec!["int1", "str1", "bool1"], false, true, true),
                 ],
             );
             assert!(schema.verify(&[]).is_ok());
@@ -415,11 +744,30 @@ mod test {
                     PropertySchema::new("str1", DataType::String, None),
                 ],
                 vec![
-                    // Invalid: string not at end in non-hashed index
-                    IndexSchema::new("index", vec!["str1", "int1"], false, false),
+                    // Valid: strings are delimited in non-hashed indexes
+                    IndexSchema::new("index", vec!["str1", "int1"], false, false, true),
                 ],
             );
-            assert!(schema.verify(&[]).is_err());
+            assert!(schema.verify(&[]).is_ok());
+        }
+
+        #[test]
+        fn test_non_hashed_index_with_multiple_strings() {
+            let schema = index_schema(
+                vec![
+                    PropertySchema::new("str1", DataType::String, None),
+                    PropertySchema::new("str2", DataType::String, None),
+                    PropertySchema::new("str3", DataType::String, None),
+                ],
+                vec![IndexSchema::new(
+                    "index",
+                    vec!["str1", "str2", "str3"],
+                    true,
+                    false,
+                    false,
+                )],
+            );
+            assert!(schema.verify(&[]).is_ok());
         }
 
         #[test]
@@ -435,6 +783,7 @@ mod test {
                     vec!["str1", "str2", "str3"],
                     false,
                     true, // hashed
+                    true,
                 )],
             );
             assert!(schema.verify(&[]).is_ok());
@@ -448,8 +797,24 @@ mod test {
                     PropertySchema::new("prop2", DataType::String, None),
                 ],
                 vec![
-                    IndexSchema::new("index1", vec!["prop1"], true, false),
-                    IndexSchema::new("index2", vec!["prop1", "prop2"], true, false),
+                    IndexSchema::new("index1", vec!["prop1"], true, false, true),
+                    IndexSchema::new("index2", vec!["prop1", "prop2"], true, false, true),
+                ],
+            );
+            assert!(schema.verify(&[]).is_ok());
+        }
+
+        #[test]
+        fn test_float_index_validation() {
+            let schema = index_schema(
+                vec![
+                    PropertySchema::new("float", DataType::Float, None),
+                    PropertySchema::new("double", DataType::Double, None),
+                ],
+                vec![
+                    IndexSchema::new("index1", vec!["float"], true, false, true),
+                    IndexSchema::new("index2", vec!["double", "float"], false, false, true),
+                    IndexSchema::new("index3", vec!["double"], false, true, true),
                 ],
             );
             assert!(schema.verify(&[]).is_ok());
@@ -460,10 +825,173 @@ mod test {
             let schema = index_schema(
                 vec![PropertySchema::new("prop1", DataType::Int, None)],
                 vec![
-                    IndexSchema::new("index", vec!["prop1"], false, false),
-                    IndexSchema::new("index", vec!["prop1"], true, false),
+                    IndexSchema::new("index", vec!["prop1"], false, false, true),
+                    IndexSchema::new("index", vec!["prop1"], true, false, true),
+                ],
+            );
+            assert!(schema.verify(&[]).is_err());
+        }
+
+        #[test]
+        fn test_list_index_validation() {
+            let props = vec![
+                PropertySchema::new("tags", DataType::StringList, None),
+                PropertySchema::new("ints", DataType::IntList, None),
+                PropertySchema::new("floats", DataType::FloatList, None),
+                PropertySchema::new("prop", DataType::Int, None),
+            ];
+
+            let schema = index_schema(
+                props.clone(),
+                vec![
+                    IndexSchema::new("index1", vec!["tags"], false, false, true),
+                    IndexSchema::new("index2", vec!["tags"], false, true, true),
+                    IndexSchema::new("index3", vec!["ints"], false, false, true),
+                    IndexSchema::new("index4", vec!["floats"], false, false, true),
+                ],
+            );
+            assert!(schema.verify(&[]).is_ok());
+
+            let invalid_indexes = vec![
+                IndexSchema::new("index", vec!["tags"], true, false, true),
+                IndexSchema::new("index", vec!["prop", "ints"], false, false, true),
+                IndexSchema::new("index", vec!["ints", "prop"], false, true, true),
+            ];
+            for index in invalid_indexes {
+                let schema = index_schema(props.clone(), vec![index]);
+                assert!(schema.verify(&[]).is_err());
+            }
+        }
+
+        #[test]
+        fn test_case_insensitive_index_validation() {
+            let props = vec![
+                PropertySchema::new("str", DataType::String, None),
+                PropertySchema::new("tags", DataType::StringList, None),
+                PropertySchema::new("prop", DataType::Int, None),
+            ];
+
+            let schema = index_schema(
+                props.clone(),
+                vec![
+                    IndexSchema::new("index1", vec!["str"], true, false, false),
+                    IndexSchema::new("index2", vec!["tags"], false, true, false),
+                    IndexSchema::new("index3", vec!["prop", "str"], false, false, false),
                 ],
             );
+            assert!(schema.verify(&[]).is_ok());
+
+            let schema = index_schema(
+                props,
+                vec![IndexSchema::new("index", vec!["prop"], false, false, false)],
+            );
+            assert!(schema.verify(&[]).is_err());
+        }
+
+        #[test]
+        fn test_conflict_policy_validation() {
+            let props = vec![
+                PropertySchema::new("email", DataType::String, None),
+                PropertySchema::new("tags", DataType::StringList, None),
+            ];
+
+            for policy in [ConflictPolicy::Fail, ConflictPolicy::Ignore] {
+                // Valid: unique index
+                let index = IndexSchema::new("index", vec!["email"], true, false, true);
+                let schema = index_schema(props.clone(), vec![index.with_conflict(policy)]);
+                assert!(schema.verify(&[]).is_ok());
+
+                // Invalid: non-unique index
+                let index = IndexSchema::new("index", vec!["email"], false, false, true);
+                let schema = index_schema(props.clone(), vec![index.with_conflict(policy)]);
+                assert!(schema.verify(&[]).is_err());
+
+                // Invalid: list indexes contain an entry for each element
+                for unique in [true, false] {
+                    let index = IndexSchema::new("index", vec!["tags"], unique, false, true);
+                    let schema = index_schema(props.clone(), vec![index.with_conflict(policy)]);
+                    assert!(schema.verify(&[]).is_err());
+                }
+            }
+        }
+
+        #[test]
+        fn test_index_filter_validation() {
+            let props = vec![
+                PropertySchema::new("email", DataType::String, None),
+                PropertySchema::new("deleted", DataType::Bool, None),
+                PropertySchema::new("tags", DataType::StringList, None),
+                PropertySchema::new("json", DataType::Json, None),
+            ];
+            let live = Filter::new_condition(
+                2,
+                ConditionType::Equal,
+                vec![Some(IsarValue::Bool(false))],
+                true,
+            );
+            let schema_with_filter = |properties: Vec<&str>, filter: Filter| {
+                let index = IndexSchema::new("index", properties, true, false, true);
+                index_schema(props.clone(), vec![index.with_filter(filter)])
+            };
+
+            // Valid: conditions combined by AND and OR
+            let filter = Filter::new_or(vec![
+                live.clone(),
+                Filter::new_condition(1, ConditionType::IsNull, vec![], true),
+            ]);
+            let schema = schema_with_filter(vec!["email"], filter);
+            assert!(schema.verify(&[]).is_ok());
+
+            // Invalid: list index
+            let index = IndexSchema::new("index", vec!["tags"], false, false, true);
+            let schema = index_schema(props.clone(), vec![index.with_filter(live.clone())]);
+            assert!(schema.verify(&[]).is_err());
+
+            // Invalid: NOT
+            let schema = schema_with_filter(vec!["email"], Filter::new_not(live.clone()));
+            assert!(schema.verify(&[]).is_err());
+
+            // Invalid: unknown property and the id
+            for property_index in [0, 5] {
+                let filter =
+                    Filter::new_condition(property_index, ConditionType::IsNull, vec![], true);
+                let schema = schema_with_filter(vec!["email"], filter);
+                assert!(schema.verify(&[]).is_err());
+            }
+
+            // Invalid: unsupported property type
+            let filter = Filter::new_condition(4, ConditionType::IsNull, vec![], true);
+            let schema = schema_with_filter(vec!["email"], filter);
+            assert!(schema.verify(&[]).is_err());
+
+            // Invalid: unsupported condition
+            let filter = Filter::new_condition(
+                1,
+                ConditionType::StringStartsWith,
+                vec![Some(IsarValue::String("a".to_string()))],
+                true,
+            );
+            let schema = schema_with_filter(vec!["email"], filter);
+            assert!(schema.verify(&[]).is_err());
+
+            // Invalid: case-insensitive condition
+            let filter = Filter::new_condition(
+                1,
+                ConditionType::Equal,
+                vec![Some(IsarValue::String("a".to_string()))],
+                false,
+            );
+            let schema = schema_with_filter(vec!["email"], filter);
+            assert!(schema.verify(&[]).is_err());
+
+            // Invalid: value of another type
+            let filter = Filter::new_condition(
+                2,
+                ConditionType::Equal,
+                vec![Some(IsarValue::Integer(0))],
+                true,
+            );
+            let schema = schema_with_filter(vec!["email"], filter);
             assert!(schema.verify(&[]).is_err());
         }
     }
@@ -478,7 +1006,13 @@ mod test {
                     PropertySchema::new("prop1", DataType::Int, None),
                     PropertySchema::new("prop2", DataType::String, None),
                 ],
-                vec![IndexSchema::new("index1", vec!["prop1"], false, false)],
+                vec![IndexSchema::new(
+                    "index1",
+                    vec!["prop1"],
+                    false,
+                    false,
+                    true,
+                )],
             );
 
             let new_schema = old_schema.clone();
@@ -560,7 +1094,7 @@ mod test {
         #[test]
         fn test_add_index() {
             let prop = PropertySchema::new("prop1", DataType::Int, None);
-            let index = IndexSchema::new("index1", vec!["prop1"], false, false);
+            let index = IndexSchema::new("index1", vec!["prop1"], false, false, true);
             let old_schema = props_schema(vec![prop.clone()]);
             let new_schema = index_schema(vec![prop.clone()], vec![index.clone()]);
 
@@ -576,7 +1110,7 @@ mod test {
         #[test]
         fn test_remove_index() {
             let prop = PropertySchema::new("prop1", DataType::Int, None);
-            let index = IndexSchema::new("index1", vec!["prop1"], false, false);
+            let index = IndexSchema::new("index1", vec!["prop1"], false, false, true);
             let old_schema = index_schema(vec![prop.clone()], vec![index.clone()]);
             let new_schema = props_schema(vec![prop.clone()]);
 
@@ -593,11 +1127,11 @@ mod test {
         fn test_change_index_properties() {
             let prop1 = PropertySchema::new("prop1", DataType::Int, None);
             let prop2 = PropertySchema::new("prop2", DataType::Int, None);
-            let old_index = IndexSchema::new("index1", vec!["prop1"], false, false);
+            let old_index = IndexSchema::new("index1", vec!["prop1"], false, false, true);
             let old_schema =
                 index_schema(vec![prop1.clone(), prop2.clone()], vec![old_index.clone()]);
 
-            let new_index = IndexSchema::new("index1", vec!["prop1", "prop2"], false, false);
+            let new_index = IndexSchema::new("index1", vec!["prop1", "prop2"], false, false, true);
             let new_schema =
                 index_schema(vec![prop1.clone(), prop2.clone()], vec![new_index.clone()]);
 
@@ -613,10 +1147,10 @@ mod test {
         #[test]
         fn test_change_index_uniqueness() {
             let prop = PropertySchema::new("prop1", DataType::Int, None);
-            let old_index = IndexSchema::new("index1", vec!["prop1"], false, false);
+            let old_index = IndexSchema::new("index1", vec!["prop1"], false, false, true);
             let old_schema = index_schema(vec![prop.clone()], vec![old_index.clone()]);
 
-            let new_index = IndexSchema::new("index1", vec!["prop1"], true, false);
+            let new_index = IndexSchema::new("index1", vec!["prop1"], true, false, true);
             let new_schema = index_schema(vec![prop.clone()], vec![new_index.clone()]);
 
             let (add_props, drop_props, add_indexes, drop_indexes) =
@@ -631,10 +1165,10 @@ mod test {
         #[test]
         fn test_change_index_hash() {
             let prop = PropertySchema::new("prop1", DataType::String, None);
-            let old_index = IndexSchema::new("index1", vec!["prop1"], false, false);
+            let old_index = IndexSchema::new("index1", vec!["prop1"], false, false, true);
             let old_schema = index_schema(vec![prop.clone()], vec![old_index.clone()]);
 
-            let new_index = IndexSchema::new("index1", vec!["prop1"], false, true);
+            let new_index = IndexSchema::new("index1", vec!["prop1"], false, true, true);
             let new_schema = index_schema(vec![prop.clone()], vec![new_index.clone()]);
 
             let (add_props, drop_props, add_indexes, drop_indexes) =
@@ -646,11 +1180,79 @@ mod test {
             assert_eq!(drop_indexes, vec!["index1"]);
         }
 
+        #[test]
+        fn test_change_index_case_sensitive() {
+            let prop = PropertySchema::new("prop1", DataType::String, None);
+            let old_index = IndexSchema::new("index1", vec!["prop1"], false, false, true);
+            let old_schema = index_schema(vec![prop.clone()], vec![old_index.clone()]);
+
+            let new_index = IndexSchema::new("index1", vec!["prop1"], false, false, false);
+            let new_schema = index_schema(vec![prop.clone()], vec![new_index.clone()]);
+
+            let (_, _, add_indexes, drop_indexes) = new_schema.find_changes(&old_schema);
+
+            assert_eq!(add_indexes, vec![&new_index]);
+            assert_eq!(drop_indexes, vec!["index1"]);
+        }
+
+        #[test]
+        fn test_index_case_sensitive_default() {
+            let json = br#"[{"name":"col","properties":[{"name":"prop1","type":"String"}],
+                "indexes":[{"name":"index1","properties":["prop1"],"unique":false,"hash":false}]}]"#;
+            let schemas = IsarSchema::from_json(json).unwrap();
+            assert!(schemas[0].indexes[0].case_sensitive);
+        }
+
+        #[test]
+        fn test_index_filter_json() {
+            let json = br#"[{"name":"col","properties":[{"name":"prop1","type":"Bool"}],
+                "indexes":[{"name":"index1","properties":["prop1"],"unique":false,"hash":false,
+                "filter":{"Condition":{"propertyIndex":1,"conditionType":"Equal",
+                "values":[{"Bool":false}],"caseSensitive":true}}}]}]"#;
+            let schemas = IsarSchema::from_json(json).unwrap();
+            let filter = Filter::new_condition(
+                1,
+                ConditionType::Equal,
+                vec![Some(IsarValue::Bool(false))],
+                true,
+            );
+            assert_eq!(schemas[0].indexes[0].filter, Some(filter));
+
+            let json = serde_json::to_vec(&schemas).unwrap();
+            assert_eq!(IsarSchema::from_json(&json).unwrap(), schemas);
+        }
+
+        #[test]
+        fn test_conflict_policy_json() {
+            let json = br#"[{"name":"col","properties":[{"name":"prop1","type":"String"}],
+                "indexes":[{"name":"index1","properties":["prop1"],"unique":true,"hash":false},
+                {"name":"index2","properties":["prop1"],"unique":true,"hash":false,
+                "onConflict":"fail"}]}]"#;
+            let schemas = IsarSchema::from_json(json).unwrap();
+            assert_eq!(schemas[0].indexes[0].on_conflict, ConflictPolicy::Replace);
+            assert_eq!(schemas[0].indexes[1].on_conflict, ConflictPolicy::Fail);
+        }
+
+        #[test]
+        fn test_change_conflict_policy() {
+            let prop1 = PropertySchema::new("prop1", DataType::Int, None);
+            let old_index = IndexSchema::new("index1", vec!["prop1"], true, false, true);
+            let old_schema = index_schema(vec![prop1.clone()], vec![old_index]);
+
+            let new_index = IndexSchema::new("index1", vec!["prop1"], true, false, true)
+                .with_conflict(ConflictPolicy::Ignore);
+            let new_schema = index_schema(vec![prop1], vec![new_index.clone()]);
+
+            let (_, _, add_indexes, drop_indexes) = new_schema.find_changes(&old_schema);
+            assert_eq!(add_indexes, vec![&new_index]);
+            assert_eq!(drop_indexes, vec!["index1"]);
+        }
+
         #[test]
         fn test_drop_index_when_property_changed() {
             let prop1 = PropertySchema::new("prop1", DataType::Int, None);
             let prop2 = PropertySchema::new("prop2", DataType::Int, None);
-            let old_index = IndexSchema::new("index1", vec!["prop1"], false, false);
+            let old_index = IndexSchema::new("index1", vec!["prop1"], false, false, true);
             let old_schema =
                 index_schema(vec![prop1.clone(), prop2.clone()], vec![old_index.clone()]);
 
@@ -668,5 +1270,19 @@ mod test {
             assert_eq!(add_indexes, vec![&old_index]);
             assert_eq!(drop_indexes, vec!["index1"]);
         }
+
+        #[test]
+        fn test_drop_embedded_index_when_object_property_changed() {
+            let prop1 = PropertySchema::new("prop1", DataType::Object, Some("embedded1"));
+            let index = IndexSchema::new("index1", vec!["prop1.value"], false, false, true);
+            let old_schema = index_schema(vec![prop1], vec![index.clone()]);
+
+            let new_prop1 = PropertySchema::new("prop1", DataType::Object, Some("embedded2"));
+            let new_schema = index_schema(vec![new_prop1], vec![index.clone()]);
+
+            let (_, _, add_indexes, drop_indexes) = new_schema.find_changes(&old_schema);
+            assert_eq!(add_indexes, vec![&index]);
+            assert_eq!(drop_indexes, vec!["index1"]);
+        }
     }
 }
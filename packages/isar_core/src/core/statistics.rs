@@ -0,0 +1,67 @@
+/// Returns the percentile of the values between 0 and 1 using linear interpolation between the
+/// closest ranks or `None` if there are no values.
+pub(crate) fn percentile(values: &mut [f64], percentile: f64) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable_by(|a, b| a.total_cmp(b));
+    let rank = percentile * (values.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    let fraction = rank - lower as f64;
+    Some(values[lower] + (values[upper] - values[lower]) * fraction)
+}
+
+/// Computes the sample variance of a stream of values using Welford's algorithm.
+#[derive(Default)]
+pub(crate) struct Variance {
+    count: u64,
+    mean: f64,
+    m2: f64,
+}
+
+impl Variance {
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// The sample variance or `None` if there are less than two values.
+    pub fn variance(&self) -> Option<f64> {
+        if self.count > 1 {
+            Some(self.m2 / (self.count - 1) as f64)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        assert_eq!(percentile(&mut [], 0.5), None);
+        assert_eq!(percentile(&mut [3.0], 0.9), Some(3.0));
+        assert_eq!(percentile(&mut [4.0, 1.0, 3.0, 2.0], 0.5), Some(2.5));
+        assert_eq!(percentile(&mut [4.0, 1.0, 3.0, 2.0, 5.0], 0.5), Some(3.0));
+        assert_eq!(percentile(&mut [4.0, 1.0, 3.0, 2.0, 5.0], 0.0), Some(1.0));
+        assert_eq!(percentile(&mut [4.0, 1.0, 3.0, 2.0, 5.0], 1.0), Some(5.0));
+        assert_eq!(percentile(&mut [10.0, 20.0], 0.25), Some(12.5));
+    }
+
+    #[test]
+    fn test_variance() {
+        let mut variance = Variance::default();
+        assert_eq!(variance.variance(), None);
+        variance.add(2.0);
+        assert_eq!(variance.variance(), None);
+        for value in [4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            variance.add(value);
+        }
+        assert!((variance.variance().unwrap() - 32.0 / 7.0).abs() < 1e-12);
+    }
+}
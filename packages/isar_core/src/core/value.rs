@@ -1,4 +1,6 @@
-#[derive(PartialEq, Clone, Debug)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 pub enum IsarValue {
     Bool(bool),
     Integer(i64),
@@ -1,6 +1,7 @@
+use std::borrow::Cow;
 use xxhash_rust::xxh3::xxh3_64;
 
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
 pub struct IndexKey {
     bytes: Vec<u8>,
     contains_null: bool,
@@ -60,8 +61,11 @@ impl IndexKey {
         self.contains_null |= value == i64::MIN;
     }
 
+    /// NaN represents null and is smaller than all other values including negative infinity.
+    /// Negative zero is stored as positive zero because they are equal.
     pub fn add_float(&mut self, value: f32) {
         let bytes: [u8; 4] = if !value.is_nan() {
+            let value = if value == 0.0 { 0.0 } else { value };
             let bits = if value.is_sign_positive() {
                 value.to_bits() + 2u32.pow(31)
             } else {
@@ -75,8 +79,10 @@ impl IndexKey {
         self.bytes.extend_from_slice(&bytes);
     }
 
+    /// Uses the same ordering as [`IndexKey::add_float`].
     pub fn add_double(&mut self, value: f64) {
         let bytes: [u8; 8] = if !value.is_nan() {
+            let value = if value == 0.0 { 0.0 } else { value };
             let bits = if value.is_sign_positive() {
                 value.to_bits() + 2u64.pow(63)
             } else {
@@ -90,12 +96,49 @@ impl IndexKey {
         self.bytes.extend_from_slice(&bytes);
     }
 
-    pub fn add_string(&mut self, value: Option<&str>) {
+    /// Case-insensitive keys contain the lowercase string.
+    pub fn add_string(&mut self, value: Option<&str>, case_sensitive: bool) {
         if let Some(value) = value {
             if value.is_empty() {
                 self.bytes.push(1);
-            } else {
+            } else if case_sensitive {
                 self.bytes.extend_from_slice(value.as_bytes());
+            } else {
+                self.bytes
+                    .extend_from_slice(value.to_lowercase().as_bytes());
+            }
+        } else {
+            self.contains_null = true;
+            self.bytes.push(0);
+        }
+    }
+
+    /// Strings that are followed by other index properties are terminated with `[0, 1]` so
+    /// that keys are ordered by the string first. Zero bytes are escaped as `[0, 255]`.
+    pub fn add_delimited_string(&mut self, value: Option<&str>, case_sensitive: bool) {
+        self.add_string_prefix(value, case_sensitive);
+        if value.is_some() {
+            self.bytes.extend_from_slice(&[0, 1]);
+        }
+    }
+
+    /// Same as [`IndexKey::add_delimited_string`] but without the terminator. The keys of all
+    /// strings starting with `value` are greater than or equal to the prefix and smaller than
+    /// the prefix followed by `0xFF`.
+    pub fn add_string_prefix(&mut self, value: Option<&str>, case_sensitive: bool) {
+        if let Some(value) = value {
+            let value = if case_sensitive {
+                Cow::Borrowed(value)
+            } else {
+                Cow::Owned(value.to_lowercase())
+            };
+            self.bytes.push(1);
+            for byte in value.bytes() {
+                if byte == 0 {
+                    self.bytes.extend_from_slice(&[0, 255]);
+                } else {
+                    self.bytes.push(byte);
+                }
             }
         } else {
             self.contains_null = true;
@@ -103,6 +146,10 @@ impl IndexKey {
         }
     }
 
+    pub fn add_max_bytes(&mut self, len: usize) {
+        self.bytes.resize(self.bytes.len() + len, u8::MAX);
+    }
+
     pub fn finish(mut self) -> (Vec<u8>, bool) {
         if self.bytes.len() > IndexKey::MAX_INDEX_SIZE {
             let hash = xxh3_64(&self.bytes);
@@ -112,9 +159,31 @@ impl IndexKey {
         (self.bytes, self.contains_null)
     }
 
+    /// Keys longer than [`IndexKey::MAX_INDEX_SIZE`] end with a hash instead of their last
+    /// bytes so they are not ordered among keys with the same prefix. The bounds of ranges are
+    /// truncated before the hash so that ranges contain all keys they would contain without
+    /// the limit and possibly more.
+    pub fn finish_bound(mut self, upper: bool) -> Vec<u8> {
+        let max_len = IndexKey::MAX_INDEX_SIZE - 8;
+        if self.bytes.len() > max_len {
+            self.bytes.truncate(max_len);
+            if upper {
+                self.bytes.extend_from_slice(&[u8::MAX; 8]);
+            }
+        }
+        self.bytes
+    }
+
     pub fn hash(&self) -> u64 {
         xxh3_64(&self.bytes)
     }
+
+    pub fn to_hash_key(&self) -> IndexKey {
+        IndexKey {
+            bytes: self.hash().to_be_bytes().to_vec(),
+            contains_null: self.contains_null,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -176,6 +245,14 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_add_max_bytes() {
+        let mut index_key = IndexKey::min();
+        index_key.add_byte(123);
+        index_key.add_max_bytes(3);
+        assert_eq!(index_key.finish(), (vec![123, 255, 255, 255], false));
+    }
+
     #[test]
     fn test_add_int() {
         let pairs = vec![
@@ -240,7 +317,7 @@ mod tests {
                 vec![123, 127, 255, 255, 254],
                 false,
             ),
-            (-0.0, vec![123, 127, 255, 255, 255], false),
+            (-0.0, vec![123, 128, 0, 0, 0], false),
             (0.0, vec![123, 128, 0, 0, 0], false),
             (0.0.next_after(f32::MAX), vec![123, 128, 0, 0, 1], false),
             (
@@ -280,11 +357,7 @@ mod tests {
                 vec![123, 127, 255, 255, 255, 255, 255, 255, 254],
                 false,
             ),
-            (
-                -0.0,
-                vec![123, 127, 255, 255, 255, 255, 255, 255, 255],
-                false,
-            ),
+            (-0.0, vec![123, 128, 0, 0, 0, 0, 0, 0, 0], false),
             (0.0, vec![123, 128, 0, 0, 0, 0, 0, 0, 0], false),
             (
                 0.0.next_after(f64::MAX),
@@ -325,11 +398,103 @@ mod tests {
         for (val, bytes, contains_null) in pairs {
             let mut index_key = IndexKey::min();
             index_key.add_byte(123);
-            index_key.add_string(val);
+            index_key.add_string(val, true);
+            assert_eq!(index_key.finish(), (bytes, contains_null));
+        }
+    }
+
+    #[test]
+    fn test_add_string_case_insensitive() {
+        let pairs = vec![
+            (None, vec![123, 0], true),
+            (Some(""), vec![123, 1], false),
+            (Some("aBc"), vec![123, 97, 98, 99], false),
+            (Some("ÄÖ"), "{äö".as_bytes().to_vec(), false),
+        ];
+
+        for (val, bytes, contains_null) in pairs {
+            let mut index_key = IndexKey::min();
+            index_key.add_byte(123);
+            index_key.add_string(val, false);
+            assert_eq!(index_key.finish(), (bytes, contains_null));
+        }
+    }
+
+    #[test]
+    fn test_add_delimited_string() {
+        let pairs = vec![
+            (None, vec![123, 0], true),
+            (Some(""), vec![123, 1, 0, 1], false),
+            (Some("a"), vec![123, 1, 97, 0, 1], false),
+            (Some("a\0b"), vec![123, 1, 97, 0, 255, 98, 0, 1], false),
+        ];
+
+        for (val, bytes, contains_null) in pairs {
+            let mut index_key = IndexKey::min();
+            index_key.add_byte(123);
+            index_key.add_delimited_string(val, true);
             assert_eq!(index_key.finish(), (bytes, contains_null));
         }
     }
 
+    #[test]
+    fn test_add_delimited_string_order() {
+        let values = vec![
+            None,
+            Some(""),
+            Some("\0"),
+            Some("\0\0"),
+            Some("\u{1}"),
+            Some("a"),
+            Some("a\0"),
+            Some("a\0a"),
+            Some("a\u{1}"),
+            Some("aa"),
+            Some("b"),
+            Some("\u{10ffff}"),
+        ];
+        let keys = values
+            .iter()
+            .map(|value| {
+                let mut index_key = IndexKey::min();
+                index_key.add_delimited_string(*value, true);
+                // the following property must not affect the order
+                index_key.add_int(i32::MAX);
+                index_key.finish().0
+            })
+            .collect::<Vec<_>>();
+
+        for i in 1..keys.len() {
+            assert!(
+                keys[i - 1] < keys[i],
+                "{:?} < {:?}",
+                values[i - 1],
+                values[i]
+            );
+        }
+    }
+
+    #[test]
+    fn test_add_string_prefix() {
+        let mut prefix = IndexKey::min();
+        prefix.add_string_prefix(Some("aB"), false);
+        assert_eq!(prefix.clone().finish(), (vec![1, 97, 98], false));
+
+        let mut upper = prefix.clone();
+        upper.add_max_bytes(1);
+        for value in ["ab", "AB", "ab\0", "abc", "ab\u{10ffff}"] {
+            let mut key = IndexKey::min();
+            key.add_delimited_string(Some(value), false);
+            key.add_long(i64::MAX);
+            assert!(prefix <= key && key <= upper, "{}", value);
+        }
+        for value in ["a", "aa", "ac", "b"] {
+            let mut key = IndexKey::min();
+            key.add_delimited_string(Some(value), false);
+            assert!(key < prefix || key > upper, "{}", value);
+        }
+    }
+
     #[test]
     fn test_finish() {
         let long_str: String = (0..1030).map(|_| "I").collect::<String>();
@@ -338,7 +503,7 @@ mod tests {
         bytes.extend_from_slice(&xxh3_64(long_str.as_bytes()).to_be_bytes());
 
         let mut index_key = IndexKey::min();
-        index_key.add_string(Some(&long_str));
+        index_key.add_string(Some(&long_str), true);
         assert_eq!(index_key.finish(), (bytes, false));
     }
 
@@ -346,9 +511,28 @@ mod tests {
     fn test_hash() {
         let mut index_key = IndexKey::min();
         index_key.add_byte(123);
-        index_key.add_string(Some("123"));
+        index_key.add_string(Some("123"), true);
 
         let bytes = vec![123, 49, 50, 51];
         assert_eq!(index_key.hash(), xxh3_64(&bytes));
     }
+
+    #[test]
+    fn test_finish_bound() {
+        let key = |len: usize| {
+            let mut key = IndexKey::min();
+            key.add_string(Some(&"a".repeat(len)), true);
+            key
+        };
+        assert_eq!(key(10).finish_bound(false), "a".repeat(10).into_bytes());
+        assert_eq!(key(10).finish_bound(true), "a".repeat(10).into_bytes());
+
+        let long = key(2000);
+        let (hashed, _) = long.clone().finish();
+        let lower = long.clone().finish_bound(false);
+        let upper = long.finish_bound(true);
+        assert_eq!(lower, "a".repeat(IndexKey::MAX_INDEX_SIZE - 8).into_bytes());
+        assert!(lower < hashed && hashed < upper);
+        assert!(key(1500).finish().0 < upper);
+    }
 }
@@ -20,6 +20,19 @@ impl<'a> IsarDeserializer<'a> {
         }
     }
 
+    /// Returns `None` if the header or the static section exceed `bytes`.
+    pub fn try_from_bytes(bytes: &'a [u8]) -> Option<Self> {
+        if bytes.len() < 3 {
+            return None;
+        }
+        let object = Self::from_bytes(bytes);
+        if object.bytes.len() >= object.static_size as usize {
+            Some(object)
+        } else {
+            None
+        }
+    }
+
     #[inline]
     fn contains_offset(&self, offset: u32) -> bool {
         self.static_size > offset
@@ -145,6 +158,61 @@ impl<'a> IsarDeserializer<'a> {
         Some((nested, length))
     }
 
+    fn try_get_offset(&self, offset: u32) -> Option<Option<usize>> {
+        if !self.contains_offset(offset) {
+            return Some(None);
+        }
+        let bytes = self.bytes.get(offset as usize..offset as usize + 3)?;
+        let offset = LittleEndian::read_u24(bytes);
+        if offset == 0 {
+            Some(None)
+        } else if offset >= self.static_size {
+            Some(Some(offset as usize))
+        } else {
+            None
+        }
+    }
+
+    /// Same as `read_dynamic` but returns `None` instead of panicking if the data is
+    /// out of bounds and `Some(None)` if the property is null.
+    pub fn try_read_dynamic(&self, offset: u32) -> Option<Option<&'a [u8]>> {
+        let Some(offset) = self.try_get_offset(offset)? else {
+            return Some(None);
+        };
+        let length = LittleEndian::read_u24(self.bytes.get(offset..offset + 3)?) as usize;
+        let bytes = self.bytes.get(offset + 3..offset + 3 + length)?;
+        Some(Some(bytes))
+    }
+
+    /// Same as `read_list` but returns `None` instead of panicking if the list is
+    /// out of bounds and `Some(None)` if the property is null.
+    pub fn try_read_list(
+        &self,
+        offset: u32,
+        element_type: DataType,
+    ) -> Option<Option<(IsarDeserializer<'a>, u32)>> {
+        let Some(offset) = self.try_get_offset(offset)? else {
+            return Some(None);
+        };
+        let nested = Self::try_from_bytes(self.bytes.get(offset..)?)?;
+        let element_size = element_type.static_size() as u32;
+        if nested.static_size % element_size == 0 {
+            Some(Some((nested, nested.static_size / element_size)))
+        } else {
+            None
+        }
+    }
+
+    /// Same as `read_nested` but returns `None` instead of panicking if the object is
+    /// out of bounds and `Some(None)` if the property is null.
+    pub fn try_read_nested(&self, offset: u32) -> Option<Option<IsarDeserializer<'a>>> {
+        let Some(offset) = self.try_get_offset(offset)? else {
+            return Some(None);
+        };
+        let nested = Self::try_from_bytes(self.bytes.get(offset..)?)?;
+        Some(Some(nested))
+    }
+
     pub fn hash_property(
         &self,
         offset: u32,
@@ -646,4 +714,53 @@ mod test {
             121
         );
     }
+
+    #[test]
+    fn test_try_from_bytes() {
+        assert!(IsarDeserializer::try_from_bytes(&[3, 0]).is_none());
+        assert!(IsarDeserializer::try_from_bytes(&[3, 0, 0, 1, 2]).is_none());
+        assert!(IsarDeserializer::try_from_bytes(&[3, 0, 0, 1, 2, 3]).is_some());
+    }
+
+    #[test]
+    fn test_try_read_dynamic() {
+        let bytes = concat!([6, 0, 0], [6, 0, 0, 0, 0, 0], [3, 0, 0, 4, 5, 6]);
+        let deserializer = IsarDeserializer::from_bytes(&bytes);
+        assert_eq!(deserializer.try_read_dynamic(0), Some(Some(&[4, 5, 6][..])));
+        assert_eq!(deserializer.try_read_dynamic(3), Some(None));
+        assert_eq!(deserializer.try_read_dynamic(6), Some(None));
+
+        let bytes = concat!([6, 0, 0], [6, 0, 0, 0, 0, 0], [4, 0, 0, 4, 5, 6]);
+        let deserializer = IsarDeserializer::from_bytes(&bytes);
+        assert_eq!(deserializer.try_read_dynamic(0), None);
+
+        let bytes = concat!([6, 0, 0], [2, 0, 0, 0, 0, 0], [3, 0, 0, 4, 5, 6]);
+        let deserializer = IsarDeserializer::from_bytes(&bytes);
+        assert_eq!(deserializer.try_read_dynamic(0), None);
+    }
+
+    #[test]
+    fn test_try_read_nested() {
+        let bytes = concat!([6, 0, 0], [6, 0, 0, 0, 0, 0], [4, 0, 0, 69, 0, 0, 0]);
+        let deserializer = IsarDeserializer::from_bytes(&bytes);
+        let nested = deserializer.try_read_nested(0).unwrap().unwrap();
+        assert_eq!(nested.read_int(0), 69);
+        assert!(deserializer.try_read_nested(3).unwrap().is_none());
+
+        let bytes = concat!([6, 0, 0], [6, 0, 0, 0, 0, 0], [5, 0, 0, 69, 0, 0, 0]);
+        let deserializer = IsarDeserializer::from_bytes(&bytes);
+        assert!(deserializer.try_read_nested(0).is_none());
+    }
+
+    #[test]
+    fn test_try_read_list() {
+        let bytes = concat!([3, 0, 0], [3, 0, 0], [3, 0, 0, 1, 0, 255]);
+        let deserializer = IsarDeserializer::from_bytes(&bytes);
+        let (_, length) = deserializer
+            .try_read_list(0, DataType::Bool)
+            .unwrap()
+            .unwrap();
+        assert_eq!(length, 3);
+        assert!(deserializer.try_read_list(0, DataType::Int).is_none());
+    }
 }
@@ -100,7 +100,15 @@ impl<'txn> Cursor<'txn> {
     }
 
     pub fn move_to_last_dup(&mut self) -> Result<Option<KeyVal<'txn>>> {
-        self.op_get(mdbx_sys::MDBX_cursor_op::MDBX_LAST_DUP, None, None)
+        // MDBX_LAST_DUP only returns the value so the key is read separately
+        if self
+            .op_get(mdbx_sys::MDBX_cursor_op::MDBX_LAST_DUP, None, None)?
+            .is_some()
+        {
+            self.op_get(mdbx_sys::MDBX_cursor_op::MDBX_GET_CURRENT, None, None)
+        } else {
+            Ok(None)
+        }
     }
 
     pub fn move_to_prev_no_dup(&mut self) -> Result<Option<KeyVal<'txn>>> {
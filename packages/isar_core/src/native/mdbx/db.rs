@@ -1,5 +1,5 @@
-use super::mdbx_result;
 use super::txn::Txn;
+use super::{mdbx_result, to_mdb_val};
 use crate::core::error::Result;
 use std::ffi::CString;
 use std::mem::size_of;
@@ -18,7 +18,8 @@ impl Db {
             flags |= mdbx_sys::MDBX_INTEGERKEY;
         }
         if dup {
-            flags |= mdbx_sys::MDBX_DUPSORT;
+            // the duplicates are ids so they are sorted as integers
+            flags |= mdbx_sys::MDBX_DUPSORT | mdbx_sys::MDBX_DUPFIXED | mdbx_sys::MDBX_INTEGERDUP;
         }
 
         let mut dbi: mdbx_sys::MDBX_dbi = 0;
@@ -65,6 +66,25 @@ impl Db {
         Ok((stat.ms_entries, size))
     }
 
+    /// Estimates the number of entries between the keys.
+    pub fn estimate_range(&self, txn: &Txn, begin: &[u8], end: &[u8]) -> Result<u64> {
+        let mut begin = unsafe { to_mdb_val(begin) };
+        let mut end = unsafe { to_mdb_val(end) };
+        let mut distance: isize = 0;
+        unsafe {
+            mdbx_result(mdbx_sys::mdbx_estimate_range(
+                txn.txn,
+                self.dbi,
+                &mut begin,
+                ptr::null_mut(),
+                &mut end,
+                ptr::null_mut(),
+                &mut distance,
+            ))?;
+        }
+        Ok(distance.unsigned_abs() as u64)
+    }
+
     pub fn clear(&self, txn: &Txn) -> Result<()> {
         unsafe { mdbx_result(mdbx_sys::mdbx_drop(txn.txn, self.dbi, false)) }?;
         Ok(())
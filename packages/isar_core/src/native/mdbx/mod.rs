@@ -26,6 +26,10 @@ pub(crate) const EMPTY_VAL: mdbx_sys::MDBX_val = mdbx_sys::MDBX_val {
 
 #[inline]
 pub(crate) unsafe fn from_mdb_val<'a>(val: &mdbx_sys::MDBX_val) -> &'a [u8] {
+    // some cursor operations leave the key unset
+    if val.iov_base.is_null() {
+        return &[];
+    }
     unsafe { slice::from_raw_parts(val.iov_base as *const u8, val.iov_len as usize) }
 }
 
@@ -7,6 +7,7 @@ use super::query::NativeQuery;
 use super::{BytesToId, IdToBytes};
 use crate::core::data_type::DataType;
 use crate::core::error::{IsarError, Result};
+use crate::core::schema::ConflictPolicy;
 use crate::core::value::IsarValue;
 use crate::core::watcher::{ChangeSet, CollectionWatchers};
 use std::sync::Arc;
@@ -72,6 +73,12 @@ impl NativeCollection {
         txn.get_cursor(db)
     }
 
+    /// Estimates the number of objects with an id between `lower` and `upper`.
+    pub fn estimate_between_ids(&self, txn: &NativeTxn, lower: i64, upper: i64) -> Result<u64> {
+        let db = self.db.ok_or(IsarError::UnsupportedOperation {})?;
+        txn.estimate_range(db, &lower.to_id_bytes(), &upper.to_id_bytes())
+    }
+
     pub fn is_embedded(&self) -> bool {
         self.id_name.is_none()
     }
@@ -128,6 +135,7 @@ impl NativeCollection {
         }
     }
 
+    /// Writes the object. Returns `false` if it was ignored because of a unique conflict.
     pub fn put<'a>(
         &self,
         txn: &'a NativeTxn,
@@ -135,7 +143,10 @@ impl NativeCollection {
         cursor: &mut TxnCursor<'a>,
         id: i64,
         bytes: &[u8],
-    ) -> Result<()> {
+    ) -> Result<bool> {
+        if !self.check_conflicts(txn, id, IsarDeserializer::from_bytes(bytes))? {
+            return Ok(false);
+        }
         let id_bytes = id.to_id_bytes();
 
         // we only fetch the previous object if there are query watchers or indexes
@@ -175,7 +186,34 @@ impl NativeCollection {
         }
 
         self.update_auto_increment(id);
-        cursor.put(&id_bytes, bytes)
+        cursor.put(&id_bytes, bytes)?;
+        Ok(true)
+    }
+
+    /// Conflicts of unique indexes that do not replace the other object have to be resolved
+    /// before anything is written. Returns `false` if the object should be ignored.
+    fn check_conflicts(&self, txn: &NativeTxn, id: i64, object: IsarDeserializer) -> Result<bool> {
+        let mut buffer = txn.request_buffer();
+        for index in &self.indexes {
+            if index.on_conflict == ConflictPolicy::Replace {
+                continue;
+            }
+            let existing_id;
+            (existing_id, buffer) = index.find_conflict(txn, id, object, buffer)?;
+            if let Some(existing_id) = existing_id {
+                if index.on_conflict == ConflictPolicy::Fail {
+                    return Err(IsarError::UniqueViolation {
+                        index: index.name.clone(),
+                        existing_id,
+                    });
+                } else {
+                    txn.recycle_buffer(buffer);
+                    return Ok(false);
+                }
+            }
+        }
+        txn.recycle_buffer(buffer);
+        Ok(true)
     }
 
     pub fn delete<'a>(
@@ -228,15 +266,47 @@ impl NativeCollection {
             }
 
             let buffer = new_object.finish()?;
-            self.put(txn, change_set, cursor, id, &buffer)?;
+            let updated = self.put(txn, change_set, cursor, id, &buffer)?;
             txn.recycle_buffer(buffer);
 
-            Ok(true)
+            Ok(updated)
         } else {
             Ok(false)
         }
     }
 
+    /// Clears the given indexes and inserts all objects again in the order of their ids.
+    /// Objects are never deleted so duplicates in a unique index fail with
+    /// [`IsarError::UniqueViolation`] regardless of the conflict policy of the index.
+    pub fn rebuild_indexes(&self, txn: &NativeTxn, indexes: &[&NativeIndex]) -> Result<()> {
+        for index in indexes {
+            index.clear(txn)?;
+        }
+
+        let mut object_buffer = txn.request_buffer();
+        let mut buffer = txn.request_buffer();
+        let cursor = self.get_cursor(txn)?;
+        for (id_bytes, bytes) in cursor.iter_between_ids(i64::MIN, i64::MAX, false, false)? {
+            let id = id_bytes.to_id();
+            // copy the object because writing the indexes may invalidate it
+            object_buffer.clear();
+            object_buffer.extend_from_slice(bytes);
+            let object = IsarDeserializer::from_bytes(&object_buffer);
+
+            for index in indexes {
+                buffer = index.create_for_object(txn, id, object, buffer, |existing_id| {
+                    Err(IsarError::UniqueViolation {
+                        index: index.name.clone(),
+                        existing_id,
+                    })
+                })?;
+            }
+        }
+        txn.recycle_buffer(object_buffer);
+        txn.recycle_buffer(buffer);
+        Ok(())
+    }
+
     pub fn clear(&self, txn: &NativeTxn) -> Result<()> {
         let db = self.db.ok_or(IsarError::UnsupportedOperation {})?;
         let mut change_set = txn.get_change_set();
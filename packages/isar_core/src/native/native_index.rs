@@ -1,21 +1,39 @@
 use super::index_key::IndexKey;
 use super::isar_deserializer::IsarDeserializer;
+use super::mdbx::cursor_iterator::CursorIterator;
 use super::mdbx::db::Db;
 use super::native_collection::NativeProperty;
-use super::native_txn::NativeTxn;
-use super::{BytesToId, IdToBytes};
+use super::native_txn::{NativeTxn, TxnCursor};
+use super::query::native_filter::NativeFilter;
+use super::{BytesToId, IdToBytes, NULL_DOUBLE, NULL_LONG};
 use crate::core::data_type::DataType;
 use crate::core::error::Result;
+use crate::core::filter_json::extract_value;
+use crate::core::schema::ConflictPolicy;
+use serde_json::Value;
 
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Clone)]
 pub(crate) struct NativeIndex {
     pub name: String,
     pub properties: Vec<NativeProperty>,
     pub unique: bool,
     pub hash: bool,
+    pub case_sensitive: bool,
+    /// The embedded object properties leading to each indexed property. Empty for properties
+    /// of the collection itself.
+    pub paths: Vec<Vec<NativeProperty>>,
+    /// The path of a JSON path index. The property is the JSON property with the declared
+    /// type of the indexed values.
+    pub json_path: Option<Vec<String>>,
+    /// Only objects matching the filter of a partial index are indexed.
+    pub filter: Option<NativeFilter>,
+    pub on_conflict: ConflictPolicy,
     db: Db,
 }
 
+/// An object without properties. Used to index the properties of null embedded objects.
+const NULL_OBJECT: [u8; 3] = [0, 0, 0];
+
 impl NativeIndex {
     pub fn new(
         name: &str,
@@ -23,31 +41,80 @@ impl NativeIndex {
         properties: Vec<NativeProperty>,
         unique: bool,
         hash: bool,
+        case_sensitive: bool,
     ) -> Self {
         NativeIndex {
             name: name.to_string(),
+            paths: vec![vec![]; properties.len()],
+            json_path: None,
+            filter: None,
+            on_conflict: ConflictPolicy::Replace,
             properties,
             unique,
             hash,
+            case_sensitive,
             db,
         }
     }
 
-    fn create_key(&self, object: IsarDeserializer, buffer: Vec<u8>) -> (Vec<u8>, bool) {
-        let mut key = IndexKey::with_buffer(buffer);
-        for property in &self.properties {
-            match property.data_type {
-                DataType::Bool => key.add_bool(object.read_bool(property.offset)),
-                DataType::Byte => key.add_byte(object.read_byte(property.offset)),
-                DataType::Int => key.add_int(object.read_int(property.offset)),
-                DataType::Float => key.add_float(object.read_float(property.offset)),
-                DataType::Long => key.add_long(object.read_long(property.offset)),
-                DataType::Double => key.add_double(object.read_double(property.offset)),
-                DataType::String => key.add_string(object.read_string(property.offset)),
-                _ => unreachable!(),
+    pub fn with_paths(mut self, paths: Vec<Vec<NativeProperty>>) -> Self {
+        self.paths = paths;
+        self
+    }
+
+    pub fn with_json_path(mut self, json_path: Vec<String>) -> Self {
+        self.json_path = Some(json_path);
+        self
+    }
+
+    pub fn with_conflict(mut self, on_conflict: ConflictPolicy) -> Self {
+        self.on_conflict = on_conflict;
+        self
+    }
+
+    /// Whether the object is not contained in a partial index.
+    fn is_excluded(&self, id: i64, object: IsarDeserializer) -> bool {
+        self.filter
+            .as_ref()
+            .is_some_and(|filter| !filter.evaluate(id, object))
+    }
+
+    /// Whether the index contains an entry for every element of a list property.
+    pub fn is_multi_entry(&self) -> bool {
+        self.properties.len() == 1 && self.properties[0].data_type.is_list()
+    }
+
+    /// Whether the string at the given position of the index has to be delimited because it
+    /// is followed by other properties. Hashed keys are never compared so they are not
+    /// delimited.
+    pub fn is_delimited(&self, position: usize) -> bool {
+        !self.hash && position + 1 < self.properties.len()
+    }
+
+    fn add_to_key(
+        &self,
+        key: &mut IndexKey,
+        object: IsarDeserializer,
+        data_type: DataType,
+        offset: u32,
+        delimited: bool,
+    ) {
+        match data_type {
+            DataType::Bool => key.add_bool(object.read_bool(offset)),
+            DataType::Byte => key.add_byte(object.read_byte(offset)),
+            DataType::Int => key.add_int(object.read_int(offset)),
+            DataType::Float => key.add_float(object.read_float(offset)),
+            DataType::Long => key.add_long(object.read_long(offset)),
+            DataType::Double => key.add_double(object.read_double(offset)),
+            DataType::String if delimited => {
+                key.add_delimited_string(object.read_string(offset), self.case_sensitive)
             }
+            DataType::String => key.add_string(object.read_string(offset), self.case_sensitive),
+            _ => unreachable!(),
         }
+    }
 
+    fn finish_key(&self, key: IndexKey) -> (Vec<u8>, bool) {
         if self.hash {
             let hash = key.hash();
             let (mut buffer, contains_null) = key.finish();
@@ -59,6 +126,70 @@ impl NativeIndex {
         }
     }
 
+    /// Adds the value at the JSON path if it has the declared type and null otherwise.
+    fn add_json_to_key(&self, key: &mut IndexKey, object: IsarDeserializer, json_path: &[String]) {
+        let property = &self.properties[0];
+        let json = object
+            .read_string(property.offset)
+            .and_then(|json| serde_json::from_str::<Value>(json).ok());
+        let value = json
+            .as_ref()
+            .and_then(|json| extract_value(json, json_path));
+        match property.data_type {
+            DataType::Bool => key.add_bool(value.and_then(|v| v.as_bool())),
+            DataType::Long => key.add_long(value.and_then(|v| v.as_i64()).unwrap_or(NULL_LONG)),
+            DataType::Double => {
+                key.add_double(value.and_then(|v| v.as_f64()).unwrap_or(NULL_DOUBLE))
+            }
+            DataType::String => key.add_string(value.and_then(|v| v.as_str()), self.case_sensitive),
+            _ => unreachable!(),
+        }
+    }
+
+    fn create_key(&self, object: IsarDeserializer, buffer: Vec<u8>) -> (Vec<u8>, bool) {
+        let mut key = IndexKey::with_buffer(buffer);
+        if let Some(json_path) = &self.json_path {
+            self.add_json_to_key(&mut key, object, json_path);
+            return self.finish_key(key);
+        }
+        for (i, property) in self.properties.iter().enumerate() {
+            let mut object = object;
+            for embedded in &self.paths[i] {
+                object = object
+                    .read_nested(embedded.offset)
+                    .unwrap_or_else(|| IsarDeserializer::from_bytes(&NULL_OBJECT));
+            }
+            let delimited = self.is_delimited(i);
+            self.add_to_key(
+                &mut key,
+                object,
+                property.data_type,
+                property.offset,
+                delimited,
+            );
+        }
+        self.finish_key(key)
+    }
+
+    /// Creates one key for every distinct element of the indexed list. Null and empty lists
+    /// are not indexed.
+    fn create_list_keys(&self, object: IsarDeserializer) -> Vec<Vec<u8>> {
+        let property = &self.properties[0];
+        let element_type = property.data_type.element_type().unwrap();
+        let mut keys = vec![];
+        if let Some((list, length)) = object.read_list(property.offset, element_type) {
+            for i in 0..length {
+                let mut key = IndexKey::min();
+                let offset = i * element_type.static_size() as u32;
+                self.add_to_key(&mut key, list, element_type, offset, false);
+                keys.push(self.finish_key(key).0);
+            }
+        }
+        keys.sort_unstable();
+        keys.dedup();
+        keys
+    }
+
     pub fn create_for_object<F>(
         &self,
         txn: &NativeTxn,
@@ -70,7 +201,17 @@ impl NativeIndex {
     where
         F: FnMut(i64) -> Result<()>,
     {
+        if self.is_excluded(id, object) {
+            return Ok(buffer);
+        }
         let mut cursor = txn.get_cursor(self.db)?;
+        if self.is_multi_entry() {
+            for key in self.create_list_keys(object) {
+                cursor.put(&key, &id.to_id_bytes())?;
+            }
+            return Ok(buffer);
+        }
+
         let (key, contains_null) = self.create_key(object, buffer);
 
         if self.unique && !contains_null {
@@ -83,6 +224,40 @@ impl NativeIndex {
         Ok(key)
     }
 
+    /// Returns the id of the object with the same key if this is a unique index. Multi-entry
+    /// indexes are never unique because `IsarSchema::verify` rejects them.
+    pub fn find_unique(
+        &self,
+        txn: &NativeTxn,
+        id: i64,
+        object: IsarDeserializer,
+        buffer: Vec<u8>,
+    ) -> Result<(Option<i64>, Vec<u8>)> {
+        if !self.unique || self.is_multi_entry() || self.is_excluded(id, object) {
+            return Ok((None, buffer));
+        }
+
+        let (key, contains_null) = self.create_key(object, buffer);
+        if contains_null {
+            return Ok((None, key));
+        }
+        let mut cursor = txn.get_cursor(self.db)?;
+        let existing_id = cursor.move_to(&key)?.map(|(_, id_bytes)| id_bytes.to_id());
+        Ok((existing_id, key))
+    }
+
+    /// Returns the id of another object with the same key if this is a unique index.
+    pub fn find_conflict(
+        &self,
+        txn: &NativeTxn,
+        id: i64,
+        object: IsarDeserializer,
+        buffer: Vec<u8>,
+    ) -> Result<(Option<i64>, Vec<u8>)> {
+        let (existing_id, buffer) = self.find_unique(txn, id, object, buffer)?;
+        Ok((existing_id.filter(|existing_id| *existing_id != id), buffer))
+    }
+
     pub fn delete_for_object(
         &self,
         txn: &NativeTxn,
@@ -90,7 +265,19 @@ impl NativeIndex {
         object: IsarDeserializer,
         buffer: Vec<u8>,
     ) -> Result<Vec<u8>> {
+        if self.is_excluded(id, object) {
+            return Ok(buffer);
+        }
         let mut cursor = txn.get_cursor(self.db)?;
+        if self.is_multi_entry() {
+            for key in self.create_list_keys(object) {
+                if cursor.move_to_key_val(&key, &id.to_id_bytes())?.is_some() {
+                    cursor.delete_current()?;
+                }
+            }
+            return Ok(buffer);
+        }
+
         let key = self.create_key(object, buffer).0;
         if cursor.move_to_key_val(&key, &id.to_id_bytes())?.is_some() {
             cursor.delete_current()?;
@@ -106,6 +293,78 @@ impl NativeIndex {
         txn.clear_db(self.db)
     }
 
+    pub fn iter_between<'txn>(
+        &self,
+        txn: &'txn NativeTxn,
+        lower_key: IndexKey,
+        upper_key: IndexKey,
+    ) -> Result<CursorIterator<'txn, TxnCursor<'txn>>> {
+        let cursor = txn.get_cursor(self.db)?;
+        let (lower_key, upper_key) = Self::finish_bounds(lower_key, upper_key);
+        cursor.iter_between(lower_key, upper_key, !self.unique, false)
+    }
+
+    /// Finishes the keys of a range in either direction using [`IndexKey::finish_bound`].
+    pub fn finish_bounds(lower_key: IndexKey, upper_key: IndexKey) -> (Vec<u8>, Vec<u8>) {
+        if lower_key <= upper_key {
+            (lower_key.finish_bound(false), upper_key.finish_bound(true))
+        } else {
+            (lower_key.finish_bound(true), upper_key.finish_bound(false))
+        }
+    }
+
+    /// Estimates the number of index entries between the keys.
+    pub fn estimate_between(
+        &self,
+        txn: &NativeTxn,
+        lower_key: IndexKey,
+        upper_key: IndexKey,
+    ) -> Result<u64> {
+        let (lower_key, upper_key) = Self::finish_bounds(lower_key, upper_key);
+        txn.estimate_range(self.db, &lower_key, &upper_key)
+    }
+
+    /// Checks the index entries of the given objects and returns the number of missing and
+    /// obsolete entries.
+    pub fn verify<'a>(
+        &self,
+        txn: &NativeTxn,
+        objects: impl Iterator<Item = (i64, IsarDeserializer<'a>)>,
+    ) -> Result<(u64, u64)> {
+        let mut cursor = txn.get_cursor(self.db)?;
+        let mut buffer = txn.request_buffer();
+        let mut found = 0;
+        let mut missing = 0;
+        for (id, object) in objects {
+            if self.is_excluded(id, object) {
+                continue;
+            }
+            if self.is_multi_entry() {
+                for key in self.create_list_keys(object) {
+                    if cursor.move_to_key_val(&key, &id.to_id_bytes())?.is_some() {
+                        found += 1;
+                    } else {
+                        missing += 1;
+                    }
+                }
+                continue;
+            }
+
+            let key = self.create_key(object, buffer).0;
+            if cursor.move_to_key_val(&key, &id.to_id_bytes())?.is_some() {
+                found += 1;
+            } else {
+                missing += 1;
+            }
+            buffer = key;
+        }
+        txn.recycle_buffer(buffer);
+
+        // the statistics of a corrupted index can be lower than the number of found entries
+        let obsolete = txn.stat(self.db)?.0.saturating_sub(found);
+        Ok((missing, obsolete))
+    }
+
     /* pub fn iter_between<'txn, 'env>(
         &self,
         cursors: &IsarCursors<'txn, 'env>,
@@ -145,36 +404,5 @@ impl NativeIndex {
 
     pub fn clear(&self, cursors: &IsarCursors) -> Result<()> {
         cursors.clear_db(self.db)
-    }
-
-    pub fn verify(&self, cursors: &IsarCursors, objects: &IntMap<IsarObject>) -> Result<()> {
-        let mut count = 0;
-
-        let mut cursor = cursors.get_cursor(self.db)?;
-        for id in objects.keys() {
-            let id = *id;
-            let object = *objects.get(id).unwrap();
-            let key_builder = IndexKeyBuilder::new(&self.properties);
-            key_builder.create_keys(object, |key| {
-                count += 1;
-
-                let result = cursor.move_to_key_val(key, &(id as i64).to_id_bytes())?;
-                if result.is_some() {
-                    Ok(true)
-                } else {
-                    Err(IsarError::DbCorrupted {
-                        message: "Missing index entry.".to_string(),
-                    })
-                }
-            })?;
-        }
-
-        if cursors.db_stat(self.db)?.0 != count {
-            Err(IsarError::DbCorrupted {
-                message: "Obsolete index entry.".to_string(),
-            })
-        } else {
-            Ok(())
-        }
     }*/
 }
@@ -1,9 +1,17 @@
+use super::IdToBytes;
+use super::isar_deserializer::IsarDeserializer;
 use super::isar_serializer::IsarSerializer;
 use super::native_collection::NativeCollection;
+use super::native_index::NativeIndex;
+use super::native_reader::NativeReader;
 use super::native_txn::{NativeTxn, TxnCursor};
+use crate::core::data_type::DataType;
 use crate::core::error::{IsarError, Result};
 use crate::core::insert::IsarInsert;
+use crate::core::reader::IsarReader;
 use crate::core::watcher::ChangeSet;
+use crate::core::writer::IsarWriter;
+use itertools::Itertools;
 use ouroboros::self_referencing;
 use std::cell::RefMut;
 
@@ -27,13 +35,20 @@ impl TxnWithCursor {
         )
     }
 
-    fn put(&mut self, collection: &NativeCollection, id: i64, bytes: &[u8]) -> Result<()> {
+    fn put(&mut self, collection: &NativeCollection, id: i64, bytes: &[u8]) -> Result<bool> {
         self.with_mut(|mut this| {
             this.txn
                 .guard(|| collection.put(this.txn, &mut this.change_set, this.cursor, id, bytes))
         })
     }
 
+    fn get(&mut self, id: i64) -> Result<Option<Vec<u8>>> {
+        self.with_cursor_mut(|cursor| {
+            let object = cursor.move_to(&id.to_id_bytes())?;
+            Ok(object.map(|(_, bytes)| bytes.to_vec()))
+        })
+    }
+
     fn close(self) -> NativeTxn {
         self.into_heads().txn
     }
@@ -46,6 +61,11 @@ pub struct NativeInsert<'a> {
 
     remaining: u32,
     pub(crate) object: IsarSerializer,
+
+    /// Objects with the same key in this index are merged instead of inserted.
+    upsert_index: Option<&'a NativeIndex>,
+    /// The properties written to the current object. Only tracked for upserts.
+    pub(crate) written: Vec<bool>,
 }
 
 impl<'a> NativeInsert<'a> {
@@ -54,32 +74,138 @@ impl<'a> NativeInsert<'a> {
         collection: &'a NativeCollection,
         all_collections: &'a Vec<NativeCollection>,
         count: u32,
+        upsert_index: Option<&'a NativeIndex>,
     ) -> Result<Self> {
         let buffer = txn.request_buffer();
         let txn_cursor = TxnWithCursor::open(txn, collection)?;
+        let written = if upsert_index.is_some() {
+            vec![false; collection.properties.len() + 1]
+        } else {
+            vec![]
+        };
         let insert = Self {
             txn_cursor,
             collection,
             all_collections,
             remaining: count,
             object: IsarSerializer::new(buffer, 0, collection.static_size),
+            upsert_index,
+            written,
         };
         Ok(insert)
     }
+
+    /// Merges the written properties of the object into the object with the same key in the
+    /// upsert index. Properties that were not written keep the value of the existing object or
+    /// are null if there is no existing object. Returns the id and the merged object.
+    fn merge(&mut self, index: &NativeIndex, id: i64, object: Vec<u8>) -> Result<(i64, Vec<u8>)> {
+        let txn = self.txn_cursor.borrow_txn();
+        let new_object = IsarDeserializer::from_bytes(&object);
+        let (existing_id, buffer) = index.find_unique(txn, id, new_object, txn.request_buffer())?;
+        txn.recycle_buffer(buffer);
+
+        let existing = if let Some(existing_id) = existing_id {
+            self.txn_cursor
+                .get(existing_id)?
+                .map(|object| (existing_id, object))
+        } else {
+            None
+        };
+
+        let buffer = self.txn_cursor.borrow_txn().request_buffer();
+        self.object = IsarSerializer::new(buffer, 0, self.collection.static_size);
+        let new_reader = NativeReader::new(id, new_object, self.collection, self.all_collections);
+        let existing_reader = existing.as_ref().map(|(existing_id, existing_object)| {
+            NativeReader::new(
+                *existing_id,
+                IsarDeserializer::from_bytes(existing_object),
+                self.collection,
+                self.all_collections,
+            )
+        });
+        let properties = self.collection.properties.iter().map(|(_, p)| p.data_type);
+        for (index, data_type) in properties.enumerate().collect_vec() {
+            let index = index as u32 + 1;
+            if self.written[index as usize] {
+                copy_property(&new_reader, self, index, data_type);
+            } else if let Some(existing_reader) = &existing_reader {
+                copy_property(existing_reader, self, index, data_type);
+            } else {
+                self.write_null(index);
+            }
+        }
+        self.txn_cursor.borrow_txn().recycle_buffer(object);
+
+        let id = existing.map_or(id, |(existing_id, _)| existing_id);
+        Ok((id, self.object.finish()?))
+    }
+}
+
+fn copy_property<'a>(
+    reader: &impl IsarReader,
+    writer: &mut impl IsarWriter<'a>,
+    index: u32,
+    data_type: DataType,
+) {
+    if reader.is_null(index) {
+        writer.write_null(index);
+        return;
+    }
+    match data_type {
+        DataType::Bool => writer.write_bool(index, reader.read_bool(index).unwrap_or_default()),
+        DataType::Byte => writer.write_byte(index, reader.read_byte(index)),
+        DataType::Int => writer.write_int(index, reader.read_int(index)),
+        DataType::Float => writer.write_float(index, reader.read_float(index)),
+        DataType::Long => writer.write_long(index, reader.read_long(index)),
+        DataType::Double => writer.write_double(index, reader.read_double(index)),
+        DataType::String | DataType::Json => {
+            writer.write_string(index, reader.read_string(index).unwrap_or_default())
+        }
+        DataType::ByteList => {
+            writer.write_byte_list(index, &reader.read_blob(index).unwrap_or_default())
+        }
+        DataType::Object => {
+            if let Some(object) = reader.read_object(index)
+                && let Some(mut object_writer) = writer.begin_object(index)
+            {
+                let properties = object.properties().map(|(_, t)| t).collect_vec();
+                for (i, data_type) in properties.into_iter().enumerate() {
+                    copy_property(&object, &mut object_writer, i as u32 + 1, data_type);
+                }
+                writer.end_object(object_writer);
+            }
+        }
+        _ => {
+            if let Some(element_type) = data_type.element_type()
+                && let Some((list, length)) = reader.read_list(index)
+                && let Some(mut list_writer) = writer.begin_list(index, length)
+            {
+                for i in 0..length {
+                    copy_property(&list, &mut list_writer, i, element_type);
+                }
+                writer.end_list(list_writer);
+            }
+        }
+    }
 }
 
 impl<'a> IsarInsert<'a> for NativeInsert<'a> {
     type Txn = NativeTxn;
 
-    fn save(&mut self, id: i64) -> Result<()> {
+    fn save(&mut self, id: i64) -> Result<Option<i64>> {
         if self.remaining > 0 {
             let mut buffer = self.object.finish()?;
-            self.txn_cursor.put(self.collection, id, &buffer)?;
+            let mut id = id;
+            if let Some(index) = self.upsert_index {
+                (id, buffer) = self.merge(index, id, buffer)?;
+                self.written.fill(false);
+            }
+            let saved = self.txn_cursor.put(self.collection, id, &buffer)?;
 
             self.remaining -= 1;
             buffer.clear();
             self.object = IsarSerializer::new(buffer, 0, self.collection.static_size);
-            Ok(())
+            Ok(saved.then_some(id))
         } else {
             Err(IsarError::UnsupportedOperation {})
         }
@@ -95,3 +221,267 @@ impl<'a> IsarInsert<'a> for NativeInsert<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::core::cursor::IsarQueryCursor;
+    use crate::core::data_type::DataType;
+    use crate::core::error::{IsarError, Result};
+    use crate::core::insert::IsarInsert;
+    use crate::core::instance::IsarInstance;
+    use crate::core::query_builder::IsarQueryBuilder;
+    use crate::core::reader::IsarReader;
+    use crate::core::schema::{ConflictPolicy, IndexSchema, IsarSchema, PropertySchema};
+    use crate::core::value::IsarValue;
+    use crate::core::writer::IsarWriter;
+    use crate::native::native_instance::NativeInstance;
+    use std::sync::Arc;
+
+    type Object = (i64, Vec<Option<IsarValue>>);
+
+    /// Opens a collection with the properties `value`, `name` and `count` and a unique index
+    /// for each of the given properties.
+    fn open(name: &str, indexes: &[(&str, ConflictPolicy)]) -> Arc<NativeInstance> {
+        let indexes = indexes
+            .iter()
+            .map(|(property, on_conflict)| {
+                IndexSchema::new(property, vec![property], true, false, true)
+                    .with_conflict(*on_conflict)
+            })
+            .collect();
+        let schema = IsarSchema::new(
+            "test",
+            Some("id"),
+            vec![
+                PropertySchema::new("value", DataType::Long, None),
+                PropertySchema::new("name", DataType::String, None),
+                PropertySchema::new("count", DataType::Long, None),
+            ],
+            indexes,
+            false,
+        );
+        NativeInstance::open_temp(name, vec![schema]).unwrap()
+    }
+
+    fn long(value: i64) -> Option<IsarValue> {
+        Some(IsarValue::Integer(value))
+    }
+
+    fn string(value: &str) -> Option<IsarValue> {
+        Some(IsarValue::String(value.to_string()))
+    }
+
+    fn write<'a>(writer: &mut impl IsarWriter<'a>, index: u32, value: &Option<IsarValue>) {
+        match value {
+            Some(IsarValue::Integer(value)) => writer.write_long(index, *value),
+            Some(IsarValue::String(value)) => writer.write_string(index, value),
+            _ => writer.write_null(index),
+        }
+    }
+
+    /// Inserts the objects and returns the results of `save`. Missing values are null.
+    fn put(instance: &NativeInstance, objects: &[Object]) -> Result<Vec<Option<i64>>> {
+        let txn = instance.begin_txn(true)?;
+        let mut insert = instance.insert(txn, 0, objects.len() as u32)?;
+        let mut ids = vec![];
+        for (id, values) in objects {
+            for index in 0..3 {
+                write(
+                    &mut insert,
+                    index + 1,
+                    values.get(index as usize).unwrap_or(&None),
+                );
+            }
+            ids.push(insert.save(*id)?);
+        }
+        let txn = insert.finish()?;
+        instance.commit_txn(txn)?;
+        Ok(ids)
+    }
+
+    /// Upserts the objects using the index and returns the results of `save`. Only the given
+    /// properties are written.
+    fn upsert(
+        instance: &NativeInstance,
+        index: &str,
+        objects: &[(i64, Vec<(u32, Option<IsarValue>)>)],
+    ) -> Result<Vec<Option<i64>>> {
+        let txn = instance.begin_txn(true)?;
+        let mut insert = instance.upsert(txn, 0, index, objects.len() as u32)?;
+        let mut ids = vec![];
+        for (id, values) in objects {
+            for (index, value) in values {
+                write(&mut insert, *index, value);
+            }
+            ids.push(insert.save(*id)?);
+        }
+        let txn = insert.finish()?;
+        instance.commit_txn(txn)?;
+        Ok(ids)
+    }
+
+    fn objects(instance: &NativeInstance) -> Vec<Object> {
+        let query = instance.query(0).unwrap().build();
+        let txn = instance.begin_txn(false).unwrap();
+        let mut objects = vec![];
+        {
+            let mut cursor = instance.query_cursor(&txn, &query, None, None).unwrap();
+            while let Some(reader) = cursor.next() {
+                let read_long = |index| (!reader.is_null(index)).then(|| reader.read_long(index));
+                let values = vec![
+                    read_long(1).and_then(long),
+                    reader.read_string(2).and_then(string),
+                    read_long(3).and_then(long),
+                ];
+                objects.push((reader.read_id(), values));
+            }
+        }
+        instance.abort_txn(txn);
+        objects
+    }
+
+    #[test]
+    fn test_unique_fail() {
+        let instance = open("insert_unique_fail", &[("value", ConflictPolicy::Fail)]);
+        put(&instance, &[(1, vec![long(5)]), (2, vec![long(6)])]).unwrap();
+
+        let violation = Err(IsarError::UniqueViolation {
+            index: "value".to_string(),
+            existing_id: 1,
+        });
+        assert_eq!(
+            put(&instance, &[(3, vec![long(7)]), (4, vec![long(5)])]),
+            violation
+        );
+        assert_eq!(
+            put(&instance, &[(2, vec![long(5), string("a")])]),
+            violation
+        );
+
+        // an object does not conflict with itself and null keys never conflict
+        let ids = put(
+            &instance,
+            &[(1, vec![long(5), string("b")]), (3, vec![]), (4, vec![])],
+        );
+        assert_eq!(ids, Ok(vec![Some(1), Some(3), Some(4)]));
+        assert_eq!(
+            objects(&instance),
+            vec![
+                (1, vec![long(5), string("b"), None]),
+                (2, vec![long(6), None, None]),
+                (3, vec![None, None, None]),
+                (4, vec![None, None, None]),
+            ]
+        );
+        NativeInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_unique_ignore() {
+        let instance = open("insert_unique_ignore", &[("value", ConflictPolicy::Ignore)]);
+        put(&instance, &[(1, vec![long(5)]), (2, vec![long(6)])]).unwrap();
+        let ids = put(
+            &instance,
+            &[
+                (3, vec![long(5), string("a")]),
+                (2, vec![long(5), string("b")]),
+                (4, vec![long(7)]),
+                (1, vec![long(5), string("c")]),
+            ],
+        );
+        assert_eq!(ids, Ok(vec![None, None, Some(4), Some(1)]));
+        assert_eq!(
+            objects(&instance),
+            vec![
+                (1, vec![long(5), string("c"), None]),
+                (2, vec![long(6), None, None]),
+                (4, vec![long(7), None, None]),
+            ]
+        );
+        NativeInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_upsert() {
+        let instance = open("insert_upsert", &[("value", ConflictPolicy::Replace)]);
+
+        // no match: properties that were not written are null
+        let ids = upsert(
+            &instance,
+            "value",
+            &[(1, vec![(1, long(5)), (2, string("a"))])],
+        );
+        assert_eq!(ids, Ok(vec![Some(1)]));
+        assert_eq!(
+            objects(&instance),
+            vec![(1, vec![long(5), string("a"), None])]
+        );
+
+        // match: the existing object keeps its id and the properties that were not written
+        let ids = upsert(
+            &instance,
+            "value",
+            &[
+                (2, vec![(1, long(5)), (3, long(7))]),
+                (3, vec![(1, long(5)), (2, None)]),
+                (4, vec![(1, long(6)), (3, long(1))]),
+            ],
+        );
+        assert_eq!(ids, Ok(vec![Some(1), Some(1), Some(4)]));
+        assert_eq!(
+            objects(&instance),
+            vec![
+                (1, vec![long(5), None, long(7)]),
+                (4, vec![long(6), None, long(1)]),
+            ]
+        );
+        NativeInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_upsert_conflicts() {
+        let indexes = [
+            ("value", ConflictPolicy::Replace),
+            ("name", ConflictPolicy::Ignore),
+            ("count", ConflictPolicy::Fail),
+        ];
+        let instance = open("insert_upsert_conflicts", &indexes);
+        put(
+            &instance,
+            &[
+                (1, vec![long(1), string("a"), long(1)]),
+                (2, vec![long(2), string("b"), long(2)]),
+            ],
+        )
+        .unwrap();
+
+        let ids = upsert(
+            &instance,
+            "value",
+            &[
+                (3, vec![(1, long(3)), (2, string("a"))]),
+                (3, vec![(1, long(1)), (2, string("a"))]),
+                (3, vec![(1, long(2)), (2, string("a"))]),
+            ],
+        );
+        assert_eq!(ids, Ok(vec![None, Some(1), None]));
+
+        let result = upsert(&instance, "value", &[(3, vec![(1, long(3)), (3, long(2))])]);
+        assert_eq!(
+            result,
+            Err(IsarError::UniqueViolation {
+                index: "count".to_string(),
+                existing_id: 2
+            })
+        );
+
+        assert_eq!(
+            objects(&instance),
+            vec![
+                (1, vec![long(1), string("a"), long(1)]),
+                (2, vec![long(2), string("b"), long(2)]),
+            ]
+        );
+        NativeInstance::close(instance, true);
+    }
+}
@@ -9,7 +9,8 @@ use super::native_txn::NativeTxn;
 use super::native_verify::verify_native;
 use super::query::{NativeQuery, NativeQueryCursor};
 use crate::core::error::{IsarError, Result};
-use crate::core::instance::{Aggregation, CompactCondition, IsarInstance};
+use crate::core::instance::{Aggregation, AggregationGroup, CompactCondition, IsarInstance};
+use crate::core::query_plan::QueryPlan;
 use crate::core::schema::IsarSchema;
 use crate::core::value::IsarValue;
 use crate::core::watcher::{WatchHandle, WatcherCallback};
@@ -54,13 +55,33 @@ impl NativeInstance {
         }
     }
 
-    fn get_collection(&self, collection_index: u16) -> Result<&NativeCollection> {
+    pub(crate) fn get_collection(&self, collection_index: u16) -> Result<&NativeCollection> {
         if let Some(collection) = self.collections.get(collection_index as usize) {
             Ok(collection)
         } else {
             Err(IsarError::IllegalArgument {})
         }
     }
+
+    /// Opens an instance in the temporary directory. Instances with the same name share their
+    /// database so they can be reopened with a different schema.
+    #[cfg(test)]
+    pub(crate) fn open_temp(name: &str, schemas: Vec<IsarSchema>) -> Result<Arc<Self>> {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        static NEXT_INSTANCE_ID: AtomicU32 = AtomicU32::new(1_000_000);
+        let instance_id = NEXT_INSTANCE_ID.fetch_add(1, Ordering::SeqCst);
+        let name = format!("{}_{}", name, std::process::id());
+        let dir = std::env::temp_dir();
+        Self::open_instance(
+            instance_id,
+            &name,
+            dir.to_str().unwrap(),
+            schemas,
+            100,
+            None,
+            None,
+        )
+    }
 }
 
 impl IsarInstance for NativeInstance {
@@ -187,7 +208,26 @@ impl IsarInstance for NativeInstance {
     ) -> Result<NativeInsert<'a>> {
         self.verify_instance_id(txn.instance_id)?;
         let collection = self.get_collection(collection_index)?;
-        NativeInsert::new(txn, collection, &self.collections, count)
+        NativeInsert::new(txn, collection, &self.collections, count, None)
+    }
+
+    fn upsert<'a>(
+        &'a self,
+        txn: NativeTxn,
+        collection_index: u16,
+        index_name: &str,
+        count: u32,
+    ) -> Result<NativeInsert<'a>> {
+        self.verify_instance_id(txn.instance_id)?;
+        let collection = self.get_collection(collection_index)?;
+        let index = collection
+            .indexes
+            .iter()
+            .find(|index| index.name == index_name && index.unique && !index.is_multi_entry());
+        let Some(index) = index else {
+            return Err(IsarError::IllegalArgument {});
+        };
+        NativeInsert::new(txn, collection, &self.collections, count, Some(index))
     }
 
     fn update(
@@ -251,8 +291,65 @@ impl IsarInstance for NativeInstance {
     ) -> Result<Self::QueryCursor<'a>> {
         self.verify_instance_id(txn.instance_id)?;
         self.verify_instance_id(query.instance_id)?;
-        let result = query.cursor(txn, &self.collections, offset, limit);
-        Ok(result)
+        query.verify_bound()?;
+        query.cursor(txn, &self.collections, None, offset, limit)
+    }
+
+    fn query_bind(
+        &self,
+        query: &mut Self::Query,
+        parameters: &[Vec<Option<IsarValue>>],
+    ) -> Result<()> {
+        self.verify_instance_id(query.instance_id)?;
+        let collection = self.get_collection(query.collection_index)?;
+        query.bind(collection, &self.collections, parameters)
+    }
+
+    fn query_properties_cursor<'a>(
+        &'a self,
+        txn: &'a Self::Txn,
+        query: &'a Self::Query,
+        properties: &[u16],
+        offset: Option<u32>,
+        limit: Option<u32>,
+    ) -> Result<Self::QueryCursor<'a>> {
+        self.verify_instance_id(txn.instance_id)?;
+        self.verify_instance_id(query.instance_id)?;
+        query.verify_bound()?;
+        let collection = self.get_collection(query.collection_index)?;
+        if properties
+            .iter()
+            .any(|p| *p != 0 && collection.get_property(*p).is_none())
+        {
+            return Err(IsarError::IllegalArgument {});
+        }
+        query.cursor(
+            txn,
+            &self.collections,
+            Some(properties.to_vec()),
+            offset,
+            limit,
+        )
+    }
+
+    fn query_page(&self, query: &Self::Query, token: Option<&str>) -> Result<Self::Query> {
+        self.verify_instance_id(query.instance_id)?;
+        query.verify_bound()?;
+        let collection = self.get_collection(query.collection_index)?;
+        query.page(collection, token)
+    }
+
+    fn query_page_token(
+        &self,
+        txn: &Self::Txn,
+        query: &Self::Query,
+        id: i64,
+    ) -> Result<Option<String>> {
+        self.verify_instance_id(txn.instance_id)?;
+        self.verify_instance_id(query.instance_id)?;
+        query.verify_bound()?;
+        let collection = self.get_collection(query.collection_index)?;
+        query.page_token(txn, collection, id)
     }
 
     fn query_aggregate(
@@ -264,8 +361,40 @@ impl IsarInstance for NativeInstance {
     ) -> Result<Option<IsarValue>> {
         self.verify_instance_id(txn.instance_id)?;
         self.verify_instance_id(query.instance_id)?;
-        let result = query.aggregate(txn, &self.collections, aggregation, property_index);
-        Ok(result)
+        query.verify_bound()?;
+        query.aggregate(txn, &self.collections, aggregation, property_index)
+    }
+
+    fn query_aggregate_batch(
+        &self,
+        txn: &Self::Txn,
+        query: &Self::Query,
+        aggregations: &[(Aggregation, Option<u16>)],
+    ) -> Result<Vec<Option<IsarValue>>> {
+        self.verify_instance_id(txn.instance_id)?;
+        self.verify_instance_id(query.instance_id)?;
+        query.verify_bound()?;
+        query.aggregate_batch(txn, &self.collections, aggregations)
+    }
+
+    fn query_aggregate_groups(
+        &self,
+        txn: &Self::Txn,
+        query: &Self::Query,
+        group_by: &[(u16, bool)],
+        aggregation: Aggregation,
+        property_index: Option<u16>,
+    ) -> Result<Vec<AggregationGroup>> {
+        self.verify_instance_id(txn.instance_id)?;
+        self.verify_instance_id(query.instance_id)?;
+        query.verify_bound()?;
+        query.aggregate_groups(
+            txn,
+            &self.collections,
+            group_by,
+            aggregation,
+            property_index,
+        )
     }
 
     fn query_update(
@@ -278,16 +407,20 @@ impl IsarInstance for NativeInstance {
     ) -> Result<u32> {
         self.verify_instance_id(txn.instance_id)?;
         self.verify_instance_id(query.instance_id)?;
+        query.verify_bound()?;
         let collection = self.get_collection(query.collection_index)?;
-        let ids = query.get_matching_ids(txn, collection, offset, limit);
+        let ids = query.get_matching_ids(txn, collection, offset, limit)?;
 
         txn.guard(|| {
             let change_set = &mut txn.get_change_set();
             let mut cursor = collection.get_cursor(txn)?;
+            let mut count = 0;
             for id in &ids {
-                collection.update(txn, change_set, &mut cursor, *id, updates)?;
+                if collection.update(txn, change_set, &mut cursor, *id, updates)? {
+                    count += 1;
+                }
             }
-            Ok(ids.len() as u32)
+            Ok(count)
         })
     }
 
@@ -300,8 +433,9 @@ impl IsarInstance for NativeInstance {
     ) -> Result<u32> {
         self.verify_instance_id(txn.instance_id)?;
         self.verify_instance_id(query.instance_id)?;
+        query.verify_bound()?;
         let collection = self.get_collection(query.collection_index)?;
-        let ids = query.get_matching_ids(txn, collection, offset, limit);
+        let ids = query.get_matching_ids(txn, collection, offset, limit)?;
 
         txn.guard(|| {
             let change_set = &mut txn.get_change_set();
@@ -313,6 +447,14 @@ impl IsarInstance for NativeInstance {
         })
     }
 
+    fn explain(&self, txn: &Self::Txn, query: &Self::Query) -> Result<QueryPlan> {
+        self.verify_instance_id(txn.instance_id)?;
+        self.verify_instance_id(query.instance_id)?;
+        query.verify_bound()?;
+        let collection = self.get_collection(query.collection_index)?;
+        query.explain(txn, collection, &self.collections)
+    }
+
     fn watch(&self, collection_index: u16, callback: WatcherCallback) -> Result<WatchHandle> {
         let collection = self.get_collection(collection_index)?;
         let handle = collection.watchers.watch(callback);
@@ -332,6 +474,7 @@ impl IsarInstance for NativeInstance {
 
     fn watch_query(&self, query: &Self::Query, callback: WatcherCallback) -> Result<WatchHandle> {
         self.verify_instance_id(query.instance_id)?;
+        query.verify_bound()?;
         let collection = self.get_collection(query.collection_index)?;
         let handle = collection.watchers.watch_query(query, callback);
         Ok(handle)
@@ -341,8 +484,37 @@ impl IsarInstance for NativeInstance {
         self.env.copy(path)
     }
 
-    fn verify(&self, txn: &Self::Txn) -> Result<()> {
-        verify_native(txn, &self.collections)
+    fn verify(&self, txn: &Self::Txn, deep: bool) -> Result<()> {
+        verify_native(txn, &self.collections, deep)
+    }
+
+    fn rebuild_index(
+        &self,
+        txn: &Self::Txn,
+        collection_index: u16,
+        index_name: &str,
+    ) -> Result<()> {
+        self.verify_instance_id(txn.instance_id)?;
+        let collection = self.get_collection(collection_index)?;
+        let index = collection
+            .indexes
+            .iter()
+            .find(|i| i.name == index_name)
+            .ok_or(IsarError::IllegalArgument {})?;
+        txn.guard(|| collection.rebuild_indexes(txn, &[index]))
+    }
+
+    fn rebuild_indexes(&self, txn: &Self::Txn) -> Result<()> {
+        self.verify_instance_id(txn.instance_id)?;
+        txn.guard(|| {
+            for collection in &self.collections {
+                if !collection.is_embedded() && !collection.indexes.is_empty() {
+                    let indexes = collection.indexes.iter().collect::<Vec<_>>();
+                    collection.rebuild_indexes(txn, &indexes)?;
+                }
+            }
+            Ok(())
+        })
     }
 
     fn close(instance: Arc<Self>, delete: bool) -> bool {
@@ -366,3 +538,157 @@ impl IsarInstance for NativeInstance {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::data_type::DataType;
+    use crate::core::insert::IsarInsert;
+    use crate::core::schema::{ConflictPolicy, IndexSchema, PropertySchema};
+    use crate::core::writer::IsarWriter;
+
+    fn schema(indexes: Vec<IndexSchema>) -> IsarSchema {
+        IsarSchema::new(
+            "test",
+            Some("id"),
+            vec![PropertySchema::new("value", DataType::Long, None)],
+            indexes,
+            false,
+        )
+    }
+
+    fn put(instance: &NativeInstance, objects: &[(i64, i64)]) {
+        let txn = instance.begin_txn(true).unwrap();
+        let mut insert = instance.insert(txn, 0, objects.len() as u32).unwrap();
+        for (id, value) in objects {
+            insert.write_long(1, *value);
+            insert.save(*id).unwrap();
+        }
+        let txn = insert.finish().unwrap();
+        instance.commit_txn(txn).unwrap();
+    }
+
+    fn count(instance: &NativeInstance) -> u32 {
+        let txn = instance.begin_txn(false).unwrap();
+        let count = instance.count(&txn, 0).unwrap();
+        instance.abort_txn(txn);
+        count
+    }
+
+    fn verify_error(instance: &NativeInstance, txn: &NativeTxn) -> String {
+        match instance.verify(txn, true) {
+            Err(IsarError::DbCorrupted { message }) => message,
+            result => panic!("unexpected result {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn test_verify_index() {
+        let index = IndexSchema::new("value", vec!["value"], false, false, true);
+        let instance =
+            NativeInstance::open_temp("verify_index", vec![schema(vec![index])]).unwrap();
+        put(&instance, &[(1, 1), (2, 2), (3, 2)]);
+
+        let txn = instance.begin_txn(true).unwrap();
+        instance.verify(&txn, false).unwrap();
+        instance.verify(&txn, true).unwrap();
+
+        let collection = &instance.collections[0];
+        collection.indexes[0].clear(&txn).unwrap();
+        instance.verify(&txn, false).unwrap();
+        assert_eq!(
+            verify_error(&instance, &txn),
+            "Index value of collection test has 3 missing and 0 obsolete entries."
+        );
+        instance.abort_txn(txn);
+
+        let txn = instance.begin_txn(true).unwrap();
+        // delete the objects without their index entries
+        {
+            let mut cursor = collection.get_cursor(&txn).unwrap();
+            while cursor.move_to_first().unwrap().is_some() {
+                cursor.delete_current().unwrap();
+            }
+        }
+        instance.verify(&txn, false).unwrap();
+        assert_eq!(
+            verify_error(&instance, &txn),
+            "Index value of collection test has 0 missing and 3 obsolete entries."
+        );
+        instance.abort_txn(txn);
+        NativeInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_rebuild_indexes() {
+        let index = IndexSchema::new("value", vec!["value"], false, false, true);
+        let instance = NativeInstance::open_temp("rebuild", vec![schema(vec![index])]).unwrap();
+        put(&instance, &[(1, 1), (2, 2), (3, 2)]);
+
+        for rebuild_all in [false, true] {
+            let txn = instance.begin_txn(true).unwrap();
+            instance.collections[0].indexes[0].clear(&txn).unwrap();
+            if rebuild_all {
+                instance.rebuild_indexes(&txn).unwrap();
+            } else {
+                instance.rebuild_index(&txn, 0, "value").unwrap();
+            }
+            instance.verify(&txn, true).unwrap();
+            instance.commit_txn(txn).unwrap();
+        }
+        assert_eq!(count(&instance), 3);
+        NativeInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_rebuild_indexes_of_other_instance() {
+        let index = || IndexSchema::new("value", vec!["value"], false, false, true);
+        let instance =
+            NativeInstance::open_temp("rebuild_instance", vec![schema(vec![index()])]).unwrap();
+        let other =
+            NativeInstance::open_temp("rebuild_other", vec![schema(vec![index()])]).unwrap();
+
+        let txn = instance.begin_txn(true).unwrap();
+        assert!(matches!(
+            other.rebuild_index(&txn, 0, "value"),
+            Err(IsarError::InstanceMismatch {})
+        ));
+        assert!(matches!(
+            other.rebuild_indexes(&txn),
+            Err(IsarError::InstanceMismatch {})
+        ));
+        instance.abort_txn(txn);
+
+        NativeInstance::close(instance, true);
+        NativeInstance::close(other, true);
+    }
+
+    /// Inserts an object with the same key as object 1 while the index is cleared.
+    fn put_duplicate(instance: &NativeInstance) {
+        put(instance, &[(1, 1), (2, 2)]);
+        let txn = instance.begin_txn(true).unwrap();
+        instance.collections[0].indexes[0].clear(&txn).unwrap();
+        instance.commit_txn(txn).unwrap();
+        put(instance, &[(3, 1)]);
+    }
+
+    #[test]
+    fn test_rebuild_index_with_duplicates() {
+        for on_conflict in [ConflictPolicy::Replace, ConflictPolicy::Fail] {
+            let index = IndexSchema::new("value", vec!["value"], true, false, true)
+                .with_conflict(on_conflict);
+            let instance =
+                NativeInstance::open_temp("rebuild_duplicates", vec![schema(vec![index])]).unwrap();
+            put_duplicate(&instance);
+            let txn = instance.begin_txn(true).unwrap();
+            let result = instance.rebuild_index(&txn, 0, "value");
+            assert!(matches!(
+                result,
+                Err(IsarError::UniqueViolation { index, existing_id: 1 }) if index == "value"
+            ));
+            instance.abort_txn(txn);
+            assert_eq!(count(&instance), 3);
+            NativeInstance::close(instance, true);
+        }
+    }
+}
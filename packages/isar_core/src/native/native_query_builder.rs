@@ -1,8 +1,11 @@
+use super::index_key::IndexKey;
 use super::native_collection::{NativeCollection, NativeProperty};
+use super::native_index::NativeIndex;
 use super::query::native_filter::NativeFilter;
 use super::query::{NativeQuery, QueryIndex};
 use crate::core::data_type::DataType;
-use crate::core::filter::{ConditionType, Filter, FilterCondition};
+use crate::core::error::{IsarError, Result};
+use crate::core::filter::{ConditionType, Filter, FilterCondition, FilterJson};
 use crate::core::query_builder::{IsarQueryBuilder, Sort};
 use crate::core::value::IsarValue;
 
@@ -11,6 +14,7 @@ pub struct NativeQueryBuilder<'a> {
     collection: &'a NativeCollection,
     all_collections: &'a [NativeCollection],
     filter: Option<Filter>,
+    indexes: Option<Vec<QueryIndex>>,
     sort: Vec<(Option<NativeProperty>, Sort, bool)>,
     distinct: Vec<(NativeProperty, bool)>,
 }
@@ -26,6 +30,7 @@ impl<'a> NativeQueryBuilder<'a> {
             collection,
             all_collections,
             filter: None,
+            indexes: None,
             sort: Vec::new(),
             distinct: Vec::new(),
         }
@@ -51,24 +56,126 @@ impl<'a> IsarQueryBuilder for NativeQueryBuilder<'a> {
         }
     }
 
+    fn add_id_range(&mut self, lower: i64, upper: i64) {
+        let indexes = self.indexes.get_or_insert_with(Vec::new);
+        if lower <= upper {
+            indexes.push(QueryIndex::Primary(lower, upper));
+        }
+    }
+
+    fn add_index_range(
+        &mut self,
+        index_name: &str,
+        lower: Vec<Option<IsarValue>>,
+        upper: Vec<Option<IsarValue>>,
+        include_lower: bool,
+        include_upper: bool,
+    ) -> Result<()> {
+        let index_index = self
+            .collection
+            .indexes
+            .iter()
+            .position(|i| i.name == index_name)
+            .ok_or(IsarError::IllegalArgument {})?;
+        let index = &self.collection.indexes[index_index];
+        if lower.len() > index.properties.len() || upper.len() > index.properties.len() {
+            return Err(IsarError::IllegalArgument {});
+        }
+
+        let range = if index.hash {
+            let full_key = lower.len() == index.properties.len();
+            if !full_key || lower != upper || !include_lower || !include_upper {
+                return Err(IsarError::IllegalArgument {});
+            }
+            index_range_key(index, &lower, true, false)
+                .map(|key| (key.to_hash_key(), key.to_hash_key()))
+        } else {
+            index_range_key(index, &lower, include_lower, false).zip(index_range_key(
+                index,
+                &upper,
+                include_upper,
+                true,
+            ))
+        };
+
+        let indexes = self.indexes.get_or_insert_with(Vec::new);
+        if let Some((lower_key, upper_key)) = range
+            && lower_key <= upper_key
+        {
+            indexes.push(QueryIndex::Secondary(
+                index_index as u16,
+                lower_key,
+                upper_key,
+            ));
+        }
+        Ok(())
+    }
+
     fn build(self) -> Self::Query {
-        let filter = self
+        let parameters = self
             .filter
-            .map(|f| filter_to_native(&f, self.collection, self.all_collections))
-            .unwrap_or(NativeFilter::stat(true));
+            .as_ref()
+            .filter(|f| f.has_parameters())
+            .map(|f| (f.clone(), self.indexes.clone()));
+        let (indexes, filter, watch_filter) = build_filter(
+            self.filter.as_ref(),
+            self.indexes,
+            self.collection,
+            self.all_collections,
+        );
 
-        NativeQuery::new(
+        let query = NativeQuery::new(
             self.instance_id,
             self.collection.collection_index,
-            vec![QueryIndex::Primary(i64::MIN, i64::MAX)],
+            indexes,
             filter,
+            watch_filter,
             self.sort,
             self.distinct,
         )
+        .with_fingerprint(self.filter.as_ref());
+        if let Some((filter, indexes)) = parameters {
+            query.with_parameters(filter, indexes)
+        } else {
+            query
+        }
+    }
+}
+
+/// Returns the index ranges, the filter evaluated for the objects in these ranges and the
+/// complete filter. If no index ranges are given, they are planned from the filter.
+pub(crate) fn build_filter(
+    filter: Option<&Filter>,
+    indexes: Option<Vec<QueryIndex>>,
+    collection: &NativeCollection,
+    all_collections: &[NativeCollection],
+) -> (Vec<QueryIndex>, NativeFilter, NativeFilter) {
+    let full_scan = vec![QueryIndex::Primary(i64::MIN, i64::MAX)];
+    if let Some(indexes) = indexes {
+        let filter = filter
+            .map(|f| filter_to_native(f, collection, all_collections))
+            .unwrap_or(NativeFilter::stat(true));
+        (indexes, filter.clone(), filter)
+    } else if let Some(filter) = filter {
+        let watch_filter = filter_to_native(filter, collection, all_collections);
+        if let Some((indexes, residual)) = plan_filter(filter, collection, all_collections) {
+            let filter = residual
+                .map(|f| filter_to_native(&f, collection, all_collections))
+                .unwrap_or(NativeFilter::stat(true));
+            (indexes, filter, watch_filter)
+        } else {
+            (full_scan, watch_filter.clone(), watch_filter)
+        }
+    } else {
+        (
+            full_scan,
+            NativeFilter::stat(true),
+            NativeFilter::stat(true),
+        )
     }
 }
 
-fn filter_to_native(
+pub(crate) fn filter_to_native(
     filter: &Filter,
     collection: &NativeCollection,
     all_collections: &[NativeCollection],
@@ -118,6 +225,8 @@ fn filter_to_native(
             let filter = filter_to_native(filter, collection, all_collections);
             NativeFilter::not(filter)
         }
+        // queries cannot be run until their parameters are bound
+        Filter::Parameter(_) => NativeFilter::stat(false),
     }
 }
 
@@ -231,9 +340,9 @@ fn native_between_filter(
                 NativeFilter::int(property, lower, upper)
             }
             DataType::Float | DataType::FloatList => {
-                let lower = lower_real(lower, include_lower)?;
-                let upper = upper_real(upper, include_upper)?;
-                NativeFilter::float(property, lower as f32, upper as f32)
+                let lower = lower_float(lower, include_lower)?;
+                let upper = upper_float(upper, include_upper)?;
+                NativeFilter::float(property, lower, upper)
             }
             DataType::Long | DataType::LongList => {
                 let lower = lower_long(lower, include_lower)?;
@@ -382,6 +491,89 @@ fn upper_real(value: Option<&IsarValue>, include: bool) -> Option<f64> {
     Some(value)
 }
 
+/// The lower bound of a double index key. Index ranges are exact so unlike the filter bounds
+/// an exclusive bound is the next larger double.
+fn lower_double(value: Option<&IsarValue>, include: bool) -> Option<f64> {
+    let mut value = if let Some(value) = value {
+        value.real()?
+    } else {
+        f64::NAN
+    };
+    if !include {
+        if value.is_nan() {
+            value = f64::NEG_INFINITY
+        } else if value == f64::INFINITY {
+            return None;
+        } else {
+            value = value.next_up();
+        }
+    }
+
+    Some(value)
+}
+
+/// The upper bound of a double index key. An exclusive bound is the next smaller double.
+fn upper_double(value: Option<&IsarValue>, include: bool) -> Option<f64> {
+    let mut value = if let Some(value) = value {
+        value.real()?
+    } else {
+        f64::NAN
+    };
+    if !include {
+        if value.is_nan() {
+            return None;
+        } else if value == f64::NEG_INFINITY {
+            value = f64::NAN;
+        } else {
+            value = value.next_down();
+        }
+    }
+
+    Some(value)
+}
+
+/// The lower bound of a float filter or index key. The value is rounded to the closest float
+/// like values that are written to float properties.
+fn lower_float(value: Option<&IsarValue>, include: bool) -> Option<f32> {
+    let Some(value) = value else {
+        return Some(if include { f32::NAN } else { f32::NEG_INFINITY });
+    };
+    let value = value.real()?;
+    if value.is_nan() {
+        return Some(if include { f32::NAN } else { f32::NEG_INFINITY });
+    }
+
+    let float = value as f32;
+    if include {
+        Some(float)
+    } else if float == f32::INFINITY {
+        None
+    } else {
+        Some(float.next_up())
+    }
+}
+
+/// The upper bound of a float filter or index key. The value is rounded like the lower bound.
+fn upper_float(value: Option<&IsarValue>, include: bool) -> Option<f32> {
+    let Some(value) = value else {
+        return if include { Some(f32::NAN) } else { None };
+    };
+    let value = value.real()?;
+    if value.is_nan() {
+        return if include { Some(f32::NAN) } else { None };
+    }
+
+    let float = value as f32;
+    if include {
+        Some(float)
+    } else if float == f32::NEG_INFINITY {
+        // only null is smaller than negative infinity
+        Some(f32::NAN)
+    } else {
+        Some(float.next_down())
+    }
+}
+
 fn lower_long(value: Option<&IsarValue>, include: bool) -> Option<i64> {
     let value = if let Some(value) = value {
         value.i64()?
@@ -499,9 +691,470 @@ fn get_max(property: Option<&NativeProperty>) -> Option<IsarValue> {
     Some(value)
 }
 
+/// Index ranges that have to be scanned and the residual filter that still needs to be
+/// evaluated for every object returned by these ranges.
+type QueryPlan = (Vec<QueryIndex>, Option<Filter>);
+
+fn plan_filter(
+    filter: &Filter,
+    collection: &NativeCollection,
+    all_collections: &[NativeCollection],
+) -> Option<QueryPlan> {
+    match filter {
+        Filter::Condition(condition) => {
+            let property = collection.get_property(condition.property_index);
+            let (indexes, exact) = plan_condition(condition, collection, &[], property)?;
+            let residual = if exact { None } else { Some(filter.clone()) };
+            Some((indexes, residual))
+        }
+        Filter::Embedded(embedded) => {
+            // follow the embedded objects to the condition
+            let mut path = vec![];
+            let mut embedded = embedded;
+            let mut embedded_collection = collection;
+            let condition = loop {
+                let property = embedded_collection.get_property(embedded.property_index)?;
+                if property.data_type != DataType::Object {
+                    return None;
+                }
+                path.push(*property);
+                embedded_collection =
+                    &all_collections[property.embedded_collection_index? as usize];
+                match embedded.filter.as_ref() {
+                    Filter::Embedded(nested) => embedded = nested,
+                    Filter::Condition(condition) => break condition,
+                    _ => return None,
+                }
+            };
+            let property = embedded_collection.get_property(condition.property_index)?;
+            // null embedded objects are indexed like objects with null properties so the
+            // filter always has to be evaluated
+            let (indexes, _) = plan_condition(condition, collection, &path, Some(property))?;
+            Some((indexes, Some(filter.clone())))
+        }
+        Filter::Json(json) => {
+            let indexes = plan_json(json, collection)?;
+            Some((indexes, Some(filter.clone())))
+        }
+        Filter::And(filters) => {
+            // Use the most selective child and keep all other children as residual
+            let (position, (indexes, residual)) = filters
+                .iter()
+                .enumerate()
+                .filter_map(|(i, f)| Some((i, plan_filter(f, collection, all_collections)?)))
+                .min_by_key(|(_, (indexes, residual))| {
+                    let primary = indexes.iter().all(|i| matches!(i, QueryIndex::Primary(..)));
+                    (residual.is_some(), indexes.len(), !primary)
+                })?;
+
+            let mut remaining = vec![];
+            for (i, filter) in filters.iter().enumerate() {
+                if i != position {
+                    remaining.push(filter.clone());
+                } else if let Some(residual) = &residual {
+                    remaining.push(residual.clone());
+                }
+            }
+            let residual = match remaining.len() {
+                0 => None,
+                1 => remaining.pop(),
+                _ => Some(Filter::And(remaining)),
+            };
+            Some((indexes, residual))
+        }
+        Filter::Or(filters) => {
+            if filters.is_empty() {
+                return None;
+            }
+
+            let mut indexes = vec![];
+            let mut exact = true;
+            for filter in filters {
+                let (child_indexes, residual) = plan_filter(filter, collection, all_collections)?;
+                indexes.extend(child_indexes);
+                exact &= residual.is_none();
+            }
+            let residual = if exact { None } else { Some(filter.clone()) };
+            Some((indexes, residual))
+        }
+        _ => None,
+    }
+}
+
+/// Returns the index ranges matching the condition and whether the ranges match the
+/// condition exactly. An empty list of ranges means that the condition never matches.
+/// `path` contains the embedded objects leading to the property and `None` is the id.
+fn plan_condition(
+    condition: &FilterCondition,
+    collection: &NativeCollection,
+    path: &[NativeProperty],
+    property: Option<&NativeProperty>,
+) -> Option<(Vec<QueryIndex>, bool)> {
+    let max = get_max(property);
+    let (lower, include_lower, upper, include_upper) = match condition.condition_type {
+        ConditionType::IsNull => (None, true, None, true),
+        ConditionType::Equal => {
+            let value = condition.values.first()?.as_ref();
+            (value, true, value, true)
+        }
+        ConditionType::Greater => (
+            condition.values.first()?.as_ref(),
+            false,
+            max.as_ref(),
+            true,
+        ),
+        ConditionType::GreaterOrEqual => {
+            (condition.values.first()?.as_ref(), true, max.as_ref(), true)
+        }
+        ConditionType::Less => (None, true, condition.values.first()?.as_ref(), false),
+        ConditionType::LessOrEqual => (None, true, condition.values.first()?.as_ref(), true),
+        ConditionType::Between => (
+            condition.values.first()?.as_ref(),
+            true,
+            condition.values.get(1)?.as_ref(),
+            true,
+        ),
+        ConditionType::StringStartsWith => {
+            let prefix = condition.values.first()?.as_ref()?.string()?;
+            if prefix.is_empty() {
+                return None;
+            }
+            return plan_index_range(
+                condition,
+                collection,
+                path,
+                property?,
+                Some(&IsarValue::String(prefix.to_string())),
+                Some(&IsarValue::String(prefix.to_string())),
+            );
+        }
+        _ => return None,
+    };
+
+    if property.is_none() {
+        if condition.condition_type == ConditionType::IsNull {
+            return None;
+        }
+        let lower = lower_id(lower, include_lower)?;
+        let upper = upper_id(upper, include_upper)?;
+        let indexes = if lower <= upper {
+            vec![QueryIndex::Primary(lower, upper)]
+        } else {
+            vec![]
+        };
+        return Some((indexes, true));
+    }
+
+    // list indexes only contain the elements and not whether the list itself is null
+    if property?.data_type.is_list() && condition.condition_type == ConditionType::IsNull {
+        return None;
+    }
+
+    // bytes are never null
+    if key_type(property?) == DataType::Byte && condition.condition_type == ConditionType::IsNull {
+        return Some((vec![], true));
+    }
+
+    let range = if key_type(property?) == DataType::String {
+        // String ranges are inclusive supersets of the condition
+        plan_index_range(condition, collection, path, property?, lower, upper)
+    } else if let Some((index_index, index)) = range_index(collection, path, property?, true) {
+        let mut lower_key = IndexKey::min();
+        let mut upper_key = IndexKey::min();
+        match key_type(property?) {
+            DataType::Bool => {
+                lower_key.add_bool(lower_bool(lower, include_lower)?);
+                upper_key.add_bool(upper_bool(upper, include_upper)?);
+            }
+            DataType::Byte => {
+                lower_key.add_byte(lower_byte(lower, include_lower)?);
+                upper_key.add_byte(upper_byte(upper, include_upper)?);
+            }
+            DataType::Int => {
+                lower_key.add_int(lower_int(lower, include_lower)?);
+                upper_key.add_int(upper_int(upper, include_upper)?);
+            }
+            DataType::Float => {
+                lower_key.add_float(lower_float(lower, include_lower)?);
+                upper_key.add_float(upper_float(upper, include_upper)?);
+            }
+            DataType::Long => {
+                lower_key.add_long(lower_long(lower, include_lower)?);
+                upper_key.add_long(upper_long(upper, include_upper)?);
+            }
+            DataType::Double => {
+                lower_key.add_double(lower_double(lower, include_lower)?);
+                upper_key.add_double(upper_double(upper, include_upper)?);
+            }
+            _ => return None,
+        }
+        Some((
+            secondary_range(index_index, index, lower_key, upper_key),
+            true,
+        ))
+    } else {
+        None
+    };
+
+    let equal = matches!(
+        condition.condition_type,
+        ConditionType::Equal | ConditionType::IsNull
+    );
+    if range.is_none() && equal {
+        plan_hashed(condition, collection, path, property?, lower)
+    } else {
+        range
+    }
+}
+
+/// Looks up a single value in a hashed index. Hash collisions are possible so the condition
+/// has to remain part of the residual filter.
+fn plan_hashed(
+    condition: &FilterCondition,
+    collection: &NativeCollection,
+    path: &[NativeProperty],
+    property: &NativeProperty,
+    value: Option<&IsarValue>,
+) -> Option<(Vec<QueryIndex>, bool)> {
+    let is_string = key_type(property) == DataType::String;
+    let index_index = collection.indexes.iter().position(|index| {
+        index.hash
+            && index.properties == [*property]
+            && index.paths[0] == path
+            && index.json_path.is_none()
+            && index.filter.is_none()
+            && (!is_string || index.case_sensitive == condition.case_sensitive)
+    })?;
+
+    let mut key = IndexKey::min();
+    match key_type(property) {
+        DataType::Bool => key.add_bool(lower_bool(value, true)?),
+        DataType::Byte => key.add_byte(lower_byte(value, true)?),
+        DataType::Int => key.add_int(lower_int(value, true)?),
+        DataType::Float => key.add_float(lower_float(value, true)?),
+        DataType::Long => key.add_long(lower_long(value, true)?),
+        DataType::Double => key.add_double(lower_double(value, true)?),
+        DataType::String => key.add_string(
+            lower_string(value, true)?.as_deref(),
+            condition.case_sensitive,
+        ),
+        _ => return None,
+    }
+    let key = key.to_hash_key();
+    let index = QueryIndex::Secondary(index_index as u16, key.clone(), key);
+    Some((vec![index], false))
+}
+
+fn plan_index_range(
+    condition: &FilterCondition,
+    collection: &NativeCollection,
+    path: &[NativeProperty],
+    property: &NativeProperty,
+    lower: Option<&IsarValue>,
+    upper: Option<&IsarValue>,
+) -> Option<(Vec<QueryIndex>, bool)> {
+    if key_type(property) != DataType::String {
+        return None;
+    }
+
+    let lower = lower_string(lower, true)?;
+    let upper = upper_string(upper, true)?;
+
+    let case_sensitive = condition.case_sensitive;
+    let (index_index, index) = range_index(collection, path, property, case_sensitive)?;
+    let mut lower_key = IndexKey::min();
+    let mut upper_key = IndexKey::min();
+    if index.is_delimited(0) {
+        lower_key.add_string_prefix(lower.as_deref(), case_sensitive);
+        upper_key.add_string_prefix(upper.as_deref(), case_sensitive);
+    } else {
+        lower_key.add_string(lower.as_deref(), case_sensitive);
+        upper_key.add_string(upper.as_deref(), case_sensitive);
+    }
+    // UTF-8 never contains 0xFF so all strings starting with upper are included
+    upper_key.add_max_bytes(1);
+
+    Some((
+        secondary_range(index_index, index, lower_key, upper_key),
+        false,
+    ))
+}
+
+/// Looks up the path of a JSON filter in a JSON path index. Values of other types than the
+/// declared type are indexed as null so the null keys are always scanned and the filter has
+/// to remain part of the residual filter.
+fn plan_json(json: &FilterJson, collection: &NativeCollection) -> Option<Vec<QueryIndex>> {
+    let json_property = collection.get_property(json.property_index)?;
+    let (index_index, index) = collection.indexes.iter().enumerate().find(|(_, index)| {
+        index.json_path.as_ref() == Some(&json.path)
+            && index.properties[0].offset == json_property.offset
+            && index.filter.is_none()
+            && (index.properties[0].data_type != DataType::String
+                || index.case_sensitive == json.case_sensitive)
+    })?;
+    let property = &index.properties[0];
+    let matches_type = json.values.iter().all(|value| {
+        matches!(
+            (property.data_type, value),
+            (DataType::Bool, Some(IsarValue::Bool(_)))
+                | (DataType::Long, Some(IsarValue::Integer(_)))
+                | (DataType::Double, Some(IsarValue::Real(_)))
+                | (DataType::String, Some(IsarValue::String(_)))
+        )
+    });
+    if !matches_type {
+        return None;
+    }
+
+    // all bounds are inclusive because the filter is evaluated anyway
+    let value = json.values.first().cloned();
+    let (lower, upper) = match json.condition_type {
+        ConditionType::IsNull => (None, None),
+        ConditionType::Equal => (value.clone()?, value?),
+        ConditionType::StringStartsWith => {
+            let prefix = value?;
+            if prefix.as_ref()?.string()?.is_empty() {
+                return None;
+            }
+            (prefix.clone(), prefix)
+        }
+        ConditionType::Greater | ConditionType::GreaterOrEqual => (value?, get_max(Some(property))),
+        ConditionType::Less | ConditionType::LessOrEqual => (None, value?),
+        ConditionType::Between => (value?, json.values.get(1)?.clone()),
+        _ => return None,
+    };
+    let lower_key = index_range_key(index, &[lower], true, false)?;
+    let mut upper_key = index_range_key(index, &[upper], true, true)?;
+    if json.condition_type == ConditionType::StringStartsWith {
+        upper_key.add_max_bytes(1);
+    }
+
+    let mut indexes = secondary_range(index_index, index, lower_key, upper_key);
+    if json.condition_type != ConditionType::IsNull {
+        let null_lower = index_range_key(index, &[None], true, false)?;
+        let null_upper = index_range_key(index, &[None], true, true)?;
+        indexes.extend(secondary_range(index_index, index, null_lower, null_upper));
+    }
+    Some(indexes)
+}
+
+/// Finds the smallest non-hashed index starting with the property. String keys require an
+/// index with the same case sensitivity. Partial indexes do not contain all objects so they
+/// are only used explicitly.
+fn range_index<'a>(
+    collection: &'a NativeCollection,
+    path: &[NativeProperty],
+    property: &NativeProperty,
+    case_sensitive: bool,
+) -> Option<(usize, &'a NativeIndex)> {
+    let is_string = key_type(property) == DataType::String;
+    collection
+        .indexes
+        .iter()
+        .enumerate()
+        .filter(|(_, index)| {
+            !index.hash
+                && index.properties.first() == Some(property)
+                && index.paths[0] == path
+                && index.json_path.is_none()
+                && index.filter.is_none()
+                && (!is_string || index.case_sensitive == case_sensitive)
+        })
+        .min_by_key(|(_, index)| index.properties.len())
+}
+
+/// Extends the keys of the first index property to cover all remaining index properties.
+fn secondary_range(
+    index_index: usize,
+    index: &NativeIndex,
+    lower_key: IndexKey,
+    mut upper_key: IndexKey,
+) -> Vec<QueryIndex> {
+    if lower_key > upper_key {
+        return vec![];
+    }
+
+    upper_key.add_max_bytes(max_suffix_len(&index.properties[1..]));
+
+    vec![QueryIndex::Secondary(
+        index_index as u16,
+        lower_key,
+        upper_key,
+    )]
+}
+
+/// The type of the index keys of a property. List indexes contain a key for every element.
+fn key_type(property: &NativeProperty) -> DataType {
+    property
+        .data_type
+        .element_type()
+        .unwrap_or(property.data_type)
+}
+
+/// Number of 0xFF bytes that have to be appended to a key prefix so that it is greater
+/// than or equal to all keys starting with the prefix.
+fn max_suffix_len(properties: &[NativeProperty]) -> usize {
+    properties
+        .iter()
+        .map(|p| match key_type(p) {
+            // string keys never start with 0xFF
+            DataType::String => 1,
+            data_type => data_type.static_size() as usize,
+        })
+        .sum()
+}
+
+/// Creates the lower or upper key of an explicit index range. Only the last value respects
+/// `include`. Returns `None` if the range is empty.
+fn index_range_key(
+    index: &NativeIndex,
+    values: &[Option<IsarValue>],
+    include: bool,
+    upper: bool,
+) -> Option<IndexKey> {
+    let mut key = IndexKey::min();
+    for (i, (value, property)) in values.iter().zip(&index.properties).enumerate() {
+        let include = include || i + 1 < values.len();
+        let value = value.as_ref();
+        match (key_type(property), upper) {
+            (DataType::Bool, false) => key.add_bool(lower_bool(value, include)?),
+            (DataType::Bool, true) => key.add_bool(upper_bool(value, include)?),
+            (DataType::Byte, false) => key.add_byte(lower_byte(value, include)?),
+            (DataType::Byte, true) => key.add_byte(upper_byte(value, include)?),
+            (DataType::Int, false) => key.add_int(lower_int(value, include)?),
+            (DataType::Int, true) => key.add_int(upper_int(value, include)?),
+            (DataType::Float, false) => key.add_float(lower_float(value, include)?),
+            (DataType::Float, true) => key.add_float(upper_float(value, include)?),
+            (DataType::Long, false) => key.add_long(lower_long(value, include)?),
+            (DataType::Long, true) => key.add_long(upper_long(value, include)?),
+            (DataType::Double, false) => key.add_double(lower_double(value, include)?),
+            (DataType::Double, true) => key.add_double(upper_double(value, include)?),
+            (DataType::String, upper) => {
+                let value = if upper {
+                    upper_string(value, include)?
+                } else {
+                    lower_string(value, include)?
+                };
+                if index.is_delimited(i) {
+                    key.add_delimited_string(value.as_deref(), index.case_sensitive)
+                } else {
+                    key.add_string(value.as_deref(), index.case_sensitive)
+                }
+            }
+            _ => return None,
+        }
+    }
+    if upper {
+        key.add_max_bytes(max_suffix_len(&index.properties[values.len()..]));
+    }
+    Some(key)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::native::NULL_LONG;
+    use crate::native::mdbx::db::Db;
 
     #[test]
     fn test_lower_bool() {
@@ -630,71 +1283,333 @@ mod tests {
     }
 
     #[test]
-    fn test_lower_long() {
-        assert_eq!(lower_long(None, true), Some(i64::MIN));
-        assert_eq!(lower_long(Some(&IsarValue::Integer(5)), true), Some(5));
-        assert_eq!(
-            lower_long(Some(&IsarValue::Integer(i64::MIN)), true),
-            Some(i64::MIN)
-        );
+    fn test_lower_double() {
+        assert!(lower_double(None, true).unwrap().is_nan());
+        assert_eq!(lower_double(Some(&IsarValue::Real(5.0)), true), Some(5.0));
 
         // Non-inclusive tests
-        assert_eq!(lower_long(Some(&IsarValue::Integer(5)), false), Some(6));
-        assert_eq!(lower_long(Some(&IsarValue::Integer(i64::MAX)), false), None);
+        assert_eq!(lower_double(None, false), Some(f64::NEG_INFINITY));
+        assert_eq!(
+            lower_double(Some(&IsarValue::Real(5.0)), false),
+            Some(5.0f64.next_up())
+        );
+        assert!(lower_double(Some(&IsarValue::Real(f64::INFINITY)), false).is_none());
     }
 
     #[test]
-    fn test_upper_long() {
-        assert_eq!(upper_long(None, true), Some(i64::MIN));
-        assert_eq!(upper_long(Some(&IsarValue::Integer(5)), true), Some(5));
+    fn test_upper_double() {
+        assert!(upper_double(None, true).unwrap().is_nan());
+        assert_eq!(upper_double(Some(&IsarValue::Real(5.0)), true), Some(5.0));
+
+        // Non-inclusive tests
+        assert!(upper_double(None, false).is_none());
         assert_eq!(
-            upper_long(Some(&IsarValue::Integer(i64::MAX)), true),
-            Some(i64::MAX)
+            upper_double(Some(&IsarValue::Real(5.0)), false),
+            Some(5.0f64.next_down())
+        );
+        assert!(
+            upper_double(Some(&IsarValue::Real(f64::NEG_INFINITY)), false)
+                .unwrap()
+                .is_nan()
         );
+    }
+
+    #[test]
+    fn test_lower_float() {
+        let lower = |value: f64, include: bool| lower_float(Some(&IsarValue::Real(value)), include);
+        assert!(lower_float(None, true).unwrap().is_nan());
+        assert_eq!(lower(5.0, true), Some(5.0));
+        assert_eq!(lower(0.1, true), Some(0.1));
+        assert_eq!(lower(-0.1, true), Some(-0.1));
+        assert_eq!(lower(1e40, true), Some(f32::INFINITY));
+        assert_eq!(lower(-1e40, true), Some(f32::NEG_INFINITY));
+        assert_eq!(lower(f64::NEG_INFINITY, true), Some(f32::NEG_INFINITY));
 
         // Non-inclusive tests
-        assert_eq!(upper_long(Some(&IsarValue::Integer(5)), false), Some(4));
-        assert_eq!(upper_long(Some(&IsarValue::Integer(i64::MIN)), false), None);
+        assert_eq!(lower_float(None, false), Some(f32::NEG_INFINITY));
+        assert_eq!(lower(5.0, false), Some(5.0f32.next_up()));
+        assert_eq!(lower(0.1, false), Some(0.1f32.next_up()));
+        assert_eq!(lower(f32::MAX as f64, false), Some(f32::INFINITY));
+        assert_eq!(lower(-1e40, false), Some(f32::MIN));
+        assert!(lower(1e40, false).is_none());
+        assert!(lower(f64::INFINITY, false).is_none());
     }
 
     #[test]
-    fn test_lower_string() {
-        assert_eq!(lower_string(None, true), Some(None));
-        assert_eq!(
-            lower_string(Some(&IsarValue::String("test".to_string())), true),
-            Some(Some("test".to_string()))
-        );
-        assert_eq!(
-            lower_string(Some(&IsarValue::String("".to_string())), true),
-            Some(Some("".to_string()))
-        );
+    fn test_upper_float() {
+        let upper = |value: f64, include: bool| upper_float(Some(&IsarValue::Real(value)), include);
+        assert!(upper_float(None, true).unwrap().is_nan());
+        assert_eq!(upper(5.0, true), Some(5.0));
+        assert_eq!(upper(0.1, true), Some(0.1));
+        assert_eq!(upper(1e40, true), Some(f32::INFINITY));
+        assert_eq!(upper(-1e40, true), Some(f32::NEG_INFINITY));
+        assert_eq!(upper(f64::INFINITY, true), Some(f32::INFINITY));
 
         // Non-inclusive tests
-        assert_eq!(lower_string(None, false), Some(Some("".to_string())));
-        assert_eq!(
-            lower_string(Some(&IsarValue::String("".to_string())), false),
-            Some(Some("\u{0}".to_string()))
-        );
-        assert_eq!(
-            lower_string(Some(&IsarValue::String("testb".to_string())), false),
-            Some(Some("testc".to_string()))
-        );
+        assert!(upper_float(None, false).is_none());
+        assert_eq!(upper(5.0, false), Some(5.0f32.next_down()));
+        assert_eq!(upper(0.1, false), Some(0.1f32.next_down()));
+        assert_eq!(upper(1e40, false), Some(f32::MAX));
+        assert_eq!(upper(f32::MIN as f64, false), Some(f32::NEG_INFINITY));
+        assert!(upper(-1e40, false).unwrap().is_nan());
+        assert!(upper(f64::NEG_INFINITY, false).unwrap().is_nan());
     }
 
     #[test]
-    fn test_upper_string() {
-        assert_eq!(upper_string(None, true), Some(None));
-        assert_eq!(
-            upper_string(Some(&IsarValue::String("test".to_string())), true),
-            Some(Some("test".to_string()))
-        );
-        assert_eq!(
-            upper_string(Some(&IsarValue::String("".to_string())), true),
-            Some(Some("".to_string()))
-        );
+    fn test_float_filter_with_and_without_index() {
+        use crate::core::cursor::IsarQueryCursor;
+        use crate::core::data_type::DataType;
+        use crate::core::filter::ConditionType;
+        use crate::core::insert::IsarInsert;
+        use crate::core::instance::IsarInstance;
+        use crate::core::reader::IsarReader;
+        use crate::core::schema::{IndexSchema, IsarSchema, PropertySchema};
+        use crate::core::writer::IsarWriter;
+        use crate::native::native_instance::NativeInstance;
 
-        // Non-inclusive tests
-        assert!(upper_string(None, false).is_none());
+        let schema = IsarSchema::new(
+            "test",
+            Some("id"),
+            vec![
+                PropertySchema::new("indexed", DataType::Float, None),
+                PropertySchema::new("plain", DataType::Float, None),
+            ],
+            vec![IndexSchema::new(
+                "indexed",
+                vec!["indexed"],
+                false,
+                false,
+                true,
+            )],
+            false,
+        );
+        let instance = NativeInstance::open_temp("float_filter", vec![schema]).unwrap();
+        let txn = instance.begin_txn(true).unwrap();
+        let mut insert = instance.insert(txn, 0, 3).unwrap();
+        for (id, value) in [(1, 0.1f32), (2, 0.2), (3, 0.1f32.next_up())] {
+            insert.write_float(1, value);
+            insert.write_float(2, value);
+            insert.save(id).unwrap();
+        }
+        let txn = insert.finish().unwrap();
+        instance.commit_txn(txn).unwrap();
+
+        let query_ids = |property_index: u16, condition_type: ConditionType| {
+            let mut qb = instance.query(0).unwrap();
+            qb.set_filter(Filter::new_condition(
+                property_index,
+                condition_type,
+                vec![Some(IsarValue::Real(0.1))],
+                true,
+            ));
+            let query = qb.build();
+            let txn = instance.begin_txn(false).unwrap();
+            let plan = instance.explain(&txn, &query).unwrap();
+            let uses_index = plan.ranges.iter().any(|r| r.index.is_some());
+            assert_eq!(uses_index, property_index == 1);
+            let mut ids = vec![];
+            {
+                let mut cursor = instance.query_cursor(&txn, &query, None, None).unwrap();
+                while let Some(reader) = cursor.next() {
+                    ids.push(reader.read_id());
+                }
+            }
+            instance.abort_txn(txn);
+            ids.sort();
+            ids
+        };
+
+        for property_index in [1, 2] {
+            assert_eq!(query_ids(property_index, ConditionType::Equal), vec![1]);
+            assert_eq!(
+                query_ids(property_index, ConditionType::LessOrEqual),
+                vec![1]
+            );
+            assert_eq!(
+                query_ids(property_index, ConditionType::Less),
+                Vec::<i64>::new()
+            );
+            assert_eq!(
+                query_ids(property_index, ConditionType::GreaterOrEqual),
+                vec![1, 2, 3]
+            );
+            assert_eq!(
+                query_ids(property_index, ConditionType::Greater),
+                vec![2, 3]
+            );
+        }
+        NativeInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_long_composite_keys() {
+        use crate::core::cursor::IsarQueryCursor;
+        use crate::core::data_type::DataType;
+        use crate::core::filter::ConditionType;
+        use crate::core::insert::IsarInsert;
+        use crate::core::instance::IsarInstance;
+        use crate::core::reader::IsarReader;
+        use crate::core::schema::{IndexSchema, IsarSchema, PropertySchema};
+        use crate::core::writer::IsarWriter;
+        use crate::native::native_instance::NativeInstance;
+
+        let schema = IsarSchema::new(
+            "test",
+            Some("id"),
+            vec![
+                PropertySchema::new("first", DataType::String, None),
+                PropertySchema::new("second", DataType::String, None),
+            ],
+            vec![IndexSchema::new(
+                "composite",
+                vec!["first", "second"],
+                false,
+                false,
+                true,
+            )],
+            false,
+        );
+        let instance = NativeInstance::open_temp("long_composite_keys", vec![schema]).unwrap();
+        let long = "a".repeat(1100);
+        let longer = format!("{long}b");
+        let txn = instance.begin_txn(true).unwrap();
+        let mut insert = instance.insert(txn, 0, 4).unwrap();
+        for (id, first, second) in [
+            (1, long.as_str(), "x"),
+            (2, long.as_str(), "y"),
+            (3, longer.as_str(), "x"),
+            (4, "b", "x"),
+        ] {
+            insert.write_string(1, first);
+            insert.write_string(2, second);
+            insert.save(id).unwrap();
+        }
+        let txn = insert.finish().unwrap();
+        instance.commit_txn(txn).unwrap();
+
+        let query_ids = |query: NativeQuery| {
+            let txn = instance.begin_txn(false).unwrap();
+            let plan = instance.explain(&txn, &query).unwrap();
+            assert!(plan.ranges.iter().all(|r| r.index.is_some()));
+            let mut ids = vec![];
+            {
+                let mut cursor = instance.query_cursor(&txn, &query, None, None).unwrap();
+                while let Some(reader) = cursor.next() {
+                    ids.push(reader.read_id());
+                }
+            }
+            instance.abort_txn(txn);
+            ids.sort();
+            ids
+        };
+        let filter_ids = |condition_type: ConditionType, values: Vec<&str>| {
+            let mut qb = instance.query(0).unwrap();
+            qb.set_filter(Filter::new_condition(
+                1,
+                condition_type,
+                values
+                    .into_iter()
+                    .map(|v| Some(IsarValue::String(v.to_string())))
+                    .collect(),
+                true,
+            ));
+            query_ids(qb.build())
+        };
+        let range_ids = |lower: &str, upper: &str| {
+            let mut qb = instance.query(0).unwrap();
+            let value = |v: &str| vec![Some(IsarValue::String(v.to_string()))];
+            qb.add_index_range("composite", value(lower), value(upper), true, true)
+                .unwrap();
+            query_ids(qb.build())
+        };
+
+        assert_eq!(filter_ids(ConditionType::Equal, vec![&long]), vec![1, 2]);
+        assert_eq!(
+            filter_ids(ConditionType::StringStartsWith, vec![&long]),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            filter_ids(ConditionType::GreaterOrEqual, vec![&longer]),
+            vec![3, 4]
+        );
+        assert_eq!(
+            filter_ids(ConditionType::Between, vec![&long, &longer]),
+            vec![1, 2, 3]
+        );
+
+        // Index ranges only compare the first bytes of long keys.
+        assert_eq!(range_ids(&long, &long), vec![1, 2, 3]);
+        assert_eq!(range_ids("a", &longer), vec![1, 2, 3]);
+        assert_eq!(range_ids(&longer, "b"), vec![1, 2, 3, 4]);
+        NativeInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_lower_long() {
+        assert_eq!(lower_long(None, true), Some(i64::MIN));
+        assert_eq!(lower_long(Some(&IsarValue::Integer(5)), true), Some(5));
+        assert_eq!(
+            lower_long(Some(&IsarValue::Integer(i64::MIN)), true),
+            Some(i64::MIN)
+        );
+
+        // Non-inclusive tests
+        assert_eq!(lower_long(Some(&IsarValue::Integer(5)), false), Some(6));
+        assert_eq!(lower_long(Some(&IsarValue::Integer(i64::MAX)), false), None);
+    }
+
+    #[test]
+    fn test_upper_long() {
+        assert_eq!(upper_long(None, true), Some(i64::MIN));
+        assert_eq!(upper_long(Some(&IsarValue::Integer(5)), true), Some(5));
+        assert_eq!(
+            upper_long(Some(&IsarValue::Integer(i64::MAX)), true),
+            Some(i64::MAX)
+        );
+
+        // Non-inclusive tests
+        assert_eq!(upper_long(Some(&IsarValue::Integer(5)), false), Some(4));
+        assert_eq!(upper_long(Some(&IsarValue::Integer(i64::MIN)), false), None);
+    }
+
+    #[test]
+    fn test_lower_string() {
+        assert_eq!(lower_string(None, true), Some(None));
+        assert_eq!(
+            lower_string(Some(&IsarValue::String("test".to_string())), true),
+            Some(Some("test".to_string()))
+        );
+        assert_eq!(
+            lower_string(Some(&IsarValue::String("".to_string())), true),
+            Some(Some("".to_string()))
+        );
+
+        // Non-inclusive tests
+        assert_eq!(lower_string(None, false), Some(Some("".to_string())));
+        assert_eq!(
+            lower_string(Some(&IsarValue::String("".to_string())), false),
+            Some(Some("\u{0}".to_string()))
+        );
+        assert_eq!(
+            lower_string(Some(&IsarValue::String("testb".to_string())), false),
+            Some(Some("testc".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_upper_string() {
+        assert_eq!(upper_string(None, true), Some(None));
+        assert_eq!(
+            upper_string(Some(&IsarValue::String("test".to_string())), true),
+            Some(Some("test".to_string()))
+        );
+        assert_eq!(
+            upper_string(Some(&IsarValue::String("".to_string())), true),
+            Some(Some("".to_string()))
+        );
+
+        // Non-inclusive tests
+        assert!(upper_string(None, false).is_none());
         assert_eq!(
             upper_string(Some(&IsarValue::String("".to_string())), false),
             Some(None)
@@ -808,4 +1723,832 @@ mod tests {
         );
         assert_eq!(get_max(Some(&object_prop)), None);
     }
+
+    fn planner_col() -> NativeCollection {
+        let long_prop = NativeProperty::new(DataType::Long, 0, None);
+        let int_prop = NativeProperty::new(DataType::Int, 8, None);
+        let string_prop = NativeProperty::new(DataType::String, 12, None);
+        let bool_prop = NativeProperty::new(DataType::Bool, 15, None);
+        let db = Db { dbi: 0, dup: false };
+        NativeCollection::new(
+            0,
+            "col",
+            Some("id"),
+            vec![
+                ("long".to_string(), long_prop),
+                ("int".to_string(), int_prop),
+                ("string".to_string(), string_prop),
+                ("bool".to_string(), bool_prop),
+            ],
+            vec![
+                NativeIndex::new("long", db, vec![long_prop], false, false, true),
+                NativeIndex::new(
+                    "int_long",
+                    db,
+                    vec![int_prop, long_prop],
+                    false,
+                    false,
+                    true,
+                ),
+                NativeIndex::new("string", db, vec![string_prop], true, false, true),
+                NativeIndex::new("bool", db, vec![bool_prop], false, true, true),
+            ],
+            None,
+        )
+    }
+
+    fn long_key(value: i64) -> IndexKey {
+        let mut key = IndexKey::min();
+        key.add_long(value);
+        key
+    }
+
+    #[test]
+    fn test_plan_equal() {
+        let col = planner_col();
+        let filter = Filter::new_condition(
+            1,
+            ConditionType::Equal,
+            vec![Some(IsarValue::Integer(5))],
+            true,
+        );
+        assert_eq!(
+            plan_filter(&filter, &col, &[]),
+            Some((
+                vec![QueryIndex::Secondary(0, long_key(5), long_key(5))],
+                None
+            ))
+        );
+    }
+
+    #[test]
+    fn test_plan_range() {
+        let col = planner_col();
+        let filter = Filter::new_condition(
+            1,
+            ConditionType::Less,
+            vec![Some(IsarValue::Integer(5))],
+            true,
+        );
+        assert_eq!(
+            plan_filter(&filter, &col, &[]),
+            Some((
+                vec![QueryIndex::Secondary(0, long_key(i64::MIN), long_key(4))],
+                None
+            ))
+        );
+
+        let filter = Filter::new_condition(
+            1,
+            ConditionType::Greater,
+            vec![Some(IsarValue::Integer(i64::MAX))],
+            true,
+        );
+        assert_eq!(plan_filter(&filter, &col, &[]), None);
+
+        let filter = Filter::new_condition(
+            1,
+            ConditionType::Between,
+            vec![Some(IsarValue::Integer(5)), Some(IsarValue::Integer(3))],
+            true,
+        );
+        assert_eq!(plan_filter(&filter, &col, &[]), Some((vec![], None)));
+    }
+
+    #[test]
+    fn test_plan_id() {
+        let col = planner_col();
+        let filter = Filter::new_condition(
+            0,
+            ConditionType::Between,
+            vec![Some(IsarValue::Integer(3)), Some(IsarValue::Integer(5))],
+            true,
+        );
+        assert_eq!(
+            plan_filter(&filter, &col, &[]),
+            Some((vec![QueryIndex::Primary(3, 5)], None))
+        );
+    }
+
+    #[test]
+    fn test_plan_composite_prefix() {
+        let col = planner_col();
+        let filter = Filter::new_condition(
+            2,
+            ConditionType::Equal,
+            vec![Some(IsarValue::Integer(7))],
+            true,
+        );
+        let mut lower = IndexKey::min();
+        lower.add_int(7);
+        let mut upper = lower.clone();
+        upper.add_max_bytes(8);
+        assert_eq!(
+            plan_filter(&filter, &col, &[]),
+            Some((vec![QueryIndex::Secondary(1, lower, upper)], None))
+        );
+    }
+
+    #[test]
+    fn test_plan_string() {
+        let col = planner_col();
+        let filter = Filter::new_condition(
+            3,
+            ConditionType::StringStartsWith,
+            vec![Some(IsarValue::String("ab".to_string()))],
+            true,
+        );
+        let mut lower = IndexKey::min();
+        lower.add_string(Some("ab"), true);
+        let mut upper = lower.clone();
+        upper.add_max_bytes(1);
+        assert_eq!(
+            plan_filter(&filter, &col, &[]),
+            Some((
+                vec![QueryIndex::Secondary(2, lower, upper)],
+                Some(filter.clone())
+            ))
+        );
+
+        let filter = Filter::new_condition(
+            3,
+            ConditionType::StringStartsWith,
+            vec![Some(IsarValue::String("ab".to_string()))],
+            false,
+        );
+        assert_eq!(plan_filter(&filter, &col, &[]), None);
+    }
+
+    #[test]
+    fn test_plan_case_insensitive() {
+        let string_prop = NativeProperty::new(DataType::String, 0, None);
+        let db = Db { dbi: 0, dup: false };
+        let col = NativeCollection::new(
+            0,
+            "col",
+            Some("id"),
+            vec![("string".to_string(), string_prop)],
+            vec![
+                NativeIndex::new("string", db, vec![string_prop], false, false, false),
+                NativeIndex::new("hashed", db, vec![string_prop], false, true, false),
+            ],
+            None,
+        );
+
+        let filter = Filter::new_condition(
+            1,
+            ConditionType::StringStartsWith,
+            vec![Some(IsarValue::String("aB".to_string()))],
+            false,
+        );
+        let mut lower = IndexKey::min();
+        lower.add_string(Some("ab"), true);
+        let mut upper = lower.clone();
+        upper.add_max_bytes(1);
+        assert_eq!(
+            plan_filter(&filter, &col, &[]),
+            Some((
+                vec![QueryIndex::Secondary(0, lower.clone(), upper.clone())],
+                Some(filter.clone())
+            ))
+        );
+
+        let filter = Filter::new_condition(
+            1,
+            ConditionType::Equal,
+            vec![Some(IsarValue::String("AB".to_string()))],
+            false,
+        );
+        assert_eq!(
+            plan_filter(&filter, &col, &[]),
+            Some((
+                vec![QueryIndex::Secondary(0, lower, upper)],
+                Some(filter.clone())
+            ))
+        );
+
+        // case-sensitive conditions cannot use case-insensitive indexes
+        let filter = Filter::new_condition(
+            1,
+            ConditionType::Equal,
+            vec![Some(IsarValue::String("AB".to_string()))],
+            true,
+        );
+        assert_eq!(plan_filter(&filter, &col, &[]), None);
+    }
+
+    #[test]
+    fn test_plan_hashed() {
+        let col = planner_col();
+        let filter = Filter::new_condition(
+            4,
+            ConditionType::Equal,
+            vec![Some(IsarValue::Bool(true))],
+            true,
+        );
+        let mut key = IndexKey::min();
+        key.add_bool(Some(true));
+        let key = key.to_hash_key();
+        assert_eq!(
+            plan_filter(&filter, &col, &[]),
+            Some((
+                vec![QueryIndex::Secondary(3, key.clone(), key)],
+                Some(filter.clone())
+            ))
+        );
+
+        let filter = Filter::new_condition(
+            4,
+            ConditionType::Less,
+            vec![Some(IsarValue::Bool(true))],
+            true,
+        );
+        assert_eq!(plan_filter(&filter, &col, &[]), None);
+    }
+
+    #[test]
+    fn test_plan_and() {
+        let col = planner_col();
+        let indexed = Filter::new_condition(
+            1,
+            ConditionType::Equal,
+            vec![Some(IsarValue::Integer(5))],
+            true,
+        );
+        let other = Filter::new_condition(
+            4,
+            ConditionType::Equal,
+            vec![Some(IsarValue::Bool(true))],
+            true,
+        );
+        let filter = Filter::new_and(vec![other.clone(), indexed]);
+        assert_eq!(
+            plan_filter(&filter, &col, &[]),
+            Some((
+                vec![QueryIndex::Secondary(0, long_key(5), long_key(5))],
+                Some(other.clone())
+            ))
+        );
+
+        let unindexed = Filter::new_condition(
+            4,
+            ConditionType::Less,
+            vec![Some(IsarValue::Bool(true))],
+            true,
+        );
+        let filter = Filter::new_and(vec![unindexed.clone(), unindexed]);
+        assert_eq!(plan_filter(&filter, &col, &[]), None);
+    }
+
+    #[test]
+    fn test_plan_or() {
+        let col = planner_col();
+        let cond1 = Filter::new_condition(
+            1,
+            ConditionType::Equal,
+            vec![Some(IsarValue::Integer(5))],
+            true,
+        );
+        let cond2 = Filter::new_condition(
+            0,
+            ConditionType::Equal,
+            vec![Some(IsarValue::Integer(8))],
+            true,
+        );
+        let filter = Filter::new_or(vec![cond1.clone(), cond2]);
+        assert_eq!(
+            plan_filter(&filter, &col, &[]),
+            Some((
+                vec![
+                    QueryIndex::Secondary(0, long_key(5), long_key(5)),
+                    QueryIndex::Primary(8, 8)
+                ],
+                None
+            ))
+        );
+
+        let other = Filter::new_condition(
+            4,
+            ConditionType::Less,
+            vec![Some(IsarValue::Bool(true))],
+            true,
+        );
+        let filter = Filter::new_or(vec![cond1, other]);
+        assert_eq!(plan_filter(&filter, &col, &[]), None);
+    }
+
+    #[test]
+    fn test_plan_double() {
+        let double_prop = NativeProperty::new(DataType::Double, 0, None);
+        let db = Db { dbi: 0, dup: false };
+        let col = NativeCollection::new(
+            0,
+            "col",
+            Some("id"),
+            vec![("double".to_string(), double_prop)],
+            vec![NativeIndex::new(
+                "double",
+                db,
+                vec![double_prop],
+                false,
+                false,
+                true,
+            )],
+            None,
+        );
+        let double_key = |value: f64| {
+            let mut key = IndexKey::min();
+            key.add_double(value);
+            key
+        };
+
+        let filter = Filter::new_condition(
+            1,
+            ConditionType::Between,
+            vec![Some(IsarValue::Real(-1.5)), Some(IsarValue::Real(2.5))],
+            true,
+        );
+        assert_eq!(
+            plan_filter(&filter, &col, &[]),
+            Some((
+                vec![QueryIndex::Secondary(0, double_key(-1.5), double_key(2.5))],
+                None
+            ))
+        );
+
+        let filter = Filter::new_condition(1, ConditionType::IsNull, vec![], true);
+        assert_eq!(
+            plan_filter(&filter, &col, &[]),
+            Some((
+                vec![QueryIndex::Secondary(
+                    0,
+                    double_key(f64::NAN),
+                    double_key(f64::NAN)
+                )],
+                None
+            ))
+        );
+
+        let filter = Filter::new_condition(
+            1,
+            ConditionType::Less,
+            vec![Some(IsarValue::Real(0.0))],
+            true,
+        );
+        assert_eq!(
+            plan_filter(&filter, &col, &[]),
+            Some((
+                vec![QueryIndex::Secondary(
+                    0,
+                    double_key(f64::NAN),
+                    double_key(0.0f64.next_down())
+                )],
+                None
+            ))
+        );
+    }
+
+    #[test]
+    fn test_plan_float() {
+        let float_prop = NativeProperty::new(DataType::Float, 0, None);
+        let db = Db { dbi: 0, dup: false };
+        let col = NativeCollection::new(
+            0,
+            "col",
+            Some("id"),
+            vec![("float".to_string(), float_prop)],
+            vec![NativeIndex::new(
+                "float",
+                db,
+                vec![float_prop],
+                false,
+                false,
+                true,
+            )],
+            None,
+        );
+        let float_key = |value: f32| {
+            let mut key = IndexKey::min();
+            key.add_float(value);
+            key
+        };
+        let plan = |condition_type: ConditionType, value: f64| {
+            let filter =
+                Filter::new_condition(1, condition_type, vec![Some(IsarValue::Real(value))], true);
+            plan_filter(&filter, &col, &[])
+        };
+
+        // 1e40 is rounded to infinity so no float is greater
+        assert_eq!(plan(ConditionType::Greater, 1e40), None);
+        assert_eq!(
+            plan(ConditionType::Less, 1e40),
+            Some((
+                vec![QueryIndex::Secondary(
+                    0,
+                    float_key(f32::NAN),
+                    float_key(f32::MAX)
+                )],
+                None
+            ))
+        );
+        assert_eq!(
+            plan(ConditionType::GreaterOrEqual, 0.1),
+            Some((
+                vec![QueryIndex::Secondary(
+                    0,
+                    float_key(0.1),
+                    float_key(f32::INFINITY)
+                )],
+                None
+            ))
+        );
+        assert_eq!(
+            plan(ConditionType::LessOrEqual, 0.1),
+            Some((
+                vec![QueryIndex::Secondary(
+                    0,
+                    float_key(f32::NAN),
+                    float_key(0.1)
+                )],
+                None
+            ))
+        );
+    }
+
+    #[test]
+    fn test_plan_byte() {
+        let byte_prop = NativeProperty::new(DataType::Byte, 0, None);
+        let db = Db { dbi: 0, dup: false };
+        let col = NativeCollection::new(
+            0,
+            "col",
+            Some("id"),
+            vec![("byte".to_string(), byte_prop)],
+            vec![NativeIndex::new(
+                "byte",
+                db,
+                vec![byte_prop],
+                false,
+                false,
+                true,
+            )],
+            None,
+        );
+        let byte_key = |value: u8| {
+            let mut key = IndexKey::min();
+            key.add_byte(value);
+            key
+        };
+
+        let filter = Filter::new_condition(
+            1,
+            ConditionType::Less,
+            vec![Some(IsarValue::Integer(10))],
+            true,
+        );
+        assert_eq!(
+            plan_filter(&filter, &col, &[]),
+            Some((
+                vec![QueryIndex::Secondary(0, byte_key(0), byte_key(9))],
+                None
+            ))
+        );
+
+        let filter = Filter::new_condition(1, ConditionType::IsNull, vec![], true);
+        assert_eq!(plan_filter(&filter, &col, &[]), Some((vec![], None)));
+    }
+
+    #[test]
+    fn test_plan_list() {
+        let list_prop = NativeProperty::new(DataType::IntList, 0, None);
+        let db = Db { dbi: 0, dup: false };
+        let col = NativeCollection::new(
+            0,
+            "col",
+            Some("id"),
+            vec![("list".to_string(), list_prop)],
+            vec![NativeIndex::new(
+                "list",
+                db,
+                vec![list_prop],
+                false,
+                false,
+                true,
+            )],
+            None,
+        );
+
+        let filter = Filter::new_condition(
+            1,
+            ConditionType::Between,
+            vec![Some(IsarValue::Integer(5)), Some(IsarValue::Integer(10))],
+            true,
+        );
+        let mut lower = IndexKey::min();
+        lower.add_int(5);
+        let mut upper = IndexKey::min();
+        upper.add_int(10);
+        assert_eq!(
+            plan_filter(&filter, &col, &[]),
+            Some((vec![QueryIndex::Secondary(0, lower, upper)], None))
+        );
+
+        let filter = Filter::new_condition(1, ConditionType::IsNull, vec![], true);
+        assert_eq!(plan_filter(&filter, &col, &[]), None);
+    }
+
+    #[test]
+    fn test_add_id_range() {
+        let col = planner_col();
+        let mut qb = NativeQueryBuilder::new(0, &col, &[]);
+        qb.add_id_range(1, 5);
+        qb.add_id_range(5, 1);
+        assert_eq!(qb.indexes, Some(vec![QueryIndex::Primary(1, 5)]));
+    }
+
+    #[test]
+    fn test_add_index_range() {
+        let col = planner_col();
+        let mut qb = NativeQueryBuilder::new(0, &col, &[]);
+        qb.add_index_range(
+            "long",
+            vec![Some(IsarValue::Integer(5))],
+            vec![Some(IsarValue::Integer(10))],
+            true,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            qb.indexes,
+            Some(vec![QueryIndex::Secondary(0, long_key(5), long_key(9))])
+        );
+
+        let mut qb = NativeQueryBuilder::new(0, &col, &[]);
+        qb.add_index_range("long", vec![], vec![None], true, false)
+            .unwrap();
+        assert_eq!(qb.indexes, Some(vec![]));
+    }
+
+    #[test]
+    fn test_add_index_range_composite() {
+        let col = planner_col();
+        let mut qb = NativeQueryBuilder::new(0, &col, &[]);
+        qb.add_index_range(
+            "int_long",
+            vec![Some(IsarValue::Integer(7)), Some(IsarValue::Integer(1))],
+            vec![Some(IsarValue::Integer(7))],
+            false,
+            true,
+        )
+        .unwrap();
+
+        let mut lower = IndexKey::min();
+        lower.add_int(7);
+        lower.add_long(2);
+        let mut upper = IndexKey::min();
+        upper.add_int(7);
+        upper.add_max_bytes(8);
+        assert_eq!(
+            qb.indexes,
+            Some(vec![QueryIndex::Secondary(1, lower, upper)])
+        );
+    }
+
+    fn names_col() -> NativeCollection {
+        let last_prop = NativeProperty::new(DataType::String, 0, None);
+        let first_prop = NativeProperty::new(DataType::String, 3, None);
+        let db = Db { dbi: 0, dup: false };
+        NativeCollection::new(
+            0,
+            "col",
+            Some("id"),
+            vec![
+                ("last".to_string(), last_prop),
+                ("first".to_string(), first_prop),
+            ],
+            vec![NativeIndex::new(
+                "last_first",
+                db,
+                vec![last_prop, first_prop],
+                false,
+                false,
+                true,
+            )],
+            None,
+        )
+    }
+
+    #[test]
+    fn test_plan_delimited_string() {
+        let col = names_col();
+        let filter = Filter::new_condition(
+            1,
+            ConditionType::StringStartsWith,
+            vec![Some(IsarValue::String("ab".to_string()))],
+            true,
+        );
+        let mut lower = IndexKey::min();
+        lower.add_string_prefix(Some("ab"), true);
+        let mut upper = lower.clone();
+        upper.add_max_bytes(2);
+        assert_eq!(
+            plan_filter(&filter, &col, &[]),
+            Some((
+                vec![QueryIndex::Secondary(0, lower, upper)],
+                Some(filter.clone())
+            ))
+        );
+
+        let filter = Filter::new_condition(2, ConditionType::Equal, vec![None], true);
+        assert_eq!(plan_filter(&filter, &col, &[]), None);
+    }
+
+    #[test]
+    fn test_plan_embedded() {
+        let zip_prop = NativeProperty::new(DataType::Long, 0, None);
+        let address_prop = NativeProperty::new(DataType::Object, 8, Some(1));
+        let db = Db { dbi: 0, dup: false };
+        let index = NativeIndex::new("zip", db, vec![zip_prop], false, false, true)
+            .with_paths(vec![vec![address_prop]]);
+        let col = NativeCollection::new(
+            0,
+            "col",
+            Some("id"),
+            vec![
+                ("zip".to_string(), zip_prop),
+                ("address".to_string(), address_prop),
+            ],
+            vec![index],
+            None,
+        );
+        let address = NativeCollection::new(
+            1,
+            "address",
+            None,
+            vec![("zip".to_string(), zip_prop)],
+            vec![],
+            None,
+        );
+        let all_collections = [col, address];
+        let col = &all_collections[0];
+
+        let condition = Filter::new_condition(
+            1,
+            ConditionType::Equal,
+            vec![Some(IsarValue::Integer(5))],
+            true,
+        );
+        let filter = Filter::new_embedded(2, condition.clone());
+        assert_eq!(
+            plan_filter(&filter, col, &all_collections),
+            Some((
+                vec![QueryIndex::Secondary(0, long_key(5), long_key(5))],
+                Some(filter.clone())
+            ))
+        );
+
+        // the index does not cover the property of the collection
+        assert_eq!(plan_filter(&condition, col, &all_collections), None);
+    }
+
+    #[test]
+    fn test_plan_json() {
+        let json_prop = NativeProperty::new(DataType::Json, 0, None);
+        let db = Db { dbi: 0, dup: false };
+        let index = NativeIndex::new(
+            "age",
+            db,
+            vec![NativeProperty::new(DataType::Long, 0, None)],
+            false,
+            false,
+            true,
+        )
+        .with_json_path(vec!["age".to_string()]);
+        let col = NativeCollection::new(
+            0,
+            "col",
+            Some("id"),
+            vec![("json".to_string(), json_prop)],
+            vec![index],
+            None,
+        );
+        let json = |condition_type, values| {
+            Filter::new_json(1, vec!["age".to_string()], condition_type, values, true)
+        };
+
+        // values of other types are indexed as null
+        let filter = json(ConditionType::Equal, vec![Some(IsarValue::Integer(5))]);
+        assert_eq!(
+            plan_filter(&filter, &col, &[]),
+            Some((
+                vec![
+                    QueryIndex::Secondary(0, long_key(5), long_key(5)),
+                    QueryIndex::Secondary(0, long_key(NULL_LONG), long_key(NULL_LONG)),
+                ],
+                Some(filter.clone())
+            ))
+        );
+
+        let filter = json(ConditionType::IsNull, vec![]);
+        assert_eq!(
+            plan_filter(&filter, &col, &[]),
+            Some((
+                vec![QueryIndex::Secondary(
+                    0,
+                    long_key(NULL_LONG),
+                    long_key(NULL_LONG)
+                )],
+                Some(filter.clone())
+            ))
+        );
+
+        // the value does not match the type of the index
+        let filter = json(ConditionType::Equal, vec![Some(IsarValue::Real(5.0))]);
+        assert_eq!(plan_filter(&filter, &col, &[]), None);
+
+        // unknown path
+        let filter = Filter::new_json(
+            1,
+            vec!["name".to_string()],
+            ConditionType::IsNull,
+            vec![],
+            true,
+        );
+        assert_eq!(plan_filter(&filter, &col, &[]), None);
+    }
+
+    #[test]
+    fn test_plan_partial() {
+        let mut col = planner_col();
+        for index in &mut col.indexes {
+            index.filter = Some(NativeFilter::stat(true));
+        }
+        let filter = Filter::new_condition(
+            1,
+            ConditionType::Equal,
+            vec![Some(IsarValue::Integer(5))],
+            true,
+        );
+        assert_eq!(plan_filter(&filter, &col, &[]), None);
+    }
+
+    #[test]
+    fn test_add_index_range_delimited_string() {
+        let col = names_col();
+        let mut qb = NativeQueryBuilder::new(0, &col, &[]);
+        qb.add_index_range(
+            "last_first",
+            vec![Some(IsarValue::String("doe".to_string())), None],
+            vec![Some(IsarValue::String("doe".to_string()))],
+            true,
+            true,
+        )
+        .unwrap();
+
+        let mut lower = IndexKey::min();
+        lower.add_delimited_string(Some("doe"), true);
+        lower.add_string(None, true);
+        let mut upper = IndexKey::min();
+        upper.add_delimited_string(Some("doe"), true);
+        upper.add_max_bytes(1);
+        assert_eq!(
+            qb.indexes,
+            Some(vec![QueryIndex::Secondary(0, lower, upper)])
+        );
+    }
+
+    #[test]
+    fn test_add_index_range_hashed() {
+        let col = planner_col();
+        let mut qb = NativeQueryBuilder::new(0, &col, &[]);
+        let value = vec![Some(IsarValue::Bool(false))];
+        qb.add_index_range("bool", value.clone(), value.clone(), true, true)
+            .unwrap();
+
+        let mut key = IndexKey::min();
+        key.add_bool(Some(false));
+        let key = key.to_hash_key();
+        assert_eq!(
+            qb.indexes,
+            Some(vec![QueryIndex::Secondary(3, key.clone(), key)])
+        );
+
+        let result = qb.add_index_range("bool", value.clone(), value, true, false);
+        assert!(matches!(result, Err(IsarError::IllegalArgument {})));
+        let result = qb.add_index_range("bool", vec![], vec![], true, true);
+        assert!(matches!(result, Err(IsarError::IllegalArgument {})));
+    }
+
+    #[test]
+    fn test_add_index_range_invalid() {
+        let col = planner_col();
+        let mut qb = NativeQueryBuilder::new(0, &col, &[]);
+        let result = qb.add_index_range("unknown", vec![], vec![], true, true);
+        assert!(matches!(result, Err(IsarError::IllegalArgument {})));
+
+        let values = vec![Some(IsarValue::Integer(1)), Some(IsarValue::Integer(2))];
+        let result = qb.add_index_range("long", values.clone(), values, true, true);
+        assert!(matches!(result, Err(IsarError::IllegalArgument {})));
+        assert_eq!(qb.indexes, None);
+    }
 }
@@ -11,6 +11,7 @@ pub struct NativeReader<'a> {
     object: IsarDeserializer<'a>,
     collection: &'a NativeCollection,
     all_collections: &'a [NativeCollection],
+    properties: Option<&'a [u16]>,
 }
 
 impl<'a> NativeReader<'a> {
@@ -25,17 +26,30 @@ impl<'a> NativeReader<'a> {
             object,
             collection,
             all_collections,
+            properties: None,
         }
     }
 
+    /// Only reads the given properties. All other properties are read as null.
+    pub(crate) fn with_properties(mut self, properties: &'a [u16]) -> Self {
+        self.properties = Some(properties);
+        self
+    }
+
     #[inline]
     fn get_property(&self, index: u32) -> Option<&NativeProperty> {
+        if self
+            .properties
+            .is_some_and(|properties| !properties.contains(&(index as u16)))
+        {
+            return None;
+        }
         self.collection.get_property(index as u16)
     }
 
     #[inline]
     fn get_offset(&self, index: u32, data_type: DataType) -> Option<u32> {
-        let property = self.collection.get_property(index as u16)?;
+        let property = self.get_property(index)?;
         if property.data_type == data_type {
             Some(property.offset)
         } else {
@@ -132,7 +146,7 @@ impl<'a> IsarReader for NativeReader<'a> {
 
     #[inline]
     fn read_string(&self, index: u32) -> Option<&str> {
-        let property = self.collection.get_property(index as u16)?;
+        let property = self.get_property(index)?;
 
         if property.data_type == DataType::String || property.data_type == DataType::Json {
             self.object.read_string(property.offset)
@@ -161,11 +175,12 @@ impl<'a> IsarReader for NativeReader<'a> {
             object,
             collection,
             all_collections: self.all_collections,
+            properties: None,
         })
     }
 
     fn read_list(&self, index: u32) -> Option<(Self::ListReader<'_>, u32)> {
-        let property = self.collection.get_property(index as u16)?;
+        let property = self.get_property(index)?;
         let element_type = property.data_type.element_type()?;
 
         let (list, length) = self.object.read_list(property.offset, element_type)?;
@@ -304,6 +319,7 @@ impl<'a> IsarReader for NativeListReader<'a> {
             object,
             collection,
             all_collections: self.all_collections,
+            properties: None,
         })
     }
 
@@ -445,6 +461,23 @@ mod test {
         assert_eq!(reader.is_null(4), true);
     }
 
+    #[test]
+    fn test_reader_with_properties() {
+        let col: NativeCollection = get_collection(vec![Int, Byte, Int]);
+        let bytes = [9, 0, 0, 44, 0, 0, 0, 1, 123, 0, 0, 0];
+        let reader = NativeReader::new(7, IsarDeserializer::from_bytes(&bytes), &col, &[])
+            .with_properties(&[3]);
+
+        assert_eq!(reader.read_id(), 7);
+        assert_eq!(reader.read_int(1), NULL_INT);
+        assert_eq!(reader.read_byte(2), 0);
+        assert_eq!(reader.read_int(3), 123);
+
+        assert_eq!(reader.is_null(1), true);
+        assert_eq!(reader.is_null(2), true);
+        assert_eq!(reader.is_null(3), false);
+    }
+
     #[test]
     fn test_reader_read_float() {
         let col: NativeCollection = get_collection(vec![Float, Byte, Float]);
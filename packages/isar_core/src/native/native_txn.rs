@@ -66,6 +66,10 @@ impl NativeTxn {
         Db::open(&self.txn, Some(name), int_key, dup)
     }
 
+    pub(crate) fn estimate_range(&self, db: Db, begin: &[u8], end: &[u8]) -> Result<u64> {
+        db.estimate_range(&self.txn, begin, end)
+    }
+
     pub(crate) fn clear_db(&self, db: Db) -> Result<()> {
         db.clear(&self.txn)
     }
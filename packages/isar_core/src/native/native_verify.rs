@@ -1,8 +1,16 @@
-use super::native_collection::NativeCollection;
+use super::BytesToId;
+use super::isar_deserializer::IsarDeserializer;
+use super::native_collection::{NativeCollection, NativeProperty};
 use super::native_txn::NativeTxn;
+use crate::core::data_type::DataType;
 use crate::core::error::{IsarError, Result};
+use std::str::from_utf8;
 
-pub(crate) fn verify_native(txn: &NativeTxn, collections: &[NativeCollection]) -> Result<()> {
+pub(crate) fn verify_native(
+    txn: &NativeTxn,
+    collections: &[NativeCollection],
+    deep: bool,
+) -> Result<()> {
     let mut db_names = vec![];
     db_names.push("_info".to_string());
     for col in collections {
@@ -19,8 +27,107 @@ pub(crate) fn verify_native(txn: &NativeTxn, collections: &[NativeCollection]) -
     actual_db_names.sort();
 
     if db_names != actual_db_names {
-        Err(IsarError::DbCorrupted {})
-    } else {
-        Ok(())
+        return Err(IsarError::DbCorrupted {
+            message: "Database names do not match the schema.".to_string(),
+        });
+    }
+
+    if deep {
+        for col in collections {
+            if !col.is_embedded() {
+                verify_collection(txn, col, collections)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn verify_collection(
+    txn: &NativeTxn,
+    collection: &NativeCollection,
+    all_collections: &[NativeCollection],
+) -> Result<()> {
+    let cursor = collection.get_cursor(txn)?;
+    for (id_bytes, bytes) in cursor.iter_between_ids(i64::MIN, i64::MAX, false, false)? {
+        let valid = IsarDeserializer::try_from_bytes(bytes)
+            .is_some_and(|object| verify_object(object, collection, all_collections));
+        if !valid {
+            return Err(IsarError::DbCorrupted {
+                message: format!(
+                    "Object {} of collection {} could not be deserialized.",
+                    id_bytes.to_id(),
+                    collection.name
+                ),
+            });
+        }
+    }
+
+    for index in &collection.indexes {
+        // objects have been verified above so they can be deserialized safely
+        let cursor = collection.get_cursor(txn)?;
+        let objects = cursor
+            .iter_between_ids(i64::MIN, i64::MAX, false, false)?
+            .map(|(id_bytes, bytes)| (id_bytes.to_id(), IsarDeserializer::from_bytes(bytes)));
+        let (missing, obsolete) = index.verify(txn, objects)?;
+        if missing != 0 || obsolete != 0 {
+            return Err(IsarError::DbCorrupted {
+                message: format!(
+                    "Index {} of collection {} has {} missing and {} obsolete entries.",
+                    index.name, collection.name, missing, obsolete
+                ),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn verify_object(
+    object: IsarDeserializer,
+    collection: &NativeCollection,
+    all_collections: &[NativeCollection],
+) -> bool {
+    collection
+        .properties
+        .iter()
+        .all(|(_, property)| verify_property(object, property, all_collections))
+}
+
+fn verify_property(
+    object: IsarDeserializer,
+    property: &NativeProperty,
+    all_collections: &[NativeCollection],
+) -> bool {
+    match property.data_type {
+        DataType::String | DataType::Json => match object.try_read_dynamic(property.offset) {
+            Some(Some(bytes)) => from_utf8(bytes).is_ok(),
+            result => result.is_some(),
+        },
+        DataType::Object => match object.try_read_nested(property.offset) {
+            Some(Some(nested)) => property
+                .embedded_collection_index
+                .and_then(|index| all_collections.get(index as usize))
+                .is_some_and(|collection| verify_object(nested, collection, all_collections)),
+            result => result.is_some(),
+        },
+        DataType::StringList | DataType::ObjectList => {
+            let element_type = property.data_type.element_type().unwrap();
+            match object.try_read_list(property.offset, element_type) {
+                Some(Some((list, length))) => (0..length).all(|i| {
+                    let element = NativeProperty::new(
+                        element_type,
+                        i * element_type.static_size() as u32,
+                        property.embedded_collection_index,
+                    );
+                    verify_property(list, &element, all_collections)
+                }),
+                result => result.is_some(),
+            }
+        }
+        data_type if data_type.is_list() => object
+            .try_read_list(property.offset, data_type.element_type().unwrap())
+            .is_some(),
+        _ => true,
     }
 }
@@ -26,6 +26,10 @@ pub(crate) trait WriterImpl<'a> {
     fn get_collections(&self) -> &'a [NativeCollection];
 
     fn get_serializer(&mut self) -> &mut IsarSerializer;
+
+    /// Called for every property that is written.
+    #[inline]
+    fn set_written(&mut self, _index: u32) {}
 }
 
 impl<'a, T: WriterImpl<'a>> IsarWriter<'a> for T {
@@ -44,6 +48,7 @@ impl<'a, T: WriterImpl<'a>> IsarWriter<'a> for T {
 
     #[inline]
     fn write_null(&mut self, index: u32) {
+        self.set_written(index);
         if let Some((data_type, offset, _)) = self.get_property(index) {
             self.get_serializer().write_null(offset, data_type);
         }
@@ -51,6 +56,7 @@ impl<'a, T: WriterImpl<'a>> IsarWriter<'a> for T {
 
     #[inline]
     fn write_bool(&mut self, index: u32, value: bool) {
+        self.set_written(index);
         if let Some(offset) = self.get_offset(index, DataType::Bool) {
             self.get_serializer().write_bool(offset, value);
         }
@@ -58,6 +64,7 @@ impl<'a, T: WriterImpl<'a>> IsarWriter<'a> for T {
 
     #[inline]
     fn write_byte(&mut self, index: u32, value: u8) {
+        self.set_written(index);
         if let Some(offset) = self.get_offset(index, DataType::Byte) {
             self.get_serializer().write_byte(offset, value);
         }
@@ -65,6 +72,7 @@ impl<'a, T: WriterImpl<'a>> IsarWriter<'a> for T {
 
     #[inline]
     fn write_int(&mut self, index: u32, value: i32) {
+        self.set_written(index);
         if let Some(offset) = self.get_offset(index, DataType::Int) {
             self.get_serializer().write_int(offset, value);
         }
@@ -72,6 +80,7 @@ impl<'a, T: WriterImpl<'a>> IsarWriter<'a> for T {
 
     #[inline]
     fn write_float(&mut self, index: u32, value: f32) {
+        self.set_written(index);
         if let Some(offset) = self.get_offset(index, DataType::Float) {
             self.get_serializer().write_float(offset, value);
         }
@@ -79,6 +88,7 @@ impl<'a, T: WriterImpl<'a>> IsarWriter<'a> for T {
 
     #[inline]
     fn write_long(&mut self, index: u32, value: i64) {
+        self.set_written(index);
         if let Some(offset) = self.get_offset(index, DataType::Long) {
             self.get_serializer().write_long(offset, value);
         }
@@ -86,6 +96,7 @@ impl<'a, T: WriterImpl<'a>> IsarWriter<'a> for T {
 
     #[inline]
     fn write_double(&mut self, index: u32, value: f64) {
+        self.set_written(index);
         if let Some(offset) = self.get_offset(index, DataType::Double) {
             self.get_serializer().write_double(offset, value);
         }
@@ -93,6 +104,7 @@ impl<'a, T: WriterImpl<'a>> IsarWriter<'a> for T {
 
     #[inline]
     fn write_string(&mut self, index: u32, value: &str) {
+        self.set_written(index);
         if let Some((data_type, index, _)) = self.get_property(index) {
             if data_type == DataType::String || data_type == DataType::Json {
                 self.get_serializer().write_dynamic(index, value.as_bytes());
@@ -102,12 +114,14 @@ impl<'a, T: WriterImpl<'a>> IsarWriter<'a> for T {
 
     #[inline]
     fn write_byte_list(&mut self, index: u32, value: &[u8]) {
+        self.set_written(index);
         if let Some(offset) = self.get_offset(index, DataType::ByteList) {
             self.get_serializer().write_dynamic(offset, value);
         }
     }
 
     fn begin_object(&mut self, index: u32) -> Option<Self::ObjectWriter> {
+        self.set_written(index);
         let (data_type, offset, collection_index) = self.get_property(index)?;
         if data_type == DataType::Object {
             let collections = self.get_collections();
@@ -128,6 +142,7 @@ impl<'a, T: WriterImpl<'a>> IsarWriter<'a> for T {
     }
 
     fn begin_list(&mut self, index: u32, length: u32) -> Option<Self::ListWriter> {
+        self.set_written(index);
         let (data_type, offset, embedded_collection_index) = self.get_property(index)?;
         if let Some(element_type) = data_type.element_type() {
             let list = self
@@ -183,6 +198,13 @@ impl<'a> WriterImpl<'a> for NativeInsert<'a> {
     fn get_serializer(&mut self) -> &mut IsarSerializer {
         &mut self.object
     }
+
+    #[inline]
+    fn set_written(&mut self, index: u32) {
+        if let Some(written) = self.written.get_mut(index as usize) {
+            *written = true;
+        }
+    }
 }
 
 pub struct NativeObjectWriter<'a> {
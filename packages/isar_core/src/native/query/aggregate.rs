@@ -1,248 +1,412 @@
 use crate::core::data_type::DataType;
+use crate::core::instance::Aggregation;
+use crate::core::statistics::{Variance, percentile};
 use crate::core::value::IsarValue;
 use crate::native::isar_deserializer::IsarDeserializer;
 use crate::native::native_collection::NativeProperty;
 use crate::native::{NULL_INT, NULL_LONG};
+use intmap::IntMap;
 use std::cmp::Ordering;
 
-pub(crate) fn aggregate_sum_average<'a>(
+/// Aggregates the objects of the iterator.
+pub(crate) fn aggregate<'a>(
     iterator: impl Iterator<Item = (i64, IsarDeserializer<'a>)>,
     property: Option<&NativeProperty>,
-    aggregate_sum: bool,
+    aggregation: Aggregation,
 ) -> Option<IsarValue> {
-    if let Some(property) = property {
-        match property.data_type {
-            DataType::Byte | DataType::Int | DataType::Long => {
-                let mut sum = 0i64;
-                let mut count = 0i64;
-                match property.data_type {
-                    DataType::Byte => {
-                        for (_, reader) in iterator {
-                            sum += reader.read_byte(property.offset) as i64;
-                            count += 1;
-                        }
-                    }
-                    DataType::Int => {
-                        for (_, reader) in iterator {
-                            let value = reader.read_int(property.offset);
-                            if value != NULL_INT {
-                                sum += value as i64;
-                                count += 1;
-                            }
-                        }
-                    }
-                    DataType::Long => {
-                        for (_, reader) in iterator {
-                            let value = reader.read_long(property.offset);
-                            if value != NULL_LONG {
-                                sum += value;
-                                count += 1;
-                            }
-                        }
-                    }
-                    _ => unreachable!(),
-                }
+    let mut accumulator = Accumulator::new(aggregation, property);
+    for (id, object) in iterator {
+        if accumulator.is_complete() {
+            break;
+        }
+        accumulator.add(id, object);
+    }
+    accumulator.finish()
+}
 
-                if aggregate_sum {
-                    Some(IsarValue::Integer(sum))
-                } else if count > 0 {
-                    Some(IsarValue::Real(sum as f64 / count as f64))
-                } else {
-                    Some(IsarValue::Real(f64::NAN))
-                }
-            }
-            DataType::Float | DataType::Double => {
-                let mut sum = 0f64;
-                let mut count = 0i64;
-                match property.data_type {
-                    DataType::Float => {
-                        for (_, reader) in iterator {
-                            let value = reader.read_float(property.offset);
-                            if !value.is_nan() {
-                                sum += value as f64;
-                                count += 1;
-                            }
-                        }
-                    }
-                    DataType::Double => {
-                        for (_, reader) in iterator {
-                            let value = reader.read_double(property.offset);
-                            if !value.is_nan() {
-                                sum += value;
-                                count += 1;
-                            }
-                        }
-                    }
-                    _ => unreachable!(),
-                }
+/// A value of a numeric property or an id.
+#[derive(Copy, Clone)]
+enum Number {
+    Integer(i64),
+    Real(f64),
+}
 
-                if aggregate_sum {
-                    Some(IsarValue::Real(sum))
-                } else if count > 0 {
-                    Some(IsarValue::Real(sum / count as f64))
-                } else {
-                    Some(IsarValue::Real(f64::NAN))
-                }
-            }
-            _ => None,
+impl Number {
+    fn to_f64(self) -> f64 {
+        match self {
+            Number::Integer(value) => value as f64,
+            Number::Real(value) => value,
         }
-    } else {
-        let mut sum = 0i64;
-        let mut count = 0i64;
-        for (id, _) in iterator {
-            if id != NULL_LONG {
-                sum += id;
-                count += 1;
-            }
+    }
+
+    fn to_value(self) -> IsarValue {
+        match self {
+            Number::Integer(value) => IsarValue::Integer(value),
+            Number::Real(value) => IsarValue::Real(value),
+        }
+    }
+}
+
+/// Whether the numbers of the property are integers or `None` if the property is not
+/// numeric. The elements of lists are only numbers if `lists` is true.
+fn integer_numbers(property: Option<&NativeProperty>, lists: bool) -> Option<bool> {
+    let Some(property) = property else {
+        return Some(true);
+    };
+    match property.data_type {
+        DataType::Byte | DataType::Int | DataType::Long => Some(true),
+        DataType::Float | DataType::Double => Some(false),
+        DataType::IntList | DataType::LongList if lists => Some(true),
+        DataType::FloatList | DataType::DoubleList if lists => Some(false),
+        _ => None,
+    }
+}
+
+/// Reads the value of a scalar numeric property or `None` if it is null.
+fn read_number(object: IsarDeserializer, offset: u32, data_type: DataType) -> Option<Number> {
+    match data_type {
+        DataType::Byte => Some(Number::Integer(object.read_byte(offset) as i64)),
+        DataType::Int => {
+            let value = object.read_int(offset);
+            (value != NULL_INT).then_some(Number::Integer(value as i64))
         }
-        if aggregate_sum {
-            Some(IsarValue::Integer(sum))
-        } else if count > 0 {
-            Some(IsarValue::Real(sum as f64 / count as f64))
-        } else {
-            Some(IsarValue::Real(f64::NAN))
+        DataType::Long => {
+            let value = object.read_long(offset);
+            (value != NULL_LONG).then_some(Number::Integer(value))
         }
+        DataType::Float => {
+            let value = object.read_float(offset);
+            (!value.is_nan()).then_some(Number::Real(value as f64))
+        }
+        DataType::Double => {
+            let value = object.read_double(offset);
+            (!value.is_nan()).then_some(Number::Real(value))
+        }
+        _ => None,
     }
 }
 
-pub(crate) fn aggregate_min_max<'a>(
-    iterator: impl Iterator<Item = (i64, IsarDeserializer<'a>)>,
+/// Calls the function with every numeric value of the object that is not null. The id is
+/// used if there is no property.
+fn for_each_number(
+    id: i64,
+    object: IsarDeserializer,
     property: Option<&NativeProperty>,
-    aggregate_min: bool,
-) -> Option<IsarValue> {
-    let min_max_cmp = if aggregate_min {
-        Ordering::Less
-    } else {
-        Ordering::Greater
+    mut f: impl FnMut(Number),
+) {
+    let Some(property) = property else {
+        if id != NULL_LONG {
+            f(Number::Integer(id));
+        }
+        return;
     };
-
-    if let Some(property) = property {
-        match property.data_type {
-            DataType::Byte => {
-                let mut min_max = if aggregate_min { 255u8 } else { 0u8 };
-                let mut has_value = false;
-                for (_, reader) in iterator {
-                    let value = reader.read_byte(property.offset);
-                    if value.cmp(&min_max) == min_max_cmp {
-                        min_max = value;
-                        has_value = true;
-                    }
-                }
-                if has_value {
-                    Some(IsarValue::Integer(min_max as i64))
-                } else {
-                    None
+    if let Some(element_type) = property.data_type.element_type() {
+        let element_size = element_type.static_size() as u32;
+        if let Some((list, length)) = object.read_list(property.offset, element_type) {
+            for i in 0..length {
+                if let Some(value) = read_number(list, i * element_size, element_type) {
+                    f(value);
                 }
             }
-            DataType::Int => {
-                let mut min_max = if aggregate_min { i32::MAX } else { i32::MIN };
-                let mut has_value = false;
-                for (_, reader) in iterator {
-                    let value = reader.read_int(property.offset);
-                    if value != NULL_INT && value.cmp(&min_max) == min_max_cmp {
-                        min_max = value;
-                        has_value = true;
+        }
+    } else if let Some(value) = read_number(object, property.offset, property.data_type) {
+        f(value);
+    }
+}
+
+/// The state of an aggregation that is updated with one object at a time.
+pub(crate) enum Accumulator {
+    Unsupported,
+    Count(i64),
+    IsEmpty(bool),
+    Sum {
+        property: Option<NativeProperty>,
+        integer: bool,
+        average: bool,
+        integer_sum: i64,
+        real_sum: f64,
+        count: i64,
+    },
+    MinMax {
+        property: Option<NativeProperty>,
+        min: bool,
+        value: Option<IsarValue>,
+    },
+    CountDistinct {
+        property: Option<NativeProperty>,
+        hashes: IntMap<u64, ()>,
+    },
+    Percentile {
+        property: Option<NativeProperty>,
+        percentile: f64,
+        values: Vec<f64>,
+    },
+    Variance {
+        property: Option<NativeProperty>,
+        std_dev: bool,
+        variance: Variance,
+    },
+    ElementCount {
+        property: NativeProperty,
+        count: i64,
+    },
+    Length {
+        property: NativeProperty,
+        aggregation: Aggregation,
+        min: Option<i64>,
+        max: Option<i64>,
+        sum: i64,
+        count: i64,
+    },
+}
+
+impl Accumulator {
+    pub fn new(aggregation: Aggregation, property: Option<&NativeProperty>) -> Self {
+        let list_property = property.filter(|p| p.data_type.element_type().is_some());
+        match aggregation {
+            Aggregation::Count => Accumulator::Count(0),
+            Aggregation::IsEmpty => Accumulator::IsEmpty(true),
+            Aggregation::Sum | Aggregation::Average => match integer_numbers(property, true) {
+                Some(integer) => Accumulator::Sum {
+                    property: property.copied(),
+                    integer,
+                    average: aggregation == Aggregation::Average,
+                    integer_sum: 0,
+                    real_sum: 0.0,
+                    count: 0,
+                },
+                None => Accumulator::Unsupported,
+            },
+            Aggregation::Min | Aggregation::Max => {
+                let string = property.is_some_and(|p| p.data_type == DataType::String);
+                if string || integer_numbers(property, true).is_some() {
+                    Accumulator::MinMax {
+                        property: property.copied(),
+                        min: aggregation == Aggregation::Min,
+                        value: None,
                     }
-                }
-                if has_value {
-                    Some(IsarValue::Integer(min_max as i64))
                 } else {
-                    None
+                    Accumulator::Unsupported
                 }
             }
-            DataType::Float => {
-                let mut min_max = if aggregate_min {
-                    f32::INFINITY
-                } else {
-                    f32::NEG_INFINITY
+            Aggregation::CountDistinct => Accumulator::CountDistinct {
+                property: property.copied(),
+                hashes: IntMap::new(),
+            },
+            Aggregation::Median | Aggregation::Percentile(_)
+                if integer_numbers(property, false).is_some() =>
+            {
+                let percentile = match aggregation {
+                    Aggregation::Percentile(percentile) => percentile.value(),
+                    _ => 0.5,
                 };
-                let mut has_value = false;
-                for (_, reader) in iterator {
-                    let value = reader.read_float(property.offset);
-                    if value.partial_cmp(&min_max) == Some(min_max_cmp) {
-                        min_max = value;
-                        has_value = true;
-                    }
-                }
-                if has_value {
-                    Some(IsarValue::Real(min_max as f64))
-                } else {
-                    None
+                Accumulator::Percentile {
+                    property: property.copied(),
+                    percentile,
+                    values: vec![],
                 }
             }
-            DataType::Long => {
-                let mut min_max = if aggregate_min { i64::MAX } else { i64::MIN };
-                let mut has_value = false;
-                for (_, reader) in iterator {
-                    let value = reader.read_long(property.offset);
-                    if value != NULL_LONG && value.cmp(&min_max) == min_max_cmp {
-                        min_max = value;
-                        has_value = true;
-                    }
+            Aggregation::StdDev | Aggregation::Variance
+                if integer_numbers(property, false).is_some() =>
+            {
+                Accumulator::Variance {
+                    property: property.copied(),
+                    std_dev: aggregation == Aggregation::StdDev,
+                    variance: Variance::default(),
                 }
-                if has_value {
-                    Some(IsarValue::Integer(min_max))
-                } else {
-                    None
+            }
+            Aggregation::ElementCount if list_property.is_some() => Accumulator::ElementCount {
+                property: *list_property.unwrap(),
+                count: 0,
+            },
+            Aggregation::MinLength | Aggregation::MaxLength | Aggregation::AverageLength
+                if list_property.is_some() =>
+            {
+                Accumulator::Length {
+                    property: *list_property.unwrap(),
+                    aggregation,
+                    min: None,
+                    max: None,
+                    sum: 0,
+                    count: 0,
                 }
             }
-            DataType::Double => {
-                let mut min_max = if aggregate_min {
-                    f64::INFINITY
+            _ => Accumulator::Unsupported,
+        }
+    }
+
+    /// Whether the result is known and adding more objects does not change it.
+    pub fn is_complete(&self) -> bool {
+        matches!(self, Accumulator::Unsupported | Accumulator::IsEmpty(false))
+    }
+
+    pub fn add(&mut self, id: i64, object: IsarDeserializer) {
+        match self {
+            Accumulator::Unsupported => {}
+            Accumulator::Count(count) => *count += 1,
+            Accumulator::IsEmpty(empty) => *empty = false,
+            Accumulator::Sum {
+                property,
+                integer_sum,
+                real_sum,
+                count,
+                ..
+            } => for_each_number(id, object, property.as_ref(), |value| {
+                match value {
+                    Number::Integer(value) => *integer_sum += value,
+                    Number::Real(value) => *real_sum += value,
+                }
+                *count += 1;
+            }),
+            Accumulator::MinMax {
+                property,
+                min,
+                value,
+            } => {
+                let wanted = if *min {
+                    Ordering::Less
                 } else {
-                    f64::NEG_INFINITY
+                    Ordering::Greater
                 };
-                let mut has_value = false;
-                for (_, reader) in iterator {
-                    let value = reader.read_double(property.offset);
-                    if value.partial_cmp(&min_max) == Some(min_max_cmp) {
-                        min_max = value;
-                        has_value = true;
+                match property {
+                    Some(property) if property.data_type == DataType::String => {
+                        let Some(new) = object.read_string(property.offset) else {
+                            return;
+                        };
+                        let replace = match value {
+                            Some(IsarValue::String(current)) => new.cmp(current) == wanted,
+                            _ => true,
+                        };
+                        if replace {
+                            *value = Some(IsarValue::String(new.to_string()));
+                        }
                     }
-                }
-                if has_value {
-                    Some(IsarValue::Real(min_max))
-                } else {
-                    None
+                    _ => for_each_number(id, object, property.as_ref(), |new| {
+                        let ordering = match (new, value.as_ref()) {
+                            (Number::Integer(new), Some(IsarValue::Integer(current))) => {
+                                Some(new.cmp(current))
+                            }
+                            (Number::Real(new), Some(IsarValue::Real(current))) => {
+                                new.partial_cmp(current)
+                            }
+                            _ => Some(wanted),
+                        };
+                        if ordering == Some(wanted) {
+                            *value = Some(new.to_value());
+                        }
+                    }),
                 }
             }
-            DataType::String => {
-                let mut min_max = if aggregate_min {
-                    "\u{10FFFF}".to_string()
+            Accumulator::CountDistinct { property, hashes } => {
+                let hash = if let Some(property) = property {
+                    if object.is_null(property.offset, property.data_type) {
+                        return;
+                    }
+                    object.hash_property(property.offset, property.data_type, true, 0)
                 } else {
-                    String::new()
+                    id as u64
                 };
-                let mut has_value = false;
-                for (_, reader) in iterator {
-                    let value = reader.read_string(property.offset);
-                    if let Some(value) = value {
-                        if value.cmp(&min_max) == min_max_cmp {
-                            min_max = value.to_string();
-                            has_value = true;
+                hashes.insert(hash, ());
+            }
+            Accumulator::Percentile {
+                property, values, ..
+            } => for_each_number(id, object, property.as_ref(), |value| {
+                values.push(value.to_f64())
+            }),
+            Accumulator::Variance {
+                property, variance, ..
+            } => for_each_number(id, object, property.as_ref(), |value| {
+                variance.add(value.to_f64())
+            }),
+            Accumulator::ElementCount { property, count } => {
+                let element_type = property.data_type.element_type().unwrap();
+                let element_size = element_type.static_size() as u32;
+                if let Some((list, length)) = object.read_list(property.offset, element_type) {
+                    for i in 0..length {
+                        if !list.is_null(i * element_size, element_type) {
+                            *count += 1;
                         }
                     }
                 }
-                if has_value {
-                    Some(IsarValue::String(min_max))
-                } else {
-                    None
+            }
+            Accumulator::Length {
+                property,
+                min,
+                max,
+                sum,
+                count,
+                ..
+            } => {
+                let element_type = property.data_type.element_type().unwrap();
+                if let Some((_, length)) = object.read_list(property.offset, element_type) {
+                    let length = length as i64;
+                    *min = Some(min.map_or(length, |min| min.min(length)));
+                    *max = Some(max.map_or(length, |max| max.max(length)));
+                    *sum += length;
+                    *count += 1;
                 }
             }
-            _ => None,
         }
-    } else {
-        let mut min_max = if aggregate_min { i64::MAX } else { i64::MIN };
-        let mut has_value = false;
-        for (id, _) in iterator {
-            if id != NULL_LONG && id.cmp(&min_max) == min_max_cmp {
-                min_max = id;
-                has_value = true;
+    }
+
+    pub fn finish(self) -> Option<IsarValue> {
+        match self {
+            Accumulator::Unsupported => None,
+            Accumulator::Count(count) => Some(IsarValue::Integer(count)),
+            Accumulator::IsEmpty(empty) => Some(IsarValue::Bool(empty)),
+            Accumulator::Sum {
+                integer,
+                average,
+                integer_sum,
+                real_sum,
+                count,
+                ..
+            } => {
+                let sum = if integer {
+                    Number::Integer(integer_sum)
+                } else {
+                    Number::Real(real_sum)
+                };
+                if !average {
+                    Some(sum.to_value())
+                } else if count > 0 {
+                    Some(IsarValue::Real(sum.to_f64() / count as f64))
+                } else {
+                    Some(IsarValue::Real(f64::NAN))
+                }
             }
-        }
-        if has_value {
-            Some(IsarValue::Integer(min_max))
-        } else {
-            None
+            Accumulator::MinMax { value, .. } => value,
+            Accumulator::CountDistinct { hashes, .. } => {
+                Some(IsarValue::Integer(hashes.len() as i64))
+            }
+            Accumulator::Percentile {
+                percentile: percentile_value,
+                mut values,
+                ..
+            } => percentile(&mut values, percentile_value).map(IsarValue::Real),
+            Accumulator::Variance {
+                std_dev, variance, ..
+            } => {
+                let variance = variance.variance()?;
+                if std_dev {
+                    Some(IsarValue::Real(variance.sqrt()))
+                } else {
+                    Some(IsarValue::Real(variance))
+                }
+            }
+            Accumulator::ElementCount { count, .. } => Some(IsarValue::Integer(count)),
+            Accumulator::Length {
+                aggregation,
+                min,
+                max,
+                sum,
+                count,
+                ..
+            } => match aggregation {
+                Aggregation::MinLength => min.map(IsarValue::Integer),
+                Aggregation::MaxLength => max.map(IsarValue::Integer),
+                _ => (count > 0).then(|| IsarValue::Real(sum as f64 / count as f64)),
+            },
         }
     }
 }
@@ -278,7 +442,7 @@ mod tests {
         let deserializer = create_deserializer(1, |s| s.write_byte(0, 5));
         let iterator = vec![(0, deserializer)];
         assert_eq!(
-            aggregate_sum_average(iterator.into_iter(), Some(&property), true),
+            aggregate(iterator.into_iter(), Some(&property), Aggregation::Sum),
             Some(IsarValue::Integer(5))
         );
 
@@ -287,7 +451,7 @@ mod tests {
         let deserializer2 = create_deserializer(1, |s| s.write_byte(0, 20));
         let iterator = vec![(0, deserializer1), (0, deserializer2)];
         assert_eq!(
-            aggregate_sum_average(iterator.into_iter(), Some(&property), false),
+            aggregate(iterator.into_iter(), Some(&property), Aggregation::Average),
             Some(IsarValue::Real(15.0))
         );
     }
@@ -302,7 +466,7 @@ mod tests {
         let deserializer3 = create_deserializer(4, |s| s.write_int(0, 50));
         let iterator = vec![(0, deserializer1), (0, deserializer2), (0, deserializer3)];
         assert_eq!(
-            aggregate_sum_average(iterator.into_iter(), Some(&property), true),
+            aggregate(iterator.into_iter(), Some(&property), Aggregation::Sum),
             Some(IsarValue::Integer(150))
         );
 
@@ -312,7 +476,7 @@ mod tests {
         let deserializer3 = create_deserializer(4, |s| s.write_int(0, 20));
         let iterator = vec![(0, deserializer1), (0, deserializer2), (0, deserializer3)];
         assert_eq!(
-            aggregate_sum_average(iterator.into_iter(), Some(&property), false),
+            aggregate(iterator.into_iter(), Some(&property), Aggregation::Average),
             Some(IsarValue::Real(5.0))
         );
     }
@@ -327,13 +491,13 @@ mod tests {
         let deserializer3 = create_deserializer(4, |s| s.write_float(0, 2.5));
         let iterator = vec![(0, deserializer1), (0, deserializer2), (0, deserializer3)];
         assert_eq!(
-            aggregate_sum_average(iterator.into_iter(), Some(&property), true),
+            aggregate(iterator.into_iter(), Some(&property), Aggregation::Sum),
             Some(IsarValue::Real(4.0))
         );
 
         // Test average with empty iterator
         let iterator = Vec::<(i64, IsarDeserializer)>::new();
-        let result = aggregate_sum_average(iterator.into_iter(), Some(&property), false);
+        let result = aggregate(iterator.into_iter(), Some(&property), Aggregation::Average);
         assert!(result.unwrap().real().unwrap().is_nan());
     }
 
@@ -347,7 +511,7 @@ mod tests {
         let deserializer3 = create_deserializer(4, |s| s.write_int(0, 50));
         let iterator = vec![(0, deserializer1), (0, deserializer2), (0, deserializer3)];
         assert_eq!(
-            aggregate_min_max(iterator.into_iter(), Some(&property), true),
+            aggregate(iterator.into_iter(), Some(&property), Aggregation::Min),
             Some(IsarValue::Integer(50))
         );
 
@@ -357,7 +521,7 @@ mod tests {
         let deserializer3 = create_deserializer(4, |s| s.write_int(0, 20));
         let iterator = vec![(0, deserializer1), (0, deserializer2), (0, deserializer3)];
         assert_eq!(
-            aggregate_min_max(iterator.into_iter(), Some(&property), false),
+            aggregate(iterator.into_iter(), Some(&property), Aggregation::Max),
             Some(IsarValue::Integer(20))
         );
     }
@@ -372,7 +536,7 @@ mod tests {
         let deserializer3 = create_deserializer(3, |s| s.write_dynamic(0, b"apple"));
         let iterator = vec![(0, deserializer1), (0, deserializer2), (0, deserializer3)];
         assert_eq!(
-            aggregate_min_max(iterator.into_iter(), Some(&property), true),
+            aggregate(iterator.into_iter(), Some(&property), Aggregation::Min),
             Some(IsarValue::String("apple".to_string()))
         );
 
@@ -382,7 +546,7 @@ mod tests {
         let deserializer3 = create_deserializer(3, |s| s.write_dynamic(0, b"dog"));
         let iterator = vec![(0, deserializer1), (0, deserializer2), (0, deserializer3)];
         assert_eq!(
-            aggregate_min_max(iterator.into_iter(), Some(&property), false),
+            aggregate(iterator.into_iter(), Some(&property), Aggregation::Max),
             Some(IsarValue::String("dog".to_string()))
         );
     }
@@ -394,7 +558,7 @@ mod tests {
         // Test empty iterator
         let iterator = Vec::<(i64, IsarDeserializer)>::new();
         assert_eq!(
-            aggregate_min_max(iterator.into_iter(), Some(&property), true),
+            aggregate(iterator.into_iter(), Some(&property), Aggregation::Min),
             None
         );
     }
@@ -407,7 +571,7 @@ mod tests {
             (2, IsarDeserializer::from_bytes(&[0, 0, 0])),
         ];
         assert_eq!(
-            aggregate_sum_average(iterator.into_iter(), None, true),
+            aggregate(iterator.into_iter(), None, Aggregation::Sum),
             Some(IsarValue::Integer(3))
         );
 
@@ -417,7 +581,7 @@ mod tests {
             (20, IsarDeserializer::from_bytes(&[0, 0, 0])),
         ];
         assert_eq!(
-            aggregate_sum_average(iterator.into_iter(), None, false),
+            aggregate(iterator.into_iter(), None, Aggregation::Average),
             Some(IsarValue::Real(15.0))
         );
 
@@ -427,7 +591,7 @@ mod tests {
             (50, IsarDeserializer::from_bytes(&[0, 0, 0])),
         ];
         assert_eq!(
-            aggregate_min_max(iterator.into_iter(), None, true),
+            aggregate(iterator.into_iter(), None, Aggregation::Min),
             Some(IsarValue::Integer(50))
         );
 
@@ -437,8 +601,170 @@ mod tests {
             (50, IsarDeserializer::from_bytes(&[0, 0, 0])),
         ];
         assert_eq!(
-            aggregate_min_max(iterator.into_iter(), None, false),
+            aggregate(iterator.into_iter(), None, Aggregation::Max),
             Some(IsarValue::Integer(100))
         );
     }
+
+    #[test]
+    fn test_aggregate_count_distinct() {
+        let property = create_property(DataType::Int);
+        let iterator = vec![
+            (1, create_deserializer(4, |s| s.write_int(0, 5))),
+            (
+                2,
+                create_deserializer(4, |s| s.write_null(0, DataType::Int)),
+            ),
+            (3, create_deserializer(4, |s| s.write_int(0, 7))),
+            (4, create_deserializer(4, |s| s.write_int(0, 5))),
+        ];
+        assert_eq!(
+            aggregate(
+                iterator.into_iter(),
+                Some(&property),
+                Aggregation::CountDistinct
+            ),
+            Some(IsarValue::Integer(2))
+        );
+    }
+
+    #[test]
+    fn test_aggregate_percentile() {
+        let property = create_property(DataType::Long);
+
+        // Test median with nulls
+        let iterator = vec![
+            (0, create_deserializer(8, |s| s.write_long(0, 30))),
+            (
+                0,
+                create_deserializer(8, |s| s.write_null(0, DataType::Long)),
+            ),
+            (0, create_deserializer(8, |s| s.write_long(0, 10))),
+            (0, create_deserializer(8, |s| s.write_long(0, 20))),
+        ];
+        assert_eq!(
+            aggregate(iterator.into_iter(), Some(&property), Aggregation::Median),
+            Some(IsarValue::Real(20.0))
+        );
+
+        // Test empty iterator
+        let iterator = Vec::<(i64, IsarDeserializer)>::new();
+        assert_eq!(
+            aggregate(iterator.into_iter(), Some(&property), Aggregation::Median),
+            None
+        );
+
+        // Test non-numeric property
+        let property = create_property(DataType::String);
+        let iterator = vec![(0, create_deserializer(3, |s| s.write_dynamic(0, b"a")))];
+        assert_eq!(
+            aggregate(iterator.into_iter(), Some(&property), Aggregation::Median),
+            None
+        );
+    }
+
+    #[test]
+    fn test_aggregate_variance() {
+        let property = create_property(DataType::Double);
+        let iterator = || {
+            vec![
+                (0, create_deserializer(8, |s| s.write_double(0, 1.0))),
+                (0, create_deserializer(8, |s| s.write_double(0, f64::NAN))),
+                (0, create_deserializer(8, |s| s.write_double(0, 3.0))),
+            ]
+            .into_iter()
+        };
+        assert_eq!(
+            aggregate(iterator(), Some(&property), Aggregation::Variance),
+            Some(IsarValue::Real(2.0))
+        );
+        assert_eq!(
+            aggregate(iterator(), Some(&property), Aggregation::StdDev),
+            Some(IsarValue::Real(2f64.sqrt()))
+        );
+    }
+
+    fn create_int_list(values: Option<&[i32]>) -> IsarDeserializer<'static> {
+        create_deserializer(3, |s| match values {
+            Some(values) => {
+                let mut list = s.begin_nested(0, values.len() as u32 * 4);
+                for (i, value) in values.iter().enumerate() {
+                    list.write_int(i as u32 * 4, *value);
+                }
+                s.end_nested(list);
+            }
+            None => s.write_null(0, DataType::IntList),
+        })
+    }
+
+    #[test]
+    fn test_aggregate_list_elements() {
+        let property = create_property(DataType::IntList);
+        let iterator = || {
+            vec![
+                (0, create_int_list(Some(&[3, NULL_INT, -2]))),
+                (0, create_int_list(None)),
+                (0, create_int_list(Some(&[]))),
+                (0, create_int_list(Some(&[7]))),
+            ]
+            .into_iter()
+        };
+        assert_eq!(
+            aggregate(iterator(), Some(&property), Aggregation::Sum),
+            Some(IsarValue::Integer(8))
+        );
+        assert_eq!(
+            aggregate(iterator(), Some(&property), Aggregation::Average),
+            Some(IsarValue::Real(8.0 / 3.0))
+        );
+        assert_eq!(
+            aggregate(iterator(), Some(&property), Aggregation::Min),
+            Some(IsarValue::Integer(-2))
+        );
+        assert_eq!(
+            aggregate(iterator(), Some(&property), Aggregation::Max),
+            Some(IsarValue::Integer(7))
+        );
+        assert_eq!(
+            aggregate(iterator(), Some(&property), Aggregation::ElementCount),
+            Some(IsarValue::Integer(3))
+        );
+    }
+
+    #[test]
+    fn test_aggregate_length() {
+        let property = create_property(DataType::IntList);
+        let iterator = || {
+            vec![
+                (0, create_int_list(Some(&[1, 2, 3]))),
+                (0, create_int_list(None)),
+                (0, create_int_list(Some(&[]))),
+            ]
+            .into_iter()
+        };
+        assert_eq!(
+            aggregate(iterator(), Some(&property), Aggregation::MinLength),
+            Some(IsarValue::Integer(0))
+        );
+        assert_eq!(
+            aggregate(iterator(), Some(&property), Aggregation::MaxLength),
+            Some(IsarValue::Integer(3))
+        );
+        assert_eq!(
+            aggregate(iterator(), Some(&property), Aggregation::AverageLength),
+            Some(IsarValue::Real(1.5))
+        );
+
+        // Test non-list property
+        let property = create_property(DataType::Int);
+        let iterator = vec![(0, create_deserializer(4, |s| s.write_int(0, 1)))];
+        assert_eq!(
+            aggregate(
+                iterator.into_iter(),
+                Some(&property),
+                Aggregation::MaxLength
+            ),
+            None
+        );
+    }
 }
@@ -1,9 +1,10 @@
 use super::QueryIndex;
+use crate::core::error::{IsarError, Result};
+use crate::native::BytesToId;
 use crate::native::isar_deserializer::IsarDeserializer;
 use crate::native::mdbx::cursor_iterator::CursorIterator;
 use crate::native::native_collection::NativeCollection;
 use crate::native::native_txn::{NativeTxn, TxnCursor};
-use crate::native::BytesToId;
 
 pub(crate) struct IndexIterator<'a> {
     txn: &'a NativeTxn,
@@ -20,77 +21,152 @@ impl<'a> IndexIterator<'a> {
         txn: &'a NativeTxn,
         collection: &'a NativeCollection,
         indexes: &[QueryIndex],
-    ) -> Self {
+    ) -> Result<Self> {
         let mut indexes = indexes.to_vec();
         indexes.reverse();
-        if let Some((iterator, primary_cursor)) =
-            Self::next_iterator(txn, collection, None, &mut indexes)
-        {
-            Self {
-                txn,
-                collection,
-                iterator: Some(iterator),
-                primary_cursor,
-                indexes,
-            }
-        } else {
-            Self {
-                txn,
-                collection,
-                iterator: None,
-                primary_cursor: None,
-                indexes,
-            }
-        }
+        let (iterator, primary_cursor) =
+            match Self::next_iterator(txn, collection, None, &mut indexes)? {
+                Some((iterator, primary_cursor)) => (Some(iterator), primary_cursor),
+                None => (None, None),
+            };
+        Ok(Self {
+            txn,
+            collection,
+            iterator,
+            primary_cursor,
+            indexes,
+        })
     }
 
+    #[allow(clippy::type_complexity)]
     fn next_iterator<'b>(
         txn: &'b NativeTxn,
         collection: &'b NativeCollection,
         primary_cursor: Option<TxnCursor<'b>>,
         indexes: &mut Vec<QueryIndex>,
-    ) -> Option<(CursorIterator<'b, TxnCursor<'b>>, Option<TxnCursor<'b>>)> {
-        let next_index = indexes.pop();
-        if let Some(QueryIndex::Primary(start, end)) = next_index {
-            let cursor = if let Some(primary_cursor) = primary_cursor {
-                primary_cursor
+    ) -> Result<Option<(CursorIterator<'b, TxnCursor<'b>>, Option<TxnCursor<'b>>)>> {
+        let get_primary_cursor = move || {
+            if let Some(primary_cursor) = primary_cursor {
+                Ok(primary_cursor)
             } else {
-                collection.get_cursor(txn).ok()?
-            };
-            let iterator = cursor.iter_between_ids(start, end, false, false).ok()?;
-            Some((iterator, None))
-        } else if let Some(QueryIndex::Secondary(start, end)) = next_index {
-            todo!()
-        } else {
-            None
+                collection.get_cursor(txn)
+            }
+        };
+        match indexes.pop() {
+            Some(QueryIndex::Primary(start, end)) => {
+                let cursor = get_primary_cursor()?;
+                let iterator = cursor.iter_between_ids(start, end, false, false)?;
+                Ok(Some((iterator, None)))
+            }
+            Some(QueryIndex::Secondary(index, start, end)) => {
+                let index = collection
+                    .indexes
+                    .get(index as usize)
+                    .ok_or(IsarError::IllegalArgument {})?;
+                let iterator = index.iter_between(txn, start, end)?;
+                Ok(Some((iterator, Some(get_primary_cursor()?))))
+            }
+            None => Ok(None),
         }
     }
 }
 
 impl<'a> Iterator for IndexIterator<'a> {
-    type Item = (i64, IsarDeserializer<'a>);
+    type Item = Result<(i64, IsarDeserializer<'a>)>;
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        let next = self.iterator.as_mut()?.next();
-        if let Some((key, value)) = next {
-            if let Some(primary_cursor) = &mut self.primary_cursor {
-                let (id, object) = primary_cursor.move_to(value).ok()??;
-                return Some((id.to_id(), IsarDeserializer::from_bytes(object)));
+        loop {
+            let next = self.iterator.as_mut()?.next();
+            if let Some((key, value)) = next {
+                let Some(primary_cursor) = &mut self.primary_cursor else {
+                    return Some(Ok((key.to_id(), IsarDeserializer::from_bytes(value))));
+                };
+                match primary_cursor.move_to(value) {
+                    Ok(Some((id, object))) => {
+                        return Some(Ok((id.to_id(), IsarDeserializer::from_bytes(object))));
+                    }
+                    // stale index entries without an object are skipped
+                    Ok(None) => continue,
+                    Err(err) => {
+                        self.iterator = None;
+                        return Some(Err(err));
+                    }
+                }
             } else {
-                return Some((key.to_id(), IsarDeserializer::from_bytes(value)));
+                let iterator = self.iterator.take();
+                let primary_cursor = if let Some(primary_cursor) = self.primary_cursor.take() {
+                    Some(primary_cursor)
+                } else {
+                    iterator.map(|i| i.close())
+                };
+                match Self::next_iterator(
+                    self.txn,
+                    self.collection,
+                    primary_cursor,
+                    &mut self.indexes,
+                ) {
+                    Ok(Some((iterator, primary_cursor))) => {
+                        self.iterator = Some(iterator);
+                        self.primary_cursor = primary_cursor;
+                    }
+                    Ok(None) => return None,
+                    Err(err) => return Some(Err(err)),
+                }
             }
-        } else {
-            let primary_cursor = if let Some(primary_cursor) = self.primary_cursor.take() {
-                Some(primary_cursor)
-            } else {
-                self.iterator.take().map(|i| i.close())
-            };
-            let (iterator, primary_cursor) =
-                Self::next_iterator(self.txn, self.collection, primary_cursor, &mut self.indexes)?;
-            self.iterator = Some(iterator);
-            self.primary_cursor = primary_cursor;
-            self.next()
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::data_type::DataType;
+    use crate::core::insert::IsarInsert;
+    use crate::core::instance::IsarInstance;
+    use crate::core::schema::{IndexSchema, IsarSchema, PropertySchema};
+    use crate::core::writer::IsarWriter;
+    use crate::native::IdToBytes;
+    use crate::native::index_key::IndexKey;
+    use crate::native::native_instance::NativeInstance;
+
+    #[test]
+    fn test_skip_index_entries_without_object() {
+        let schema = IsarSchema::new(
+            "test",
+            Some("id"),
+            vec![PropertySchema::new("value", DataType::Long, None)],
+            vec![IndexSchema::new("value", vec!["value"], false, false, true)],
+            false,
+        );
+        let instance = NativeInstance::open_temp("index_iterator", vec![schema]).unwrap();
+        let txn = instance.begin_txn(true).unwrap();
+        let mut insert = instance.insert(txn, 0, 4).unwrap();
+        for id in 1..=4 {
+            insert.write_long(1, id);
+            insert.save(id).unwrap();
+        }
+        let txn = insert.finish().unwrap();
+
+        // delete objects 1 and 3 without their index entries
+        let collection = instance.get_collection(0).unwrap();
+        {
+            let mut cursor = collection.get_cursor(&txn).unwrap();
+            for id in [1i64, 3] {
+                cursor.move_to(&id.to_id_bytes()).unwrap().unwrap();
+                cursor.delete_current().unwrap();
+            }
+        }
+
+        let range = QueryIndex::Secondary(0, IndexKey::min(), IndexKey::max());
+        let ids = IndexIterator::new(&txn, collection, &[range])
+            .unwrap()
+            .map(|next| next.map(|(id, _)| id))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(ids, vec![2, 4]);
+
+        instance.abort_txn(txn);
+        NativeInstance::close(instance, true);
+    }
+}
@@ -1,16 +1,28 @@
-use self::aggregate::{aggregate_min_max, aggregate_sum_average};
+use self::aggregate::{Accumulator, aggregate};
 use self::native_filter::NativeFilter;
 use self::query_iterator::QueryIterator;
 use super::index_key::IndexKey;
 use super::isar_deserializer::IsarDeserializer;
 use super::native_collection::{NativeCollection, NativeProperty};
+use super::native_index::NativeIndex;
+use super::native_query_builder::build_filter;
 use super::native_reader::NativeReader;
 use super::native_txn::NativeTxn;
+use super::{IdToBytes, NULL_INT, NULL_LONG};
 use crate::core::cursor::IsarQueryCursor;
-use crate::core::instance::Aggregation;
+use crate::core::data_type::DataType;
+use crate::core::error::{IsarError, Result};
+use crate::core::filter::Filter;
+use crate::core::instance::{Aggregation, AggregationGroup};
+use crate::core::page_token::PageToken;
 use crate::core::query_builder::Sort;
+use crate::core::query_plan::{QueryPlan, QueryPlanRange};
 use crate::core::value::IsarValue;
 use crate::core::watcher::QueryMatches;
+use intmap::IntMap;
+use itertools::Itertools;
+use serde_json::json;
+use xxhash_rust::xxh3::xxh3_64;
 
 mod aggregate;
 mod index_iterator;
@@ -20,10 +32,311 @@ mod sorted_query_iterator;
 mod unsorted_distinct_query_iterator;
 mod unsorted_query_iterator;
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Debug)]
 pub(crate) enum QueryIndex {
     Primary(i64, i64),
-    Secondary(IndexKey, IndexKey),
+    /// Range of the index at the given position in `NativeCollection::indexes`.
+    Secondary(u16, IndexKey, IndexKey),
+}
+
+impl QueryIndex {
+    /// Merges overlapping ascending ranges of the same index so that every entry is only
+    /// visited once.
+    pub(crate) fn merge(indexes: Vec<QueryIndex>) -> Vec<QueryIndex> {
+        let mut primary = vec![];
+        let mut secondary = vec![];
+        let mut other = vec![];
+        for index in indexes {
+            match index {
+                QueryIndex::Primary(lower, upper) if lower <= upper => primary.push((lower, upper)),
+                QueryIndex::Secondary(index, lower, upper) if lower <= upper => {
+                    secondary.push((index, lower, upper))
+                }
+                index => other.push(index),
+            }
+        }
+        primary.sort_unstable();
+        secondary.sort_unstable_by(|(i1, l1, _), (i2, l2, _)| (i1, l1).cmp(&(i2, l2)));
+
+        let mut merged = vec![];
+        for (lower, upper) in primary {
+            if let Some(QueryIndex::Primary(_, last_upper)) = merged.last_mut()
+                && lower <= last_upper.saturating_add(1)
+            {
+                *last_upper = (*last_upper).max(upper);
+                continue;
+            }
+            merged.push(QueryIndex::Primary(lower, upper));
+        }
+        for (index, lower, upper) in secondary {
+            if let Some(QueryIndex::Secondary(last_index, _, last_upper)) = merged.last_mut()
+                && *last_index == index
+                && lower <= *last_upper
+            {
+                if upper > *last_upper {
+                    *last_upper = upper;
+                }
+                continue;
+            }
+            merged.push(QueryIndex::Secondary(index, lower, upper));
+        }
+        merged.extend(other);
+        merged
+    }
+
+    /// Whether an object may be returned by more than one of the (merged) ranges.
+    pub(crate) fn has_duplicates(indexes: &[QueryIndex]) -> bool {
+        let target = |index: &QueryIndex| match index {
+            QueryIndex::Primary(..) => None,
+            QueryIndex::Secondary(index, ..) => Some(*index),
+        };
+        let descending = |index: &QueryIndex| match index {
+            QueryIndex::Primary(lower, upper) => lower > upper,
+            QueryIndex::Secondary(_, lower, upper) => lower > upper,
+        };
+        indexes.len() > 1
+            && (indexes.iter().any(|i| target(i) != target(&indexes[0]))
+                || indexes.iter().any(descending))
+    }
+
+    /// Returns ranges that iterate the objects in the given sort order or `None` if the
+    /// results have to be sorted. Ascending id ranges match id sorts and ascending ranges of an
+    /// index match sorts by its leading properties. Descending sorts iterate the ranges
+    /// backwards. Full scans use a matching index if there is one.
+    pub(crate) fn sorted_ranges(
+        indexes: &[QueryIndex],
+        collection: &NativeCollection,
+        sort: &[(Option<NativeProperty>, Sort, bool)],
+    ) -> Option<Vec<QueryIndex>> {
+        let Some((first_property, direction, _)) = sort.first() else {
+            return Some(indexes.to_vec());
+        };
+        if sort.iter().any(|(_, s, _)| s != direction) || QueryIndex::has_duplicates(indexes) {
+            return None;
+        }
+        // The entries of an index key are ordered by id so an id sort after all properties of
+        // the index is implied.
+        let (sort, by_id) = match sort.split_last() {
+            Some(((None, _, _), properties)) if !properties.is_empty() => (properties, true),
+            _ => (sort, false),
+        };
+        let matches_sort = |index: &NativeIndex| {
+            Self::index_matches_sort(index, sort)
+                && (!by_id || index.properties.len() == sort.len())
+        };
+        let mut ranges = match (indexes.first(), first_property) {
+            (Some(QueryIndex::Primary(..)), None)
+                if indexes
+                    .iter()
+                    .all(|i| matches!(i, QueryIndex::Primary(lower, upper) if lower <= upper)) =>
+            {
+                indexes.to_vec()
+            }
+            (Some(QueryIndex::Secondary(index, ..)), Some(_))
+                if collection
+                    .indexes
+                    .get(*index as usize)
+                    .is_some_and(matches_sort)
+                    && indexes.iter().all(
+                        |i| matches!(i, QueryIndex::Secondary(_, lower, upper) if lower <= upper),
+                    ) =>
+            {
+                indexes.to_vec()
+            }
+            (Some(QueryIndex::Primary(i64::MIN, i64::MAX)), Some(_)) if indexes.len() == 1 => {
+                // only non-unique indexes without a filter contain every object exactly once
+                let index = collection.indexes.iter().position(|index| {
+                    !index.unique && index.filter.is_none() && matches_sort(index)
+                })?;
+                let mut upper = IndexKey::min();
+                upper.add_max_bytes(IndexKey::MAX_INDEX_SIZE);
+                vec![QueryIndex::Secondary(index as u16, IndexKey::min(), upper)]
+            }
+            _ => return None,
+        };
+
+        if *direction == Sort::Desc {
+            ranges.reverse();
+            for range in &mut ranges {
+                match range {
+                    QueryIndex::Primary(lower, upper) => std::mem::swap(lower, upper),
+                    QueryIndex::Secondary(_, lower, upper) => std::mem::swap(lower, upper),
+                }
+            }
+        }
+        Some(ranges)
+    }
+
+    /// Clips ascending ranges that are ordered by the first sort property so that they start at
+    /// the position of the page token. Full scans use a matching index to seek the position.
+    pub(crate) fn seek(
+        indexes: &[QueryIndex],
+        collection: &NativeCollection,
+        first: &(Option<NativeProperty>, Sort, bool),
+        token: &PageToken,
+    ) -> Vec<QueryIndex> {
+        let (property, direction, _) = first;
+        let Some(property) = property else {
+            return indexes
+                .iter()
+                .filter_map(|index| match index {
+                    QueryIndex::Primary(lower, upper) if lower <= upper => {
+                        let (lower, upper) = if *direction == Sort::Asc {
+                            ((*lower).max(token.id.checked_add(1)?), *upper)
+                        } else {
+                            (*lower, (*upper).min(token.id.checked_sub(1)?))
+                        };
+                        (lower <= upper).then_some(QueryIndex::Primary(lower, upper))
+                    }
+                    index => Some(index.clone()),
+                })
+                .collect();
+        };
+
+        let seekable = |index: &NativeIndex| Self::index_starts_with(index, first);
+        let indexes =
+            match indexes {
+                [QueryIndex::Primary(i64::MIN, i64::MAX)] => {
+                    let Some(index) = collection.indexes.iter().position(|index| {
+                        !index.unique && index.filter.is_none() && seekable(index)
+                    }) else {
+                        return indexes.to_vec();
+                    };
+                    let mut upper = IndexKey::min();
+                    upper.add_max_bytes(IndexKey::MAX_INDEX_SIZE);
+                    vec![QueryIndex::Secondary(index as u16, IndexKey::min(), upper)]
+                }
+                _ => indexes.to_vec(),
+            };
+
+        let value = token.values.first().and_then(|v| v.as_ref());
+        let seek_key = |index: &NativeIndex| {
+            let mut key = IndexKey::min();
+            match property.data_type {
+                DataType::Bool => key.add_bool(value.and_then(|v| v.bool())),
+                DataType::Byte => key.add_byte(value.and_then(|v| v.u8()).unwrap_or(0)),
+                DataType::Int => key.add_int(value.and_then(|v| v.i32()).unwrap_or(NULL_INT)),
+                DataType::Long => key.add_long(value.and_then(|v| v.i64()).unwrap_or(NULL_LONG)),
+                // null is stored as NaN which has the smallest key but is sorted last
+                DataType::Float => key.add_float(value?.real()? as f32),
+                DataType::Double => key.add_double(value?.real()?),
+                DataType::String => {
+                    let value = value.and_then(|v| v.string());
+                    if index.is_delimited(0) {
+                        key.add_delimited_string(value, index.case_sensitive);
+                    } else if value.is_some_and(|v| v.is_empty() || v.starts_with('\0')) {
+                        // the keys of strings starting with a zero byte are smaller than the
+                        // key of the empty string
+                        return None;
+                    } else {
+                        key.add_string(value, index.case_sensitive);
+                    }
+                }
+                _ => return None,
+            }
+            Some(key)
+        };
+        // the keys of null reals are before the keys of the values that are sorted before them
+        let null_upper = match property.data_type {
+            DataType::Float | DataType::Double if *direction == Sort::Asc => {
+                let mut key = IndexKey::min();
+                if property.data_type == DataType::Float {
+                    key.add_float(f32::NAN);
+                } else {
+                    key.add_double(f64::NAN);
+                }
+                key.add_max_bytes(IndexKey::MAX_INDEX_SIZE);
+                Some(key)
+            }
+            _ => None,
+        };
+
+        indexes
+            .into_iter()
+            .flat_map(|index| match index {
+                QueryIndex::Secondary(i, lower, upper) if lower <= upper => {
+                    let key = collection
+                        .indexes
+                        .get(i as usize)
+                        .filter(|index| seekable(index))
+                        .and_then(seek_key);
+                    let Some(key) = key else {
+                        return vec![QueryIndex::Secondary(i, lower, upper)];
+                    };
+                    let mut ranges = vec![];
+                    if *direction == Sort::Asc {
+                        if let Some(null_upper) = &null_upper {
+                            let null_upper = upper.clone().min(null_upper.clone());
+                            ranges.push(QueryIndex::Secondary(i, lower.clone(), null_upper));
+                        }
+                        ranges.push(QueryIndex::Secondary(i, lower.max(key), upper));
+                    } else {
+                        let mut upper_key = key;
+                        upper_key.add_max_bytes(IndexKey::MAX_INDEX_SIZE);
+                        ranges.push(QueryIndex::Secondary(i, lower, upper.min(upper_key)));
+                    }
+                    ranges.retain(|range| {
+                        matches!(range, QueryIndex::Secondary(_, lower, upper) if lower <= upper)
+                    });
+                    ranges
+                }
+                index => vec![index],
+            })
+            .collect()
+    }
+
+    /// Whether the keys of the index start with the first sort property. The keys of reals and
+    /// strings are mostly but not always ordered like the values so they are only used to seek.
+    fn index_starts_with(
+        index: &NativeIndex,
+        first: &(Option<NativeProperty>, Sort, bool),
+    ) -> bool {
+        let (property, _, case_sensitive) = first;
+        match property.map(|p| p.data_type) {
+            Some(DataType::Float | DataType::Double) => {}
+            Some(DataType::String) if index.case_sensitive == *case_sensitive => {}
+            _ => return Self::index_matches_sort(index, &[*first]),
+        }
+        !index.hash
+            && !index.is_multi_entry()
+            && index.json_path.is_none()
+            && index.paths.iter().all(|path| path.is_empty())
+            && index.properties.first() == property.as_ref()
+    }
+
+    /// Whether the keys of the index are ordered by the sort properties. Only fixed size
+    /// properties are considered because their keys compare like the values.
+    fn index_matches_sort(
+        index: &NativeIndex,
+        sort: &[(Option<NativeProperty>, Sort, bool)],
+    ) -> bool {
+        let sortable = |property: &NativeProperty| {
+            matches!(
+                property.data_type,
+                DataType::Bool | DataType::Byte | DataType::Int | DataType::Long
+            )
+        };
+        !index.hash
+            && !index.is_multi_entry()
+            && index.json_path.is_none()
+            && index.paths.iter().all(|path| path.is_empty())
+            && sort.len() <= index.properties.len()
+            && sort
+                .iter()
+                .zip(&index.properties)
+                .all(|((p, _, _), property)| *p == Some(*property) && sortable(property))
+    }
+
+    /// Whether the range belongs to a list index that may contain an object more than once.
+    pub(crate) fn is_multi_entry(&self, collection: &NativeCollection) -> bool {
+        match self {
+            QueryIndex::Primary(..) => false,
+            QueryIndex::Secondary(index, ..) => collection
+                .indexes
+                .get(*index as usize)
+                .is_some_and(|index| index.is_multi_entry()),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -32,8 +345,16 @@ pub struct NativeQuery {
     pub(crate) collection_index: u16,
     pub(self) indexes: Vec<QueryIndex>,
     pub(self) filter: NativeFilter,
+    // The complete filter including the conditions covered by the indexes.
+    pub(self) watch_filter: NativeFilter,
     pub(self) sort: Vec<(Option<NativeProperty>, Sort, bool)>,
     pub(self) distinct: Vec<(NativeProperty, bool)>,
+    // The filter with parameters and the index ranges the query was built from.
+    pub(self) parameters: Option<(Filter, Option<Vec<QueryIndex>>)>,
+    // Whether all parameters have been bound.
+    pub(self) bound: bool,
+    // Identifies the query in page tokens. Pages keep the fingerprint of their query.
+    pub(self) fingerprint: u64,
 }
 
 impl NativeQuery {
@@ -42,26 +363,114 @@ impl NativeQuery {
         collection_index: u16,
         indexes: Vec<QueryIndex>,
         filter: NativeFilter,
+        watch_filter: NativeFilter,
         sort: Vec<(Option<NativeProperty>, Sort, bool)>,
         distinct: Vec<(NativeProperty, bool)>,
     ) -> Self {
-        Self {
+        let query = Self {
             instance_id,
             collection_index,
-            indexes,
+            indexes: QueryIndex::merge(indexes),
             filter,
+            watch_filter,
             sort,
             distinct,
+            parameters: None,
+            bound: true,
+            fingerprint: 0,
+        };
+        query.with_fingerprint(None)
+    }
+
+    /// Fingerprints the query using the filter it was built from.
+    pub(crate) fn with_fingerprint(mut self, filter: Option<&Filter>) -> Self {
+        self.fingerprint = self.compute_fingerprint(filter);
+        self
+    }
+
+    /// Hashes a stable description of the query. The filter is the filter of the query
+    /// builder with the values of its parameters.
+    fn compute_fingerprint(&self, filter: Option<&Filter>) -> u64 {
+        let indexes = self
+            .indexes
+            .iter()
+            .map(|index| match index {
+                QueryIndex::Primary(lower, upper) => json!([lower, upper]),
+                QueryIndex::Secondary(index, lower, upper) => {
+                    json!([index, lower.hash(), upper.hash()])
+                }
+            })
+            .collect_vec();
+        let sort = self
+            .sort
+            .iter()
+            .map(|(p, sort, case_sensitive)| {
+                json!([p.map(|p| p.offset), *sort == Sort::Asc, case_sensitive])
+            })
+            .collect_vec();
+        let distinct = self
+            .distinct
+            .iter()
+            .map(|(p, case_sensitive)| json!([p.offset, case_sensitive]))
+            .collect_vec();
+        let description = json!([self.collection_index, filter, indexes, sort, distinct]);
+        xxh3_64(description.to_string().as_bytes())
+    }
+
+    /// Keeps the filter with parameters so the query can be rebuilt when they are bound.
+    pub(crate) fn with_parameters(
+        mut self,
+        filter: Filter,
+        indexes: Option<Vec<QueryIndex>>,
+    ) -> Self {
+        self.parameters = Some((filter, indexes));
+        self.bound = false;
+        self
+    }
+
+    /// Queries with parameters cannot be used until the parameters are bound.
+    pub(crate) fn verify_bound(&self) -> Result<()> {
+        if self.bound {
+            Ok(())
+        } else {
+            Err(IsarError::IllegalArgument {})
         }
     }
 
+    /// Rebuilds the filter and the index ranges of the query using the values of the
+    /// parameters.
+    pub(crate) fn bind(
+        &mut self,
+        collection: &NativeCollection,
+        all_collections: &[NativeCollection],
+        parameters: &[Vec<Option<IsarValue>>],
+    ) -> Result<()> {
+        let Some((filter, indexes)) = &self.parameters else {
+            return Err(IsarError::IllegalArgument {});
+        };
+        let bound_filter = filter.bind(parameters)?;
+        let (indexes, filter, watch_filter) = build_filter(
+            Some(&bound_filter),
+            indexes.clone(),
+            collection,
+            all_collections,
+        );
+        self.indexes = QueryIndex::merge(indexes);
+        self.filter = filter;
+        self.watch_filter = watch_filter;
+        self.bound = true;
+        self.fingerprint = self.compute_fingerprint(Some(&bound_filter));
+        Ok(())
+    }
+
     pub(crate) fn cursor<'a>(
         &'a self,
         txn: &'a NativeTxn,
         all_collections: &'a [NativeCollection],
+        properties: Option<Vec<u16>>,
         offset: Option<u32>,
         limit: Option<u32>,
-    ) -> NativeQueryCursor<'a> {
+    ) -> Result<NativeQueryCursor<'a>> {
         let collection = &all_collections[self.collection_index as usize];
         let iterator = QueryIterator::new(
             txn,
@@ -70,8 +479,95 @@ impl NativeQuery {
             false,
             offset.unwrap_or(0),
             limit.unwrap_or(u32::MAX),
-        );
-        NativeQueryCursor::new(iterator, collection, all_collections)
+        )?;
+        Ok(NativeQueryCursor::new(
+            iterator,
+            collection,
+            all_collections,
+            properties,
+        ))
+    }
+
+    /// The sort of pages. Ties are broken by the id so every object has a unique position. The
+    /// ids are sorted in the direction of the last property like the entries of an index key.
+    fn page_sort(&self) -> Vec<(Option<NativeProperty>, Sort, bool)> {
+        let mut sort = self.sort.clone();
+        if let Some(position) = sort.iter().position(|(p, _, _)| p.is_none()) {
+            sort.truncate(position + 1);
+        } else {
+            let direction = sort.last().map_or(Sort::Asc, |(_, s, _)| *s);
+            sort.push((None, direction, true));
+        }
+        sort
+    }
+
+    /// Returns a query for the objects after the page token. Distinct queries cannot be paged
+    /// because earlier pages would be needed to know which objects are duplicates.
+    pub(crate) fn page(&self, collection: &NativeCollection, token: Option<&str>) -> Result<Self> {
+        if !self.distinct.is_empty() {
+            return Err(IsarError::IllegalArgument {});
+        }
+        let sort = self.page_sort();
+        let mut query = self.clone();
+        // pages use the current values of the parameters
+        query.parameters = None;
+        if let Some(token) = token {
+            let token = PageToken::decode(token, sort.len() - 1, self.fingerprint)?;
+            query.indexes = QueryIndex::seek(&self.indexes, collection, &sort[0], &token);
+            let keyset = NativeFilter::keyset(sort.clone(), token);
+            query.filter = NativeFilter::and(vec![query.filter, keyset.clone()]);
+            query.watch_filter = NativeFilter::and(vec![query.watch_filter, keyset]);
+        }
+        query.sort = sort;
+        Ok(query)
+    }
+
+    pub(crate) fn page_token(
+        &self,
+        txn: &NativeTxn,
+        collection: &NativeCollection,
+        id: i64,
+    ) -> Result<Option<String>> {
+        let mut cursor = collection.get_cursor(txn)?;
+        let Some((_, bytes)) = cursor.move_to(&id.to_id_bytes())? else {
+            return Ok(None);
+        };
+        let object = IsarDeserializer::from_bytes(bytes);
+        let values = self
+            .page_sort()
+            .iter()
+            .filter_map(|(p, _, _)| p.as_ref())
+            .map(|p| Self::read_value(object, p))
+            .collect();
+        Ok(Some(PageToken::new(values, id, self.fingerprint).encode()))
+    }
+
+    fn read_value(object: IsarDeserializer, property: &NativeProperty) -> Option<IsarValue> {
+        let offset = property.offset;
+        match property.data_type {
+            DataType::Bool => object.read_bool(offset).map(IsarValue::Bool),
+            DataType::Byte => Some(IsarValue::Integer(object.read_byte(offset) as i64)),
+            DataType::Int => {
+                let value = object.read_int(offset);
+                (value != NULL_INT).then_some(IsarValue::Integer(value as i64))
+            }
+            DataType::Float => {
+                let value = object.read_float(offset);
+                (!value.is_nan()).then_some(IsarValue::Real(value as f64))
+            }
+            DataType::Long => {
+                let value = object.read_long(offset);
+                (value != NULL_LONG).then_some(IsarValue::Integer(value))
+            }
+            DataType::Double => {
+                let value = object.read_double(offset);
+                (!value.is_nan()).then_some(IsarValue::Real(value))
+            }
+            DataType::String => object
+                .read_string(offset)
+                .map(|value| IsarValue::String(value.to_string())),
+            _ => None,
+        }
     }
 
     pub(crate) fn aggregate(
@@ -80,7 +576,7 @@ impl NativeQuery {
         all_collections: &[NativeCollection],
         aggregation: Aggregation,
         property_index: Option<u16>,
-    ) -> Option<IsarValue> {
+    ) -> Result<Option<IsarValue>> {
         let collection = &all_collections[self.collection_index as usize];
         let property = if let Some(property_index) = property_index {
             collection.get_property(property_index)
@@ -88,17 +584,172 @@ impl NativeQuery {
             None
         };
 
-        let mut iterator = QueryIterator::new(txn, collection, self, true, 0, u32::MAX);
-        match aggregation {
-            Aggregation::Min | Aggregation::Max => {
-                aggregate_min_max(iterator, property, aggregation == Aggregation::Min)
+        let iterator = QueryIterator::new(txn, collection, self, true, 0, u32::MAX)?;
+        itertools::process_results(iterator, |objects| {
+            aggregate(objects, property, aggregation)
+        })
+    }
+
+    /// Evaluates all aggregations using a single iteration of the query.
+    pub(crate) fn aggregate_batch(
+        &self,
+        txn: &NativeTxn,
+        all_collections: &[NativeCollection],
+        aggregations: &[(Aggregation, Option<u16>)],
+    ) -> Result<Vec<Option<IsarValue>>> {
+        let collection = &all_collections[self.collection_index as usize];
+        let mut accumulators = aggregations
+            .iter()
+            .map(|(aggregation, property_index)| {
+                let property = if let Some(property_index) = property_index {
+                    collection.get_property(*property_index)
+                } else {
+                    None
+                };
+                Accumulator::new(*aggregation, property)
+            })
+            .collect_vec();
+        for next in QueryIterator::new(txn, collection, self, true, 0, u32::MAX)? {
+            let (id, object) = next?;
+            if accumulators.iter().all(|a| a.is_complete()) {
+                break;
             }
-            Aggregation::Sum | Aggregation::Average => {
-                aggregate_sum_average(iterator, property, aggregation == Aggregation::Sum)
+            for accumulator in &mut accumulators {
+                accumulator.add(id, object);
             }
-            Aggregation::Count => Some(IsarValue::Integer(iterator.count() as i64)),
-            Aggregation::IsEmpty => Some(IsarValue::Bool(iterator.next().is_none())),
         }
+        Ok(accumulators.into_iter().map(|a| a.finish()).collect())
+    }
+
+    /// Groups the objects by the hash of the group properties like distinct queries and
+    /// aggregates each group while iterating the query. The values of a group are read from
+    /// its first object.
+    pub(crate) fn aggregate_groups(
+        &self,
+        txn: &NativeTxn,
+        all_collections: &[NativeCollection],
+        group_by: &[(u16, bool)],
+        aggregation: Aggregation,
+        property_index: Option<u16>,
+    ) -> Result<Vec<AggregationGroup>> {
+        let collection = &all_collections[self.collection_index as usize];
+        let property = if let Some(property_index) = property_index {
+            collection.get_property(property_index)
+        } else {
+            None
+        };
+        if group_by.is_empty() {
+            return Err(IsarError::IllegalArgument {});
+        }
+        let group_properties = group_by
+            .iter()
+            .map(|(property_index, case_sensitive)| {
+                match collection.get_property(*property_index) {
+                    Some(property) if property.data_type.is_scalar() => {
+                        Ok((property, *case_sensitive))
+                    }
+                    _ => Err(IsarError::IllegalArgument {}),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut group_positions = IntMap::new();
+        let mut groups: Vec<(Vec<Option<IsarValue>>, Accumulator)> = vec![];
+        for next in QueryIterator::new(txn, collection, self, true, 0, u32::MAX)? {
+            let (id, object) = next?;
+            let hash = group_properties
+                .iter()
+                .fold(0, |hash, (property, case_sensitive)| {
+                    object.hash_property(property.offset, property.data_type, *case_sensitive, hash)
+                });
+            let position = if let Some(position) = group_positions.get(hash) {
+                *position
+            } else {
+                let values = group_properties
+                    .iter()
+                    .map(|(property, _)| Self::read_value(object, property))
+                    .collect::<Vec<_>>();
+                groups.push((values, Accumulator::new(aggregation, property)));
+                group_positions.insert(hash, groups.len() - 1);
+                groups.len() - 1
+            };
+            groups[position].1.add(id, object);
+        }
+
+        let groups = groups
+            .into_iter()
+            .map(|(values, accumulator)| AggregationGroup {
+                values,
+                result: accumulator.finish(),
+            })
+            .collect();
+        Ok(groups)
+    }
+
+    /// Describes the ranges the query iterates. The row estimates are provided by MDBX and may
+    /// be inaccurate for large ranges.
+    pub(crate) fn explain(
+        &self,
+        txn: &NativeTxn,
+        collection: &NativeCollection,
+        all_collections: &[NativeCollection],
+    ) -> Result<QueryPlan> {
+        let sorted_ranges = QueryIndex::sorted_ranges(&self.indexes, collection, &self.sort);
+        let sort_in_memory = sorted_ranges.is_none();
+        let ranges = sorted_ranges.unwrap_or_else(|| self.indexes.clone());
+
+        let mut plan_ranges = vec![];
+        let mut estimated_rows = 0;
+        for range in &ranges {
+            let (index, bounds, rows) = match range {
+                QueryIndex::Primary(lower, upper) => {
+                    let rows = collection.estimate_between_ids(
+                        txn,
+                        *lower.min(upper),
+                        *lower.max(upper),
+                    )?;
+                    (None, format!("{}..={}", lower, upper), rows)
+                }
+                QueryIndex::Secondary(index, lower, upper) => {
+                    let index = &collection.indexes[*index as usize];
+                    let (lower_bytes, upper_bytes) =
+                        NativeIndex::finish_bounds(lower.clone(), upper.clone());
+                    let rows = if lower <= upper {
+                        index.estimate_between(txn, lower.clone(), upper.clone())?
+                    } else {
+                        index.estimate_between(txn, upper.clone(), lower.clone())?
+                    };
+                    let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02x}", b)).join("");
+                    let bounds = format!("{}..={}", hex(&lower_bytes), hex(&upper_bytes));
+                    (Some(index.name.clone()), bounds, rows)
+                }
+            };
+            estimated_rows += rows;
+            plan_ranges.push(QueryPlanRange { index, bounds });
+        }
+
+        let has_duplicates = QueryIndex::has_duplicates(&self.indexes)
+            || self.indexes.iter().any(|i| i.is_multi_entry(collection));
+        let mut details = vec![];
+        if has_duplicates {
+            details.push("skip objects returned by previous ranges".to_string());
+        }
+        if sort_in_memory {
+            details.push("sort matches in memory".to_string());
+        } else if !self.sort.is_empty() {
+            details.push("read ranges in sort order".to_string());
+        }
+        if !self.distinct.is_empty() {
+            details.push("skip objects with duplicate distinct values".to_string());
+        }
+
+        Ok(QueryPlan {
+            ranges: plan_ranges,
+            filter: self.filter.describe(collection, all_collections),
+            sort_in_memory,
+            estimated_rows,
+            details,
+        })
     }
 
     pub(crate) fn get_matching_ids(
@@ -107,7 +758,7 @@ impl NativeQuery {
         collection: &NativeCollection,
         offset: Option<u32>,
         limit: Option<u32>,
-    ) -> Vec<i64> {
+    ) -> Result<Vec<i64>> {
         let iterator = QueryIterator::new(
             txn,
             collection,
@@ -115,8 +766,8 @@ impl NativeQuery {
             false,
             offset.unwrap_or(0),
             limit.unwrap_or(u32::MAX),
-        );
-        iterator.map(|(id, _)| id).collect()
+        )?;
+        iterator.map(|next| next.map(|(id, _)| id)).collect()
     }
 }
 
@@ -124,7 +775,7 @@ impl QueryMatches for NativeQuery {
     type Object<'a> = IsarDeserializer<'a>;
 
     fn matches<'a>(&self, id: i64, object: &IsarDeserializer<'a>) -> bool {
-        self.filter.evaluate(id, *object)
+        self.watch_filter.evaluate(id, *object)
     }
 }
 
@@ -132,6 +783,7 @@ pub struct NativeQueryCursor<'a> {
     iterator: QueryIterator<'a>,
     collection: &'a NativeCollection,
     all_collections: &'a [NativeCollection],
+    properties: Option<Vec<u16>>,
 }
 
 impl<'a> NativeQueryCursor<'a> {
@@ -139,11 +791,13 @@ impl<'a> NativeQueryCursor<'a> {
         iterator: QueryIterator<'a>,
         collection: &'a NativeCollection,
         all_collections: &'a [NativeCollection],
+        properties: Option<Vec<u16>>,
     ) -> Self {
         Self {
             iterator,
             collection,
             all_collections,
+            properties,
         }
     }
 }
@@ -156,12 +810,954 @@ impl<'a> IsarQueryCursor for NativeQueryCursor<'a> {
 
     #[inline]
     fn next(&mut self) -> Option<Self::Reader<'_>> {
-        let (id, object) = self.iterator.next()?;
-        Some(NativeReader::new(
-            id,
-            object,
-            self.collection,
-            self.all_collections,
-        ))
+        // query cursors cannot report errors so they end the iteration
+        let (id, object) = self.iterator.next()?.ok()?;
+        let reader = NativeReader::new(id, object, self.collection, self.all_collections);
+        if let Some(properties) = &self.properties {
+            Some(reader.with_properties(properties))
+        } else {
+            Some(reader)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::filter::ConditionType;
+    use crate::core::insert::IsarInsert;
+    use crate::core::instance::{IsarInstance, Percentile};
+    use crate::core::query_builder::IsarQueryBuilder;
+    use crate::core::reader::IsarReader;
+    use crate::core::schema::{IndexSchema, IsarSchema, PropertySchema};
+    use crate::core::writer::IsarWriter;
+    use crate::native::native_instance::NativeInstance;
+    use std::sync::Arc;
+
+    fn key(value: i32) -> IndexKey {
+        let mut key = IndexKey::min();
+        key.add_int(value);
+        key
+    }
+
+    #[test]
+    fn test_merge_primary() {
+        let merged = QueryIndex::merge(vec![
+            QueryIndex::Primary(10, 20),
+            QueryIndex::Primary(1, 5),
+            QueryIndex::Primary(6, 8),
+            QueryIndex::Primary(15, 30),
+        ]);
+        assert_eq!(
+            merged,
+            vec![QueryIndex::Primary(1, 8), QueryIndex::Primary(10, 30)]
+        );
+        assert!(!QueryIndex::has_duplicates(&merged));
+    }
+
+    #[test]
+    fn test_merge_secondary() {
+        let merged = QueryIndex::merge(vec![
+            QueryIndex::Secondary(0, key(5), key(10)),
+            QueryIndex::Secondary(1, key(1), key(2)),
+            QueryIndex::Secondary(0, key(1), key(6)),
+            QueryIndex::Secondary(0, key(20), key(20)),
+        ]);
+        assert_eq!(
+            merged,
+            vec![
+                QueryIndex::Secondary(0, key(1), key(10)),
+                QueryIndex::Secondary(0, key(20), key(20)),
+                QueryIndex::Secondary(1, key(1), key(2)),
+            ]
+        );
+        assert!(QueryIndex::has_duplicates(&merged));
+        assert!(!QueryIndex::has_duplicates(&merged[..2]));
+    }
+
+    #[test]
+    fn test_describe_filter() {
+        use crate::native::mdbx::db::Db;
+
+        let long_prop = NativeProperty::new(DataType::Long, 0, None);
+        let string_prop = NativeProperty::new(DataType::String, 8, None);
+        let col = NativeCollection::new(
+            0,
+            "col",
+            Some("key"),
+            vec![
+                ("long".to_string(), long_prop),
+                ("string".to_string(), string_prop),
+            ],
+            vec![],
+            Some(Db { dbi: 0, dup: false }),
+        );
+        let filter = NativeFilter::or(vec![
+            NativeFilter::and(vec![
+                NativeFilter::long(&long_prop, 1, 5),
+                NativeFilter::not(NativeFilter::string_contains(&string_prop, "A", false)),
+            ]),
+            NativeFilter::string(&string_prop, None, Some("b"), true),
+            NativeFilter::id(3, 3),
+        ]);
+        assert_eq!(
+            filter.describe(&col, &[]).unwrap(),
+            "((long BETWEEN 1 AND 5 AND NOT string CONTAINS \"a\" COLLATE NOCASE) OR \
+            string BETWEEN NULL AND \"b\" OR key BETWEEN 3 AND 3)"
+        );
+        assert_eq!(NativeFilter::stat(true).describe(&col, &[]), None);
+    }
+
+    #[test]
+    fn test_sorted_ranges() {
+        use crate::native::mdbx::db::Db;
+
+        let long_prop = NativeProperty::new(DataType::Long, 0, None);
+        let string_prop = NativeProperty::new(DataType::String, 8, None);
+        let db = Db { dbi: 0, dup: false };
+        let col = NativeCollection::new(
+            0,
+            "col",
+            Some("id"),
+            vec![
+                ("long".to_string(), long_prop),
+                ("string".to_string(), string_prop),
+            ],
+            vec![
+                NativeIndex::new(
+                    "long_string",
+                    db,
+                    vec![long_prop, string_prop],
+                    false,
+                    false,
+                    true,
+                ),
+                NativeIndex::new("string", db, vec![string_prop], false, false, true),
+                NativeIndex::new("long", db, vec![long_prop], false, false, true),
+            ],
+            None,
+        );
+        let sorted = |indexes: &[QueryIndex], sort: &[(Option<NativeProperty>, Sort, bool)]| {
+            QueryIndex::sorted_ranges(indexes, &col, sort)
+        };
+        let id_asc = [(None, Sort::Asc, true)];
+        let id_desc = [(None, Sort::Desc, true)];
+        let long_asc = [(Some(long_prop), Sort::Asc, true)];
+        let long_desc = [(Some(long_prop), Sort::Desc, true)];
+        let primary = [QueryIndex::Primary(1, 5), QueryIndex::Primary(8, 9)];
+        let secondary = [QueryIndex::Secondary(0, key(1), key(2))];
+
+        assert_eq!(sorted(&primary, &[]), Some(primary.to_vec()));
+        assert_eq!(sorted(&primary, &id_asc), Some(primary.to_vec()));
+        assert_eq!(
+            sorted(&primary, &id_desc),
+            Some(vec![QueryIndex::Primary(9, 8), QueryIndex::Primary(5, 1)])
+        );
+        assert_eq!(sorted(&primary, &long_asc), None);
+        assert_eq!(sorted(&[QueryIndex::Primary(5, 1)], &id_asc), None);
+
+        assert_eq!(sorted(&secondary, &long_asc), Some(secondary.to_vec()));
+        assert_eq!(
+            sorted(&secondary, &long_desc),
+            Some(vec![QueryIndex::Secondary(0, key(2), key(1))])
+        );
+        assert_eq!(sorted(&secondary, &id_asc), None);
+        assert_eq!(
+            sorted(&secondary, &[long_asc[0], (None, Sort::Desc, true)]),
+            None
+        );
+        // strings keys do not always compare like the values
+        assert_eq!(
+            sorted(
+                &secondary,
+                &[long_asc[0], (Some(string_prop), Sort::Asc, true)]
+            ),
+            None
+        );
+        assert_eq!(
+            sorted(
+                &[QueryIndex::Secondary(1, key(1), key(2))],
+                &[(Some(string_prop), Sort::Asc, true)]
+            ),
+            None
+        );
+        assert_eq!(
+            sorted(
+                &[secondary[0].clone(), QueryIndex::Primary(1, 5)],
+                &long_asc
+            ),
+            None
+        );
+
+        // full scans use a matching index
+        let full_scan = [QueryIndex::Primary(i64::MIN, i64::MAX)];
+        let mut upper = IndexKey::min();
+        upper.add_max_bytes(IndexKey::MAX_INDEX_SIZE);
+        assert_eq!(
+            sorted(&full_scan, &long_desc),
+            Some(vec![QueryIndex::Secondary(
+                0,
+                upper.clone(),
+                IndexKey::min()
+            )])
+        );
+        assert_eq!(
+            sorted(&full_scan, &[(Some(string_prop), Sort::Asc, true)]),
+            None
+        );
+
+        // ids are ordered within an index key
+        let long_range = [QueryIndex::Secondary(2, key(1), key(2))];
+        assert_eq!(
+            sorted(&long_range, &[long_asc[0], id_asc[0]]),
+            Some(long_range.to_vec())
+        );
+        assert_eq!(
+            sorted(&long_range, &[long_desc[0], id_desc[0]]),
+            Some(vec![QueryIndex::Secondary(2, key(2), key(1))])
+        );
+        assert_eq!(sorted(&long_range, &[long_asc[0], id_desc[0]]), None);
+        assert_eq!(sorted(&secondary, &[long_asc[0], id_asc[0]]), None);
+        assert_eq!(
+            sorted(&full_scan, &[long_desc[0], id_desc[0]]),
+            Some(vec![QueryIndex::Secondary(2, upper, IndexKey::min())])
+        );
+    }
+
+    #[test]
+    fn test_merge_keeps_descending() {
+        let merged = QueryIndex::merge(vec![QueryIndex::Primary(5, 1), QueryIndex::Primary(2, 3)]);
+        assert_eq!(
+            merged,
+            vec![QueryIndex::Primary(2, 3), QueryIndex::Primary(5, 1)]
+        );
+        assert!(QueryIndex::has_duplicates(&merged));
+    }
+
+    /// A collection with the properties `long`, `string`, `int` and `double`.
+    fn open(name: &str, indexes: Vec<IndexSchema>) -> Arc<NativeInstance> {
+        let schema = IsarSchema::new(
+            "test",
+            Some("id"),
+            vec![
+                PropertySchema::new("long", DataType::Long, None),
+                PropertySchema::new("string", DataType::String, None),
+                PropertySchema::new("int", DataType::Int, None),
+                PropertySchema::new("double", DataType::Double, None),
+            ],
+            indexes,
+            false,
+        );
+        NativeInstance::open_temp(name, vec![schema]).unwrap()
+    }
+
+    fn long_index() -> IndexSchema {
+        IndexSchema::new("long", vec!["long"], false, false, true)
+    }
+
+    fn long(value: i64) -> Option<IsarValue> {
+        Some(IsarValue::Integer(value))
+    }
+
+    fn real(value: f64) -> Option<IsarValue> {
+        Some(IsarValue::Real(value))
+    }
+
+    fn string(value: &str) -> Option<IsarValue> {
+        Some(IsarValue::String(value.to_string()))
+    }
+
+    /// Inserts the objects. Missing values are null.
+    fn put(instance: &NativeInstance, objects: &[(i64, Vec<Option<IsarValue>>)]) {
+        let txn = instance.begin_txn(true).unwrap();
+        let mut insert = instance.insert(txn, 0, objects.len() as u32).unwrap();
+        for (id, values) in objects {
+            for index in 1..=4 {
+                match values.get(index as usize - 1).cloned().flatten() {
+                    Some(IsarValue::Integer(value)) if index == 3 => {
+                        insert.write_int(index, value as i32)
+                    }
+                    Some(IsarValue::Integer(value)) => insert.write_long(index, value),
+                    Some(IsarValue::Real(value)) => insert.write_double(index, value),
+                    Some(IsarValue::String(value)) => insert.write_string(index, &value),
+                    _ => insert.write_null(index),
+                }
+            }
+            insert.save(*id).unwrap();
+        }
+        let txn = insert.finish().unwrap();
+        instance.commit_txn(txn).unwrap();
+    }
+
+    /// Objects 1 to 12 with the `long` values 0, 1, 2 and null repeated three times.
+    fn put_duplicate_keys(instance: &NativeInstance) {
+        let objects = (1..=12)
+            .map(|id| {
+                let value = if id % 4 == 0 { None } else { long(id % 4 - 1) };
+                (id, vec![value])
+            })
+            .collect_vec();
+        put(instance, &objects);
+    }
+
+    /// Returns the ids and values of the objects matching the query in the order of the query.
+    fn query_objects(
+        instance: &NativeInstance,
+        query: &NativeQuery,
+        limit: Option<u32>,
+    ) -> Vec<(i64, Vec<Option<IsarValue>>)> {
+        let txn = instance.begin_txn(false).unwrap();
+        let mut objects = vec![];
+        {
+            let mut cursor = instance.query_cursor(&txn, query, None, limit).unwrap();
+            while let Some(reader) = cursor.next() {
+                let values = vec![
+                    (!reader.is_null(1))
+                        .then(|| reader.read_long(1))
+                        .and_then(long),
+                    reader.read_string(2).and_then(string),
+                    (!reader.is_null(3))
+                        .then(|| reader.read_int(3) as i64)
+                        .and_then(long),
+                    (!reader.is_null(4))
+                        .then(|| reader.read_double(4))
+                        .and_then(real),
+                ];
+                objects.push((reader.read_id(), values));
+            }
+        }
+        instance.abort_txn(txn);
+        objects
+    }
+
+    fn query_ids(instance: &NativeInstance, query: &NativeQuery) -> Vec<i64> {
+        query_objects(instance, query, None)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Returns the `long` values in query order and the sorted ids of the objects.
+    fn query_longs(instance: &NativeInstance, query: &NativeQuery) -> (Vec<Option<i64>>, Vec<i64>) {
+        let objects = query_objects(instance, query, None);
+        let values = objects
+            .iter()
+            .map(|(_, values)| values[0].as_ref().and_then(|v| v.i64()))
+            .collect();
+        let ids = objects.iter().map(|(id, _)| *id).sorted().collect();
+        (values, ids)
+    }
+
+    #[test]
+    fn test_sort_id_ranges() {
+        let instance = open("query_sort_id_ranges", vec![]);
+        put_duplicate_keys(&instance);
+        for (sort, expected) in [
+            (Sort::Asc, vec![2, 3, 4, 5, 7, 8, 9]),
+            (Sort::Desc, vec![9, 8, 7, 5, 4, 3, 2]),
+        ] {
+            let mut qb = instance.query(0).unwrap();
+            qb.add_id_range(7, 9);
+            qb.add_id_range(2, 5);
+            qb.add_sort(0, sort, true);
+            assert_eq!(query_ids(&instance, &qb.build()), expected);
+        }
+        NativeInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_sort_index_ranges_with_duplicates() {
+        let instance = open("query_sort_index_ranges", vec![long_index()]);
+        put_duplicate_keys(&instance);
+        let ranges = [
+            (0, 1, vec![0, 0, 0, 1, 1, 1], vec![1, 2, 5, 6, 9, 10]),
+            (1, 2, vec![1, 1, 1, 2, 2, 2], vec![2, 3, 6, 7, 10, 11]),
+            // the upper key is missing and followed by another key
+            (-5, -1, vec![], vec![]),
+        ];
+        for (lower, upper, values, ids) in ranges {
+            let mut values = values.into_iter().map(Some).collect_vec();
+            for sort in [Sort::Asc, Sort::Desc] {
+                let mut qb = instance.query(0).unwrap();
+                qb.add_index_range("long", vec![long(lower)], vec![long(upper)], true, true)
+                    .unwrap();
+                qb.add_sort(1, sort, true);
+                let result = query_longs(&instance, &qb.build());
+                assert_eq!(result, (values.clone(), ids.clone()));
+                values.reverse();
+            }
+        }
+        NativeInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_sort_full_scan_by_index() {
+        let instance = open("query_sort_full_scan", vec![long_index()]);
+        put_duplicate_keys(&instance);
+        let all_ids = (1..=12).collect_vec();
+        let query = instance.query(0).unwrap().build();
+        assert_eq!(query_ids(&instance, &query), all_ids);
+
+        let mut values = vec![None, None, None, Some(0), Some(0), Some(0)];
+        values.extend([Some(1), Some(1), Some(1), Some(2), Some(2), Some(2)]);
+        for sort in [Sort::Asc, Sort::Desc] {
+            let mut qb = instance.query(0).unwrap();
+            qb.add_sort(1, sort, true);
+            let result = query_longs(&instance, &qb.build());
+            assert_eq!(result, (values.clone(), all_ids.clone()));
+            values.reverse();
+        }
+        NativeInstance::close(instance, true);
+    }
+
+    /// Reads all pages of the query and returns the ids of every page.
+    fn page_ids(instance: &NativeInstance, query: &NativeQuery, limit: u32) -> Vec<Vec<i64>> {
+        let mut pages = vec![];
+        let mut token = None;
+        loop {
+            let page = instance.query_page(query, token.as_deref()).unwrap();
+            let ids = query_objects(instance, &page, Some(limit))
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect_vec();
+            let Some(last) = ids.last() else {
+                return pages;
+            };
+            let txn = instance.begin_txn(false).unwrap();
+            token = instance.query_page_token(&txn, &page, *last).unwrap();
+            instance.abort_txn(txn);
+            pages.push(ids);
+        }
+    }
+
+    #[test]
+    fn test_pages() {
+        let instance = open("query_pages", vec![long_index()]);
+        put_duplicate_keys(&instance);
+
+        // ties are ordered by id in the direction of the last sort property
+        let sorts = [
+            (vec![], (1..=12).collect_vec()),
+            (vec![(0, Sort::Desc)], (1..=12).rev().collect_vec()),
+            (
+                vec![(1, Sort::Asc)],
+                vec![4, 8, 12, 1, 5, 9, 2, 6, 10, 3, 7, 11],
+            ),
+            (
+                vec![(1, Sort::Desc)],
+                vec![11, 7, 3, 10, 6, 2, 9, 5, 1, 12, 8, 4],
+            ),
+            (
+                vec![(1, Sort::Desc), (0, Sort::Asc)],
+                vec![3, 7, 11, 2, 6, 10, 1, 5, 9, 4, 8, 12],
+            ),
+        ];
+        for (sort, ids) in sorts {
+            let mut qb = instance.query(0).unwrap();
+            for (property, sort) in &sort {
+                qb.add_sort(*property, *sort, true);
+            }
+            let query = qb.build();
+            let pages = page_ids(&instance, &query, 5);
+            assert_eq!(pages.iter().map(|p| p.len()).collect_vec(), vec![5, 5, 2]);
+            assert_eq!(pages.concat(), ids);
+
+            // the order of the index is used for the pages
+            if sort.len() == 1 {
+                let txn = instance.begin_txn(false).unwrap();
+                let page = instance.query_page(&query, None).unwrap();
+                assert!(!instance.explain(&txn, &page).unwrap().sort_in_memory);
+                instance.abort_txn(txn);
+            }
+        }
+        NativeInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_pages_of_equal_keys() {
+        let instance = open("query_pages_of_equal_keys", vec![long_index()]);
+        // the bytes of the ids are not in the order of the ids
+        let ids = vec![-65536, -256, -1, 1, 255, 256, 257, 65536];
+        let objects = ids.iter().map(|id| (*id, vec![long(0)])).collect_vec();
+        put(&instance, &objects);
+
+        for sort in [Sort::Asc, Sort::Desc] {
+            let mut qb = instance.query(0).unwrap();
+            qb.add_sort(1, sort, true);
+            let query = qb.build();
+            let expected = if sort == Sort::Asc {
+                ids.clone()
+            } else {
+                ids.iter().copied().rev().collect_vec()
+            };
+            assert_eq!(page_ids(&instance, &query, 3).concat(), expected);
+        }
+        NativeInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_pages_of_real_and_string_keys() {
+        let indexes = vec![
+            IndexSchema::new("string", vec!["string"], false, false, true),
+            IndexSchema::new("double", vec!["double"], false, false, true),
+        ];
+        let instance = open("query_pages_of_real_and_string_keys", indexes);
+        let values = [
+            (real(1.5), string("b")),
+            (None, None),
+            (real(-2.0), string("a")),
+            (real(0.5), string("")),
+            (real(1.5), string("b")),
+            (None, None),
+            (real(3.0), string("c")),
+            (real(0.5), string("ab")),
+        ];
+        let objects = values
+            .into_iter()
+            .zip(1..)
+            .map(|((double, string), id)| (id, vec![None, string, None, double]))
+            .collect_vec();
+        put(&instance, &objects);
+
+        for property in [2, 4] {
+            for sort in [Sort::Asc, Sort::Desc] {
+                let mut qb = instance.query(0).unwrap();
+                qb.add_sort(property, sort, true);
+                let query = qb.build();
+                let page = instance.query_page(&query, None).unwrap();
+                let expected = query_ids(&instance, &page);
+                assert_eq!(expected.len(), 8);
+                for limit in 1..=3 {
+                    assert_eq!(page_ids(&instance, &query, limit).concat(), expected);
+                }
+            }
+        }
+        NativeInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_page_token_of_other_query() {
+        let instance = open("query_page_token_of_other_query", vec![]);
+        put_duplicate_keys(&instance);
+        let query = |sort: Sort, upper: i64| {
+            let mut qb = instance.query(0).unwrap();
+            qb.add_id_range(1, upper);
+            qb.add_sort(1, sort, true);
+            qb.build()
+        };
+        let query1 = query(Sort::Asc, 10);
+        let page = instance.query_page(&query1, None).unwrap();
+        let txn = instance.begin_txn(false).unwrap();
+        let token = instance.query_page_token(&txn, &page, 5).unwrap().unwrap();
+        instance.abort_txn(txn);
+
+        assert!(instance.query_page(&query1, Some(&token)).is_ok());
+        assert!(instance.query_page(&page, Some(&token)).is_ok());
+        for other in [query(Sort::Desc, 10), query(Sort::Asc, 11)] {
+            assert!(matches!(
+                instance.query_page(&other, Some(&token)),
+                Err(IsarError::IllegalArgument {})
+            ));
+        }
+        NativeInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_explain_index() {
+        let instance = open("query_explain_index", vec![long_index()]);
+        put_duplicate_keys(&instance);
+        let mut qb = instance.query(0).unwrap();
+        qb.set_filter(Filter::new_condition(
+            1,
+            ConditionType::Between,
+            vec![long(1), long(2)],
+            true,
+        ));
+        qb.add_sort(1, Sort::Asc, true);
+        let query = qb.build();
+
+        let txn = instance.begin_txn(false).unwrap();
+        let plan = instance.explain(&txn, &query).unwrap();
+        instance.abort_txn(txn);
+        assert_eq!(plan.ranges.len(), 1);
+        assert_eq!(plan.ranges[0].index.as_deref(), Some("long"));
+        assert!(!plan.ranges[0].bounds.is_empty());
+        assert!(!plan.sort_in_memory);
+        NativeInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_explain_sort_in_memory() {
+        let instance = open("query_explain_sort_in_memory", vec![long_index()]);
+        put_duplicate_keys(&instance);
+        let mut qb = instance.query(0).unwrap();
+        qb.set_filter(Filter::new_condition(
+            3,
+            ConditionType::Greater,
+            vec![long(5)],
+            true,
+        ));
+        qb.add_sort(2, Sort::Desc, true);
+        let query = qb.build();
+
+        let txn = instance.begin_txn(false).unwrap();
+        let plan = instance.explain(&txn, &query).unwrap();
+        instance.abort_txn(txn);
+        assert_eq!(plan.ranges.len(), 1);
+        assert_eq!(plan.ranges[0].index, None);
+        // the residual filter uses the names of the properties
+        assert!(plan.filter.unwrap().starts_with("int "));
+        assert!(plan.sort_in_memory);
+        NativeInstance::close(instance, true);
+    }
+
+    /// Objects with groups of `long` and `string` values and the `int` values to aggregate.
+    fn put_groups(instance: &NativeInstance) {
+        let objects = [
+            (1, long(1), "a", long(10)),
+            (2, long(1), "A", long(20)),
+            (3, long(2), "a", None),
+            (4, long(2), "b", long(5)),
+            (5, None, "b", long(7)),
+            (6, long(1), "b", long(-4)),
+            (7, long(2), "a", long(9)),
+        ];
+        let objects = objects
+            .into_iter()
+            .map(|(id, group, name, value)| (id, vec![group, string(name), value]))
+            .collect_vec();
+        put(instance, &objects);
+    }
+
+    /// Aggregates the groups of all objects and returns the groups sorted by their values.
+    /// Strings of case-insensitive groups may be spelled like any object of the group so they
+    /// are lowercased.
+    fn aggregate_groups(
+        instance: &NativeInstance,
+        group_by: &[(u16, bool)],
+        aggregation: Aggregation,
+        property_index: Option<u16>,
+    ) -> Vec<(Vec<Option<IsarValue>>, Option<IsarValue>)> {
+        let query = instance.query(0).unwrap().build();
+        let txn = instance.begin_txn(false).unwrap();
+        let groups = instance
+            .query_aggregate_groups(&txn, &query, group_by, aggregation, property_index)
+            .unwrap();
+        instance.abort_txn(txn);
+        groups
+            .into_iter()
+            .map(|group| {
+                let values = group
+                    .values
+                    .into_iter()
+                    .map(|value| match value {
+                        Some(IsarValue::String(value)) => string(&value.to_lowercase()),
+                        value => value,
+                    })
+                    .collect_vec();
+                (values, group.result)
+            })
+            .sorted_by_key(|(values, _)| format!("{:?}", values))
+            .collect()
+    }
+
+    #[test]
+    fn test_aggregate_groups() {
+        let instance = open("query_aggregate_groups", vec![]);
+        put_groups(&instance);
+
+        // Count, Sum, Min, Max and Average of each group ordered by the group values
+        let by_long = [
+            (vec![long(1)], [3, 26, -4, 20], 26.0 / 3.0),
+            (vec![long(2)], [3, 14, 5, 9], 7.0),
+            (vec![None], [1, 7, 7, 7], 7.0),
+        ];
+        let by_long_string = [
+            (vec![long(1), string("a")], [2, 30, 10, 20], 15.0),
+            (vec![long(1), string("b")], [1, -4, -4, -4], -4.0),
+            (vec![long(2), string("a")], [2, 9, 9, 9], 9.0),
+            (vec![long(2), string("b")], [1, 5, 5, 5], 5.0),
+            (vec![None, string("b")], [1, 7, 7, 7], 7.0),
+        ];
+        let group_bys: [(&[(u16, bool)], &[_]); 2] = [
+            (&[(1, true)], &by_long),
+            (&[(1, true), (2, false)], &by_long_string),
+        ];
+        for (group_by, expected) in group_bys {
+            let aggregations = [
+                (Aggregation::Count, None),
+                (Aggregation::Sum, Some(3)),
+                (Aggregation::Min, Some(3)),
+                (Aggregation::Max, Some(3)),
+            ];
+            for (i, (aggregation, property_index)) in aggregations.into_iter().enumerate() {
+                let expected = expected
+                    .iter()
+                    .map(|(values, results, _)| (values.clone(), long(results[i])))
+                    .sorted_by_key(|(values, _)| format!("{:?}", values))
+                    .collect_vec();
+                assert_eq!(
+                    aggregate_groups(&instance, group_by, aggregation, property_index),
+                    expected,
+                    "{:?}",
+                    aggregation
+                );
+            }
+            let expected = expected
+                .iter()
+                .map(|(values, _, average)| (values.clone(), real(*average)))
+                .sorted_by_key(|(values, _)| format!("{:?}", values))
+                .collect_vec();
+            assert_eq!(
+                aggregate_groups(&instance, group_by, Aggregation::Average, Some(3)),
+                expected
+            );
+        }
+        NativeInstance::close(instance, true);
+    }
+
+    /// Aggregates the objects whose `long` value is `long_value` or all objects if it is `None`.
+    fn aggregate(
+        instance: &NativeInstance,
+        long_value: Option<i64>,
+        aggregation: Aggregation,
+        property_index: Option<u16>,
+    ) -> Option<IsarValue> {
+        let mut qb = instance.query(0).unwrap();
+        if let Some(value) = long_value {
+            qb.set_filter(Filter::new_condition(
+                1,
+                ConditionType::Equal,
+                vec![long(value)],
+                true,
+            ));
+        }
+        let query = qb.build();
+        let txn = instance.begin_txn(false).unwrap();
+        let result = instance
+            .query_aggregate(&txn, &query, aggregation, property_index)
+            .unwrap();
+        instance.abort_txn(txn);
+        result
+    }
+
+    #[test]
+    fn test_aggregate_statistics() {
+        let instance = open("query_aggregate_statistics", vec![]);
+        let values = [10, 20, 0, 5, 7, -4, 9];
+        let objects = values
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let group = if i == 6 { 2 } else { 1 };
+                let (int, double) = if i == 2 {
+                    (None, None)
+                } else {
+                    (long(value), real(value as f64 / 2.0))
+                };
+                (i as i64 + 1, vec![long(group), None, int, double])
+            })
+            .collect_vec();
+        put(&instance, &objects);
+
+        let percentile = |percentile| Aggregation::Percentile(Percentile::new(percentile).unwrap());
+        let variance = 1817.0 / 30.0;
+        // The ints -4, 5, 7, 9, 10 and 20 and the doubles of half their value
+        let all = [
+            (Aggregation::Median, 8.0),
+            (percentile(0.0), -4.0),
+            (percentile(0.25), 5.5),
+            (percentile(1.0), 20.0),
+            (Aggregation::Variance, variance),
+            (Aggregation::StdDev, f64::sqrt(variance)),
+        ];
+        for (aggregation, expected) in all {
+            for (property_index, factor) in [(3, 1.0), (4, 0.5)] {
+                let factor = if aggregation == Aggregation::Variance {
+                    factor * factor
+                } else {
+                    factor
+                };
+                let result = aggregate(&instance, None, aggregation, Some(property_index));
+                let Some(IsarValue::Real(result)) = result else {
+                    panic!("{:?} of {}: {:?}", aggregation, property_index, result);
+                };
+                assert!(
+                    (result - expected * factor).abs() < 1e-9,
+                    "{:?} of {}: {}",
+                    aggregation,
+                    property_index,
+                    result
+                );
+            }
+        }
+
+        // A single value has a percentile but no sample variance
+        for property_index in [3, 4] {
+            let value = if property_index == 3 { 9.0 } else { 4.5 };
+            for aggregation in [Aggregation::Median, percentile(0.9)] {
+                assert_eq!(
+                    aggregate(&instance, Some(2), aggregation, Some(property_index)),
+                    real(value)
+                );
+            }
+            for aggregation in [
+                Aggregation::Median,
+                percentile(0.9),
+                Aggregation::Variance,
+                Aggregation::StdDev,
+            ] {
+                assert_eq!(
+                    aggregate(&instance, Some(3), aggregation, Some(property_index)),
+                    None
+                );
+            }
+            for aggregation in [Aggregation::Variance, Aggregation::StdDev] {
+                assert_eq!(
+                    aggregate(&instance, Some(2), aggregation, Some(property_index)),
+                    None
+                );
+            }
+        }
+        NativeInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_aggregate_batch() {
+        let instance = open("query_aggregate_batch", vec![]);
+        put_groups(&instance);
+
+        let percentile = Aggregation::Percentile(Percentile::new(0.3).unwrap());
+        let batch = [
+            (Aggregation::Count, None),
+            (Aggregation::IsEmpty, None),
+            (Aggregation::Min, Some(3)),
+            (Aggregation::Max, Some(3)),
+            (Aggregation::Sum, Some(3)),
+            (Aggregation::Average, Some(3)),
+            (Aggregation::CountDistinct, Some(3)),
+            (Aggregation::Median, Some(3)),
+            (percentile, Some(3)),
+            (Aggregation::StdDev, Some(3)),
+            (Aggregation::Variance, Some(3)),
+            (Aggregation::Min, Some(2)),
+            (Aggregation::Max, Some(2)),
+            (Aggregation::CountDistinct, Some(2)),
+        ];
+        // All objects, a group and no objects
+        for long_value in [None, Some(1), Some(3)] {
+            let mut qb = instance.query(0).unwrap();
+            if let Some(value) = long_value {
+                qb.set_filter(Filter::new_condition(
+                    1,
+                    ConditionType::Equal,
+                    vec![long(value)],
+                    true,
+                ));
+            }
+            let query = qb.build();
+            let txn = instance.begin_txn(false).unwrap();
+            let results = instance
+                .query_aggregate_batch(&txn, &query, &batch)
+                .unwrap();
+            assert!(
+                instance
+                    .query_aggregate_batch(&txn, &query, &[])
+                    .unwrap()
+                    .is_empty()
+            );
+            instance.abort_txn(txn);
+
+            assert_eq!(results.len(), batch.len());
+            assert_eq!(results[1], Some(IsarValue::Bool(long_value == Some(3))));
+            for ((aggregation, property_index), result) in batch.into_iter().zip(results) {
+                let expected = aggregate(&instance, long_value, aggregation, property_index);
+                // Compares the debug output because an empty average may be NaN
+                assert_eq!(
+                    format!("{:?}", result),
+                    format!("{:?}", expected),
+                    "{:?} of {:?} with {:?}",
+                    aggregation,
+                    property_index,
+                    long_value
+                );
+            }
+        }
+        NativeInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_query_bind() {
+        for (name, indexes) in [
+            ("query_bind", vec![]),
+            ("query_bind_index", vec![long_index()]),
+        ] {
+            let instance = open(name, indexes);
+            let objects = (1..=9).map(|id| (id, vec![long(id % 3)])).collect_vec();
+            put(&instance, &objects);
+            let mut qb = instance.query(0).unwrap();
+            qb.set_filter(Filter::new_or(vec![
+                Filter::new_not(Filter::new_parameter(0, 1, ConditionType::Between, true)),
+                Filter::new_parameter(1, 1, ConditionType::Equal, true),
+            ]));
+            qb.add_sort(0, Sort::Asc, true);
+            let mut query = qb.build();
+
+            // Unbound parameters cannot be run
+            let unbound = |query: &NativeQuery| {
+                let txn = instance.begin_txn(true).unwrap();
+                assert!(matches!(
+                    instance.query_cursor(&txn, query, None, None),
+                    Err(IsarError::IllegalArgument {})
+                ));
+                assert_eq!(
+                    instance.query_aggregate(&txn, query, Aggregation::Count, None),
+                    Err(IsarError::IllegalArgument {})
+                );
+                assert_eq!(
+                    instance.query_delete(&txn, query, None, None),
+                    Err(IsarError::IllegalArgument {})
+                );
+                assert!(matches!(
+                    instance.query_page(query, None),
+                    Err(IsarError::IllegalArgument {})
+                ));
+                assert!(instance.explain(&txn, query).is_err());
+                instance.abort_txn(txn);
+            };
+            unbound(&query);
+            assert_eq!(
+                instance.query_bind(&mut query, &[vec![long(1), long(2)]]),
+                Err(IsarError::IllegalArgument {})
+            );
+            unbound(&query);
+
+            let bindings = [
+                (vec![long(1), long(2)], long(1), vec![1, 3, 4, 6, 7, 9]),
+                (vec![long(0), long(1)], None, vec![2, 5, 8]),
+                (vec![long(0), long(0)], long(0), (1..=9).collect_vec()),
+                (vec![None, long(2)], long(5), vec![]),
+            ];
+            for (between, equal, expected) in bindings {
+                instance
+                    .query_bind(&mut query, &[between, vec![equal]])
+                    .unwrap();
+                assert_eq!(query_ids(&instance, &query), expected);
+                let txn = instance.begin_txn(false).unwrap();
+                let count = instance
+                    .query_aggregate(&txn, &query, Aggregation::Count, None)
+                    .unwrap();
+                instance.abort_txn(txn);
+                assert_eq!(count, long(expected.len() as i64));
+            }
+
+            // Queries without parameters cannot be bound
+            let mut query = instance.query(0).unwrap().build();
+            assert_eq!(
+                instance.query_bind(&mut query, &[vec![long(1)]]),
+                Err(IsarError::IllegalArgument {})
+            );
+            NativeInstance::close(instance, true);
+        }
     }
 }
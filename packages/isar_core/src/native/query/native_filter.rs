@@ -2,13 +2,17 @@ use crate::core::data_type::DataType;
 use crate::core::fast_wild_match::fast_wild_match;
 use crate::core::filter::ConditionType;
 use crate::core::filter_json::matches_json;
+use crate::core::page_token::PageToken;
+use crate::core::query_builder::Sort;
 use crate::core::value::IsarValue;
 use crate::native::isar_deserializer::IsarDeserializer;
-use crate::native::native_collection::NativeProperty;
+use crate::native::native_collection::{NativeCollection, NativeProperty};
+use crate::native::{NULL_DOUBLE, NULL_FLOAT, NULL_INT, NULL_LONG};
 use enum_dispatch::enum_dispatch;
 use itertools::Itertools;
 use paste::paste;
 use serde_json::Value;
+use std::cmp::Ordering;
 
 #[macro_export]
 macro_rules! primitive_create {
@@ -236,11 +240,34 @@ impl NativeFilter {
         NativeFilter(filter_cond)
     }
 
+    /// Matches the objects that come after the page token in the given sort order. The sort
+    /// has to end with the id.
+    pub(crate) fn keyset(
+        sort: Vec<(Option<NativeProperty>, Sort, bool)>,
+        token: PageToken,
+    ) -> NativeFilter {
+        let filter_cond = Filter::Keyset(KeysetCond { sort, token });
+        NativeFilter(filter_cond)
+    }
+
     pub fn stat(value: bool) -> NativeFilter {
         let filter_cond = Filter::Static(StaticCond { value });
         NativeFilter(filter_cond)
     }
 
+    /// Description of the filter for query plans or `None` if it matches every object. The
+    /// conditions use the names of the properties and are written like SQL conditions.
+    pub(crate) fn describe(
+        &self,
+        collection: &NativeCollection,
+        all_collections: &[NativeCollection],
+    ) -> Option<String> {
+        match &self.0 {
+            Filter::Static(StaticCond { value: true }) => None,
+            filter => Some(describe(filter, collection, all_collections, "")),
+        }
+    }
+
     pub(crate) fn evaluate(&self, id: i64, object: IsarDeserializer) -> bool {
         self.0.evaluate(id, object)
     }
@@ -281,9 +308,173 @@ enum Filter {
     And(AndCond),
     Or(OrCond),
     Not(NotCond),
+    Keyset(KeysetCond),
     Static(StaticCond),
 }
 
+fn describe(
+    filter: &Filter,
+    collection: &NativeCollection,
+    all_collections: &[NativeCollection],
+    prefix: &str,
+) -> String {
+    let name = |offset: u32| {
+        let name = collection
+            .properties
+            .iter()
+            .find(|(_, p)| p.offset == offset)
+            .map_or("?", |(name, _)| name.as_str());
+        format!("{}{}", prefix, name)
+    };
+    let string = |bytes: &Option<Vec<u8>>| match bytes {
+        Some(bytes) => format!("{:?}", String::from_utf8_lossy(bytes)),
+        None => "NULL".to_string(),
+    };
+    let value = |value: &Option<IsarValue>| match value {
+        Some(IsarValue::Bool(value)) => value.to_string(),
+        Some(IsarValue::Integer(value)) => value.to_string(),
+        Some(IsarValue::Real(value)) => value.to_string(),
+        Some(IsarValue::String(value)) => format!("{:?}", value),
+        None => "NULL".to_string(),
+    };
+    let collate = |case_sensitive: bool| {
+        if case_sensitive {
+            ""
+        } else {
+            " COLLATE NOCASE"
+        }
+    };
+    let join = |filters: &[Filter], separator: &str| {
+        let filters = filters
+            .iter()
+            .map(|f| describe(f, collection, all_collections, prefix))
+            .join(separator);
+        format!("({})", filters)
+    };
+    macro_rules! between {
+        ($cond:expr, $any:expr) => {
+            format!(
+                "{}{} BETWEEN {} AND {}",
+                $any,
+                name($cond.offset),
+                $cond.lower,
+                $cond.upper
+            )
+        };
+    }
+    macro_rules! string_op {
+        ($cond:expr, $any:expr, $op:expr) => {
+            format!(
+                "{}{} {} {:?}{}",
+                $any,
+                name($cond.offset),
+                $op,
+                $cond.value,
+                collate($cond.case_sensitive)
+            )
+        };
+    }
+    match filter {
+        Filter::IsNull(cond) => format!("{} IS NULL", name(cond.offset)),
+        Filter::IdBetween(cond) => {
+            let id_name = collection.id_name.as_deref().unwrap_or("id");
+            format!(
+                "{}{} BETWEEN {} AND {}",
+                prefix, id_name, cond.lower, cond.upper
+            )
+        }
+        Filter::BoolBetween(cond) => format!(
+            "{} BETWEEN {} AND {}",
+            name(cond.offset),
+            value(&cond.lower.map(IsarValue::Bool)),
+            value(&cond.upper.map(IsarValue::Bool))
+        ),
+        Filter::ByteBetween(cond) => between!(cond, ""),
+        Filter::IntBetween(cond) => between!(cond, ""),
+        Filter::LongBetween(cond) => between!(cond, ""),
+        Filter::FloatBetween(cond) => between!(cond, ""),
+        Filter::DoubleBetween(cond) => between!(cond, ""),
+        Filter::StringBetween(cond) => format!(
+            "{} BETWEEN {} AND {}{}",
+            name(cond.offset),
+            string(&cond.lower),
+            string(&cond.upper),
+            collate(cond.case_sensitive)
+        ),
+        Filter::StringEndsWith(cond) => string_op!(cond, "", "ENDS WITH"),
+        Filter::StringContains(cond) => string_op!(cond, "", "CONTAINS"),
+        Filter::StringMatches(cond) => string_op!(cond, "", "MATCHES"),
+        Filter::AnyByteBetween(cond) => between!(cond, "ANY "),
+        Filter::AnyBoolBetween(cond) => format!(
+            "ANY {} BETWEEN {} AND {}",
+            name(cond.offset),
+            value(&cond.lower.map(IsarValue::Bool)),
+            value(&cond.upper.map(IsarValue::Bool))
+        ),
+        Filter::AnyIntBetween(cond) => between!(cond, "ANY "),
+        Filter::AnyLongBetween(cond) => between!(cond, "ANY "),
+        Filter::AnyFloatBetween(cond) => between!(cond, "ANY "),
+        Filter::AnyDoubleBetween(cond) => between!(cond, "ANY "),
+        Filter::AnyStringBetween(cond) => format!(
+            "ANY {} BETWEEN {} AND {}{}",
+            name(cond.offset),
+            string(&cond.lower),
+            string(&cond.upper),
+            collate(cond.case_sensitive)
+        ),
+        Filter::AnyStringEndsWith(cond) => string_op!(cond, "ANY ", "ENDS WITH"),
+        Filter::AnyStringContains(cond) => string_op!(cond, "ANY ", "CONTAINS"),
+        Filter::AnyStringMatches(cond) => string_op!(cond, "ANY ", "MATCHES"),
+        Filter::Embedded(cond) => {
+            let embedded = collection
+                .properties
+                .iter()
+                .find(|(_, p)| p.offset == cond.offset)
+                .and_then(|(_, p)| p.embedded_collection_index)
+                .and_then(|index| all_collections.get(index as usize));
+            match embedded {
+                Some(embedded) => {
+                    let prefix = format!("{}.", name(cond.offset));
+                    describe(&cond.filter, embedded, all_collections, &prefix)
+                }
+                None => "FALSE".to_string(),
+            }
+        }
+        Filter::Json(cond) => format!(
+            "{}${} {:?} ({}){}",
+            name(cond.offset),
+            cond.path.iter().map(|p| format!(".{}", p)).join(""),
+            cond.condition_type,
+            cond.values.iter().map(value).join(", "),
+            collate(cond.case_sensitive)
+        ),
+        Filter::And(cond) => join(&cond.filters, " AND "),
+        Filter::Or(cond) => join(&cond.filters, " OR "),
+        Filter::Not(cond) => format!(
+            "NOT {}",
+            describe(&cond.filter, collection, all_collections, prefix)
+        ),
+        Filter::Keyset(cond) => {
+            let names = cond
+                .sort
+                .iter()
+                .map(|(p, sort, _)| {
+                    let name = p.map_or_else(
+                        || collection.id_name.as_deref().unwrap_or("id").to_string(),
+                        |p| name(p.offset),
+                    );
+                    let sort = if *sort == Sort::Asc { "ASC" } else { "DESC" };
+                    format!("{} {}", name, sort)
+                })
+                .join(", ");
+            let values = cond.token.values.iter().map(value);
+            let values = values.chain([cond.token.id.to_string()]).join(", ");
+            format!("({}) AFTER ({})", names, values)
+        }
+        Filter::Static(cond) => if cond.value { "TRUE" } else { "FALSE" }.to_string(),
+    }
+}
+
 #[enum_dispatch(Filter)]
 trait Condition {
     fn evaluate(&self, id: i64, object: IsarDeserializer) -> bool;
@@ -638,3 +829,71 @@ impl Condition for StaticCond {
         self.value
     }
 }
+
+#[derive(Clone, Debug)]
+struct KeysetCond {
+    sort: Vec<(Option<NativeProperty>, Sort, bool)>,
+    token: PageToken,
+}
+
+impl KeysetCond {
+    // Compares like the sorted query iterator with null values stored as the null sentinel.
+    fn compare_property(
+        object: IsarDeserializer,
+        property: &NativeProperty,
+        case_sensitive: bool,
+        value: Option<&IsarValue>,
+    ) -> Ordering {
+        let offset = property.offset;
+        match property.data_type {
+            DataType::Bool => object.read_bool(offset).cmp(&value.and_then(|v| v.bool())),
+            DataType::Byte => object
+                .read_byte(offset)
+                .cmp(&value.and_then(|v| v.u8()).unwrap_or(0)),
+            DataType::Int => object
+                .read_int(offset)
+                .cmp(&value.and_then(|v| v.i32()).unwrap_or(NULL_INT)),
+            DataType::Float => {
+                let value = value.and_then(|v| v.real()).map(|v| v as f32);
+                object
+                    .read_float(offset)
+                    .total_cmp(&value.unwrap_or(NULL_FLOAT))
+            }
+            DataType::Long => object
+                .read_long(offset)
+                .cmp(&value.and_then(|v| v.i64()).unwrap_or(NULL_LONG)),
+            DataType::Double => object
+                .read_double(offset)
+                .total_cmp(&value.and_then(|v| v.real()).unwrap_or(NULL_DOUBLE)),
+            DataType::String => {
+                let str = object.read_string(offset);
+                let value = value.and_then(|v| v.string());
+                if case_sensitive {
+                    str.cmp(&value)
+                } else {
+                    str.map(|s| s.to_lowercase())
+                        .cmp(&value.map(|s| s.to_lowercase()))
+                }
+            }
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+impl Condition for KeysetCond {
+    fn evaluate(&self, id: i64, object: IsarDeserializer) -> bool {
+        let mut values = self.token.values.iter();
+        for (property, sort, case_sensitive) in &self.sort {
+            let ord = if let Some(property) = property {
+                let value = values.next().and_then(|v| v.as_ref());
+                Self::compare_property(object, property, *case_sensitive, value)
+            } else {
+                id.cmp(&self.token.id)
+            };
+            if ord != Ordering::Equal {
+                return (ord == Ordering::Greater) == (*sort == Sort::Asc);
+            }
+        }
+        false
+    }
+}
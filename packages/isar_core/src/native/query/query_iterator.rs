@@ -2,7 +2,8 @@ use super::index_iterator::IndexIterator;
 use super::sorted_query_iterator::SortedQueryIterator;
 use super::unsorted_distinct_query_iterator::UnsortedDistinctQueryIterator;
 use super::unsorted_query_iterator::UnsortedQueryIterator;
-use super::NativeQuery;
+use super::{NativeQuery, QueryIndex};
+use crate::core::error::Result;
 use crate::native::isar_deserializer::IsarDeserializer;
 use crate::native::native_collection::NativeCollection;
 use crate::native::native_txn::NativeTxn;
@@ -21,19 +22,30 @@ impl<'a> QueryIterator<'a> {
         ignore_sort: bool,
         offset: u32,
         limit: u32,
-    ) -> Self {
-        let index_iterator = IndexIterator::new(txn, collection, &query.indexes);
-        if !query.sort.is_empty() && !ignore_sort {
-            QueryIterator::Sorted(SortedQueryIterator::new(
+    ) -> Result<Self> {
+        let has_duplicates = QueryIndex::has_duplicates(&query.indexes)
+            || query.indexes.iter().any(|i| i.is_multi_entry(collection));
+        // Objects that can be iterated in sort order are streamed.
+        let sorted_ranges = if ignore_sort {
+            Some(query.indexes.clone())
+        } else {
+            QueryIndex::sorted_ranges(&query.indexes, collection, &query.sort)
+        };
+        let Some(ranges) = sorted_ranges else {
+            let index_iterator = IndexIterator::new(txn, collection, &query.indexes)?;
+            return Ok(QueryIterator::Sorted(SortedQueryIterator::new(
                 index_iterator,
-                false,
+                has_duplicates,
                 &query.filter,
                 &query.sort,
                 &query.distinct,
                 offset,
                 limit,
-            ))
-        } else if !query.distinct.is_empty() {
+            )?));
+        };
+
+        let index_iterator = IndexIterator::new(txn, collection, &ranges)?;
+        let iterator = if !query.distinct.is_empty() {
             QueryIterator::UnsortedDistinct(UnsortedDistinctQueryIterator::new(
                 index_iterator,
                 &query.filter,
@@ -44,17 +56,18 @@ impl<'a> QueryIterator<'a> {
         } else {
             QueryIterator::Unsorted(UnsortedQueryIterator::new(
                 index_iterator,
-                false,
+                has_duplicates,
                 &query.filter,
                 offset,
                 limit,
             ))
-        }
+        };
+        Ok(iterator)
     }
 }
 
 impl<'txn> Iterator for QueryIterator<'txn> {
-    type Item = (i64, IsarDeserializer<'txn>);
+    type Item = Result<(i64, IsarDeserializer<'txn>)>;
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
@@ -1,12 +1,14 @@
 use super::index_iterator::IndexIterator;
 use super::native_filter::NativeFilter;
 use crate::core::data_type::DataType;
+use crate::core::error::Result;
 use crate::core::query_builder::Sort;
 use crate::native::isar_deserializer::IsarDeserializer;
 use crate::native::native_collection::NativeProperty;
 use intmap::IntMap;
 use itertools::Itertools;
 use std::cmp::Ordering;
+use std::collections::BinaryHeap;
 use std::iter::{Skip, Take};
 use std::vec::IntoIter;
 
@@ -16,49 +18,62 @@ pub(crate) struct SortedQueryIterator<'txn> {
 
 impl<'a> SortedQueryIterator<'a> {
     pub fn new(
-        mut iterator: IndexIterator<'a>,
+        iterator: IndexIterator<'a>,
         has_duplicates: bool,
         filter: &NativeFilter,
         sort: &[(Option<NativeProperty>, Sort, bool)],
         distinct: &[(NativeProperty, bool)],
         offset: u32,
         limit: u32,
-    ) -> SortedQueryIterator<'a> {
+    ) -> Result<SortedQueryIterator<'a>> {
         let mut returned_ids = if has_duplicates {
             Some(IntMap::new())
         } else {
             None
         };
 
-        let mut results = vec![];
-        while let Some((id, object)) = iterator.next() {
-            if let Some(returned_ids) = &mut returned_ids {
-                if returned_ids.insert(id as u64, ()).is_some() {
-                    continue;
+        let results = itertools::process_results(iterator, |objects| {
+            let matches = objects.filter(|(id, object)| {
+                if let Some(returned_ids) = &mut returned_ids
+                    && returned_ids.insert(*id as u64, ()).is_some()
+                {
+                    return false;
                 }
-            }
-            if filter.evaluate(id, object) {
-                results.push((id, object));
-            }
-        }
+                filter.evaluate(*id, *object)
+            });
 
-        results.sort_unstable_by(|(id1, o1), (id2, o2)| {
-            for (p, sort, case_sensitive) in sort {
-                let ord = if let Some(p) = p {
-                    Self::compare_property(o1, o2, p.offset, p.data_type, *case_sensitive)
-                } else {
-                    id1.cmp(id2)
-                };
-                if ord != Ordering::Equal {
-                    return if *sort == Sort::Asc {
-                        ord
-                    } else {
-                        ord.reverse()
+            // Without distinct only the first `offset + limit` objects are needed so we keep
+            // them in a bounded heap instead of sorting all matches.
+            let bound = offset.saturating_add(limit);
+            if distinct.is_empty() && bound != u32::MAX {
+                let mut heap = BinaryHeap::new();
+                for (position, (id, object)) in matches.enumerate() {
+                    let entry = SortEntry {
+                        position,
+                        id,
+                        object,
+                        sort,
                     };
+                    if heap.len() < bound as usize {
+                        heap.push(entry);
+                    } else if let Some(mut last) = heap.peek_mut()
+                        && entry < *last
+                    {
+                        *last = entry;
+                    }
                 }
+                heap.into_sorted_vec()
+                    .into_iter()
+                    .map(|entry| (entry.id, entry.object))
+                    .collect_vec()
+            } else {
+                let mut results = matches.collect_vec();
+                results.sort_unstable_by(|(id1, o1), (id2, o2)| {
+                    Self::compare(sort, *id1, o1, *id2, o2)
+                });
+                results
             }
-            Ordering::Equal
-        });
+        })?;
 
         if !distinct.is_empty() {
             let mut hashes = IntMap::new();
@@ -78,17 +93,41 @@ impl<'a> SortedQueryIterator<'a> {
                 .skip(offset as usize)
                 .take(limit as usize)
                 .collect_vec();
-            SortedQueryIterator {
+            Ok(SortedQueryIterator {
                 iterator: results.into_iter().skip(0).take(usize::MAX),
-            }
+            })
         } else {
-            SortedQueryIterator {
+            Ok(SortedQueryIterator {
                 iterator: results
                     .into_iter()
                     .skip(offset as usize)
                     .take(limit as usize),
+            })
+        }
+    }
+
+    fn compare(
+        sort: &[(Option<NativeProperty>, Sort, bool)],
+        id1: i64,
+        o1: &IsarDeserializer,
+        id2: i64,
+        o2: &IsarDeserializer,
+    ) -> Ordering {
+        for (p, sort, case_sensitive) in sort {
+            let ord = if let Some(p) = p {
+                Self::compare_property(o1, o2, p.offset, p.data_type, *case_sensitive)
+            } else {
+                id1.cmp(&id2)
+            };
+            if ord != Ordering::Equal {
+                return if *sort == Sort::Asc {
+                    ord
+                } else {
+                    ord.reverse()
+                };
             }
         }
+        Ordering::Equal
     }
 
     fn compare_property(
@@ -120,11 +159,41 @@ impl<'a> SortedQueryIterator<'a> {
     }
 }
 
+/// A match in the bounded heap. Equal objects are ordered by their position so the result is
+/// the same as a stable sort.
+struct SortEntry<'a, 'txn> {
+    position: usize,
+    id: i64,
+    object: IsarDeserializer<'txn>,
+    sort: &'a [(Option<NativeProperty>, Sort, bool)],
+}
+
+impl Ord for SortEntry<'_, '_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        SortedQueryIterator::compare(self.sort, self.id, &self.object, other.id, &other.object)
+            .then(self.position.cmp(&other.position))
+    }
+}
+
+impl PartialOrd for SortEntry<'_, '_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for SortEntry<'_, '_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for SortEntry<'_, '_> {}
+
 impl<'txn> Iterator for SortedQueryIterator<'txn> {
-    type Item = (i64, IsarDeserializer<'txn>);
+    type Item = Result<(i64, IsarDeserializer<'txn>)>;
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        self.iterator.next()
+        self.iterator.next().map(Ok)
     }
 }
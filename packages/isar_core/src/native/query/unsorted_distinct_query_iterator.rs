@@ -1,5 +1,6 @@
 use super::index_iterator::IndexIterator;
 use super::native_filter::NativeFilter;
+use crate::core::error::Result;
 use crate::native::isar_deserializer::IsarDeserializer;
 use crate::native::native_collection::NativeProperty;
 use intmap::IntMap;
@@ -33,11 +34,15 @@ impl<'a> UnsortedDistinctQueryIterator<'a> {
 }
 
 impl<'a> Iterator for UnsortedDistinctQueryIterator<'a> {
-    type Item = (i64, IsarDeserializer<'a>);
+    type Item = Result<(i64, IsarDeserializer<'a>)>;
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        while let Some((id, object)) = self.iterator.next() {
+        for next in self.iterator.by_ref() {
+            let (id, object) = match next {
+                Ok(next) => next,
+                Err(err) => return Some(Err(err)),
+            };
             if self.filter.evaluate(id, object) {
                 let hash = self
                     .properties
@@ -55,7 +60,7 @@ impl<'a> Iterator for UnsortedDistinctQueryIterator<'a> {
                         self.skip -= 1;
                     } else if self.take > 0 {
                         self.take -= 1;
-                        return Some((id, object));
+                        return Some(Ok((id, object)));
                     } else {
                         return None;
                     }
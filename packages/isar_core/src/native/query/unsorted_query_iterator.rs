@@ -1,4 +1,5 @@
 use super::{index_iterator::IndexIterator, native_filter::NativeFilter};
+use crate::core::error::Result;
 use crate::native::isar_deserializer::IsarDeserializer;
 use intmap::IntMap;
 
@@ -34,11 +35,15 @@ impl<'a> UnsortedQueryIterator<'a> {
 }
 
 impl<'a> Iterator for UnsortedQueryIterator<'a> {
-    type Item = (i64, IsarDeserializer<'a>);
+    type Item = Result<(i64, IsarDeserializer<'a>)>;
 
     #[inline]
     fn next(&mut self) -> Option<Self::Item> {
-        while let Some((id, object)) = self.iterator.next() {
+        for next in self.iterator.by_ref() {
+            let (id, object) = match next {
+                Ok(next) => next,
+                Err(err) => return Some(Err(err)),
+            };
             if let Some(returned_ids) = &mut self.returned_ids {
                 if returned_ids.insert(id as u64, ()).is_some() {
                     continue;
@@ -49,7 +54,7 @@ impl<'a> Iterator for UnsortedQueryIterator<'a> {
                     self.skip -= 1;
                 } else if self.take > 0 {
                     self.take -= 1;
-                    return Some((id, object));
+                    return Some(Ok((id, object)));
                 } else {
                     return None;
                 }
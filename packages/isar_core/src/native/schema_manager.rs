@@ -2,14 +2,15 @@ use super::mdbx::db::Db;
 use super::mdbx::env::Env;
 use super::native_collection::{NativeCollection, NativeProperty};
 use super::native_index::NativeIndex;
+use super::native_query_builder::filter_to_native;
 use super::native_txn::NativeTxn;
 use crate::core::error::{IsarError, Result};
+use crate::core::filter::Filter;
 use crate::core::schema::{IsarSchema, PropertySchema};
 use itertools::Itertools;
-use std::borrow::Cow;
 use std::sync::Arc;
 
-const ISAR_FILE_VERSION: u8 = 3;
+const ISAR_FILE_VERSION: u8 = 4;
 
 pub(crate) fn perform_migration(
     instance_id: u32,
@@ -23,33 +24,62 @@ pub(crate) fn perform_migration(
 
     let schema_names = schemas.iter().map(|c| c.name.to_string()).collect_vec();
 
+    // the properties of all schemas are required upfront to resolve the paths of indexes on
+    // embedded objects
+    let mut merged_properties = vec![];
+    let mut changed_embedded = vec![];
+    for (i, schema) in schemas.iter().enumerate() {
+        if let Some(existing_schema) = existing_schemas.iter().find(|c| c.name == schema.name) {
+            let (merged, changed) = merge_properties(schema, existing_schema);
+            if changed && schema.embedded {
+                changed_embedded.push(i as u16);
+            }
+            merged_properties.push(merged);
+        } else {
+            merged_properties.push(schema.properties.clone());
+        }
+    }
+    let all_properties = schemas
+        .iter()
+        .zip(&merged_properties)
+        .map(|(schema, merged_properties)| {
+            let mut properties = get_properties(merged_properties, &schema_names);
+            // sort properties by position in schema
+            properties.sort_by_key(|(name, _)| {
+                schema
+                    .properties
+                    .iter()
+                    .position(|p| p.name.as_ref() == Some(name))
+                    .unwrap()
+            });
+            properties
+        })
+        .collect_vec();
+
     let mut collections = vec![];
-    for schema in schemas.iter_mut() {
-        let existing_schema_index = existing_schemas.iter().position(|c| c.name == schema.name);
+    for (schema, merged_properties) in schemas.iter_mut().zip(merged_properties) {
+        let existing_schema = existing_schemas.iter().find(|c| c.name == schema.name);
 
         let txn = NativeTxn::new(instance_id, env, true)?;
-        let merged_properties = if let Some(existing_schema_index) = existing_schema_index {
-            let existing_schema = &existing_schemas[existing_schema_index];
-
-            let merged_properties = migrate_collection(&txn, &schema, existing_schema)?;
-            Cow::Owned(merged_properties)
+        let mut added_indexes = if let Some(existing_schema) = existing_schema {
+            migrate_collection(&txn, &schema, existing_schema, &merged_properties)?
         } else {
-            Cow::Borrowed(&schema.properties)
+            vec![]
         };
 
-        let mut properties = get_properties(&merged_properties, &schema_names);
-        // sort properties by position in schema
-        properties.sort_by_key(|(name, _)| {
-            schema
-                .properties
-                .iter()
-                .position(|p| p.name.as_ref() == Some(name))
-                .unwrap()
-        });
-
-        if let Cow::Owned(merged_properties) = merged_properties {
-            schema.properties = merged_properties;
+        let properties = all_properties[collections.len()].clone();
+        let filters = schema
+            .indexes
+            .iter()
+            .map(|i| i.filter.clone())
+            .collect_vec();
+        for index in &mut schema.indexes {
+            index.filter = index
+                .filter
+                .as_ref()
+                .map(|f| merge_filter(f, &schema.properties, &merged_properties));
         }
+        schema.properties = merged_properties;
         schema.version = ISAR_FILE_VERSION;
         save_schema(&txn, info_db, &schema)?;
 
@@ -62,24 +92,54 @@ pub(crate) fn perform_migration(
         let mut indexes = vec![];
         for index in &schema.indexes {
             let index_db = open_index_db(&txn, &schema.name, &index.name)?;
-            let properties = index
-                .properties
-                .iter()
-                .map(|p| {
-                    properties
-                        .iter()
-                        .find(|(name, _)| name == p)
-                        .unwrap()
-                        .1
-                        .clone()
-                })
-                .collect_vec();
-            let index =
-                NativeIndex::new(&index.name, index_db, properties, index.unique, index.hash);
+            if let Some(json_type) = index.json_type {
+                let mut path = index.properties[0].split('.').map(|p| p.to_string());
+                let name = path.next().unwrap();
+                let json_property = properties.iter().find(|(n, _)| n == &name).unwrap().1;
+                let property = NativeProperty::new(json_type, json_property.offset, None);
+                let index = NativeIndex::new(
+                    &index.name,
+                    index_db,
+                    vec![property],
+                    index.unique,
+                    index.hash,
+                    index.case_sensitive,
+                )
+                .with_json_path(path.collect())
+                .with_conflict(index.on_conflict);
+                indexes.push(index);
+                continue;
+            }
+
+            let mut index_properties = vec![];
+            let mut paths = vec![];
+            for index_property in &index.properties {
+                let (path, property) =
+                    resolve_index_property(index_property, &properties, &all_properties);
+                if path
+                    .iter()
+                    .any(|p| changed_embedded.contains(&p.embedded_collection_index.unwrap()))
+                    && !added_indexes.contains(&index.name)
+                {
+                    added_indexes.push(index.name.clone());
+                }
+                index_properties.push(property);
+                paths.push(path);
+            }
+            let index = NativeIndex::new(
+                &index.name,
+                index_db,
+                index_properties,
+                index.unique,
+                index.hash,
+                index.case_sensitive,
+            )
+            .with_paths(paths)
+            .with_conflict(index.on_conflict);
             indexes.push(index);
         }
 
-        let col = NativeCollection::new(
+        let mut col = NativeCollection::new(
             collections.len() as u16,
             &schema.name,
             schema.id_name.as_deref(),
@@ -87,9 +147,24 @@ pub(crate) fn perform_migration(
             indexes,
             db,
         );
+        let filters = filters
+            .iter()
+            .map(|f| f.as_ref().map(|f| filter_to_native(f, &col, &[])))
+            .collect_vec();
+        for (index, filter) in col.indexes.iter_mut().zip(filters) {
+            index.filter = filter;
+        }
 
         if !col.is_embedded() {
             col.init_auto_increment(&txn)?;
+            if !added_indexes.is_empty() {
+                let indexes = col
+                    .indexes
+                    .iter()
+                    .filter(|i| added_indexes.contains(&i.name))
+                    .collect_vec();
+                col.rebuild_indexes(&txn, &indexes)?;
+            }
         }
         txn.commit()?;
 
@@ -157,19 +232,78 @@ fn migrate_collection(
     txn: &NativeTxn,
     schema: &IsarSchema,
     existing_schema: &IsarSchema,
-) -> Result<Vec<PropertySchema>> {
+    merged_properties: &[PropertySchema],
+) -> Result<Vec<String>> {
     if existing_schema.version != ISAR_FILE_VERSION {
         return Err(IsarError::VersionError {});
     }
 
-    let (add_properties, drop_properties, add_indexes, drop_indexes) =
-        schema.find_changes(&existing_schema);
+    let (_, _, add_indexes, drop_indexes) = schema.find_changes(&existing_schema);
 
     for index in &drop_indexes {
         let index_db = open_index_db(txn, &schema.name, index)?;
         txn.drop_db(index_db)?;
     }
 
+    let mut added_indexes = add_indexes.iter().map(|i| i.name.clone()).collect_vec();
+    for index in &schema.indexes {
+        let filter = index
+            .filter
+            .as_ref()
+            .map(|f| merge_filter(f, &schema.properties, merged_properties));
+        let filter_changed = existing_schema
+            .indexes
+            .iter()
+            .any(|i| i.name == index.name && i.filter != filter);
+        if filter_changed && !added_indexes.contains(&index.name) {
+            added_indexes.push(index.name.clone());
+        }
+    }
+    Ok(added_indexes)
+}
+
+/// The saved schema contains the merged properties so the property positions of index filters
+/// are mapped to the positions of the merged properties.
+fn merge_filter(
+    filter: &Filter,
+    properties: &[PropertySchema],
+    merged_properties: &[PropertySchema],
+) -> Filter {
+    match filter {
+        Filter::Condition(condition) => {
+            let name = &properties[condition.property_index as usize - 1].name;
+            let position = merged_properties
+                .iter()
+                .position(|p| &p.name == name)
+                .unwrap();
+            let mut condition = condition.clone();
+            condition.property_index = position as u16 + 1;
+            Filter::Condition(condition)
+        }
+        Filter::And(filters) => Filter::And(
+            filters
+                .iter()
+                .map(|f| merge_filter(f, properties, merged_properties))
+                .collect(),
+        ),
+        Filter::Or(filters) => Filter::Or(
+            filters
+                .iter()
+                .map(|f| merge_filter(f, properties, merged_properties))
+                .collect(),
+        ),
+        _ => filter.clone(),
+    }
+}
+
+/// Merges the properties of the schema into the existing properties. Also returns whether
+/// existing properties were dropped or changed.
+fn merge_properties(
+    schema: &IsarSchema,
+    existing_schema: &IsarSchema,
+) -> (Vec<PropertySchema>, bool) {
+    let (add_properties, drop_properties, _, _) = schema.find_changes(existing_schema);
+
     let mut merged_properties = existing_schema.properties.clone();
 
     for property in &drop_properties {
@@ -185,7 +319,32 @@ fn migrate_collection(
         merged_properties.push(property.clone());
     }
 
-    Ok(merged_properties)
+    (merged_properties, !drop_properties.is_empty())
+}
+
+/// Resolves a (dotted) index property to the embedded object properties leading to it and the
+/// property itself.
+fn resolve_index_property(
+    index_property: &str,
+    properties: &[(String, NativeProperty)],
+    all_properties: &[Vec<(String, NativeProperty)>],
+) -> (Vec<NativeProperty>, NativeProperty) {
+    let mut properties = properties;
+    let mut path = vec![];
+    for name in index_property.split('.') {
+        let property = properties
+            .iter()
+            .find(|(n, _)| n == name)
+            .unwrap()
+            .1
+            .clone();
+        if let Some(embedded_collection_index) = property.embedded_collection_index {
+            properties = &all_properties[embedded_collection_index as usize];
+        }
+        path.push(property);
+    }
+    let property = path.pop().unwrap();
+    (path, property)
 }
 
 fn get_properties(
@@ -210,3 +369,131 @@ fn get_properties(
     }
     properties
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::core::cursor::IsarQueryCursor;
+    use crate::core::data_type::DataType;
+    use crate::core::error::{IsarError, Result};
+    use crate::core::filter::{ConditionType, Filter};
+    use crate::core::insert::IsarInsert;
+    use crate::core::instance::IsarInstance;
+    use crate::core::query_builder::IsarQueryBuilder;
+    use crate::core::reader::IsarReader;
+    use crate::core::schema::{ConflictPolicy, IndexSchema, IsarSchema, PropertySchema};
+    use crate::core::value::IsarValue;
+    use crate::core::writer::IsarWriter;
+    use crate::native::native_instance::NativeInstance;
+    use std::sync::Arc;
+
+    fn schema(indexes: Vec<IndexSchema>) -> IsarSchema {
+        IsarSchema::new(
+            "test",
+            Some("id"),
+            vec![PropertySchema::new("value", DataType::Long, None)],
+            indexes,
+            false,
+        )
+    }
+
+    /// Opens the instance with the schema, inserts the objects and closes it again.
+    fn create(name: &str, values: &[Option<i64>]) {
+        let instance = NativeInstance::open_temp(name, vec![schema(vec![])]).unwrap();
+        let txn = instance.begin_txn(true).unwrap();
+        let mut insert = instance.insert(txn, 0, values.len() as u32).unwrap();
+        for (id, value) in values.iter().enumerate() {
+            match value {
+                Some(value) => insert.write_long(1, *value),
+                None => insert.write_null(1),
+            }
+            insert.save(id as i64 + 1).unwrap();
+        }
+        let txn = insert.finish().unwrap();
+        instance.commit_txn(txn).unwrap();
+        NativeInstance::close(instance, false);
+    }
+
+    fn count(instance: &NativeInstance) -> u32 {
+        let txn = instance.begin_txn(false).unwrap();
+        let count = instance.count(&txn, 0).unwrap();
+        instance.abort_txn(txn);
+        count
+    }
+
+    fn query_ids(instance: &Arc<NativeInstance>, filter: Filter) -> Vec<i64> {
+        let mut qb = instance.query(0).unwrap();
+        qb.set_filter(filter);
+        let query = qb.build();
+        let txn = instance.begin_txn(false).unwrap();
+        let mut ids = vec![];
+        {
+            let mut cursor = instance.query_cursor(&txn, &query, None, None).unwrap();
+            while let Some(reader) = cursor.next() {
+                ids.push(reader.read_id());
+            }
+        }
+        instance.abort_txn(txn);
+        ids
+    }
+
+    #[test]
+    fn test_migrate_added_index() {
+        create(
+            "migrate_added_index",
+            &[Some(3), Some(1), Some(2), Some(1), None],
+        );
+
+        let index = IndexSchema::new("value", vec!["value"], false, false, true);
+        let instance =
+            NativeInstance::open_temp("migrate_added_index", vec![schema(vec![index])]).unwrap();
+        let txn = instance.begin_txn(false).unwrap();
+        instance.verify(&txn, true).unwrap();
+        instance.abort_txn(txn);
+
+        let equal = Filter::new_condition(
+            1,
+            ConditionType::Equal,
+            vec![Some(IsarValue::Integer(1))],
+            true,
+        );
+        assert_eq!(query_ids(&instance, equal), vec![2, 4]);
+        let between = Filter::new_condition(
+            1,
+            ConditionType::Between,
+            vec![Some(IsarValue::Integer(2)), Some(IsarValue::Integer(3))],
+            true,
+        );
+        let mut ids = query_ids(&instance, between);
+        ids.sort();
+        assert_eq!(ids, vec![1, 3]);
+        NativeInstance::close(instance, true);
+    }
+
+    fn migrate_to_unique_index(on_conflict: ConflictPolicy) -> Result<Arc<NativeInstance>> {
+        let name = format!("migrate_unique_index_{:?}", on_conflict);
+        create(&name, &[Some(1), Some(2), Some(1), None, None]);
+        let index =
+            IndexSchema::new("value", vec!["value"], true, false, true).with_conflict(on_conflict);
+        let result = NativeInstance::open_temp(&name, vec![schema(vec![index])]);
+
+        // the migration is rolled back so the old schema still has all objects
+        let instance = NativeInstance::open_temp(&name, vec![schema(vec![])]).unwrap();
+        assert_eq!(count(&instance), 5);
+        NativeInstance::close(instance, true);
+        result
+    }
+
+    #[test]
+    fn test_migrate_unique_index_with_duplicates() {
+        for on_conflict in [
+            ConflictPolicy::Replace,
+            ConflictPolicy::Ignore,
+            ConflictPolicy::Fail,
+        ] {
+            assert!(matches!(
+                migrate_to_unique_index(on_conflict),
+                Err(IsarError::UniqueViolation { index, existing_id: 1 }) if index == "value"
+            ));
+        }
+    }
+}
@@ -1,11 +1,12 @@
 use super::sql::{
-    add_column_sql, create_index_sql, create_table_sql, drop_column_sql, drop_index_sql,
-    sql_data_type,
+    add_column_sql, conflict_trigger_name, create_conflict_sql, create_index_sql,
+    create_list_index_sql, create_table_sql, drop_column_sql, drop_conflict_sql, drop_index_sql,
+    drop_list_index_sql, schema_index_filter_sql, sql_data_type,
 };
 use super::sqlite3::SQLite3;
 use super::sqlite_txn::SQLiteTxn;
 use crate::core::error::{IsarError, Result};
-use crate::core::schema::{IndexSchema, IsarSchema, PropertySchema};
+use crate::core::schema::{ConflictPolicy, IndexSchema, IsarSchema, PropertySchema};
 use crate::sqlite::sqlite_collection::SQLiteProperty;
 use itertools::Itertools;
 
@@ -17,13 +18,12 @@ pub(crate) fn perform_migration(txn: &SQLiteTxn, schemas: &[IsarSchema]) -> Resu
         for collection in schemas {
             if !collection.embedded {
                 if table_names.contains(&collection.name) {
-                    update_table(sqlite, collection)?;
+                    update_table(sqlite, collection, schemas)?;
                 } else {
                     let sql = create_table_sql(collection);
                     sqlite.prepare(&sql)?.step()?;
                     for index in &collection.indexes {
-                        let sql = create_index_sql(&collection.name, index);
-                        sqlite.prepare(&sql)?.step()?;
+                        create_index(sqlite, collection, index, schemas)?;
                     }
                 }
             }
@@ -31,6 +31,13 @@ pub(crate) fn perform_migration(txn: &SQLiteTxn, schemas: &[IsarSchema]) -> Resu
 
         for table in table_names {
             if !schemas.iter().any(|c| c.name == table && !c.embedded) {
+                // the tables of list indexes are not dropped automatically
+                let existing_schema = read_col_schema(sqlite, &table)?;
+                for index in &existing_schema.indexes {
+                    if is_list_index(&existing_schema, index) {
+                        drop_index(sqlite, &existing_schema, &index.name)?;
+                    }
+                }
                 let sql = format!("DROP TABLE {}", table);
                 sqlite.prepare(&sql)?.step()?;
             }
@@ -43,6 +50,7 @@ pub(crate) fn perform_migration(txn: &SQLiteTxn, schemas: &[IsarSchema]) -> Resu
 fn read_col_schema(sqlite: &SQLite3, name: &str) -> Result<IsarSchema> {
     let columns = sqlite.get_table_columns(name)?;
     let indexes = sqlite.get_table_indexes(name)?;
+    let triggers = sqlite.get_table_triggers(name)?;
 
     let mut properties = columns
         .iter()
@@ -65,24 +73,56 @@ fn read_col_schema(sqlite: &SQLite3, name: &str) -> Result<IsarSchema> {
 
     let indexes = indexes
         .iter()
-        .map(|(name, unique, cols)| {
-            let name = name.split('_').last().unwrap();
+        .map(|(index_name, unique, cols, case_sensitive, json_type)| {
+            let index_name = index_name.split('_').last().unwrap();
             let cols = cols.iter().map(|c| c.as_str()).collect();
-            IndexSchema::new(name, cols, *unique, false)
+            let mut index = IndexSchema::new(index_name, cols, *unique, false, *case_sensitive);
+            index.json_type = *json_type;
+            // the conflict policy is stored in the names of its triggers
+            for on_conflict in [ConflictPolicy::Fail, ConflictPolicy::Ignore] {
+                let trigger = conflict_trigger_name(name, index_name, "insert", on_conflict);
+                if triggers.contains(&trigger.unwrap()) {
+                    index.on_conflict = on_conflict;
+                }
+            }
+            index
         })
         .collect();
 
     Ok(IsarSchema::new(name, None, properties, indexes, false))
 }
 
-fn update_table(sqlite: &SQLite3, collection: &IsarSchema) -> Result<()> {
-    let existing_schema = read_col_schema(sqlite, &collection.name)?;
-    let (add_properties, drop_properties, add_indexes, drop_indexes) =
+fn update_table(sqlite: &SQLite3, collection: &IsarSchema, schemas: &[IsarSchema]) -> Result<()> {
+    let mut existing_schema = read_col_schema(sqlite, &collection.name)?;
+
+    // filters cannot be read from the database so partial indexes are compared using the SQL
+    // of their filter
+    let mut changed_filters = vec![];
+    for existing_index in &mut existing_schema.indexes {
+        if let Some(index) = collection
+            .indexes
+            .iter()
+            .find(|i| i.name == existing_index.name)
+        {
+            let index_name = format!("{}_{}", collection.name, index.name);
+            if sqlite.get_index_filter(&index_name)? != schema_index_filter_sql(collection, index) {
+                changed_filters.push(index);
+            }
+            existing_index.filter = index.filter.clone();
+        }
+    }
+
+    let (add_properties, drop_properties, mut add_indexes, mut drop_indexes) =
         collection.find_changes(&existing_schema);
+    for index in changed_filters {
+        if !drop_indexes.contains(&index.name) {
+            drop_indexes.push(index.name.clone());
+            add_indexes.push(index);
+        }
+    }
 
     for index in drop_indexes {
-        let sql = drop_index_sql(&collection.name, &index);
-        sqlite.prepare(&sql)?.step()?;
+        drop_index(sqlite, &existing_schema, &index)?;
     }
 
     for property in &drop_properties {
@@ -96,9 +136,59 @@ fn update_table(sqlite: &SQLite3, collection: &IsarSchema) -> Result<()> {
     }
 
     for index in &add_indexes {
-        let sql = create_index_sql(&collection.name, index);
+        create_index(sqlite, collection, index, schemas)?;
+    }
+
+    Ok(())
+}
+
+fn is_list_index(collection: &IsarSchema, index: &IndexSchema) -> bool {
+    collection
+        .properties
+        .iter()
+        .find(|p| p.name.as_ref() == index.properties.first())
+        .is_some_and(|p| p.data_type.is_list())
+}
+
+fn create_index(
+    sqlite: &SQLite3,
+    collection: &IsarSchema,
+    index: &IndexSchema,
+    schemas: &[IsarSchema],
+) -> Result<()> {
+    if is_list_index(collection, index) {
+        for sql in create_list_index_sql(collection, index) {
+            sqlite.prepare(&sql)?.step()?;
+        }
+    } else {
+        let sql = create_index_sql(collection, index, schemas);
         sqlite.prepare(&sql)?.step()?;
+        if index.on_conflict != ConflictPolicy::Replace {
+            for sql in create_conflict_sql(collection, index, schemas) {
+                sqlite.prepare(&sql)?.step()?;
+            }
+        }
     }
+    Ok(())
+}
 
+fn drop_index(sqlite: &SQLite3, existing_schema: &IsarSchema, index_name: &str) -> Result<()> {
+    let index = existing_schema
+        .indexes
+        .iter()
+        .find(|i| i.name == index_name);
+    if index.is_some_and(|index| is_list_index(existing_schema, index)) {
+        for sql in drop_list_index_sql(&existing_schema.name, index_name) {
+            sqlite.prepare(&sql)?.step()?;
+        }
+    } else {
+        if let Some(index) = index {
+            for sql in drop_conflict_sql(&existing_schema.name, index) {
+                sqlite.prepare(&sql)?.step()?;
+            }
+        }
+        let sql = drop_index_sql(&existing_schema.name, index_name);
+        sqlite.prepare(&sql)?.step()?;
+    }
     Ok(())
 }
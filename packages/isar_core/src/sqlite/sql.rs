@@ -1,12 +1,16 @@
+use super::sql_filter::filter_sql;
 use super::sqlite_collection::{SQLiteCollection, SQLiteProperty};
 use super::sqlite_query::QueryParam;
 use super::sqlite3::SQLite3;
 use crate::core::data_type::DataType;
-use crate::core::schema::{IndexSchema, IsarSchema, PropertySchema};
+use crate::core::filter::Filter;
+use crate::core::query_builder::Sort;
+use crate::core::schema::{ConflictPolicy, IndexSchema, IsarSchema, PropertySchema};
 use crate::core::value::IsarValue;
 use itertools::Itertools;
 use std::borrow::Cow;
 use std::cmp::min;
+use std::iter::once;
 use std::vec;
 
 pub(crate) fn create_table_sql(collection: &IsarSchema) -> String {
@@ -44,21 +48,370 @@ pub(crate) fn drop_column_sql(collection: &IsarSchema, property_name: &str) -> S
     )
 }
 
-pub(crate) fn create_index_sql(table_name: &str, index: &IndexSchema) -> String {
-    format!(
+pub(crate) fn create_index_sql(
+    collection: &IsarSchema,
+    index: &IndexSchema,
+    schemas: &[IsarSchema],
+) -> String {
+    let columns = index.properties.iter().map(|name| {
+        let property = collection.find_index_property(name, schemas);
+        let data_type = index.json_type.or(property.map(|p| p.data_type));
+        format!(
+            "{}{}",
+            index_column_sql(index, name),
+            index_collate_sql(index, data_type == Some(DataType::String))
+        )
+    });
+    let mut sql = format!(
         "CREATE {} INDEX {}_{} ON {} ({})",
         if index.unique { "UNIQUE" } else { "" },
-        table_name,
+        collection.name,
         index.name,
+        collection.name,
+        columns.collect_vec().join(", ")
+    );
+    if let Some(filter_sql) = schema_index_filter_sql(collection, index) {
+        sql.push_str(" WHERE ");
+        sql.push_str(&filter_sql);
+    }
+    sql
+}
+
+pub(crate) fn schema_index_filter_sql(
+    collection: &IsarSchema,
+    index: &IndexSchema,
+) -> Option<String> {
+    row_index_filter_sql(collection, index, "")
+}
+
+/// The filter of a partial index with the columns prefixed by `row` like `NEW.`.
+fn row_index_filter_sql(collection: &IsarSchema, index: &IndexSchema, row: &str) -> Option<String> {
+    let filter = index.filter.as_ref()?;
+    let properties = collection
+        .properties
+        .iter()
+        .map(|p| {
+            let name = format!("{}{}", row, p.name.as_deref().unwrap_or_default());
+            SQLiteProperty::new(&name, p.data_type, None)
+        })
+        .collect_vec();
+    Some(index_filter_sql(&properties, filter))
+}
+
+/// The condition of a partial index. The schema cannot contain parameters so the values are
+/// inlined.
+pub(crate) fn index_filter_sql(properties: &[SQLiteProperty], filter: &Filter) -> String {
+    let get_property = |_, property_index: u16| {
+        let index = (property_index as usize).checked_sub(1)?;
+        properties.get(index)
+    };
+    let (sql, params) = filter_sql(0, &get_property, &[], filter.clone());
+    let mut params = params.into_iter();
+    let mut filter_sql = String::new();
+    for char in sql.chars() {
+        if char == '?'
+            && let Some(QueryParam::Value(value)) = params.next()
+        {
+            filter_sql.push_str(&sql_literal(&value));
+        } else {
+            filter_sql.push(char);
+        }
+    }
+    filter_sql
+}
+
+fn sql_literal(value: &IsarValue) -> String {
+    match value {
+        IsarValue::Bool(value) => (if *value { "1" } else { "0" }).to_string(),
+        IsarValue::Integer(value) => value.to_string(),
+        IsarValue::Real(value) => format!("{:?}", value),
+        IsarValue::String(value) => format!("'{}'", value.replace('\'', "''")),
+    }
+}
+
+/// Properties of embedded objects are indexed using expressions that extract them from the
+/// JSON column of the object. JSON path indexes only extract values of the declared type.
+pub(crate) fn index_column_sql(index: &IndexSchema, property: &str) -> String {
+    row_index_column_sql(index, property, "")
+}
+
+pub(crate) fn row_index_column_sql(index: &IndexSchema, property: &str, row: &str) -> String {
+    if let Some((column, path)) = property.split_once('.') {
+        let column = format!("{}{}", row, column);
+        let extract_sql = format!("json_extract({}, '$.{}')", column, path);
+        if let Some(json_type) = index.json_type {
+            format!(
+                "CASE WHEN json_valid({}) AND json_type({}, '$.{}') IN ({}) THEN {} END",
+                column,
+                column,
+                path,
+                json_type_sql(json_type),
+                extract_sql
+            )
+        } else {
+            extract_sql
+        }
+    } else {
+        format!("{}{}", row, property)
+    }
+}
+
+fn json_type_sql(data_type: DataType) -> &'static str {
+    match data_type {
+        DataType::Bool => "'true', 'false'",
+        DataType::Long => "'integer'",
+        DataType::Double => "'integer', 'real'",
+        _ => "'text'",
+    }
+}
+
+/// Reverses `index_column_sql` for the columns of an existing index. Also returns the type
+/// of JSON path indexes.
+pub(crate) fn index_column_property(column_sql: &str) -> Option<(String, Option<DataType>)> {
+    let column_sql = column_sql.trim();
+    let extract_start = column_sql.find("json_extract(")?;
+    let extract_end = extract_start + column_sql[extract_start..].find(')')?;
+    let args = &column_sql[extract_start + "json_extract(".len()..extract_end];
+    let (column, path) = args.split_once(',')?;
+    let path = path.trim().strip_prefix("'$.")?.strip_suffix('\'')?;
+    let property = format!("{}.{}", column.trim(), path);
+
+    if extract_start == 0 {
+        return Some((property, None));
+    }
+    let types_start = column_sql.find(" IN (")? + " IN (".len();
+    let types_end = types_start + column_sql[types_start..].find(')')?;
+    let json_type = [
+        DataType::Bool,
+        DataType::Long,
+        DataType::Double,
+        DataType::String,
+    ]
+    .into_iter()
+    .find(|t| json_type_sql(*t) == &column_sql[types_start..types_end])?;
+    Some((property, Some(json_type)))
+}
+
+/// Case-insensitive indexes use the same collation as case-insensitive filters.
+fn index_collate_sql(index: &IndexSchema, is_string: bool) -> &'static str {
+    if !index.case_sensitive && is_string {
+        " COLLATE NOCASE"
+    } else {
+        ""
+    }
+}
+
+/// Prefix of the tables that are maintained next to the collection tables. Collection names
+/// must not begin with an underscore so they never use it.
+pub(crate) const INTERNAL_TABLE_PREFIX: &str = "_isar_";
+
+/// Table in which the triggers of unique indexes with the `Fail` policy record conflicts.
+pub(crate) const CONFLICT_TABLE: &str = "_isar_conflict";
+
+pub(crate) fn conflict_trigger_name(
+    table_name: &str,
+    index_name: &str,
+    event: &str,
+    on_conflict: ConflictPolicy,
+) -> Option<String> {
+    let policy = match on_conflict {
+        ConflictPolicy::Replace => return None,
+        ConflictPolicy::Fail => "fail",
+        ConflictPolicy::Ignore => "ignore",
+    };
+    Some(format!(
+        "{}_{}_{}_{}",
+        table_name, index_name, event, policy
+    ))
+}
+
+/// Unique indexes replace other rows using `INSERT OR REPLACE`. For other policies triggers look
+/// for a conflicting row before a row is written and skip it. Conflicts of the `Fail` policy
+/// are recorded first so they can be reported after the statement.
+pub(crate) fn create_conflict_sql(
+    collection: &IsarSchema,
+    index: &IndexSchema,
+    schemas: &[IsarSchema],
+) -> Vec<String> {
+    let table_name = &collection.name;
+    let key_sql = index.properties.iter().map(|name| {
+        let property = collection.find_index_property(name, schemas);
+        let data_type = index.json_type.or(property.map(|p| p.data_type));
+        format!(
+            "{} = {}{}",
+            index_column_sql(index, name),
+            row_index_column_sql(index, name, "NEW."),
+            index_collate_sql(index, data_type == Some(DataType::String))
+        )
+    });
+    let mut conflict_sql = format!(
+        "FROM {} WHERE {} AND {} != NEW.{}",
         table_name,
-        index.properties.join(", ")
-    )
+        key_sql.collect_vec().join(" AND "),
+        SQLiteProperty::ID_NAME,
+        SQLiteProperty::ID_NAME
+    );
+    let mut when_sql = String::new();
+    if let Some(filter_sql) = schema_index_filter_sql(collection, index) {
+        conflict_sql.push_str(&format!(" AND {}", filter_sql));
+        let new_filter_sql = row_index_filter_sql(collection, index, "NEW.").unwrap();
+        when_sql.push_str(&format!("{} AND ", new_filter_sql));
+    }
+
+    let mut sql = vec![];
+    let mut record_sql = String::new();
+    if index.on_conflict == ConflictPolicy::Fail {
+        sql.push(format!(
+            "CREATE TABLE IF NOT EXISTS {} (idx TEXT NOT NULL, id INTEGER NOT NULL)",
+            CONFLICT_TABLE
+        ));
+        record_sql = format!(
+            "INSERT INTO {} SELECT '{}', {} {} LIMIT 1; ",
+            CONFLICT_TABLE,
+            index.name,
+            SQLiteProperty::ID_NAME,
+            conflict_sql
+        );
+    }
+    for event in ["insert", "update"] {
+        let trigger_name =
+            conflict_trigger_name(table_name, &index.name, event, index.on_conflict).unwrap();
+        sql.push(format!(
+            "CREATE TRIGGER {} BEFORE {} ON {} WHEN {}EXISTS (SELECT 1 {}) BEGIN \
+            {}SELECT RAISE(IGNORE); END",
+            trigger_name,
+            event.to_ascii_uppercase(),
+            table_name,
+            when_sql,
+            conflict_sql,
+            record_sql
+        ));
+    }
+    sql
+}
+
+pub(crate) fn drop_conflict_sql(table_name: &str, index: &IndexSchema) -> Vec<String> {
+    ["insert", "update"]
+        .iter()
+        .filter_map(|event| {
+            conflict_trigger_name(table_name, &index.name, event, index.on_conflict)
+        })
+        .map(|trigger_name| format!("DROP TRIGGER {}", trigger_name))
+        .collect()
+}
+
+pub(crate) fn select_conflict_sql() -> String {
+    format!("SELECT idx, id FROM {} LIMIT 1", CONFLICT_TABLE)
 }
 
 pub(crate) fn drop_index_sql(table_name: &str, index_name: &str) -> String {
     format!("DROP INDEX {}_{}", table_name, index_name)
 }
 
+pub(crate) fn reindex_sql(table_name: &str, index_name: &str) -> String {
+    format!("REINDEX {}_{}", table_name, index_name)
+}
+
+/// Selects the oldest object with the same key as another object of a unique index. Keys with
+/// a null value are never duplicates like in SQLite's unique indexes.
+pub(crate) fn select_duplicate_sql(collection: &SQLiteCollection, index: &IndexSchema) -> String {
+    let columns = index
+        .properties
+        .iter()
+        .map(|name| index_column_sql(index, name))
+        .collect_vec();
+    let mut conditions = columns
+        .iter()
+        .map(|column| format!("{} IS NOT NULL", column))
+        .collect_vec();
+    if let Some(filter) = &index.filter {
+        let filter_sql = index_filter_sql(&collection.properties, filter);
+        conditions.push(format!("({})", filter_sql));
+    }
+    // the collation only affects strings
+    let keys = columns
+        .iter()
+        .map(|column| format!("{}{}", column, index_collate_sql(index, true)))
+        .join(", ");
+    format!(
+        "SELECT MIN(_rowid_) FROM {} WHERE {} GROUP BY {} HAVING COUNT(*) > 1 ORDER BY 1 LIMIT 1",
+        collection.name,
+        conditions.join(" AND "),
+        keys
+    )
+}
+
+/// Name of the table that stores the elements of a list index.
+pub(crate) fn list_index_table(table_name: &str, index_name: &str) -> String {
+    format!("{}{}_{}", INTERNAL_TABLE_PREFIX, table_name, index_name)
+}
+
+/// List indexes store one row for every element in a separate table. Triggers keep it in sync
+/// with the list column. `INSERT OR REPLACE` does not fire delete triggers so the insert
+/// trigger removes stale rows first.
+pub(crate) fn create_list_index_sql(collection: &IsarSchema, index: &IndexSchema) -> Vec<String> {
+    let table_name = &collection.name;
+    let index_table = list_index_table(table_name, &index.name);
+    let property = &index.properties[0];
+    let is_string = collection
+        .properties
+        .iter()
+        .any(|p| p.name.as_ref() == Some(property) && p.data_type == DataType::StringList);
+    let insert_sql = format!(
+        "INSERT INTO {} SELECT value, NEW._rowid_ FROM json_each(NEW.{})",
+        index_table, property
+    );
+    vec![
+        format!(
+            "CREATE TABLE {} ({}, _id INTEGER NOT NULL)",
+            index_table, property
+        ),
+        format!(
+            "CREATE INDEX {}_value ON {} ({}{}, _id)",
+            index_table,
+            index_table,
+            property,
+            index_collate_sql(index, is_string)
+        ),
+        format!("CREATE INDEX {}_id ON {} (_id)", index_table, index_table),
+        format!(
+            "CREATE TRIGGER {}_insert AFTER INSERT ON {} BEGIN \
+            DELETE FROM {} WHERE _id = NEW._rowid_; {}; END",
+            index_table, table_name, index_table, insert_sql
+        ),
+        format!(
+            "CREATE TRIGGER {}_update AFTER UPDATE OF {} ON {} BEGIN \
+            DELETE FROM {} WHERE _id = OLD._rowid_; {}; END",
+            index_table, property, table_name, index_table, insert_sql
+        ),
+        format!(
+            "CREATE TRIGGER {}_delete AFTER DELETE ON {} BEGIN \
+            DELETE FROM {} WHERE _id = OLD._rowid_; END",
+            index_table, table_name, index_table
+        ),
+        fill_list_index_sql(table_name, &index.name, property),
+    ]
+}
+
+pub(crate) fn fill_list_index_sql(table_name: &str, index_name: &str, property: &str) -> String {
+    format!(
+        "INSERT INTO {} SELECT j.value, t._rowid_ FROM {} t, json_each(t.{}) j",
+        list_index_table(table_name, index_name),
+        table_name,
+        property
+    )
+}
+
+pub(crate) fn drop_list_index_sql(table_name: &str, index_name: &str) -> Vec<String> {
+    let index_table = list_index_table(table_name, index_name);
+    vec![
+        format!("DROP TRIGGER {}_insert", index_table),
+        format!("DROP TRIGGER {}_update", index_table),
+        format!("DROP TRIGGER {}_delete", index_table),
+        format!("DROP TABLE {}", index_table),
+    ]
+}
+
 pub(crate) fn select_properties_sql(collection: &SQLiteCollection) -> String {
     let mut sql = String::new();
     sql.push_str(SQLiteProperty::ID_NAME);
@@ -69,6 +422,22 @@ pub(crate) fn select_properties_sql(collection: &SQLiteCollection) -> String {
     sql
 }
 
+/// Selects `NULL` instead of the properties that are not in the projection so the columns still
+/// match the property indexes.
+pub(crate) fn select_projection_sql(collection: &SQLiteCollection, properties: &[u16]) -> String {
+    let mut sql = String::new();
+    sql.push_str(SQLiteProperty::ID_NAME);
+    for (i, prop) in collection.properties.iter().enumerate() {
+        sql.push(',');
+        if properties.contains(&(i as u16 + 1)) {
+            sql.push_str(&prop.name);
+        } else {
+            sql.push_str("NULL");
+        }
+    }
+    sql
+}
+
 pub(crate) fn insert_sql(name: &str, properties: &[SQLiteProperty], count: u32) -> (u32, String) {
     let mut sql = String::new();
     sql.push_str("INSERT OR REPLACE INTO ");
@@ -103,6 +472,75 @@ pub(crate) fn insert_sql(name: &str, properties: &[SQLiteProperty], count: u32)
     (batch_size, sql)
 }
 
+/// Inserts a single object and returns its id unless a conflict trigger skipped it.
+pub(crate) fn insert_returning_sql(name: &str, properties: &[SQLiteProperty]) -> String {
+    let (_, sql) = insert_sql(name, properties, 1);
+    format!("{} RETURNING {}", sql, SQLiteProperty::ID_NAME)
+}
+
+/// Inserts a single object and merges it with the object that has the same key in the given
+/// unique index. Each property has a flag parameter after the values that decides whether the
+/// new or the existing value is kept. Returns the id of the saved object.
+pub(crate) fn upsert_sql(collection: &SQLiteCollection, index: &IndexSchema) -> String {
+    let properties = &collection.properties;
+    let columns = once(SQLiteProperty::ID_NAME)
+        .chain(properties.iter().map(|p| p.name.as_str()))
+        .join(", ");
+    let values = (1..=properties.len() + 1)
+        .map(|i| format!("?{}", i))
+        .join(", ");
+    let merged = properties
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            let flag = properties.len() + 2 + i;
+            format!("iif(?{}, _new.{}, _old.{})", flag, p.name, p.name)
+        })
+        .join(", ");
+
+    let mut join_sql = index
+        .properties
+        .iter()
+        .map(|name| {
+            format!(
+                "{} = {}{}",
+                row_index_column_sql(index, name, "_old."),
+                row_index_column_sql(index, name, "_new."),
+                index_collate_sql(index, true)
+            )
+        })
+        .join(" AND ");
+    if let Some(filter) = &index.filter {
+        for row in ["_old.", "_new."] {
+            let properties = properties
+                .iter()
+                .map(|p| SQLiteProperty::new(&format!("{}{}", row, p.name), p.data_type, None))
+                .collect_vec();
+            join_sql.push_str(&format!(" AND {}", index_filter_sql(&properties, filter)));
+        }
+    }
+
+    let mut sql = format!(
+        "WITH _new ({}) AS (VALUES ({})) INSERT OR REPLACE INTO {} ({}) SELECT coalesce(_old.{}, _new.{})",
+        columns,
+        values,
+        collection.name,
+        columns,
+        SQLiteProperty::ID_NAME,
+        SQLiteProperty::ID_NAME
+    );
+    if !merged.is_empty() {
+        sql.push_str(&format!(", {}", merged));
+    }
+    sql.push_str(&format!(
+        " FROM _new LEFT JOIN {} AS _old ON {} RETURNING {}",
+        collection.name,
+        join_sql,
+        SQLiteProperty::ID_NAME
+    ));
+    sql
+}
+
 pub(crate) fn update_properties_sql(
     collection: &SQLiteCollection,
     updates: &[(u16, Option<IsarValue>)],
@@ -126,6 +564,98 @@ pub(crate) fn update_properties_sql(
     (sql, params)
 }
 
+/// The INDEXED BY, WHERE, ORDER BY and GROUP BY clauses of a query.
+pub(crate) fn query_sql<S: AsRef<str>>(
+    index_sql: &str,
+    conditions: &[String],
+    sort: &[(S, Sort, bool)],
+    distinct: &[(S, bool)],
+) -> String {
+    let mut sql = index_sql.to_string();
+    if !conditions.is_empty() {
+        sql.push_str(" WHERE ");
+        sql.push_str(&conditions.join(" AND "));
+    }
+    if !sort.is_empty() {
+        sql.push_str(" ORDER BY ");
+        sql.push_str(
+            &sort
+                .iter()
+                .map(|(prop, sort, case_sensitive)| {
+                    format!(
+                        "{} COLLATE {}{}",
+                        prop.as_ref(),
+                        sort_collation(*case_sensitive),
+                        if *sort == Sort::Asc { "" } else { " DESC" }
+                    )
+                })
+                .join(", "),
+        );
+    }
+    if !distinct.is_empty() {
+        sql.push_str(" GROUP BY ");
+        sql.push_str(
+            &distinct
+                .iter()
+                .map(|(prop, case_sensitive)| {
+                    format!(
+                        "{} COLLATE {}",
+                        prop.as_ref(),
+                        sort_collation(*case_sensitive)
+                    )
+                })
+                .join(", "),
+        );
+    }
+    sql
+}
+
+fn sort_collation(case_sensitive: bool) -> &'static str {
+    if case_sensitive { "BINARY" } else { "NOCASE" }
+}
+
+/// Lexicographic condition for the rows after the given values in the sort order. Null is
+/// smaller than any other value.
+pub(crate) fn keyset_sql(
+    sort: &[(String, Sort, bool)],
+    values: &[Option<IsarValue>],
+    params: &mut Vec<QueryParam>,
+) -> String {
+    let (Some((property, sort_order, case_sensitive)), Some(value)) =
+        (sort.first(), values.first())
+    else {
+        return "FALSE".to_string();
+    };
+    let collate = sort_collation(*case_sensitive);
+
+    let mut push_value = |sql: String| {
+        if let Some(value) = value {
+            params.push(QueryParam::Value(value.clone()));
+        }
+        sql
+    };
+    let after_sql = match (sort_order, value) {
+        (Sort::Asc, None) => format!("{} IS NOT NULL", property),
+        (Sort::Asc, Some(_)) => push_value(format!("{} > ? COLLATE {}", property, collate)),
+        (Sort::Desc, None) => "FALSE".to_string(),
+        (Sort::Desc, Some(_)) => push_value(format!(
+            "({} < ? COLLATE {} OR {} IS NULL)",
+            property, collate, property
+        )),
+    };
+    if sort.len() == 1 {
+        return after_sql;
+    }
+
+    let equal_sql = if value.is_some() {
+        push_value(format!("{} = ? COLLATE {}", property, collate))
+    } else {
+        format!("{} IS NULL", property)
+    };
+    let rest_sql = keyset_sql(&sort[1..], &values[1..], params);
+    format!("({} OR ({} AND {}))", after_sql, equal_sql, rest_sql)
+}
+
 pub(crate) fn offset_limit_sql(offset: Option<u32>, limit: Option<u32>) -> String {
     let mut sql = String::new();
     if let Some(offset) = offset {
@@ -1,3 +1,4 @@
+use super::sql::index_column_sql;
 use super::sqlite_collection::SQLiteProperty;
 use super::sqlite_query::{JsonCondition, QueryParam};
 use super::sqlite3::SQLiteFnContext;
@@ -5,25 +6,29 @@ use crate::core::data_type::DataType;
 use crate::core::error::Result;
 use crate::core::filter::{ConditionType, Filter, FilterCondition, FilterJson};
 use crate::core::filter_json::matches_json;
+use crate::core::schema::IndexSchema;
 use crate::core::value::IsarValue;
 use serde_json::Value;
 use std::borrow::Cow;
 use std::vec;
 
+/// `indexes` contains the indexes on embedded properties and JSON paths.
 pub(crate) fn filter_sql<'a, G>(
     collection_index: u16,
     get_property: &G,
+    indexes: &[&IndexSchema],
     filter: Filter,
 ) -> (String, Vec<QueryParam>)
 where
     G: Fn(u16, u16) -> Option<&'a SQLiteProperty>,
 {
-    filter_sql_path(collection_index, get_property, filter, vec![])
+    filter_sql_path(collection_index, get_property, indexes, filter, vec![])
 }
 
 fn filter_sql_path<'a, G>(
     collection_index: u16,
     get_property: &G,
+    indexes: &[&IndexSchema],
     filter: Filter,
     mut path: Vec<String>,
 ) -> (String, Vec<QueryParam>)
@@ -33,18 +38,24 @@ where
     match filter {
         Filter::Condition(condition) => {
             let property = get_property(collection_index, condition.property_index);
-            filter_condition(property, condition, path)
+            filter_condition(property, condition, path, indexes)
         }
         Filter::Json(json) => {
             let property = get_property(collection_index, json.property_index);
-            filter_json(property, json)
+            filter_json(property, json, indexes)
         }
         Filter::Embedded(embedded) => {
             let property = get_property(collection_index, embedded.property_index);
             if let Some(property) = property {
                 if let Some(collection_index) = property.collection_index {
                     path.push(property.name.clone());
-                    return filter_sql_path(collection_index, get_property, *embedded.filter, path);
+                    return filter_sql_path(
+                        collection_index,
+                        get_property,
+                        indexes,
+                        *embedded.filter,
+                        path,
+                    );
                 }
             }
             ("FALSE".to_string(), vec![])
@@ -56,8 +67,13 @@ where
                 if !sql.is_empty() {
                     sql.push_str(" AND ");
                 }
-                let (filter_sql, filter_params) =
-                    filter_sql_path(collection_index, get_property, filter, path.clone());
+                let (filter_sql, filter_params) = filter_sql_path(
+                    collection_index,
+                    get_property,
+                    indexes,
+                    filter,
+                    path.clone(),
+                );
                 sql.push_str(&filter_sql);
                 params.extend(filter_params.into_iter());
             }
@@ -70,17 +86,25 @@ where
                 if !sql.is_empty() {
                     sql.push_str(" OR ");
                 }
-                let (filter_sql, filter_params) =
-                    filter_sql_path(collection_index, get_property, filter, path.clone());
+                let (filter_sql, filter_params) = filter_sql_path(
+                    collection_index,
+                    get_property,
+                    indexes,
+                    filter,
+                    path.clone(),
+                );
                 sql.push_str(&filter_sql);
                 params.extend(filter_params.into_iter());
             }
             (format!("({})", sql), params)
         }
         Filter::Not(filter) => {
-            let (sql, params) = filter_sql_path(collection_index, get_property, *filter, path);
+            let (sql, params) =
+                filter_sql_path(collection_index, get_property, indexes, *filter, path);
             (format!("NOT {}", sql), params)
         }
+        // queries cannot be run until their parameters are bound
+        Filter::Parameter(_) => ("FALSE".to_string(), vec![]),
     }
 }
 
@@ -88,14 +112,24 @@ fn filter_condition(
     property: Option<&SQLiteProperty>,
     condition: FilterCondition,
     mut path: Vec<String>,
+    indexes: &[&IndexSchema],
 ) -> (String, Vec<QueryParam>) {
     let property_type = property.map(|p| p.data_type).unwrap_or(DataType::Long);
     let property_name = property
         .map(|p| p.name.as_str())
         .unwrap_or(SQLiteProperty::ID_NAME);
     if !path.is_empty() {
-        let column_name = path.remove(0);
         path.push(property_name.to_string());
+        let indexed_path = path.join(".");
+        let index = indexes
+            .iter()
+            .find(|i| i.json_type.is_none() && i.properties[0] == indexed_path);
+        let prefilter = index.and_then(|index| {
+            let index_sql = index_column_sql(index, &indexed_path);
+            index_prefilter(&index_sql, property_type, &condition)
+        });
+
+        let column_name = path.remove(0);
         let sql = format!("{}({}, ?)", FN_FILTER_JSON_NAME, column_name);
         let condition = JsonCondition {
             path,
@@ -103,10 +137,28 @@ fn filter_condition(
             values: condition.values,
             case_sensitive: condition.case_sensitive,
         };
-        (sql, vec![QueryParam::JsonCondition(condition)])
+        let param = QueryParam::JsonCondition(condition);
+        if let Some((prefilter_sql, mut params)) = prefilter {
+            params.push(param);
+            (format!("({} AND {})", prefilter_sql, sql), params)
+        } else {
+            (sql, vec![param])
+        }
     } else if property_type.is_list() {
+        let index_table = property.and_then(|p| p.index_table.as_deref());
         if condition.condition_type == ConditionType::IsNull {
             (format!("{} IS NULL", property_name), vec![])
+        } else if let Some(index_table) = index_table
+            && let Some((sql, params)) = filter_condition_type(property_name, &condition)
+        {
+            // the index table contains a row for every element
+            let sql = format!(
+                "{} IN (SELECT _id FROM {} WHERE {})",
+                SQLiteProperty::ID_NAME,
+                index_table,
+                sql
+            );
+            (sql, params)
         } else {
             let sql = format!(
                 "({} IS NOT NULL AND {}({}, ?))",
@@ -125,22 +177,113 @@ fn filter_condition(
     }
 }
 
-fn filter_json(property: Option<&SQLiteProperty>, json: FilterJson) -> (String, Vec<QueryParam>) {
+/// Compares the index expression of an embedded property or JSON path so that SQLite can use
+/// the index. The result is a superset of the matching objects and the JSON filter still has
+/// to be evaluated. Case-insensitive string conditions are not prefiltered.
+fn index_prefilter(
+    index_sql: &str,
+    property_type: DataType,
+    condition: &FilterCondition,
+) -> Option<(String, Vec<QueryParam>)> {
+    let supported_type = matches!(
+        property_type,
+        DataType::Byte | DataType::Int | DataType::Long | DataType::Float | DataType::Double
+    ) || property_type == DataType::String && condition.case_sensitive;
+    let supported_condition = matches!(
+        condition.condition_type,
+        ConditionType::IsNull
+            | ConditionType::Equal
+            | ConditionType::Greater
+            | ConditionType::GreaterOrEqual
+            | ConditionType::Less
+            | ConditionType::LessOrEqual
+            | ConditionType::Between
+            | ConditionType::StringStartsWith
+    );
+    if !supported_type || !supported_condition {
+        None
+    } else if condition.condition_type == ConditionType::StringStartsWith {
+        // LIKE cannot use indexes with the default collation
+        let prefix = condition.values.first()?.as_ref()?.string()?;
+        let sql = format!("({} >= ? AND {} <= ?)", index_sql, index_sql);
+        let values = vec![
+            QueryParam::Value(IsarValue::String(prefix.to_string())),
+            QueryParam::Value(IsarValue::String(format!(
+                "{}{}",
+                prefix,
+                IsarValue::MAX_STRING
+            ))),
+        ];
+        Some((sql, values))
+    } else {
+        filter_condition_type(index_sql, condition)
+    }
+}
+
+fn filter_json(
+    property: Option<&SQLiteProperty>,
+    json: FilterJson,
+    indexes: &[&IndexSchema],
+) -> (String, Vec<QueryParam>) {
     if let Some(property) = property {
         if property.data_type == DataType::Json {
-            let sql = format!("{}({}, ?)", property.name, FN_FILTER_JSON_NAME);
+            let prefilter = json_index_prefilter(property, &json, indexes);
+            let sql = format!("{}({}, ?)", FN_FILTER_JSON_NAME, property.name);
             let condition = JsonCondition {
                 path: json.path,
                 condition_type: json.condition_type,
                 values: json.values,
                 case_sensitive: json.case_sensitive,
             };
-            return (sql, vec![QueryParam::JsonCondition(condition)]);
+            let param = QueryParam::JsonCondition(condition);
+            if let Some((prefilter_sql, mut params)) = prefilter {
+                params.push(param);
+                return (format!("({} AND {})", prefilter_sql, sql), params);
+            }
+            return (sql, vec![param]);
         }
     }
     ("FALSE".to_string(), vec![])
 }
 
+/// Values of other types than the declared type of a JSON path index are indexed as null so
+/// the null values always have to be included.
+fn json_index_prefilter(
+    property: &SQLiteProperty,
+    json: &FilterJson,
+    indexes: &[&IndexSchema],
+) -> Option<(String, Vec<QueryParam>)> {
+    let path = format!("{}.{}", property.name, json.path.join("."));
+    let index = indexes.iter().find(|i| i.properties[0] == path)?;
+    let json_type = index.json_type?;
+    let matches_type = json.values.iter().all(|value| {
+        matches!(
+            (json_type, value),
+            (DataType::Bool, Some(IsarValue::Bool(_)))
+                | (DataType::Long, Some(IsarValue::Integer(_)))
+                | (DataType::Double, Some(IsarValue::Real(_)))
+                | (DataType::String, Some(IsarValue::String(_)))
+        )
+    });
+    if !matches_type {
+        return None;
+    }
+
+    let condition = FilterCondition {
+        property_index: 0,
+        condition_type: json.condition_type,
+        values: json.values.clone(),
+        case_sensitive: json.case_sensitive,
+    };
+    let index_sql = index_column_sql(index, &path);
+    let (sql, params) = index_prefilter(&index_sql, json_type, &condition)?;
+    if json.condition_type == ConditionType::IsNull {
+        Some((sql, params))
+    } else {
+        Some((format!("({} OR {} IS NULL)", sql, index_sql), params))
+    }
+}
+
 fn filter_condition_type(
     property_name: &str,
     condition: &FilterCondition,
@@ -229,10 +372,23 @@ fn filter_condition_type(
         }
         ConditionType::StringStartsWith => {
             if let Some(IsarValue::String(prefix)) = condition.values.get(0)? {
-                values.push(IsarValue::String(format!("{}%", escape_wildcard(prefix))));
-                match condition.case_sensitive {
-                    true => format!("{} LIKE ? ESCAPE '\\'", property_name),
-                    false => format!("LOWER({}) LIKE LOWER(?) ESCAPE '\\'", property_name),
+                if condition.case_sensitive {
+                    values.push(IsarValue::String(format!("{}%", escape_wildcard(prefix))));
+                    format!("{} LIKE ? ESCAPE '\\'", property_name)
+                } else {
+                    // the range allows SQLite to use case-insensitive indexes
+                    values.push(IsarValue::String(prefix.clone()));
+                    values.push(IsarValue::String(format!(
+                        "{}{}",
+                        prefix,
+                        IsarValue::MAX_STRING
+                    )));
+                    values.push(IsarValue::String(format!("{}%", escape_wildcard(prefix))));
+                    format!(
+                        "({p} >= ?{c} AND {p} <= ?{c} AND LOWER({p}) LIKE LOWER(?) ESCAPE '\\')",
+                        p = property_name,
+                        c = collate
+                    )
                 }
             } else {
                 "FALSE".to_string()
@@ -1,15 +1,29 @@
+use crate::core::data_type::DataType;
 use crate::core::error::{IsarError, Result};
 use ffi::sqlite3_busy_timeout;
+use itertools::Itertools;
 use libsqlite3_sys as ffi;
-use std::cell::Cell;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
 use std::ffi::{CStr, CString, c_char, c_int, c_void};
 use std::{ptr, slice};
 
+use super::sql::{INTERNAL_TABLE_PREFIX, index_column_property};
 use super::sql_filter::{FN_FILTER_JSON_NAME, sql_fn_filter_json};
+use super::sqlite_query::create_statistics_functions;
+
+/// Name, uniqueness, columns, case sensitivity and JSON type of an index.
+pub(crate) type TableIndex = (String, bool, Vec<String>, bool, Option<DataType>);
+
+/// Columns, case sensitivity and JSON type of an index.
+type IndexColumns = (Vec<String>, bool, Option<DataType>);
 
 pub(crate) struct SQLite3 {
     db: *mut ffi::sqlite3,
     free_update_hook: Cell<Option<unsafe extern "C" fn(*mut std::os::raw::c_void)>>,
+    // Prepared statements that are not in use, the most recently used last.
+    statement_cache: RefCell<Vec<(String, *mut ffi::sqlite3_stmt)>>,
 }
 
 unsafe impl Send for SQLite3 {}
@@ -17,6 +31,8 @@ unsafe impl Send for SQLite3 {}
 impl SQLite3 {
     pub(crate) const MAX_PARAM_COUNT: u32 = 999;
 
+    const STATEMENT_CACHE_SIZE: usize = 16;
+
     pub fn open(path: &str, encryption_key: Option<&str>) -> Result<SQLite3> {
         let flags = ffi::SQLITE_OPEN_READWRITE | ffi::SQLITE_OPEN_CREATE | ffi::SQLITE_OPEN_NOMUTEX;
         let c_path = CString::new(path).unwrap();
@@ -27,6 +43,7 @@ impl SQLite3 {
                 let sqlite = SQLite3 {
                     db,
                     free_update_hook: Cell::new(None),
+                    statement_cache: RefCell::new(vec![]),
                 };
                 if let Some(encryption_key) = encryption_key {
                     sqlite
@@ -65,9 +82,25 @@ impl SQLite3 {
         }
         self.prepare("PRAGMA case_sensitive_like = true")?.step()?;
         self.create_function(FN_FILTER_JSON_NAME, 2, sql_fn_filter_json)?;
+        create_statistics_functions(self)?;
+        self.create_case_folding()?;
         Ok(())
     }
 
+    /// `NOCASE` and `LOWER()` only fold ASCII characters. They are replaced by versions that
+    /// lowercase like the native backend so case-insensitive filters, sorts and indexes match
+    /// the same strings on both backends.
+    fn create_case_folding(&self) -> Result<()> {
+        self.create_collation("NOCASE", |a, b| a.to_lowercase().cmp(&b.to_lowercase()))?;
+        self.create_function("lower", 1, |ctx| {
+            if !ctx.is_null(0) {
+                let lowercase = ctx.get_str(0).to_lowercase();
+                ctx.set_str_result(&lowercase);
+            }
+            Ok(())
+        })
+    }
+
     pub fn prepare(&self, sql: &str) -> Result<SQLiteStatement> {
         let mut stmt: *mut ffi::sqlite3_stmt = ptr::null_mut();
         let mut c_tail = ptr::null();
@@ -80,13 +113,46 @@ impl SQLite3 {
                 &mut c_tail as *mut *const c_char,
             );
             if r == ffi::SQLITE_OK {
-                Ok(SQLiteStatement { stmt, sqlite: self })
+                Ok(SQLiteStatement {
+                    stmt,
+                    sqlite: self,
+                    cache_key: None,
+                })
             } else {
                 Err(sqlite_err(self.db, r))
             }
         }
     }
 
+    /// Like [`prepare`](Self::prepare) but reuses a statement with the same SQL that was
+    /// prepared before. The statement is reset and returned to the cache when it is dropped.
+    pub fn prepare_cached(&self, sql: &str) -> Result<SQLiteStatement<'_>> {
+        let mut cache = self.statement_cache.borrow_mut();
+        if let Some(position) = cache.iter().rposition(|(cached_sql, _)| cached_sql == sql) {
+            let (sql, stmt) = cache.remove(position);
+            return Ok(SQLiteStatement {
+                stmt,
+                sqlite: self,
+                cache_key: Some(sql),
+            });
+        }
+        drop(cache);
+        let mut statement = self.prepare(sql)?;
+        statement.cache_key = Some(sql.to_string());
+        Ok(statement)
+    }
+
+    fn return_to_cache(&self, sql: String, stmt: *mut ffi::sqlite3_stmt) {
+        let mut cache = self.statement_cache.borrow_mut();
+        if cache.len() >= Self::STATEMENT_CACHE_SIZE {
+            let (_, oldest) = cache.remove(0);
+            unsafe {
+                ffi::sqlite3_finalize(oldest);
+            }
+        }
+        cache.push((sql, stmt));
+    }
+
     pub fn get_table_names(&self) -> Result<Vec<String>> {
         let mut stmt = self.prepare("PRAGMA table_list")?;
         let mut names = vec![];
@@ -94,7 +160,9 @@ impl SQLite3 {
             let table_type = stmt.get_text(2);
             if table_type == "table" {
                 let name = stmt.get_text(1);
-                if !name.to_ascii_lowercase().starts_with("sqlite_") {
+                if !name.to_ascii_lowercase().starts_with("sqlite_")
+                    && !name.starts_with(INTERNAL_TABLE_PREFIX)
+                {
                     names.push(name.to_string());
                 }
             }
@@ -113,7 +181,7 @@ impl SQLite3 {
         Ok(cols)
     }
 
-    pub fn get_table_indexes(&self, table_name: &str) -> Result<Vec<(String, bool, Vec<String>)>> {
+    pub fn get_table_indexes(&self, table_name: &str) -> Result<Vec<TableIndex>> {
         let mut stmt = self.prepare(&format!("PRAGMA index_list({})", table_name))?;
         let mut index_names_unique = vec![];
         while stmt.step()? {
@@ -125,16 +193,92 @@ impl SQLite3 {
         }
         let mut indexes = vec![];
         for (index_name, unique) in index_names_unique {
-            let mut stmt = self.prepare(&format!("PRAGMA index_info({})", index_name))?;
-            let mut cols = vec![];
-            while stmt.step()? {
-                cols.push(stmt.get_text(2).to_string());
+            let (cols, case_sensitive, json_type) = self.get_index_columns(&index_name)?;
+            indexes.push((index_name, unique, cols, case_sensitive, json_type));
+        }
+
+        // list indexes are stored in separate tables that are maintained by triggers
+        let index_tables = self
+            .get_table_triggers(table_name)?
+            .into_iter()
+            .filter_map(|trigger| Some(trigger.strip_suffix("_insert")?.to_string()))
+            .collect_vec();
+        for index_table in index_tables {
+            let (mut cols, case_sensitive, _) =
+                self.get_index_columns(&format!("{}_value", index_table))?;
+            if let Some(index_name) = index_table.strip_prefix(INTERNAL_TABLE_PREFIX)
+                && !cols.is_empty()
+            {
+                // the shadow index also covers the _id column
+                cols.truncate(1);
+                indexes.push((index_name.to_string(), false, cols, case_sensitive, None));
             }
-            indexes.push((index_name, unique, cols));
         }
         Ok(indexes)
     }
 
+    pub fn get_table_triggers(&self, table_name: &str) -> Result<Vec<String>> {
+        let mut stmt =
+            self.prepare("SELECT name FROM sqlite_master WHERE type = 'trigger' AND tbl_name = ?")?;
+        stmt.bind_text(0, table_name)?;
+        let mut names = vec![];
+        while stmt.step()? {
+            names.push(stmt.get_text(0).to_string());
+        }
+        Ok(names)
+    }
+
+    fn get_index_columns(&self, index_name: &str) -> Result<IndexColumns> {
+        let mut stmt = self.prepare(&format!("PRAGMA index_xinfo({})", index_name))?;
+        let mut cols = vec![];
+        let mut expressions = vec![];
+        let mut case_sensitive = true;
+        let mut json_type = None;
+        while stmt.step()? {
+            // skip auxiliary columns like the rowid
+            if stmt.get_int(5) == 1 {
+                if stmt.get_int(1) == -2 {
+                    expressions.push(cols.len());
+                }
+                cols.push(stmt.get_text(2).to_string());
+                case_sensitive &= !stmt.get_text(4).eq_ignore_ascii_case("NOCASE");
+            }
+        }
+
+        // expression columns have no name so they are read from the index definition
+        if !expressions.is_empty()
+            && let Some(sql) = self.get_index_sql(index_name)?
+        {
+            let (columns_sql, _) = split_index_sql(&sql);
+            for i in expressions {
+                if let Some((property, data_type)) =
+                    columns_sql.get(i).and_then(|c| index_column_property(c))
+                {
+                    cols[i] = property;
+                    json_type = json_type.or(data_type);
+                }
+            }
+        }
+        Ok((cols, case_sensitive, json_type))
+    }
+
+    /// Returns the `WHERE` clause of a partial index.
+    pub fn get_index_filter(&self, index_name: &str) -> Result<Option<String>> {
+        let sql = self.get_index_sql(index_name)?;
+        Ok(sql.and_then(|sql| split_index_sql(&sql).1.map(|filter| filter.to_string())))
+    }
+
+    fn get_index_sql(&self, index_name: &str) -> Result<Option<String>> {
+        let mut stmt =
+            self.prepare("SELECT sql FROM sqlite_master WHERE type = 'index' AND name = ?")?;
+        stmt.bind_text(0, index_name)?;
+        if stmt.step()? {
+            Ok(Some(stmt.get_text(0).to_string()))
+        } else {
+            Ok(None)
+        }
+    }
+
     pub fn count_changes(&self) -> i32 {
         unsafe { ffi::sqlite3_changes(self.db) }
     }
@@ -191,6 +335,135 @@ impl SQLite3 {
         }
     }
 
+    /// Registers a collation that compares strings using the given function.
+    pub fn create_collation<F>(&self, name: &str, compare: F) -> Result<()>
+    where
+        F: Fn(&str, &str) -> Ordering + Send + 'static,
+    {
+        unsafe extern "C" fn call_boxed_closure<F>(
+            compare: *mut c_void,
+            len1: c_int,
+            str1: *const c_void,
+            len2: c_int,
+            str2: *const c_void,
+        ) -> c_int
+        where
+            F: Fn(&str, &str) -> Ordering,
+        {
+            let to_str = |str: *const c_void, len: c_int| {
+                if len > 0 {
+                    let bytes = unsafe { slice::from_raw_parts(str.cast::<u8>(), len as usize) };
+                    String::from_utf8_lossy(bytes)
+                } else {
+                    Cow::Borrowed("")
+                }
+            };
+            let compare = unsafe { &*compare.cast::<F>() };
+            compare(&to_str(str1, len1), &to_str(str2, len2)) as c_int
+        }
+
+        let boxed_f = Box::into_raw(Box::new(compare));
+        let c_name = CString::new(name).unwrap();
+        let r = unsafe {
+            ffi::sqlite3_create_collation_v2(
+                self.db,
+                c_name.as_ptr(),
+                ffi::SQLITE_UTF8,
+                boxed_f.cast(),
+                Some(call_boxed_closure::<F>),
+                Some(free_boxed_value::<F>),
+            )
+        };
+
+        if r == ffi::SQLITE_OK {
+            Ok(())
+        } else {
+            Err(sqlite_err(self.db, r))
+        }
+    }
+
+    /// Registers an aggregate function. Every group starts with the default state, `step` is
+    /// called for every row of the group and `finish` sets the result.
+    pub fn create_aggregate_function<A, S, F>(
+        &self,
+        name: &str,
+        args: u32,
+        step: S,
+        finish: F,
+    ) -> Result<()>
+    where
+        A: Default,
+        S: Fn(&mut A, &SQLiteFnContext<'_>) + Send + 'static,
+        F: Fn(A, &mut SQLiteFnContext<'_>) + Send + 'static,
+    {
+        unsafe extern "C" fn call_step<A, S, F>(
+            ctx: *mut ffi::sqlite3_context,
+            argc: c_int,
+            argv: *mut *mut ffi::sqlite3_value,
+        ) where
+            A: Default,
+            S: Fn(&mut A, &SQLiteFnContext<'_>),
+        {
+            let fn_ctx = SQLiteFnContext {
+                ctx,
+                args: unsafe { slice::from_raw_parts(argv, argc as usize) },
+            };
+            // the aggregate context is zeroed memory that stores a pointer to the state
+            let state = unsafe {
+                ffi::sqlite3_aggregate_context(ctx, size_of::<*mut A>() as c_int).cast::<*mut A>()
+            };
+            if state.is_null() {
+                unsafe { ffi::sqlite3_result_error_nomem(ctx) };
+                return;
+            }
+            unsafe {
+                if (*state).is_null() {
+                    *state = Box::into_raw(Box::<A>::default());
+                }
+                let (step, _) = &*ffi::sqlite3_user_data(ctx).cast::<(S, F)>();
+                step(&mut **state, &fn_ctx);
+            }
+        }
+
+        unsafe extern "C" fn call_finish<A, S, F>(ctx: *mut ffi::sqlite3_context)
+        where
+            A: Default,
+            F: Fn(A, &mut SQLiteFnContext<'_>),
+        {
+            let state = unsafe { ffi::sqlite3_aggregate_context(ctx, 0).cast::<*mut A>() };
+            let value = if state.is_null() || unsafe { (*state).is_null() } {
+                A::default()
+            } else {
+                unsafe { *Box::from_raw(*state) }
+            };
+            let mut fn_ctx = SQLiteFnContext { ctx, args: &[] };
+            let (_, finish) = unsafe { &*ffi::sqlite3_user_data(ctx).cast::<(S, F)>() };
+            finish(value, &mut fn_ctx);
+        }
+
+        let boxed_f = Box::into_raw(Box::new((step, finish)));
+        let c_name = CString::new(name).unwrap();
+        let r = unsafe {
+            ffi::sqlite3_create_function_v2(
+                self.db,
+                c_name.as_ptr(),
+                args as i32,
+                ffi::SQLITE_UTF8,
+                boxed_f.cast(),
+                None,
+                Some(call_step::<A, S, F>),
+                Some(call_finish::<A, S, F>),
+                Some(free_boxed_value::<(S, F)>),
+            )
+        };
+
+        if r == ffi::SQLITE_OK {
+            Ok(())
+        } else {
+            Err(sqlite_err(self.db, r))
+        }
+    }
+
     pub fn set_update_hook<F>(&self, func: F)
     where
         F: FnMut(i64) + 'static,
@@ -229,6 +502,9 @@ impl SQLite3 {
 impl Drop for SQLite3 {
     fn drop(&mut self) {
         unsafe {
+            for (_, stmt) in self.statement_cache.get_mut().drain(..) {
+                ffi::sqlite3_finalize(stmt);
+            }
             ffi::sqlite3_close(self.db);
         }
     }
@@ -245,6 +521,15 @@ pub(crate) struct SQLiteFnContext<'a> {
 
 #[allow(dead_code)]
 impl<'a> SQLiteFnContext<'a> {
+    pub fn is_numeric(&self, index: usize) -> bool {
+        let value_type = unsafe { ffi::sqlite3_value_type(self.args[index]) };
+        value_type == ffi::SQLITE_INTEGER || value_type == ffi::SQLITE_FLOAT
+    }
+
+    pub fn is_null(&self, index: usize) -> bool {
+        unsafe { ffi::sqlite3_value_type(self.args[index]) == ffi::SQLITE_NULL }
+    }
+
     pub fn get_int(&self, index: usize) -> i64 {
         unsafe { ffi::sqlite3_value_int64(self.args[index]) }
     }
@@ -348,6 +633,8 @@ impl<'a> SQLiteFnContext<'a> {
 pub(crate) struct SQLiteStatement<'sqlite> {
     stmt: *mut ffi::sqlite3_stmt,
     sqlite: &'sqlite SQLite3,
+    // The SQL of statements that are returned to the cache.
+    cache_key: Option<String>,
 }
 
 impl<'sqlite> SQLiteStatement<'sqlite> {
@@ -375,6 +662,17 @@ impl<'sqlite> SQLiteStatement<'sqlite> {
         }
     }
 
+    pub fn clear_bindings(&mut self) -> Result<()> {
+        unsafe {
+            let r = ffi::sqlite3_clear_bindings(self.stmt);
+            if r == ffi::SQLITE_OK {
+                Ok(())
+            } else {
+                Err(sqlite_err(self.sqlite.db, r))
+            }
+        }
+    }
+
     pub fn is_null(&self, col: u32) -> bool {
         unsafe { ffi::sqlite3_column_type(self.stmt, col as i32) == ffi::SQLITE_NULL }
     }
@@ -512,7 +810,13 @@ impl<'sqlite> SQLiteStatement<'sqlite> {
 impl Drop for SQLiteStatement<'_> {
     fn drop(&mut self) {
         unsafe {
-            ffi::sqlite3_finalize(self.stmt);
+            if let Some(sql) = self.cache_key.take() {
+                ffi::sqlite3_reset(self.stmt);
+                ffi::sqlite3_clear_bindings(self.stmt);
+                self.sqlite.return_to_cache(sql, self.stmt);
+            } else {
+                ffi::sqlite3_finalize(self.stmt);
+            }
         }
     }
 }
@@ -535,3 +839,146 @@ impl<'a> ToOwned for SQLiteStatement<'a> {
         panic!("SQLiteStatement can't be cloned")
     }
 }
+
+/// Splits the column list of a `CREATE INDEX` statement and removes the collations. Also
+/// returns the `WHERE` clause of partial indexes.
+fn split_index_sql(sql: &str) -> (Vec<&str>, Option<&str>) {
+    let mut columns = vec![];
+    let mut filter = None;
+    let Some(start) = sql.find('(') else {
+        return (columns, filter);
+    };
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut column_start = start + 1;
+    for (i, char) in sql.char_indices().filter(|(i, _)| *i > start) {
+        match char {
+            '\'' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted && depth > 0 => depth -= 1,
+            ',' | ')' if !quoted && depth == 0 => {
+                let column = sql[column_start..i].trim();
+                columns.push(column.strip_suffix(" COLLATE NOCASE").unwrap_or(column));
+                column_start = i + 1;
+                if char == ')' {
+                    filter = sql[i + 1..].trim().strip_prefix("WHERE ");
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    (columns, filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prepare_cached() {
+        let sqlite = SQLite3::open(":memory:", None).unwrap();
+        let sql = "SELECT ?, ?";
+
+        let mut stmt = sqlite.prepare_cached(sql).unwrap();
+        let raw_stmt = stmt.stmt;
+        stmt.bind_long(0, 1).unwrap();
+        stmt.bind_long(1, 2).unwrap();
+        assert!(stmt.step().unwrap());
+        assert_eq!((stmt.get_long(0), stmt.get_long(1)), (1, 2));
+        drop(stmt);
+        assert_eq!(sqlite.statement_cache.borrow().len(), 1);
+
+        // The statement is reused without the values bound before
+        let mut stmt = sqlite.prepare_cached(sql).unwrap();
+        assert_eq!(stmt.stmt, raw_stmt);
+        assert!(sqlite.statement_cache.borrow().is_empty());
+        stmt.bind_long(1, 3).unwrap();
+        assert!(stmt.step().unwrap());
+        assert!(stmt.is_null(0));
+        assert_eq!(stmt.get_long(1), 3);
+
+        // A statement in use is not shared
+        let other = sqlite.prepare_cached(sql).unwrap();
+        assert_ne!(other.stmt, raw_stmt);
+        drop(stmt);
+        drop(other);
+        assert_eq!(sqlite.statement_cache.borrow().len(), 2);
+
+        // The least recently used statements are finalized
+        for i in 0..SQLite3::STATEMENT_CACHE_SIZE {
+            sqlite.prepare_cached(&format!("SELECT {}", i)).unwrap();
+        }
+        let cache = sqlite.statement_cache.borrow();
+        assert_eq!(cache.len(), SQLite3::STATEMENT_CACHE_SIZE);
+        assert!(cache.iter().all(|(cached_sql, _)| cached_sql != sql));
+    }
+
+    #[test]
+    fn test_case_folding() {
+        let sqlite = SQLite3::open(":memory:", None).unwrap();
+        let query_long = |sql: &str| {
+            let mut stmt = sqlite.prepare(sql).unwrap();
+            assert!(stmt.step().unwrap());
+            stmt.get_long(0)
+        };
+        assert_eq!(query_long("SELECT 'ÄPFEL' = 'äpfel' COLLATE NOCASE"), 1);
+        assert_eq!(query_long("SELECT 'Äpfel' < 'b' COLLATE NOCASE"), 0);
+        assert_eq!(query_long("SELECT lower('ÄPFEL') = 'äpfel'"), 1);
+        assert_eq!(query_long("SELECT lower(NULL) IS NULL"), 1);
+
+        sqlite
+            .prepare("CREATE TABLE test (value TEXT)")
+            .unwrap()
+            .step()
+            .unwrap();
+        sqlite
+            .prepare("CREATE INDEX test_value ON test (value COLLATE NOCASE)")
+            .unwrap()
+            .step()
+            .unwrap();
+        for value in ["Äpfel", "äpfel", "apfel", "Birne"] {
+            let mut stmt = sqlite.prepare("INSERT INTO test VALUES (?)").unwrap();
+            stmt.bind_text(0, value).unwrap();
+            stmt.step().unwrap();
+        }
+        assert_eq!(
+            query_long("SELECT COUNT(*) FROM test WHERE value = 'ÄPFEL' COLLATE NOCASE"),
+            2
+        );
+        assert_eq!(
+            query_long("SELECT COUNT(*) FROM test WHERE lower(value) LIKE lower('Ä%')"),
+            2
+        );
+
+        // the index is ordered by the same collation
+        let mut stmt = sqlite.prepare("PRAGMA integrity_check").unwrap();
+        assert!(stmt.step().unwrap());
+        assert_eq!(stmt.get_text(0), "ok");
+    }
+
+    #[test]
+    fn test_get_table_names() {
+        let sqlite = SQLite3::open(":memory:", None).unwrap();
+        for sql in [
+            "CREATE TABLE test (a)",
+            "CREATE TABLE _other (a)",
+            "CREATE TABLE _isar_conflict (a)",
+            "CREATE TRIGGER test_insert AFTER INSERT ON test BEGIN SELECT 1; END",
+            "CREATE TRIGGER \"it's\" AFTER INSERT ON _other BEGIN SELECT 1; END",
+            "CREATE INDEX \"it's\" ON test (a)",
+        ] {
+            sqlite.prepare(sql).unwrap().step().unwrap();
+        }
+        assert_eq!(sqlite.get_table_names().unwrap(), vec!["_other", "test"]);
+        assert_eq!(
+            sqlite.get_table_triggers("test").unwrap(),
+            vec!["test_insert"]
+        );
+        assert_eq!(sqlite.get_table_triggers("_other").unwrap(), vec!["it's"]);
+        assert_eq!(
+            sqlite.get_index_sql("it's").unwrap(),
+            Some("CREATE INDEX \"it's\" ON test (a)".to_string())
+        );
+    }
+}
@@ -1,11 +1,13 @@
 use std::sync::Arc;
 use std::sync::atomic::{self, AtomicI64};
 
+use super::sql::{select_conflict_sql, select_duplicate_sql};
 use super::sqlite_query::SQLiteQuery;
 use super::sqlite_txn::SQLiteTxn;
+use super::sqlite3::SQLite3;
 use crate::core::data_type::DataType;
-use crate::core::error::Result;
-use crate::core::schema::IndexSchema;
+use crate::core::error::{IsarError, Result};
+use crate::core::schema::{ConflictPolicy, IndexSchema};
 use crate::core::watcher::CollectionWatchers;
 
 #[derive(Debug)]
@@ -14,6 +16,8 @@ pub(crate) struct SQLiteProperty {
     pub data_type: DataType,
     // for embedded objects
     pub collection_index: Option<u16>,
+    // table of the list index on this property
+    pub index_table: Option<String>,
 }
 
 impl SQLiteProperty {
@@ -24,6 +28,7 @@ impl SQLiteProperty {
             name: name.to_string(),
             data_type,
             collection_index,
+            index_table: None,
         }
     }
 }
@@ -35,7 +40,7 @@ pub(crate) struct SQLiteCollection {
     pub watchers: Arc<CollectionWatchers<SQLiteQuery>>,
     auto_increment: AtomicI64,
 
-    // these are only used for verification
+    // these are only used for verification and unique conflicts
     pub indexes: Vec<IndexSchema>,
 }
 
@@ -60,6 +65,41 @@ impl SQLiteCollection {
         self.id_name.is_none()
     }
 
+    /// Returns the first unique conflict recorded by the triggers of indexes with the `Fail`
+    /// policy. The transaction is rolled back afterwards so the conflict table stays empty.
+    pub fn check_conflicts(&self, sqlite: &SQLite3) -> Result<()> {
+        let fails = self
+            .indexes
+            .iter()
+            .any(|i| i.on_conflict == ConflictPolicy::Fail);
+        if !fails {
+            return Ok(());
+        }
+
+        let mut stmt = sqlite.prepare(&select_conflict_sql())?;
+        if stmt.step()? {
+            Err(IsarError::UniqueViolation {
+                index: stmt.get_text(0).to_string(),
+                existing_id: stmt.get_long(1),
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the oldest object with the same key as another object of the unique index.
+    pub fn find_duplicate(&self, sqlite: &SQLite3, index: &IndexSchema) -> Result<Option<i64>> {
+        if !index.unique || self.is_list_index(index) {
+            return Ok(None);
+        }
+        let mut stmt = sqlite.prepare(&select_duplicate_sql(self, index))?;
+        if stmt.step()? {
+            Ok(Some(stmt.get_long(0)))
+        } else {
+            Ok(None)
+        }
+    }
+
     pub fn init_auto_increment(&self, txn: &SQLiteTxn) -> Result<()> {
         let sqlite = txn.get_sqlite(false)?;
 
@@ -91,6 +131,13 @@ impl SQLiteCollection {
         }
     }
 
+    pub fn is_list_index(&self, index: &IndexSchema) -> bool {
+        self.properties
+            .iter()
+            .find(|p| Some(&p.name) == index.properties.first())
+            .is_some_and(|p| p.data_type.is_list())
+    }
+
     pub fn get_property_name(&self, property_index: u16) -> &str {
         if let Some(property) = self.get_property(property_index) {
             &property.name
@@ -1,11 +1,13 @@
-use super::sql::insert_sql;
+use super::sql::{insert_returning_sql, insert_sql, upsert_sql};
 use super::sqlite_collection::SQLiteCollection;
 use super::sqlite_txn::SQLiteTxn;
 use super::sqlite3::SQLiteStatement;
 use crate::core::error::{IsarError, Result};
 use crate::core::insert::IsarInsert;
+use crate::core::schema::{ConflictPolicy, IndexSchema};
 use ouroboros::self_referencing;
 use std::cell::Cell;
+use std::cmp::min;
 
 #[self_referencing]
 struct TxnWithStatement {
@@ -16,23 +18,20 @@ struct TxnWithStatement {
 }
 
 impl TxnWithStatement {
-    fn open(
-        txn: SQLiteTxn,
-        collection: &SQLiteCollection,
-        count: u32,
-    ) -> Result<(u32, TxnWithStatement)> {
-        let (batch_size, sql) = insert_sql(&collection.name, &collection.properties, count);
-        let txn_stmt = Self::try_new(txn, |txn| {
-            Ok(Cell::new(txn.get_sqlite(true)?.prepare(&sql)?))
-        })?;
-        Ok((batch_size, txn_stmt))
+    fn open(txn: SQLiteTxn, sql: &str) -> Result<TxnWithStatement> {
+        Self::try_new(txn, |txn| {
+            Ok(Cell::new(txn.get_sqlite(true)?.prepare(sql)?))
+        })
     }
 
     fn next(&mut self, collection: &SQLiteCollection, count: u32) -> Result<u32> {
         let (batch_size, sql) = insert_sql(&collection.name, &collection.properties, count);
 
         self.with_mut(|s| {
-            s.txn.guard(|| s.statement.get_mut().step())?;
+            s.txn.guard(|| {
+                s.statement.get_mut().step()?;
+                collection.check_conflicts(s.txn.get_sqlite(true)?)
+            })?;
             s.statement.replace(s.txn.get_sqlite(true)?.prepare(&sql)?);
             Ok(())
         })?;
@@ -40,8 +39,36 @@ impl TxnWithStatement {
         Ok(batch_size)
     }
 
-    fn finish(mut self) -> Result<SQLiteTxn> {
-        self.with_mut(|s| s.txn.guard(|| s.statement.get_mut().step()))?;
+    /// Executes the statement of a single object and resets it for the next object. Returns the
+    /// id of the saved object or `None` if a conflict trigger skipped it.
+    fn save_one(&mut self, collection: &SQLiteCollection) -> Result<Option<i64>> {
+        self.with_mut(|s| {
+            s.txn.guard(|| {
+                let stmt = s.statement.get_mut();
+                let id = if stmt.step()? {
+                    Some(stmt.get_long(0))
+                } else {
+                    None
+                };
+                stmt.reset()?;
+                stmt.clear_bindings()?;
+                collection.check_conflicts(s.txn.get_sqlite(true)?)?;
+                Ok(id)
+            })
+        })
+    }
+
+    fn close(self) -> SQLiteTxn {
+        self.into_heads().txn
+    }
+
+    fn finish(mut self, collection: &SQLiteCollection) -> Result<SQLiteTxn> {
+        self.with_mut(|s| {
+            s.txn.guard(|| {
+                s.statement.get_mut().step()?;
+                collection.check_conflicts(s.txn.get_sqlite(true)?)
+            })
+        })?;
         Ok(self.into_heads().txn)
     }
 }
@@ -55,6 +82,12 @@ pub struct SQLiteInsert<'a> {
     remaining: u32,
     pub(crate) batch_size: u32,
     pub(crate) batch_remaining: u32,
+
+    /// Upserts and inserts into collections with indexes that ignore conflicts save the objects
+    /// one by one to return whether they were saved.
+    single: bool,
+    /// The properties written to the current object. Only tracked for upserts.
+    pub(crate) written: Vec<bool>,
 }
 
 impl<'a> SQLiteInsert<'a> {
@@ -63,8 +96,26 @@ impl<'a> SQLiteInsert<'a> {
         collection: &'a SQLiteCollection,
         all_collections: &'a Vec<SQLiteCollection>,
         count: u32,
+        upsert_index: Option<&IndexSchema>,
     ) -> Result<Self> {
-        let (batch_size, txn_stmt) = TxnWithStatement::open(txn, collection, count)?;
+        let ignores = collection
+            .indexes
+            .iter()
+            .any(|i| i.on_conflict == ConflictPolicy::Ignore);
+        let (batch_size, sql) = if let Some(index) = upsert_index {
+            (min(count, 1), upsert_sql(collection, index))
+        } else if ignores {
+            let sql = insert_returning_sql(&collection.name, &collection.properties);
+            (min(count, 1), sql)
+        } else {
+            insert_sql(&collection.name, &collection.properties, count)
+        };
+        let written = if upsert_index.is_some() {
+            vec![false; collection.properties.len() + 1]
+        } else {
+            vec![]
+        };
+        let txn_stmt = TxnWithStatement::open(txn, &sql)?;
         let insert = Self {
             collection,
             all_collections,
@@ -72,10 +123,33 @@ impl<'a> SQLiteInsert<'a> {
             remaining: count - batch_size,
             batch_size,
             batch_remaining: batch_size,
+            single: upsert_index.is_some() || ignores,
+            written,
         };
         Ok(insert)
     }
 
+    fn save_single(&mut self) -> Result<Option<i64>> {
+        let properties = self.collection.properties.len() as u32;
+        let written = &self.written;
+        self.txn_stmt.with_statement_mut(|stmt| {
+            for (index, written) in written.iter().enumerate().skip(1) {
+                stmt.get_mut()
+                    .bind_int(properties + index as u32, *written as i32)?;
+            }
+            Ok::<_, IsarError>(())
+        })?;
+        self.written.fill(false);
+
+        let id = self.txn_stmt.save_one(self.collection)?;
+        if self.remaining > 0 {
+            self.remaining -= 1;
+        } else {
+            self.batch_remaining = 0;
+        }
+        Ok(id)
+    }
+
     #[inline]
     pub(crate) fn with_stmt<T>(&mut self, callback: impl FnOnce(&mut SQLiteStatement) -> T) -> T {
         self.txn_stmt
@@ -86,13 +160,16 @@ impl<'a> SQLiteInsert<'a> {
 impl<'a> IsarInsert<'a> for SQLiteInsert<'a> {
     type Txn = SQLiteTxn;
 
-    fn save(&mut self, id: i64) -> Result<()> {
+    fn save(&mut self, id: i64) -> Result<Option<i64>> {
         if self.batch_remaining > 0 {
             self.collection.update_auto_increment(id);
 
             let id_property = (self.batch_size - self.batch_remaining)
                 * (self.collection.properties.len() as u32 + 1);
             self.with_stmt(|stmt| stmt.bind_long(id_property, id))?;
+            if self.single {
+                return self.save_single();
+            }
 
             self.batch_remaining -= 1;
             if self.batch_remaining == 0 && self.remaining > 0 {
@@ -102,13 +179,280 @@ impl<'a> IsarInsert<'a> for SQLiteInsert<'a> {
                 self.batch_remaining = batch_size;
             }
 
-            Ok(())
+            Ok(Some(id))
         } else {
             Err(IsarError::UnsupportedOperation {})
         }
     }
 
     fn finish(self) -> Result<Self::Txn> {
-        self.txn_stmt.finish()
+        if self.single {
+            Ok(self.txn_stmt.close())
+        } else {
+            self.txn_stmt.finish(self.collection)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::cursor::IsarQueryCursor;
+    use crate::core::data_type::DataType;
+    use crate::core::error::{IsarError, Result};
+    use crate::core::insert::IsarInsert;
+    use crate::core::instance::IsarInstance;
+    use crate::core::query_builder::IsarQueryBuilder;
+    use crate::core::reader::IsarReader;
+    use crate::core::schema::{ConflictPolicy, IndexSchema, IsarSchema, PropertySchema};
+    use crate::core::value::IsarValue;
+    use crate::core::writer::IsarWriter;
+    use crate::sqlite::sqlite_instance::SQLiteInstance;
+
+    type Object = (i64, Vec<Option<IsarValue>>);
+
+    /// Opens a collection with the properties `value`, `name` and `count` and a unique index
+    /// for each of the given properties.
+    fn open(name: &str, indexes: &[(&str, ConflictPolicy)]) -> SQLiteInstance {
+        let indexes = indexes
+            .iter()
+            .map(|(property, on_conflict)| {
+                IndexSchema::new(property, vec![property], true, false, true)
+                    .with_conflict(*on_conflict)
+            })
+            .collect();
+        let schema = IsarSchema::new(
+            "test",
+            Some("id"),
+            vec![
+                PropertySchema::new("value", DataType::Long, None),
+                PropertySchema::new("name", DataType::String, None),
+                PropertySchema::new("count", DataType::Long, None),
+            ],
+            indexes,
+            false,
+        );
+        SQLiteInstance::open_temp(name, vec![schema]).unwrap()
+    }
+
+    fn long(value: i64) -> Option<IsarValue> {
+        Some(IsarValue::Integer(value))
+    }
+
+    fn string(value: &str) -> Option<IsarValue> {
+        Some(IsarValue::String(value.to_string()))
+    }
+
+    fn write<'a>(writer: &mut impl IsarWriter<'a>, index: u32, value: &Option<IsarValue>) {
+        match value {
+            Some(IsarValue::Integer(value)) => writer.write_long(index, *value),
+            Some(IsarValue::String(value)) => writer.write_string(index, value),
+            _ => writer.write_null(index),
+        }
+    }
+
+    /// Inserts the objects and returns the results of `save`. Missing values are null.
+    fn put(instance: &SQLiteInstance, objects: &[Object]) -> Result<Vec<Option<i64>>> {
+        let txn = instance.begin_txn(true)?;
+        let mut insert = instance.insert(txn, 0, objects.len() as u32)?;
+        let mut ids = vec![];
+        for (id, values) in objects {
+            for index in 0..3 {
+                write(
+                    &mut insert,
+                    index + 1,
+                    values.get(index as usize).unwrap_or(&None),
+                );
+            }
+            ids.push(insert.save(*id)?);
+        }
+        let txn = insert.finish()?;
+        instance.commit_txn(txn)?;
+        Ok(ids)
+    }
+
+    /// Upserts the objects using the index and returns the results of `save`. Only the given
+    /// properties are written.
+    fn upsert(
+        instance: &SQLiteInstance,
+        index: &str,
+        objects: &[(i64, Vec<(u32, Option<IsarValue>)>)],
+    ) -> Result<Vec<Option<i64>>> {
+        let txn = instance.begin_txn(true)?;
+        let mut insert = instance.upsert(txn, 0, index, objects.len() as u32)?;
+        let mut ids = vec![];
+        for (id, values) in objects {
+            for (index, value) in values {
+                write(&mut insert, *index, value);
+            }
+            ids.push(insert.save(*id)?);
+        }
+        let txn = insert.finish()?;
+        instance.commit_txn(txn)?;
+        Ok(ids)
+    }
+
+    fn objects(instance: &SQLiteInstance) -> Vec<Object> {
+        let query = instance.query(0).unwrap().build();
+        let txn = instance.begin_txn(false).unwrap();
+        let mut objects = vec![];
+        {
+            let mut cursor = instance.query_cursor(&txn, &query, None, None).unwrap();
+            while let Some(reader) = cursor.next() {
+                let read_long = |index| (!reader.is_null(index)).then(|| reader.read_long(index));
+                let values = vec![
+                    read_long(1).and_then(long),
+                    reader.read_string(2).and_then(string),
+                    read_long(3).and_then(long),
+                ];
+                objects.push((reader.read_id(), values));
+            }
+        }
+        instance.abort_txn(txn);
+        objects
+    }
+
+    #[test]
+    fn test_unique_fail() {
+        let instance = open("insert_unique_fail", &[("value", ConflictPolicy::Fail)]);
+        put(&instance, &[(1, vec![long(5)]), (2, vec![long(6)])]).unwrap();
+
+        let violation = Err(IsarError::UniqueViolation {
+            index: "value".to_string(),
+            existing_id: 1,
+        });
+        assert_eq!(
+            put(&instance, &[(3, vec![long(7)]), (4, vec![long(5)])]),
+            violation
+        );
+        assert_eq!(
+            put(&instance, &[(2, vec![long(5), string("a")])]),
+            violation
+        );
+
+        // an object does not conflict with itself and null keys never conflict
+        let ids = put(
+            &instance,
+            &[(1, vec![long(5), string("b")]), (3, vec![]), (4, vec![])],
+        );
+        assert_eq!(ids, Ok(vec![Some(1), Some(3), Some(4)]));
+        assert_eq!(
+            objects(&instance),
+            vec![
+                (1, vec![long(5), string("b"), None]),
+                (2, vec![long(6), None, None]),
+                (3, vec![None, None, None]),
+                (4, vec![None, None, None]),
+            ]
+        );
+        SQLiteInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_unique_ignore() {
+        let instance = open("insert_unique_ignore", &[("value", ConflictPolicy::Ignore)]);
+        put(&instance, &[(1, vec![long(5)]), (2, vec![long(6)])]).unwrap();
+        let ids = put(
+            &instance,
+            &[
+                (3, vec![long(5), string("a")]),
+                (2, vec![long(5), string("b")]),
+                (4, vec![long(7)]),
+                (1, vec![long(5), string("c")]),
+            ],
+        );
+        assert_eq!(ids, Ok(vec![None, None, Some(4), Some(1)]));
+        assert_eq!(
+            objects(&instance),
+            vec![
+                (1, vec![long(5), string("c"), None]),
+                (2, vec![long(6), None, None]),
+                (4, vec![long(7), None, None]),
+            ]
+        );
+        SQLiteInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_upsert() {
+        let instance = open("insert_upsert", &[("value", ConflictPolicy::Replace)]);
+
+        // no match: properties that were not written are null
+        let ids = upsert(
+            &instance,
+            "value",
+            &[(1, vec![(1, long(5)), (2, string("a"))])],
+        );
+        assert_eq!(ids, Ok(vec![Some(1)]));
+        assert_eq!(
+            objects(&instance),
+            vec![(1, vec![long(5), string("a"), None])]
+        );
+
+        // match: the existing object keeps its id and the properties that were not written
+        let ids = upsert(
+            &instance,
+            "value",
+            &[
+                (2, vec![(1, long(5)), (3, long(7))]),
+                (3, vec![(1, long(5)), (2, None)]),
+                (4, vec![(1, long(6)), (3, long(1))]),
+            ],
+        );
+        assert_eq!(ids, Ok(vec![Some(1), Some(1), Some(4)]));
+        assert_eq!(
+            objects(&instance),
+            vec![
+                (1, vec![long(5), None, long(7)]),
+                (4, vec![long(6), None, long(1)]),
+            ]
+        );
+        SQLiteInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_upsert_conflicts() {
+        let indexes = [
+            ("value", ConflictPolicy::Replace),
+            ("name", ConflictPolicy::Ignore),
+            ("count", ConflictPolicy::Fail),
+        ];
+        let instance = open("insert_upsert_conflicts", &indexes);
+        put(
+            &instance,
+            &[
+                (1, vec![long(1), string("a"), long(1)]),
+                (2, vec![long(2), string("b"), long(2)]),
+            ],
+        )
+        .unwrap();
+
+        let ids = upsert(
+            &instance,
+            "value",
+            &[
+                (3, vec![(1, long(3)), (2, string("a"))]),
+                (3, vec![(1, long(1)), (2, string("a"))]),
+                (3, vec![(1, long(2)), (2, string("a"))]),
+            ],
+        );
+        assert_eq!(ids, Ok(vec![None, Some(1), None]));
+
+        let result = upsert(&instance, "value", &[(3, vec![(1, long(3)), (3, long(2))])]);
+        assert_eq!(
+            result,
+            Err(IsarError::UniqueViolation {
+                index: "count".to_string(),
+                existing_id: 2
+            })
+        );
+
+        assert_eq!(
+            objects(&instance),
+            vec![
+                (1, vec![long(1), string("a"), long(1)]),
+                (2, vec![long(2), string("b"), long(2)]),
+            ]
+        );
+        SQLiteInstance::close(instance, true);
     }
 }
@@ -1,3 +1,4 @@
+use super::sql::{fill_list_index_sql, list_index_table, reindex_sql};
 use super::sqlite_collection::SQLiteCollection;
 use super::sqlite_cursor::SQLiteCursor;
 use super::sqlite_insert::SQLiteInsert;
@@ -10,9 +11,10 @@ use super::sqlite_verify::verify_sqlite;
 use super::sqlite3::SQLite3;
 use crate::core::error::{IsarError, Result};
 use crate::core::filter::{ConditionType, Filter};
-use crate::core::instance::{Aggregation, CompactCondition, IsarInstance};
+use crate::core::instance::{Aggregation, AggregationGroup, CompactCondition, IsarInstance};
 use crate::core::query_builder::IsarQueryBuilder;
-use crate::core::schema::IsarSchema;
+use crate::core::query_plan::QueryPlan;
+use crate::core::schema::{IndexSchema, IsarSchema};
 use crate::core::value::IsarValue;
 use crate::core::watcher::{WatchHandle, WatcherCallback};
 use parking_lot::lock_api::RawMutex;
@@ -59,10 +61,38 @@ impl SQLiteInstanceInfo {
 pub struct SQLiteInstance {
     info: Arc<SQLiteInstanceInfo>,
     sqlite: Rc<SQLite3>,
-    txn_active: Cell<bool>,
+    txn_active: Rc<Cell<bool>>,
 }
 
 impl SQLiteInstance {
+    fn verify_instance_id(&self, instance_id: u32) -> Result<()> {
+        if self.info.instance_id != instance_id {
+            Err(IsarError::InstanceMismatch {})
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Opens an instance in the temporary directory. Instances with the same name share their
+    /// database so they can be reopened with a different schema.
+    #[cfg(test)]
+    pub(crate) fn open_temp(name: &str, schemas: Vec<IsarSchema>) -> Result<Self> {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        static NEXT_INSTANCE_ID: AtomicU32 = AtomicU32::new(2_000_000);
+        let instance_id = NEXT_INSTANCE_ID.fetch_add(1, Ordering::SeqCst);
+        let name = format!("{}_{}", name, std::process::id());
+        let dir = std::env::temp_dir();
+        Self::open_instance(
+            instance_id,
+            &name,
+            dir.to_str().unwrap(),
+            schemas,
+            100,
+            None,
+            None,
+        )
+    }
+
     fn get_collection(&self, collection_index: u16) -> Result<&SQLiteCollection> {
         if let Some(collection) = self.info.collections.get(collection_index as usize) {
             Ok(collection)
@@ -70,6 +100,42 @@ impl SQLiteInstance {
             Err(IsarError::IllegalArgument {})
         }
     }
+
+    /// Runs `REINDEX` and reports a duplicate key in one of the unique indexes as a unique
+    /// violation instead of the constraint error of SQLite. Objects are never deleted.
+    fn reindex<'a>(
+        sqlite: &SQLite3,
+        sql: &str,
+        indexes: impl IntoIterator<Item = (&'a SQLiteCollection, &'a IndexSchema)>,
+    ) -> Result<()> {
+        let Err(err) = sqlite.prepare(sql)?.step() else {
+            return Ok(());
+        };
+        for (collection, index) in indexes {
+            if let Some(existing_id) = collection.find_duplicate(sqlite, index)? {
+                return Err(IsarError::UniqueViolation {
+                    index: index.name.clone(),
+                    existing_id,
+                });
+            }
+        }
+        Err(err)
+    }
+
+    fn refill_list_index(
+        sqlite: &SQLite3,
+        collection: &SQLiteCollection,
+        index: &IndexSchema,
+    ) -> Result<()> {
+        let sql = format!(
+            "DELETE FROM {}",
+            list_index_table(&collection.name, &index.name)
+        );
+        sqlite.prepare(&sql)?.step()?;
+        let sql = fill_list_index_sql(&collection.name, &index.name, &index.properties[0]);
+        sqlite.prepare(&sql)?.step()?;
+        Ok(())
+    }
 }
 
 impl IsarInstance for SQLiteInstance {
@@ -112,7 +178,7 @@ impl IsarInstance for SQLiteInstance {
         Some(Self {
             info,
             sqlite: Rc::new(sqlite),
-            txn_active: Cell::new(false),
+            txn_active: Rc::new(Cell::new(false)),
         })
     }
 
@@ -147,7 +213,7 @@ impl IsarInstance for SQLiteInstance {
         Ok(Self {
             info,
             sqlite: Rc::new(sqlite),
-            txn_active: Cell::new(false),
+            txn_active: Rc::new(Cell::new(false)),
         })
     }
 
@@ -167,29 +233,32 @@ impl IsarInstance for SQLiteInstance {
             self.info.write_mutex.lock();
         }
         if self.txn_active.replace(true) {
-            Err(IsarError::TransactionActive {})
-        } else {
-            SQLiteTxn::new(self.sqlite.clone(), write)
+            return Err(IsarError::TransactionActive {});
+        }
+
+        let info = self.info.clone();
+        let txn_active = self.txn_active.clone();
+        let release = move || {
+            txn_active.replace(false);
+            if write {
+                unsafe { info.write_mutex.unlock() };
+            }
+        };
+        match SQLiteTxn::new(self.info.instance_id, self.sqlite.clone(), write) {
+            Ok(txn) => Ok(txn.with_release(release)),
+            Err(err) => {
+                release();
+                Err(err)
+            }
         }
     }
 
     fn commit_txn(&self, txn: SQLiteTxn) -> Result<()> {
-        self.txn_active.replace(false);
-        let write = txn.is_write();
-        let result = txn.commit();
-        if write {
-            unsafe { self.info.write_mutex.unlock() };
-        }
-        result
+        txn.commit()
     }
 
     fn abort_txn(&self, txn: SQLiteTxn) {
-        self.txn_active.replace(false);
-        let write = txn.is_write();
         txn.abort();
-        if write {
-            unsafe { self.info.write_mutex.unlock() };
-        }
     }
 
     fn auto_increment(&self, collection_index: u16) -> i64 {
@@ -214,7 +283,30 @@ impl IsarInstance for SQLiteInstance {
         let collection = self.get_collection(collection_index)?;
         txn.monitor_changes(&collection.watchers);
 
-        SQLiteInsert::new(txn, collection, &self.info.collections, count)
+        SQLiteInsert::new(txn, collection, &self.info.collections, count, None)
+    }
+
+    fn upsert<'a>(
+        &'a self,
+        txn: SQLiteTxn,
+        collection_index: u16,
+        index_name: &str,
+        count: u32,
+    ) -> Result<Self::Insert<'a>> {
+        let collection = self.get_collection(collection_index)?;
+        let index = collection.indexes.iter().find(|index| {
+            let is_list = collection
+                .properties
+                .iter()
+                .any(|p| p.name == index.properties[0] && p.data_type.is_list());
+            index.name == index_name && index.unique && !is_list
+        });
+        let Some(index) = index else {
+            return Err(IsarError::IllegalArgument {});
+        };
+        txn.monitor_changes(&collection.watchers);
+
+        SQLiteInsert::new(txn, collection, &self.info.collections, count, Some(index))
     }
 
     fn update(
@@ -289,7 +381,44 @@ impl IsarInstance for SQLiteInstance {
         offset: Option<u32>,
         limit: Option<u32>,
     ) -> Result<Self::QueryCursor<'a>> {
-        query.cursor(txn, &self.info.collections, offset, limit)
+        query.verify_bound()?;
+        query.cursor(txn, &self.info.collections, None, offset, limit)
+    }
+
+    fn query_bind(
+        &self,
+        query: &mut Self::Query,
+        parameters: &[Vec<Option<IsarValue>>],
+    ) -> Result<()> {
+        query.bind(&self.info.collections, parameters)
+    }
+
+    fn query_properties_cursor<'a>(
+        &'a self,
+        txn: &'a Self::Txn,
+        query: &'a Self::Query,
+        properties: &[u16],
+        offset: Option<u32>,
+        limit: Option<u32>,
+    ) -> Result<Self::QueryCursor<'a>> {
+        query.verify_bound()?;
+        query.cursor(txn, &self.info.collections, Some(properties), offset, limit)
+    }
+
+    fn query_page(&self, query: &Self::Query, token: Option<&str>) -> Result<Self::Query> {
+        query.verify_bound()?;
+        query.page(token)
+    }
+
+    fn query_page_token(
+        &self,
+        txn: &Self::Txn,
+        query: &Self::Query,
+        id: i64,
+    ) -> Result<Option<String>> {
+        query.verify_bound()?;
+        let collection = self.get_collection(query.collection_index)?;
+        query.page_token(txn, collection, id)
     }
 
     fn query_aggregate(
@@ -299,9 +428,38 @@ impl IsarInstance for SQLiteInstance {
         aggregation: Aggregation,
         property_index: Option<u16>,
     ) -> Result<Option<IsarValue>> {
+        query.verify_bound()?;
         query.aggregate(txn, &self.info.collections, aggregation, property_index)
     }
 
+    fn query_aggregate_batch(
+        &self,
+        txn: &Self::Txn,
+        query: &Self::Query,
+        aggregations: &[(Aggregation, Option<u16>)],
+    ) -> Result<Vec<Option<IsarValue>>> {
+        query.verify_bound()?;
+        query.aggregate_batch(txn, &self.info.collections, aggregations)
+    }
+
+    fn query_aggregate_groups(
+        &self,
+        txn: &Self::Txn,
+        query: &Self::Query,
+        group_by: &[(u16, bool)],
+        aggregation: Aggregation,
+        property_index: Option<u16>,
+    ) -> Result<Vec<AggregationGroup>> {
+        query.verify_bound()?;
+        query.aggregate_groups(
+            txn,
+            &self.info.collections,
+            group_by,
+            aggregation,
+            property_index,
+        )
+    }
+
     fn query_update(
         &self,
         txn: &Self::Txn,
@@ -310,6 +468,7 @@ impl IsarInstance for SQLiteInstance {
         limit: Option<u32>,
         updates: &[(u16, Option<IsarValue>)],
     ) -> Result<u32> {
+        query.verify_bound()?;
         let collection = self.get_collection(query.collection_index)?;
         txn.monitor_changes(&collection.watchers);
         let result =
@@ -325,6 +484,7 @@ impl IsarInstance for SQLiteInstance {
         offset: Option<u32>,
         limit: Option<u32>,
     ) -> Result<u32> {
+        query.verify_bound()?;
         let collection = self.get_collection(query.collection_index)?;
         txn.monitor_changes(&collection.watchers);
         let result = txn.guard(|| query.delete(txn, &self.info.collections, offset, limit))?;
@@ -332,6 +492,12 @@ impl IsarInstance for SQLiteInstance {
         Ok(result)
     }
 
+    fn explain(&self, txn: &Self::Txn, query: &Self::Query) -> Result<QueryPlan> {
+        query.verify_bound()?;
+        let collection = self.get_collection(query.collection_index)?;
+        query.explain(txn, collection)
+    }
+
     fn watch(&self, collection_index: u16, callback: WatcherCallback) -> Result<WatchHandle> {
         let collection = self.get_collection(collection_index)?;
         let handle = collection.watchers.watch(callback);
@@ -350,6 +516,7 @@ impl IsarInstance for SQLiteInstance {
     }
 
     fn watch_query(&self, query: &Self::Query, callback: WatcherCallback) -> Result<WatchHandle> {
+        query.verify_bound()?;
         let collection = self.get_collection(query.collection_index)?;
         let handle = collection.watchers.watch_query(query, callback);
         Ok(handle)
@@ -365,11 +532,173 @@ impl IsarInstance for SQLiteInstance {
         Ok(())
     }
 
-    fn verify(&self, _txn: &Self::Txn) -> Result<()> {
-        verify_sqlite(&self.sqlite, &self.info.collections)
+    fn verify(&self, _txn: &Self::Txn, deep: bool) -> Result<()> {
+        verify_sqlite(&self.sqlite, &self.info.collections, deep)
+    }
+
+    fn rebuild_index(
+        &self,
+        txn: &Self::Txn,
+        collection_index: u16,
+        index_name: &str,
+    ) -> Result<()> {
+        self.verify_instance_id(txn.instance_id)?;
+        let collection = self.get_collection(collection_index)?;
+        let index = collection
+            .indexes
+            .iter()
+            .find(|i| i.name == index_name)
+            .ok_or(IsarError::IllegalArgument {})?;
+        txn.guard(|| {
+            let sqlite = txn.get_sqlite(true)?;
+            if collection.is_list_index(index) {
+                Self::refill_list_index(sqlite, collection, index)?;
+            } else {
+                let sql = reindex_sql(&collection.name, index_name);
+                Self::reindex(sqlite, &sql, [(collection, index)])?;
+            }
+            Ok(())
+        })
+    }
+
+    fn rebuild_indexes(&self, txn: &Self::Txn) -> Result<()> {
+        self.verify_instance_id(txn.instance_id)?;
+        txn.guard(|| {
+            let sqlite = txn.get_sqlite(true)?;
+            let indexes = self
+                .info
+                .collections
+                .iter()
+                .flat_map(|c| c.indexes.iter().map(move |i| (c, i)));
+            Self::reindex(sqlite, "REINDEX", indexes)?;
+            for collection in &self.info.collections {
+                for index in &collection.indexes {
+                    if collection.is_list_index(index) {
+                        Self::refill_list_index(sqlite, collection, index)?;
+                    }
+                }
+            }
+            Ok(())
+        })
     }
 
     fn close(instance: Self::Instance, delete: bool) -> bool {
         close_instance(instance.info, instance.sqlite, delete)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::data_type::DataType;
+    use crate::core::insert::IsarInsert;
+    use crate::core::schema::PropertySchema;
+    use crate::core::writer::IsarWriter;
+
+    fn schema(indexes: Vec<IndexSchema>) -> IsarSchema {
+        IsarSchema::new(
+            "test",
+            Some("id"),
+            vec![PropertySchema::new("value", DataType::Long, None)],
+            indexes,
+            false,
+        )
+    }
+
+    fn put(instance: &SQLiteInstance, objects: &[(i64, i64)]) {
+        let txn = instance.begin_txn(true).unwrap();
+        let mut insert = instance.insert(txn, 0, objects.len() as u32).unwrap();
+        for (id, value) in objects {
+            insert.write_long(1, *value);
+            insert.save(*id).unwrap();
+        }
+        let txn = insert.finish().unwrap();
+        instance.commit_txn(txn).unwrap();
+    }
+
+    fn count(instance: &SQLiteInstance) -> u32 {
+        let txn = instance.begin_txn(false).unwrap();
+        let count = instance.count(&txn, 0).unwrap();
+        instance.abort_txn(txn);
+        count
+    }
+
+    fn execute(txn: &SQLiteTxn, sql: &str) {
+        txn.get_sqlite(true)
+            .unwrap()
+            .prepare(sql)
+            .unwrap()
+            .step()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_rebuild_indexes_of_other_instance() {
+        let index = || IndexSchema::new("value", vec!["value"], false, false, true);
+        let instance =
+            SQLiteInstance::open_temp("rebuild_instance", vec![schema(vec![index()])]).unwrap();
+        let other =
+            SQLiteInstance::open_temp("rebuild_other", vec![schema(vec![index()])]).unwrap();
+        put(&instance, &[(1, 1), (2, 2)]);
+
+        let txn = instance.begin_txn(true).unwrap();
+        assert!(matches!(
+            other.rebuild_index(&txn, 0, "value"),
+            Err(IsarError::InstanceMismatch {})
+        ));
+        assert!(matches!(
+            other.rebuild_indexes(&txn),
+            Err(IsarError::InstanceMismatch {})
+        ));
+        instance.rebuild_index(&txn, 0, "value").unwrap();
+        instance.rebuild_indexes(&txn).unwrap();
+        instance.verify(&txn, true).unwrap();
+        instance.commit_txn(txn).unwrap();
+        assert_eq!(count(&instance), 2);
+
+        SQLiteInstance::close(instance, true);
+        SQLiteInstance::close(other, true);
+    }
+
+    /// Changes the SQL of the index in the schema without rebuilding it.
+    fn replace_index_sql(instance: &SQLiteInstance, from: &str, to: &str) {
+        let txn = instance.begin_txn(true).unwrap();
+        execute(&txn, "PRAGMA writable_schema = ON");
+        let sql = format!(
+            "UPDATE sqlite_master SET sql = replace(sql, '{}', '{}') WHERE name = 'test_value'",
+            from, to
+        );
+        execute(&txn, &sql);
+        execute(&txn, "PRAGMA writable_schema = RESET");
+        instance.commit_txn(txn).unwrap();
+    }
+
+    #[test]
+    fn test_rebuild_index_with_duplicates() {
+        let index = IndexSchema::new("value", vec!["value"], true, false, true);
+        let instance =
+            SQLiteInstance::open_temp("rebuild_duplicates", vec![schema(vec![index])]).unwrap();
+        put(&instance, &[(1, 1), (2, 2)]);
+        // insert a duplicate while the index is not unique
+        replace_index_sql(&instance, "UNIQUE", "");
+        put(&instance, &[(3, 1)]);
+        replace_index_sql(&instance, "CREATE  INDEX", "CREATE UNIQUE INDEX");
+
+        // a failed rebuild closes the transaction
+        for rebuild_all in [false, true] {
+            let txn = instance.begin_txn(true).unwrap();
+            let result = if rebuild_all {
+                instance.rebuild_indexes(&txn)
+            } else {
+                instance.rebuild_index(&txn, 0, "value")
+            };
+            assert!(matches!(
+                result,
+                Err(IsarError::UniqueViolation { index, existing_id: 1 }) if index == "value"
+            ));
+            instance.abort_txn(txn);
+        }
+        assert_eq!(count(&instance), 3);
+        SQLiteInstance::close(instance, true);
+    }
+}
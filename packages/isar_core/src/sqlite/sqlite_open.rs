@@ -1,4 +1,5 @@
 use super::schema_manager::perform_migration;
+use super::sql::list_index_table;
 use super::sqlite_collection::{SQLiteCollection, SQLiteProperty};
 use super::sqlite_instance::SQLiteInstanceInfo;
 use super::sqlite_txn::SQLiteTxn;
@@ -59,13 +60,13 @@ pub(crate) fn open_sqlite(
     sqlite.prepare("PRAGMA journal_mode=WAL")?.step()?;
 
     let sqlite = Rc::new(sqlite);
-    let txn = SQLiteTxn::new(sqlite.clone(), true)?;
+    let txn = SQLiteTxn::new(instance_id, sqlite.clone(), true)?;
     perform_migration(&txn, &schemas)?;
     txn.commit()?;
 
     let collections = get_collections(&schemas);
     {
-        let txn = SQLiteTxn::new(sqlite.clone(), false)?;
+        let txn = SQLiteTxn::new(instance_id, sqlite.clone(), false)?;
         for collection in &collections {
             if !collection.is_embedded() {
                 collection.init_auto_increment(&txn)?;
@@ -83,7 +84,7 @@ pub(crate) fn open_sqlite(
 fn get_collections(schemas: &[IsarSchema]) -> Vec<SQLiteCollection> {
     let mut collections = Vec::new();
     for collection_schema in schemas {
-        let properties = collection_schema
+        let mut properties = collection_schema
             .properties
             .iter()
             .filter_map(|p| {
@@ -99,6 +100,16 @@ fn get_collections(schemas: &[IsarSchema]) -> Vec<SQLiteCollection> {
                 }
             })
             .collect_vec();
+        for index in &collection_schema.indexes {
+            let property = properties
+                .iter_mut()
+                .find(|p| p.name == index.properties[0]);
+            if let Some(property) = property
+                && property.data_type.is_list()
+            {
+                property.index_table = Some(list_index_table(&collection_schema.name, &index.name));
+            }
+        }
         let collection = SQLiteCollection::new(
             collection_schema.name.clone(),
             collection_schema.id_name.clone(),
@@ -1,17 +1,29 @@
-use super::sql::{offset_limit_sql, select_properties_sql, update_properties_sql};
+use super::sql::{
+    keyset_sql, offset_limit_sql, query_sql, select_projection_sql, select_properties_sql,
+    update_properties_sql,
+};
 use super::sql_filter::FN_FILTER_JSON_COND_PTR_TYPE;
 use super::sqlite_collection::{SQLiteCollection, SQLiteProperty};
+use super::sqlite_query_builder::build_filter_sql;
 use super::sqlite_reader::SQLiteReader;
 use super::sqlite_txn::SQLiteTxn;
-use super::sqlite3::SQLiteStatement;
+use super::sqlite3::{SQLite3, SQLiteStatement};
 use crate::core::cursor::IsarQueryCursor;
 use crate::core::data_type::DataType;
-use crate::core::error::Result;
-use crate::core::filter::ConditionType;
-use crate::core::instance::Aggregation;
+use crate::core::error::{IsarError, Result};
+use crate::core::filter::{ConditionType, Filter};
+use crate::core::instance::{Aggregation, AggregationGroup};
+use crate::core::page_token::PageToken;
+use crate::core::query_builder::Sort;
+use crate::core::query_plan::{QueryPlan, QueryPlanRange};
+use crate::core::statistics::{self, Variance};
 use crate::core::value::IsarValue;
 use crate::core::watcher::QueryMatches;
+use itertools::Itertools;
+use serde_json::json;
 use std::borrow::Cow;
+use std::collections::HashMap;
+use xxhash_rust::xxh3::xxh3_64;
 
 #[derive(Clone, Debug, PartialEq)]
 pub(crate) enum QueryParam {
@@ -36,39 +48,229 @@ pub struct SQLiteQuery {
     sql: String,
     has_sort_distinct: bool,
     params: Vec<QueryParam>,
+    // The parts of the query used to build pages.
+    index_sql: String,
+    conditions: Vec<String>,
+    sort: Vec<(String, Sort, bool)>,
+    distinct: Vec<(String, bool)>,
+    // The number of leading conditions and params that select the ranges.
+    covered: (usize, usize),
+    // The filter with parameters the query was built from.
+    parameters: Option<Filter>,
+    // Whether all parameters have been bound.
+    bound: bool,
+    // Identifies the query in page tokens. Pages keep the fingerprint of their query.
+    fingerprint: u64,
+}
+
+/// Hashes a stable description of the query.
+fn fingerprint(collection_index: u16, sql: &str, params: &[QueryParam]) -> u64 {
+    let params = params
+        .iter()
+        .map(|param| match param {
+            QueryParam::Value(value) => json!(value),
+            QueryParam::JsonCondition(cond) => json!([
+                cond.path,
+                cond.condition_type,
+                cond.values,
+                cond.case_sensitive
+            ]),
+        })
+        .collect_vec();
+    xxh3_64(
+        json!([collection_index, sql, params])
+            .to_string()
+            .as_bytes(),
+    )
 }
 
 impl SQLiteQuery {
     pub(crate) fn new(
         collection_index: u16,
-        sql: String,
-        has_sort_distinct: bool,
+        index_sql: String,
+        conditions: Vec<String>,
+        sort: Vec<(String, Sort, bool)>,
+        distinct: Vec<(String, bool)>,
         params: Vec<QueryParam>,
+        covered: (usize, usize),
     ) -> Self {
+        // INDEXED BY is not supported by UPDATE ... SET so a subquery is required
+        let has_sort_distinct = !sort.is_empty() || !distinct.is_empty() || !index_sql.is_empty();
+        let sql = query_sql(&index_sql, &conditions, &sort, &distinct);
+        let fingerprint = fingerprint(collection_index, &sql, &params);
         Self {
             collection_index,
             sql,
             has_sort_distinct,
             params,
+            index_sql,
+            conditions,
+            sort,
+            distinct,
+            covered,
+            parameters: None,
+            bound: true,
+            fingerprint,
+        }
+    }
+
+    /// Keeps the filter with parameters so the query can be rebuilt when they are bound.
+    pub(crate) fn with_parameters(mut self, filter: Filter) -> Self {
+        self.parameters = Some(filter);
+        self.bound = false;
+        self
+    }
+
+    /// Queries with parameters cannot be used until the parameters are bound.
+    pub(crate) fn verify_bound(&self) -> Result<()> {
+        if self.bound {
+            Ok(())
+        } else {
+            Err(IsarError::IllegalArgument {})
+        }
+    }
+
+    /// Rebuilds the conditions of the query using the values of the parameters. The
+    /// conditions that select the ranges do not depend on the filter and are kept.
+    pub(crate) fn bind(
+        &mut self,
+        all_collections: &[SQLiteCollection],
+        parameters: &[Vec<Option<IsarValue>>],
+    ) -> Result<()> {
+        let filter = self
+            .parameters
+            .clone()
+            .ok_or(IsarError::IllegalArgument {})?;
+        let (filter_sql, filter_params) = build_filter_sql(
+            all_collections,
+            self.collection_index,
+            filter.bind(parameters)?,
+        );
+        let (covered_conditions, covered_params) = self.covered;
+        let mut conditions = self.conditions[..covered_conditions].to_vec();
+        conditions.push(filter_sql);
+        let mut params = self.params[..covered_params].to_vec();
+        params.extend(filter_params);
+        *self = SQLiteQuery::new(
+            self.collection_index,
+            std::mem::take(&mut self.index_sql),
+            conditions,
+            std::mem::take(&mut self.sort),
+            std::mem::take(&mut self.distinct),
+            params,
+            self.covered,
+        )
+        .with_parameters(filter);
+        self.bound = true;
+        Ok(())
+    }
+
+    /// The sort of pages. Ties are broken by the id so every object has a unique position. The
+    /// ids are sorted in the direction of the last property so an index can provide the order.
+    fn page_sort(&self) -> Vec<(String, Sort, bool)> {
+        let mut sort = self.sort.clone();
+        if let Some(position) = sort
+            .iter()
+            .position(|(p, _, _)| p == SQLiteProperty::ID_NAME)
+        {
+            sort.truncate(position + 1);
+        } else {
+            let direction = sort.last().map_or(Sort::Asc, |(_, s, _)| *s);
+            sort.push((SQLiteProperty::ID_NAME.to_string(), direction, true));
         }
+        sort
+    }
+
+    /// Returns a query for the objects after the page token. Distinct queries cannot be paged
+    /// because earlier pages would be needed to know which objects are duplicates.
+    pub(crate) fn page(&self, token: Option<&str>) -> Result<Self> {
+        if !self.distinct.is_empty() {
+            return Err(IsarError::IllegalArgument {});
+        }
+        let sort = self.page_sort();
+        let mut conditions = self.conditions.clone();
+        let mut params = self.params.clone();
+        if let Some(token) = token {
+            let token = PageToken::decode(token, sort.len() - 1, self.fingerprint)?;
+            let mut values = token.values;
+            values.push(Some(IsarValue::Integer(token.id)));
+            conditions.push(keyset_sql(&sort, &values, &mut params));
+        }
+        let mut query = SQLiteQuery::new(
+            self.collection_index,
+            self.index_sql.clone(),
+            conditions,
+            sort,
+            vec![],
+            params,
+            self.covered,
+        );
+        query.fingerprint = self.fingerprint;
+        Ok(query)
+    }
+
+    pub(crate) fn page_token(
+        &self,
+        txn: &SQLiteTxn,
+        collection: &SQLiteCollection,
+        id: i64,
+    ) -> Result<Option<String>> {
+        let sort = self.page_sort();
+        let properties = sort[..sort.len() - 1]
+            .iter()
+            .map(|(name, _, _)| collection.properties.iter().find(|p| p.name == *name))
+            .collect::<Vec<_>>();
+        let columns = sort
+            .iter()
+            .map(|(name, _, _)| name.as_str())
+            .collect::<Vec<_>>();
+        let sql = format!(
+            "SELECT {} FROM {} WHERE {} = ?",
+            columns.join(", "),
+            collection.name,
+            SQLiteProperty::ID_NAME
+        );
+        let mut stmt = txn.get_sqlite(false)?.prepare(&sql)?;
+        stmt.bind_long(0, id)?;
+        if !stmt.step()? {
+            return Ok(None);
+        }
+        let values = properties
+            .iter()
+            .enumerate()
+            .map(|(col, property)| read_value(&stmt, col as u32, (*property)?.data_type))
+            .collect();
+        Ok(Some(PageToken::new(values, id, self.fingerprint).encode()))
     }
 
     pub(crate) fn cursor<'a>(
         &'a self,
         txn: &'a SQLiteTxn,
         all_collections: &'a [SQLiteCollection],
+        properties: Option<&[u16]>,
         offset: Option<u32>,
         limit: Option<u32>,
     ) -> Result<SQLiteQueryCursor<'a>> {
         let collection = &all_collections[self.collection_index as usize];
+        let select_sql = if let Some(properties) = properties {
+            if properties
+                .iter()
+                .any(|p| *p != 0 && collection.get_property(*p).is_none())
+            {
+                return Err(IsarError::IllegalArgument {});
+            }
+            select_projection_sql(collection, properties)
+        } else {
+            select_properties_sql(collection)
+        };
         let sql = format!(
             "SELECT {} FROM {} {} {}",
-            select_properties_sql(collection),
+            select_sql,
             collection.name,
             self.sql,
             offset_limit_sql(offset, limit)
         );
-        let mut stmt = txn.get_sqlite(false)?.prepare(&sql)?;
+        let mut stmt = txn.get_sqlite(false)?.prepare_cached(&sql)?;
         Self::bind_params(&mut stmt, &self.params, 0)?;
 
         Ok(SQLiteQueryCursor {
@@ -91,56 +293,154 @@ impl SQLiteQuery {
             .get_property(property_index.unwrap_or(0))
             .map_or(DataType::Long, |p| p.data_type);
 
-        let aggregation_sql = match aggregation {
-            Aggregation::Count => "COUNT(*)".to_string(),
-            Aggregation::IsEmpty => SQLiteProperty::ID_NAME.to_string(),
-            Aggregation::Min => {
-                format!("MIN({})", property_name)
-            }
-            Aggregation::Max => {
-                format!("MAX({})", property_name)
-            }
-            Aggregation::Sum => {
-                format!("SUM({})", property_name)
-            }
-            Aggregation::Average => {
-                format!("AVG({})", property_name)
-            }
-        };
         let sql = format!(
             "SELECT {} FROM {} {}",
-            aggregation_sql, collection.name, self.sql
+            aggregation_sql(aggregation, property_name, property_type),
+            collection.name,
+            self.sql
         );
-        let mut stmt = txn.get_sqlite(false)?.prepare(&sql)?;
+        let mut stmt = txn.get_sqlite(false)?.prepare_cached(&sql)?;
         Self::bind_params(&mut stmt, &self.params, 0)?;
 
         let has_next = stmt.step()?;
-        let result = match aggregation {
-            Aggregation::Count => IsarValue::Integer(stmt.get_long(0)),
-            Aggregation::IsEmpty => IsarValue::Bool(!has_next),
-            Aggregation::Min | Aggregation::Max | Aggregation::Sum => {
-                if aggregation == Aggregation::Sum || !stmt.is_null(0) {
-                    match property_type {
-                        DataType::Byte | DataType::Int | DataType::Long => {
-                            IsarValue::Integer(stmt.get_long(0))
-                        }
-                        DataType::Float | DataType::Double => IsarValue::Real(stmt.get_double(0)),
-                        DataType::String => IsarValue::String(stmt.get_text(0).to_string()),
-                        _ => return Ok(None),
+        Ok(read_aggregation(
+            &stmt,
+            0,
+            aggregation,
+            property_type,
+            has_next,
+        ))
+    }
+
+    pub(crate) fn aggregate_groups(
+        &self,
+        txn: &SQLiteTxn,
+        all_collections: &[SQLiteCollection],
+        group_by: &[(u16, bool)],
+        aggregation: Aggregation,
+        property_index: Option<u16>,
+    ) -> Result<Vec<AggregationGroup>> {
+        let collection = &all_collections[self.collection_index as usize];
+        let property_name = collection.get_property_name(property_index.unwrap_or(0));
+        let property_type = collection
+            .get_property(property_index.unwrap_or(0))
+            .map_or(DataType::Long, |p| p.data_type);
+        if group_by.is_empty() {
+            return Err(IsarError::IllegalArgument {});
+        }
+        let group_properties = group_by
+            .iter()
+            .map(|(property_index, case_sensitive)| {
+                match collection.get_property(*property_index) {
+                    Some(property) if property.data_type.is_scalar() => {
+                        Ok((property, *case_sensitive))
                     }
+                    _ => Err(IsarError::IllegalArgument {}),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let group_names = group_properties
+            .iter()
+            .map(|(property, case_sensitive)| (property.name.as_str(), *case_sensitive))
+            .collect::<Vec<_>>();
+
+        let sql = format!(
+            "SELECT {}, {} FROM {} {}",
+            group_names.iter().map(|(name, _)| name).join(", "),
+            aggregation_sql(aggregation, property_name, property_type),
+            collection.name,
+            self.aggregate_sql(collection, &group_names)
+        );
+        let mut stmt = txn.get_sqlite(false)?.prepare_cached(&sql)?;
+        Self::bind_params(&mut stmt, &self.params, 0)?;
+
+        let result_col = group_properties.len() as u32;
+        let mut groups = vec![];
+        while stmt.step()? {
+            let values = group_properties
+                .iter()
+                .enumerate()
+                .map(|(col, (property, _))| read_value(&stmt, col as u32, property.data_type))
+                .collect();
+            let result = read_aggregation(&stmt, result_col, aggregation, property_type, true);
+            groups.push(AggregationGroup { values, result });
+        }
+        Ok(groups)
+    }
+
+    /// Evaluates all aggregations using a single `SELECT`.
+    pub(crate) fn aggregate_batch(
+        &self,
+        txn: &SQLiteTxn,
+        all_collections: &[SQLiteCollection],
+        aggregations: &[(Aggregation, Option<u16>)],
+    ) -> Result<Vec<Option<IsarValue>>> {
+        if aggregations.is_empty() {
+            return Ok(vec![]);
+        }
+        let collection = &all_collections[self.collection_index as usize];
+        let columns = aggregations
+            .iter()
+            .map(|(aggregation, property_index)| {
+                if *aggregation == Aggregation::IsEmpty {
+                    // the query always returns a row because of the other aggregations
+                    "COUNT(*) = 0".to_string()
                 } else {
-                    return Ok(None);
+                    let property_name = collection.get_property_name(property_index.unwrap_or(0));
+                    let property_type = collection
+                        .get_property(property_index.unwrap_or(0))
+                        .map_or(DataType::Long, |p| p.data_type);
+                    aggregation_sql(*aggregation, property_name, property_type)
                 }
-            }
-            Aggregation::Average => {
-                if !stmt.is_null(0) {
-                    IsarValue::Real(stmt.get_double(0))
+            })
+            .join(", ");
+        let sql = format!(
+            "SELECT {} FROM {} {}",
+            columns,
+            collection.name,
+            self.aggregate_sql::<&str>(collection, &[])
+        );
+        let mut stmt = txn.get_sqlite(false)?.prepare_cached(&sql)?;
+        Self::bind_params(&mut stmt, &self.params, 0)?;
+
+        stmt.step()?;
+        let results = aggregations
+            .iter()
+            .enumerate()
+            .map(|(col, (aggregation, property_index))| {
+                let col = col as u32;
+                if *aggregation == Aggregation::IsEmpty {
+                    Some(IsarValue::Bool(stmt.get_long(col) != 0))
                 } else {
-                    return Ok(None);
+                    let property_type = collection
+                        .get_property(property_index.unwrap_or(0))
+                        .map_or(DataType::Long, |p| p.data_type);
+                    read_aggregation(&stmt, col, *aggregation, property_type, true)
                 }
-            }
-        };
-        Ok(Some(result))
+            })
+            .collect();
+        Ok(results)
+    }
+
+    /// The conditions of the query grouped by the given properties. The distinct objects are
+    /// selected first because both use GROUP BY.
+    fn aggregate_sql<S: AsRef<str>>(
+        &self,
+        collection: &SQLiteCollection,
+        group_by: &[(S, bool)],
+    ) -> String {
+        if self.distinct.is_empty() {
+            query_sql(&self.index_sql, &self.conditions, &[], group_by)
+        } else {
+            let distinct_sql = format!(
+                "{} IN (SELECT {} FROM {} {})",
+                SQLiteProperty::ID_NAME,
+                SQLiteProperty::ID_NAME,
+                collection.name,
+                query_sql(&self.index_sql, &self.conditions, &[], &self.distinct)
+            );
+            query_sql("", &[distinct_sql], &[], group_by)
+        }
     }
 
     pub(crate) fn update(
@@ -172,6 +472,7 @@ impl SQLiteQuery {
         Self::bind_params(&mut stmt, &update_params, 0)?;
         Self::bind_params(&mut stmt, &self.params, update_params.len())?;
         stmt.step()?;
+        collection.check_conflicts(sqlite)?;
         let count = sqlite.count_changes();
         Ok(count as u32)
     }
@@ -205,6 +506,51 @@ impl SQLiteQuery {
         Ok(count as u32)
     }
 
+    /// Builds the plan from the output of `EXPLAIN QUERY PLAN`. SQLite does not report row
+    /// estimates so they are computed from the statistics collected by `ANALYZE`.
+    pub(crate) fn explain(
+        &self,
+        txn: &SQLiteTxn,
+        collection: &SQLiteCollection,
+    ) -> Result<QueryPlan> {
+        let sqlite = txn.get_sqlite(false)?;
+        let sql = format!(
+            "EXPLAIN QUERY PLAN SELECT {} FROM {} {}",
+            select_properties_sql(collection),
+            collection.name,
+            self.sql
+        );
+        let mut stmt = sqlite.prepare(&sql)?;
+        Self::bind_params(&mut stmt, &self.params, 0)?;
+        let mut details = vec![];
+        while stmt.step()? {
+            details.push(stmt.get_text(3).to_string());
+        }
+
+        let ranges = details
+            .iter()
+            .filter_map(|detail| plan_range(&collection.name, detail))
+            .collect_vec();
+        let stats = read_stats(sqlite, &collection.name)?;
+        let estimated_rows = ranges.iter().map(|r| estimate_rows(r, &stats)).sum();
+
+        let (covered_conditions, _) = self.covered;
+        let filter = &self.conditions[covered_conditions..];
+        Ok(QueryPlan {
+            ranges,
+            filter: if filter.is_empty() {
+                None
+            } else {
+                Some(filter.join(" AND "))
+            },
+            sort_in_memory: details
+                .iter()
+                .any(|d| d.starts_with("USE TEMP B-TREE FOR") && d.contains("ORDER BY")),
+            estimated_rows,
+            details,
+        })
+    }
+
     fn bind_params(stmt: &mut SQLiteStatement, params: &[QueryParam], offset: usize) -> Result<()> {
         for (i, params) in params.iter().enumerate() {
             let col = (i + offset) as u32;
@@ -225,6 +571,342 @@ impl SQLiteQuery {
     }
 }
 
+const FN_PERCENTILE_NAME: &str = "isar_percentile";
+const FN_VARIANCE_NAME: &str = "isar_variance";
+const FN_STD_DEV_NAME: &str = "isar_std_dev";
+
+/// Registers the aggregate functions for statistics that SQLite does not provide.
+pub(crate) fn create_statistics_functions(sqlite: &SQLite3) -> Result<()> {
+    sqlite.create_aggregate_function(
+        FN_PERCENTILE_NAME,
+        2,
+        |(values, percentile): &mut (Vec<f64>, f64), ctx| {
+            if ctx.is_numeric(0) {
+                values.push(ctx.get_double(0));
+            }
+            *percentile = ctx.get_double(1);
+        },
+        |(mut values, percentile), ctx| {
+            if let Some(result) = statistics::percentile(&mut values, percentile) {
+                ctx.set_double_result(result);
+            }
+        },
+    )?;
+    for (name, std_dev) in [(FN_VARIANCE_NAME, false), (FN_STD_DEV_NAME, true)] {
+        sqlite.create_aggregate_function(
+            name,
+            1,
+            |variance: &mut Variance, ctx| {
+                if ctx.is_numeric(0) {
+                    variance.add(ctx.get_double(0));
+                }
+            },
+            move |variance, ctx| {
+                if let Some(result) = variance.variance() {
+                    ctx.set_double_result(if std_dev { result.sqrt() } else { result });
+                }
+            },
+        )?;
+    }
+    Ok(())
+}
+
+fn aggregation_sql(
+    aggregation: Aggregation,
+    property_name: &str,
+    property_type: DataType,
+) -> String {
+    let numeric_list = matches!(
+        property_type,
+        DataType::IntList | DataType::LongList | DataType::FloatList | DataType::DoubleList
+    );
+    // aggregate the elements of each list first and then combine the results of all lists
+    let elements = |function: &str| {
+        format!(
+            "(SELECT {}(value) FROM json_each({}))",
+            function, property_name
+        )
+    };
+    match aggregation {
+        Aggregation::Min | Aggregation::Max | Aggregation::Sum if numeric_list => {
+            let function = match aggregation {
+                Aggregation::Min => "MIN",
+                Aggregation::Max => "MAX",
+                _ => "SUM",
+            };
+            format!("{}({})", function, elements(function))
+        }
+        Aggregation::Average if numeric_list => {
+            format!(
+                "SUM({}) * 1.0 / SUM({})",
+                elements("SUM"),
+                elements("COUNT")
+            )
+        }
+        // byte lists are stored as blobs and cannot contain null elements
+        Aggregation::ElementCount if property_type == DataType::ByteList => {
+            format!("SUM(LENGTH({}))", property_name)
+        }
+        Aggregation::ElementCount if property_type.is_list() => {
+            format!("SUM({})", elements("COUNT"))
+        }
+        Aggregation::MinLength | Aggregation::MaxLength | Aggregation::AverageLength
+            if property_type.is_list() =>
+        {
+            let function = match aggregation {
+                Aggregation::MinLength => "MIN",
+                Aggregation::MaxLength => "MAX",
+                _ => "AVG",
+            };
+            if property_type == DataType::ByteList {
+                format!("{}(LENGTH({}))", function, property_name)
+            } else {
+                format!("{}(json_array_length({}))", function, property_name)
+            }
+        }
+        Aggregation::ElementCount
+        | Aggregation::MinLength
+        | Aggregation::MaxLength
+        | Aggregation::AverageLength => "NULL".to_string(),
+        Aggregation::Count => "COUNT(*)".to_string(),
+        Aggregation::IsEmpty => SQLiteProperty::ID_NAME.to_string(),
+        Aggregation::Min => {
+            format!("MIN({})", property_name)
+        }
+        Aggregation::Max => {
+            format!("MAX({})", property_name)
+        }
+        Aggregation::Sum => {
+            format!("SUM({})", property_name)
+        }
+        Aggregation::Average => {
+            format!("AVG({})", property_name)
+        }
+        Aggregation::CountDistinct => {
+            format!("COUNT(DISTINCT {})", property_name)
+        }
+        Aggregation::Median => {
+            format!("{}({}, 0.5)", FN_PERCENTILE_NAME, property_name)
+        }
+        Aggregation::Percentile(percentile) => {
+            format!(
+                "{}({}, {:?})",
+                FN_PERCENTILE_NAME,
+                property_name,
+                percentile.value()
+            )
+        }
+        Aggregation::StdDev => {
+            format!("{}({})", FN_STD_DEV_NAME, property_name)
+        }
+        Aggregation::Variance => {
+            format!("{}({})", FN_VARIANCE_NAME, property_name)
+        }
+    }
+}
+
+fn read_aggregation(
+    stmt: &SQLiteStatement,
+    col: u32,
+    aggregation: Aggregation,
+    property_type: DataType,
+    has_next: bool,
+) -> Option<IsarValue> {
+    let result = match aggregation {
+        Aggregation::Count | Aggregation::CountDistinct => IsarValue::Integer(stmt.get_long(col)),
+        Aggregation::IsEmpty => IsarValue::Bool(!has_next),
+        Aggregation::Min | Aggregation::Max | Aggregation::Sum => {
+            if aggregation == Aggregation::Sum || !stmt.is_null(col) {
+                match property_type {
+                    DataType::Byte
+                    | DataType::Int
+                    | DataType::Long
+                    | DataType::IntList
+                    | DataType::LongList => IsarValue::Integer(stmt.get_long(col)),
+                    DataType::Float
+                    | DataType::Double
+                    | DataType::FloatList
+                    | DataType::DoubleList => IsarValue::Real(stmt.get_double(col)),
+                    DataType::String => IsarValue::String(stmt.get_text(col).to_string()),
+                    _ => return None,
+                }
+            } else {
+                return None;
+            }
+        }
+        Aggregation::ElementCount => {
+            if property_type.is_list() {
+                IsarValue::Integer(stmt.get_long(col))
+            } else {
+                return None;
+            }
+        }
+        Aggregation::MinLength | Aggregation::MaxLength => {
+            if !stmt.is_null(col) {
+                IsarValue::Integer(stmt.get_long(col))
+            } else {
+                return None;
+            }
+        }
+        Aggregation::Average | Aggregation::AverageLength => {
+            if !stmt.is_null(col) {
+                IsarValue::Real(stmt.get_double(col))
+            } else {
+                return None;
+            }
+        }
+        Aggregation::Median
+        | Aggregation::Percentile(_)
+        | Aggregation::StdDev
+        | Aggregation::Variance => {
+            // booleans are stored as integers but are not numbers
+            let numeric = matches!(
+                property_type,
+                DataType::Byte
+                    | DataType::Int
+                    | DataType::Long
+                    | DataType::Float
+                    | DataType::Double
+            );
+            if numeric && !stmt.is_null(col) {
+                IsarValue::Real(stmt.get_double(col))
+            } else {
+                return None;
+            }
+        }
+    };
+    Some(result)
+}
+
+fn read_value(stmt: &SQLiteStatement, col: u32, data_type: DataType) -> Option<IsarValue> {
+    if stmt.is_null(col) {
+        return None;
+    }
+    match data_type {
+        DataType::Bool => Some(IsarValue::Bool(stmt.get_long(col) != 0)),
+        DataType::Byte | DataType::Int | DataType::Long => {
+            Some(IsarValue::Integer(stmt.get_long(col)))
+        }
+        DataType::Float | DataType::Double => Some(IsarValue::Real(stmt.get_double(col))),
+        DataType::String => Some(IsarValue::String(stmt.get_text(col).to_string())),
+        _ => None,
+    }
+}
+
+/// Parses a `SCAN` or `SEARCH` step of the table like `SEARCH t USING INDEX t_name (a=?)`.
+/// Statistics of a table collected by `ANALYZE`.
+#[derive(Default)]
+struct TableStats {
+    /// The number of rows of the table.
+    rows: u64,
+    /// The average number of rows for the key prefixes of each index. The indexes are named
+    /// like in the query plan.
+    indexes: HashMap<String, Vec<u64>>,
+}
+
+/// Reads the statistics of the table from `sqlite_stat1`. Tables that have not been analyzed
+/// have no rows.
+fn read_stats(sqlite: &SQLite3, table: &str) -> Result<TableStats> {
+    let mut stats = TableStats::default();
+    let mut stmt = sqlite
+        .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'sqlite_stat1'")?;
+    if !stmt.step()? {
+        return Ok(stats);
+    }
+    let mut stmt = sqlite.prepare("SELECT idx, stat FROM sqlite_stat1 WHERE tbl = ?")?;
+    stmt.bind_text(0, table)?;
+    while stmt.step()? {
+        let numbers = stmt
+            .get_text(1)
+            .split(' ')
+            .map_while(|n| n.parse::<u64>().ok())
+            .collect_vec();
+        let Some(rows) = numbers.first() else {
+            continue;
+        };
+        // indexes with a filter only contain some of the rows
+        stats.rows = stats.rows.max(*rows);
+        if !stmt.is_null(0) {
+            let index = stmt.get_text(0);
+            let index = index
+                .strip_prefix(table)
+                .and_then(|i| i.strip_prefix('_'))
+                .unwrap_or(index);
+            stats
+                .indexes
+                .insert(index.to_string(), numbers[1..].to_vec());
+        }
+    }
+    Ok(stats)
+}
+
+/// Estimates the rows read from the range like SQLite without detailed statistics: every
+/// equality constraint uses the average rows per key of the index and every inequality
+/// reduces the rows to a quarter.
+fn estimate_rows(range: &QueryPlanRange, stats: &TableStats) -> u64 {
+    let constraints = range.bounds.split(" AND ").filter(|c| !c.is_empty());
+    let (equal, other): (Vec<_>, Vec<_>) = constraints.partition(|c| {
+        c.contains('=') && !c.contains(">=") && !c.contains("<=") && !c.contains("!=")
+    });
+    let mut rows = match (&range.index, equal.len()) {
+        (_, 0) => stats.rows,
+        (None, _) => 1,
+        (Some(index), equal) => {
+            let averages = stats.indexes.get(index).map_or(&[][..], |a| a.as_slice());
+            averages
+                .get(equal - 1)
+                .or(averages.last())
+                .copied()
+                .unwrap_or(stats.rows)
+        }
+    };
+    for _ in other {
+        rows /= 4;
+    }
+    rows
+}
+
+fn plan_range(table: &str, detail: &str) -> Option<QueryPlanRange> {
+    let (search, rest) = if let Some(rest) = detail.strip_prefix("SEARCH ") {
+        (true, rest)
+    } else {
+        (false, detail.strip_prefix("SCAN ")?)
+    };
+    let rest = rest.strip_prefix("TABLE ").unwrap_or(rest);
+    let rest = rest.strip_prefix(table)?;
+    if !rest.is_empty() && !rest.starts_with(' ') {
+        return None;
+    }
+    let rest = rest.trim_start();
+
+    let (index, constraint) = if let Some(using) = rest.strip_prefix("USING ") {
+        if let Some(constraint) = using.strip_prefix("INTEGER PRIMARY KEY") {
+            (None, constraint)
+        } else {
+            let (_, after) = using.split_once("INDEX ")?;
+            let (name, constraint) = after.split_once(' ').unwrap_or((after, ""));
+            let index = name
+                .strip_prefix(table)
+                .and_then(|n| n.strip_prefix('_'))
+                .unwrap_or(name);
+            (Some(index.to_string()), constraint)
+        }
+    } else {
+        (None, "")
+    };
+    let bounds = if search {
+        let constraint = constraint.trim();
+        let constraint = constraint.strip_prefix('(').unwrap_or(constraint);
+        constraint
+            .strip_suffix(')')
+            .unwrap_or(constraint)
+            .to_string()
+    } else {
+        String::new()
+    };
+    Some(QueryPlanRange { index, bounds })
+}
+
 impl QueryMatches for SQLiteQuery {
     type Object<'a> = ();
 
@@ -259,3 +941,823 @@ impl<'a> IsarQueryCursor for SQLiteQueryCursor<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::insert::IsarInsert;
+    use crate::core::instance::{IsarInstance, Percentile};
+    use crate::core::query_builder::IsarQueryBuilder;
+    use crate::core::reader::IsarReader;
+    use crate::core::schema::{IndexSchema, IsarSchema, PropertySchema};
+    use crate::core::writer::IsarWriter;
+    use crate::sqlite::sqlite_instance::SQLiteInstance;
+
+    #[test]
+    fn test_plan_range() {
+        let range = |index: Option<&str>, bounds: &str| {
+            Some(QueryPlanRange {
+                index: index.map(|i| i.to_string()),
+                bounds: bounds.to_string(),
+            })
+        };
+        assert_eq!(plan_range("col", "SCAN col"), range(None, ""));
+        assert_eq!(plan_range("col", "SCAN TABLE col"), range(None, ""));
+        assert_eq!(
+            plan_range("col", "SCAN col USING INDEX col_idx"),
+            range(Some("idx"), "")
+        );
+        assert_eq!(
+            plan_range(
+                "col",
+                "SEARCH col USING INTEGER PRIMARY KEY (rowid>? AND rowid<?)"
+            ),
+            range(None, "rowid>? AND rowid<?")
+        );
+        assert_eq!(
+            plan_range(
+                "col",
+                "SEARCH col USING COVERING INDEX col_idx (a=? AND b>?)"
+            ),
+            range(Some("idx"), "a=? AND b>?")
+        );
+        assert_eq!(plan_range("col", "SCAN col2"), None);
+        assert_eq!(plan_range("col", "USE TEMP B-TREE FOR ORDER BY"), None);
+        assert_eq!(plan_range("col", "MULTI-INDEX OR"), None);
+    }
+
+    #[test]
+    fn test_estimate_rows() {
+        let sqlite = SQLite3::open(":memory:", None).unwrap();
+        let range = |index: Option<&str>, bounds: &str| QueryPlanRange {
+            index: index.map(|i| i.to_string()),
+            bounds: bounds.to_string(),
+        };
+        sqlite
+            .prepare("CREATE TABLE col (a INTEGER, b INTEGER)")
+            .unwrap()
+            .step()
+            .unwrap();
+        sqlite
+            .prepare("CREATE INDEX col_ab ON col (a, b)")
+            .unwrap()
+            .step()
+            .unwrap();
+        let stats = read_stats(&sqlite, "col").unwrap();
+        assert_eq!(estimate_rows(&range(None, ""), &stats), 0);
+        assert_eq!(estimate_rows(&range(None, "rowid=?"), &stats), 1);
+
+        let mut insert = sqlite
+            .prepare("INSERT INTO col (a, b) VALUES (?, ?)")
+            .unwrap();
+        for i in 0..400 {
+            insert.bind_long(0, i % 10).unwrap();
+            insert.bind_long(1, i).unwrap();
+            insert.step().unwrap();
+            insert.reset().unwrap();
+        }
+        drop(insert);
+        sqlite.prepare("ANALYZE").unwrap().step().unwrap();
+
+        let stats = read_stats(&sqlite, "col").unwrap();
+        assert_eq!(estimate_rows(&range(None, ""), &stats), 400);
+        assert_eq!(estimate_rows(&range(Some("ab"), ""), &stats), 400);
+        assert_eq!(estimate_rows(&range(None, "rowid=?"), &stats), 1);
+        assert_eq!(estimate_rows(&range(None, "rowid>?"), &stats), 100);
+        assert_eq!(
+            estimate_rows(&range(None, "rowid>? AND rowid<?"), &stats),
+            25
+        );
+        assert_eq!(estimate_rows(&range(Some("ab"), "a=?"), &stats), 40);
+        assert_eq!(estimate_rows(&range(Some("ab"), "a=? AND b=?"), &stats), 1);
+        assert_eq!(estimate_rows(&range(Some("ab"), "a=? AND b>?"), &stats), 10);
+        assert_eq!(estimate_rows(&range(Some("ab"), "a>=?"), &stats), 100);
+    }
+
+    /// A collection with the properties `long`, `string`, `int` and `double`.
+    fn open(name: &str, indexes: Vec<IndexSchema>) -> SQLiteInstance {
+        let schema = IsarSchema::new(
+            "test",
+            Some("id"),
+            vec![
+                PropertySchema::new("long", DataType::Long, None),
+                PropertySchema::new("string", DataType::String, None),
+                PropertySchema::new("int", DataType::Int, None),
+                PropertySchema::new("double", DataType::Double, None),
+            ],
+            indexes,
+            false,
+        );
+        SQLiteInstance::open_temp(name, vec![schema]).unwrap()
+    }
+
+    fn long_index() -> IndexSchema {
+        IndexSchema::new("long", vec!["long"], false, false, true)
+    }
+
+    fn long(value: i64) -> Option<IsarValue> {
+        Some(IsarValue::Integer(value))
+    }
+
+    fn real(value: f64) -> Option<IsarValue> {
+        Some(IsarValue::Real(value))
+    }
+
+    fn string(value: &str) -> Option<IsarValue> {
+        Some(IsarValue::String(value.to_string()))
+    }
+
+    /// Inserts the objects. Missing values are null.
+    fn put(instance: &SQLiteInstance, objects: &[(i64, Vec<Option<IsarValue>>)]) {
+        let txn = instance.begin_txn(true).unwrap();
+        let mut insert = instance.insert(txn, 0, objects.len() as u32).unwrap();
+        for (id, values) in objects {
+            for index in 1..=4 {
+                match values.get(index as usize - 1).cloned().flatten() {
+                    Some(IsarValue::Integer(value)) if index == 3 => {
+                        insert.write_int(index, value as i32)
+                    }
+                    Some(IsarValue::Integer(value)) => insert.write_long(index, value),
+                    Some(IsarValue::Real(value)) => insert.write_double(index, value),
+                    Some(IsarValue::String(value)) => insert.write_string(index, &value),
+                    _ => insert.write_null(index),
+                }
+            }
+            insert.save(*id).unwrap();
+        }
+        let txn = insert.finish().unwrap();
+        instance.commit_txn(txn).unwrap();
+    }
+
+    /// Objects 1 to 12 with the `long` values 0, 1, 2 and null repeated three times.
+    fn put_duplicate_keys(instance: &SQLiteInstance) {
+        let objects = (1..=12)
+            .map(|id| {
+                let value = if id % 4 == 0 { None } else { long(id % 4 - 1) };
+                (id, vec![value])
+            })
+            .collect_vec();
+        put(instance, &objects);
+    }
+
+    /// Returns the ids and values of the objects matching the query in the order of the query.
+    fn query_objects(
+        instance: &SQLiteInstance,
+        query: &SQLiteQuery,
+        limit: Option<u32>,
+    ) -> Vec<(i64, Vec<Option<IsarValue>>)> {
+        let txn = instance.begin_txn(false).unwrap();
+        let mut objects = vec![];
+        {
+            let mut cursor = instance.query_cursor(&txn, query, None, limit).unwrap();
+            while let Some(reader) = cursor.next() {
+                let values = vec![
+                    (!reader.is_null(1))
+                        .then(|| reader.read_long(1))
+                        .and_then(long),
+                    reader.read_string(2).and_then(string),
+                    (!reader.is_null(3))
+                        .then(|| reader.read_int(3) as i64)
+                        .and_then(long),
+                    (!reader.is_null(4))
+                        .then(|| reader.read_double(4))
+                        .and_then(real),
+                ];
+                objects.push((reader.read_id(), values));
+            }
+        }
+        instance.abort_txn(txn);
+        objects
+    }
+
+    fn query_ids(instance: &SQLiteInstance, query: &SQLiteQuery) -> Vec<i64> {
+        query_objects(instance, query, None)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Returns the `long` values in query order and the sorted ids of the objects.
+    fn query_longs(instance: &SQLiteInstance, query: &SQLiteQuery) -> (Vec<Option<i64>>, Vec<i64>) {
+        let objects = query_objects(instance, query, None);
+        let values = objects
+            .iter()
+            .map(|(_, values)| values[0].as_ref().and_then(|v| v.i64()))
+            .collect();
+        let ids = objects.iter().map(|(id, _)| *id).sorted().collect();
+        (values, ids)
+    }
+
+    #[test]
+    fn test_sort_id_ranges() {
+        let instance = open("query_sort_id_ranges", vec![]);
+        put_duplicate_keys(&instance);
+        for (sort, expected) in [
+            (Sort::Asc, vec![2, 3, 4, 5, 7, 8, 9]),
+            (Sort::Desc, vec![9, 8, 7, 5, 4, 3, 2]),
+        ] {
+            let mut qb = instance.query(0).unwrap();
+            qb.add_id_range(7, 9);
+            qb.add_id_range(2, 5);
+            qb.add_sort(0, sort, true);
+            assert_eq!(query_ids(&instance, &qb.build()), expected);
+        }
+        SQLiteInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_sort_index_ranges_with_duplicates() {
+        let instance = open("query_sort_index_ranges", vec![long_index()]);
+        put_duplicate_keys(&instance);
+        let ranges = [
+            (0, 1, vec![0, 0, 0, 1, 1, 1], vec![1, 2, 5, 6, 9, 10]),
+            (1, 2, vec![1, 1, 1, 2, 2, 2], vec![2, 3, 6, 7, 10, 11]),
+            // the upper key is missing and followed by another key
+            (-5, -1, vec![], vec![]),
+        ];
+        for (lower, upper, values, ids) in ranges {
+            let mut values = values.into_iter().map(Some).collect_vec();
+            for sort in [Sort::Asc, Sort::Desc] {
+                let mut qb = instance.query(0).unwrap();
+                qb.add_index_range("long", vec![long(lower)], vec![long(upper)], true, true)
+                    .unwrap();
+                qb.add_sort(1, sort, true);
+                let result = query_longs(&instance, &qb.build());
+                assert_eq!(result, (values.clone(), ids.clone()));
+                values.reverse();
+            }
+        }
+        SQLiteInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_sort_full_scan_by_index() {
+        let instance = open("query_sort_full_scan", vec![long_index()]);
+        put_duplicate_keys(&instance);
+        let all_ids = (1..=12).collect_vec();
+        let query = instance.query(0).unwrap().build();
+        assert_eq!(query_ids(&instance, &query), all_ids);
+
+        let mut values = vec![None, None, None, Some(0), Some(0), Some(0)];
+        values.extend([Some(1), Some(1), Some(1), Some(2), Some(2), Some(2)]);
+        for sort in [Sort::Asc, Sort::Desc] {
+            let mut qb = instance.query(0).unwrap();
+            qb.add_sort(1, sort, true);
+            let result = query_longs(&instance, &qb.build());
+            assert_eq!(result, (values.clone(), all_ids.clone()));
+            values.reverse();
+        }
+        SQLiteInstance::close(instance, true);
+    }
+
+    /// Reads all pages of the query and returns the ids of every page.
+    fn page_ids(instance: &SQLiteInstance, query: &SQLiteQuery, limit: u32) -> Vec<Vec<i64>> {
+        let mut pages = vec![];
+        let mut token = None;
+        loop {
+            let page = instance.query_page(query, token.as_deref()).unwrap();
+            let ids = query_objects(instance, &page, Some(limit))
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect_vec();
+            let Some(last) = ids.last() else {
+                return pages;
+            };
+            let txn = instance.begin_txn(false).unwrap();
+            token = instance.query_page_token(&txn, &page, *last).unwrap();
+            instance.abort_txn(txn);
+            pages.push(ids);
+        }
+    }
+
+    #[test]
+    fn test_pages() {
+        let instance = open("query_pages", vec![long_index()]);
+        put_duplicate_keys(&instance);
+
+        // ties are ordered by id in the direction of the last sort property
+        let sorts = [
+            (vec![], (1..=12).collect_vec()),
+            (vec![(0, Sort::Desc)], (1..=12).rev().collect_vec()),
+            (
+                vec![(1, Sort::Asc)],
+                vec![4, 8, 12, 1, 5, 9, 2, 6, 10, 3, 7, 11],
+            ),
+            (
+                vec![(1, Sort::Desc)],
+                vec![11, 7, 3, 10, 6, 2, 9, 5, 1, 12, 8, 4],
+            ),
+            (
+                vec![(1, Sort::Desc), (0, Sort::Asc)],
+                vec![3, 7, 11, 2, 6, 10, 1, 5, 9, 4, 8, 12],
+            ),
+        ];
+        for (sort, ids) in sorts {
+            let mut qb = instance.query(0).unwrap();
+            for (property, sort) in &sort {
+                qb.add_sort(*property, *sort, true);
+            }
+            let query = qb.build();
+            let pages = page_ids(&instance, &query, 5);
+            assert_eq!(pages.iter().map(|p| p.len()).collect_vec(), vec![5, 5, 2]);
+            assert_eq!(pages.concat(), ids);
+
+            // the order of the index is used for the pages
+            if sort.len() == 1 {
+                let txn = instance.begin_txn(false).unwrap();
+                let page = instance.query_page(&query, None).unwrap();
+                assert!(!instance.explain(&txn, &page).unwrap().sort_in_memory);
+                instance.abort_txn(txn);
+            }
+        }
+        SQLiteInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_pages_of_equal_keys() {
+        let instance = open("query_pages_of_equal_keys", vec![long_index()]);
+        // the bytes of the ids are not in the order of the ids
+        let ids = vec![-65536, -256, -1, 1, 255, 256, 257, 65536];
+        let objects = ids.iter().map(|id| (*id, vec![long(0)])).collect_vec();
+        put(&instance, &objects);
+
+        for sort in [Sort::Asc, Sort::Desc] {
+            let mut qb = instance.query(0).unwrap();
+            qb.add_sort(1, sort, true);
+            let query = qb.build();
+            let expected = if sort == Sort::Asc {
+                ids.clone()
+            } else {
+                ids.iter().copied().rev().collect_vec()
+            };
+            assert_eq!(page_ids(&instance, &query, 3).concat(), expected);
+        }
+        SQLiteInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_pages_of_real_and_string_keys() {
+        let indexes = vec![
+            IndexSchema::new("string", vec!["string"], false, false, true),
+            IndexSchema::new("double", vec!["double"], false, false, true),
+        ];
+        let instance = open("query_pages_of_real_and_string_keys", indexes);
+        let values = [
+            (real(1.5), string("b")),
+            (None, None),
+            (real(-2.0), string("a")),
+            (real(0.5), string("")),
+            (real(1.5), string("b")),
+            (None, None),
+            (real(3.0), string("c")),
+            (real(0.5), string("ab")),
+        ];
+        let objects = values
+            .into_iter()
+            .zip(1..)
+            .map(|((double, string), id)| (id, vec![None, string, None, double]))
+            .collect_vec();
+        put(&instance, &objects);
+
+        for property in [2, 4] {
+            for sort in [Sort::Asc, Sort::Desc] {
+                let mut qb = instance.query(0).unwrap();
+                qb.add_sort(property, sort, true);
+                let query = qb.build();
+                let page = instance.query_page(&query, None).unwrap();
+                let expected = query_ids(&instance, &page);
+                assert_eq!(expected.len(), 8);
+                for limit in 1..=3 {
+                    assert_eq!(page_ids(&instance, &query, limit).concat(), expected);
+                }
+            }
+        }
+        SQLiteInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_page_token_of_other_query() {
+        let instance = open("query_page_token_of_other_query", vec![]);
+        put_duplicate_keys(&instance);
+        let query = |sort: Sort, upper: i64| {
+            let mut qb = instance.query(0).unwrap();
+            qb.add_id_range(1, upper);
+            qb.add_sort(1, sort, true);
+            qb.build()
+        };
+        let query1 = query(Sort::Asc, 10);
+        let page = instance.query_page(&query1, None).unwrap();
+        let txn = instance.begin_txn(false).unwrap();
+        let token = instance.query_page_token(&txn, &page, 5).unwrap().unwrap();
+        instance.abort_txn(txn);
+
+        assert!(instance.query_page(&query1, Some(&token)).is_ok());
+        assert!(instance.query_page(&page, Some(&token)).is_ok());
+        for other in [query(Sort::Desc, 10), query(Sort::Asc, 11)] {
+            assert!(matches!(
+                instance.query_page(&other, Some(&token)),
+                Err(IsarError::IllegalArgument {})
+            ));
+        }
+        SQLiteInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_explain_index() {
+        let instance = open("query_explain_index", vec![long_index()]);
+        put_duplicate_keys(&instance);
+        let mut qb = instance.query(0).unwrap();
+        qb.set_filter(Filter::new_condition(
+            1,
+            ConditionType::Between,
+            vec![long(1), long(2)],
+            true,
+        ));
+        qb.add_sort(1, Sort::Asc, true);
+        let query = qb.build();
+
+        let txn = instance.begin_txn(false).unwrap();
+        let plan = instance.explain(&txn, &query).unwrap();
+        instance.abort_txn(txn);
+        assert_eq!(plan.ranges.len(), 1);
+        assert_eq!(plan.ranges[0].index.as_deref(), Some("long"));
+        assert!(!plan.ranges[0].bounds.is_empty());
+        assert!(!plan.sort_in_memory);
+        SQLiteInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_explain_sort_in_memory() {
+        let instance = open("query_explain_sort_in_memory", vec![long_index()]);
+        put_duplicate_keys(&instance);
+        let mut qb = instance.query(0).unwrap();
+        qb.set_filter(Filter::new_condition(
+            3,
+            ConditionType::Greater,
+            vec![long(5)],
+            true,
+        ));
+        qb.add_sort(2, Sort::Desc, true);
+        let query = qb.build();
+
+        let txn = instance.begin_txn(false).unwrap();
+        let plan = instance.explain(&txn, &query).unwrap();
+        instance.abort_txn(txn);
+        assert_eq!(plan.ranges.len(), 1);
+        assert_eq!(plan.ranges[0].index, None);
+        // the residual filter uses the names of the properties
+        assert!(plan.filter.unwrap().starts_with("int "));
+        assert!(plan.sort_in_memory);
+        SQLiteInstance::close(instance, true);
+    }
+
+    /// Objects with groups of `long` and `string` values and the `int` values to aggregate.
+    fn put_groups(instance: &SQLiteInstance) {
+        let objects = [
+            (1, long(1), "a", long(10)),
+            (2, long(1), "A", long(20)),
+            (3, long(2), "a", None),
+            (4, long(2), "b", long(5)),
+            (5, None, "b", long(7)),
+            (6, long(1), "b", long(-4)),
+            (7, long(2), "a", long(9)),
+        ];
+        let objects = objects
+            .into_iter()
+            .map(|(id, group, name, value)| (id, vec![group, string(name), value]))
+            .collect_vec();
+        put(instance, &objects);
+    }
+
+    /// Aggregates the groups of all objects and returns the groups sorted by their values.
+    /// Strings of case-insensitive groups may be spelled like any object of the group so they
+    /// are lowercased.
+    fn aggregate_groups(
+        instance: &SQLiteInstance,
+        group_by: &[(u16, bool)],
+        aggregation: Aggregation,
+        property_index: Option<u16>,
+    ) -> Vec<(Vec<Option<IsarValue>>, Option<IsarValue>)> {
+        let query = instance.query(0).unwrap().build();
+        let txn = instance.begin_txn(false).unwrap();
+        let groups = instance
+            .query_aggregate_groups(&txn, &query, group_by, aggregation, property_index)
+            .unwrap();
+        instance.abort_txn(txn);
+        groups
+            .into_iter()
+            .map(|group| {
+                let values = group
+                    .values
+                    .into_iter()
+                    .map(|value| match value {
+                        Some(IsarValue::String(value)) => string(&value.to_lowercase()),
+                        value => value,
+                    })
+                    .collect_vec();
+                (values, group.result)
+            })
+            .sorted_by_key(|(values, _)| format!("{:?}", values))
+            .collect()
+    }
+
+    #[test]
+    fn test_aggregate_groups() {
+        let instance = open("query_aggregate_groups", vec![]);
+        put_groups(&instance);
+
+        // Count, Sum, Min, Max and Average of each group ordered by the group values
+        let by_long = [
+            (vec![long(1)], [3, 26, -4, 20], 26.0 / 3.0),
+            (vec![long(2)], [3, 14, 5, 9], 7.0),
+            (vec![None], [1, 7, 7, 7], 7.0),
+        ];
+        let by_long_string = [
+            (vec![long(1), string("a")], [2, 30, 10, 20], 15.0),
+            (vec![long(1), string("b")], [1, -4, -4, -4], -4.0),
+            (vec![long(2), string("a")], [2, 9, 9, 9], 9.0),
+            (vec![long(2), string("b")], [1, 5, 5, 5], 5.0),
+            (vec![None, string("b")], [1, 7, 7, 7], 7.0),
+        ];
+        let group_bys: [(&[(u16, bool)], &[_]); 2] = [
+            (&[(1, true)], &by_long),
+            (&[(1, true), (2, false)], &by_long_string),
+        ];
+        for (group_by, expected) in group_bys {
+            let aggregations = [
+                (Aggregation::Count, None),
+                (Aggregation::Sum, Some(3)),
+                (Aggregation::Min, Some(3)),
+                (Aggregation::Max, Some(3)),
+            ];
+            for (i, (aggregation, property_index)) in aggregations.into_iter().enumerate() {
+                let expected = expected
+                    .iter()
+                    .map(|(values, results, _)| (values.clone(), long(results[i])))
+                    .sorted_by_key(|(values, _)| format!("{:?}", values))
+                    .collect_vec();
+                assert_eq!(
+                    aggregate_groups(&instance, group_by, aggregation, property_index),
+                    expected,
+                    "{:?}",
+                    aggregation
+                );
+            }
+            let expected = expected
+                .iter()
+                .map(|(values, _, average)| (values.clone(), real(*average)))
+                .sorted_by_key(|(values, _)| format!("{:?}", values))
+                .collect_vec();
+            assert_eq!(
+                aggregate_groups(&instance, group_by, Aggregation::Average, Some(3)),
+                expected
+            );
+        }
+        SQLiteInstance::close(instance, true);
+    }
+
+    /// Aggregates the objects whose `long` value is `long_value` or all objects if it is `None`.
+    fn aggregate(
+        instance: &SQLiteInstance,
+        long_value: Option<i64>,
+        aggregation: Aggregation,
+        property_index: Option<u16>,
+    ) -> Option<IsarValue> {
+        let mut qb = instance.query(0).unwrap();
+        if let Some(value) = long_value {
+            qb.set_filter(Filter::new_condition(
+                1,
+                ConditionType::Equal,
+                vec![long(value)],
+                true,
+            ));
+        }
+        let query = qb.build();
+        let txn = instance.begin_txn(false).unwrap();
+        let result = instance
+            .query_aggregate(&txn, &query, aggregation, property_index)
+            .unwrap();
+        instance.abort_txn(txn);
+        result
+    }
+
+    #[test]
+    fn test_aggregate_statistics() {
+        let instance = open("query_aggregate_statistics", vec![]);
+        let values = [10, 20, 0, 5, 7, -4, 9];
+        let objects = values
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let group = if i == 6 { 2 } else { 1 };
+                let (int, double) = if i == 2 {
+                    (None, None)
+                } else {
+                    (long(value), real(value as f64 / 2.0))
+                };
+                (i as i64 + 1, vec![long(group), None, int, double])
+            })
+            .collect_vec();
+        put(&instance, &objects);
+
+        let percentile = |percentile| Aggregation::Percentile(Percentile::new(percentile).unwrap());
+        let variance = 1817.0 / 30.0;
+        // The ints -4, 5, 7, 9, 10 and 20 and the doubles of half their value
+        let all = [
+            (Aggregation::Median, 8.0),
+            (percentile(0.0), -4.0),
+            (percentile(0.25), 5.5),
+            (percentile(1.0), 20.0),
+            (Aggregation::Variance, variance),
+            (Aggregation::StdDev, f64::sqrt(variance)),
+        ];
+        for (aggregation, expected) in all {
+            for (property_index, factor) in [(3, 1.0), (4, 0.5)] {
+                let factor = if aggregation == Aggregation::Variance {
+                    factor * factor
+                } else {
+                    factor
+                };
+                let result = aggregate(&instance, None, aggregation, Some(property_index));
+                let Some(IsarValue::Real(result)) = result else {
+                    panic!("{:?} of {}: {:?}", aggregation, property_index, result);
+                };
+                assert!(
+                    (result - expected * factor).abs() < 1e-9,
+                    "{:?} of {}: {}",
+                    aggregation,
+                    property_index,
+                    result
+                );
+            }
+        }
+
+        // A single value has a percentile but no sample variance
+        for property_index in [3, 4] {
+            let value = if property_index == 3 { 9.0 } else { 4.5 };
+            for aggregation in [Aggregation::Median, percentile(0.9)] {
+                assert_eq!(
+                    aggregate(&instance, Some(2), aggregation, Some(property_index)),
+                    real(value)
+                );
+            }
+            for aggregation in [
+                Aggregation::Median,
+                percentile(0.9),
+                Aggregation::Variance,
+                Aggregation::StdDev,
+            ] {
+                assert_eq!(
+                    aggregate(&instance, Some(3), aggregation, Some(property_index)),
+                    None
+                );
+            }
+            for aggregation in [Aggregation::Variance, Aggregation::StdDev] {
+                assert_eq!(
+                    aggregate(&instance, Some(2), aggregation, Some(property_index)),
+                    None
+                );
+            }
+        }
+        SQLiteInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_aggregate_batch() {
+        let instance = open("query_aggregate_batch", vec![]);
+        put_groups(&instance);
+
+        let percentile = Aggregation::Percentile(Percentile::new(0.3).unwrap());
+        let batch = [
+            (Aggregation::Count, None),
+            (Aggregation::IsEmpty, None),
+            (Aggregation::Min, Some(3)),
+            (Aggregation::Max, Some(3)),
+            (Aggregation::Sum, Some(3)),
+            (Aggregation::Average, Some(3)),
+            (Aggregation::CountDistinct, Some(3)),
+            (Aggregation::Median, Some(3)),
+            (percentile, Some(3)),
+            (Aggregation::StdDev, Some(3)),
+            (Aggregation::Variance, Some(3)),
+            (Aggregation::Min, Some(2)),
+            (Aggregation::Max, Some(2)),
+            (Aggregation::CountDistinct, Some(2)),
+        ];
+        // All objects, a group and no objects
+        for long_value in [None, Some(1), Some(3)] {
+            let mut qb = instance.query(0).unwrap();
+            if let Some(value) = long_value {
+                qb.set_filter(Filter::new_condition(
+                    1,
+                    ConditionType::Equal,
+                    vec![long(value)],
+                    true,
+                ));
+            }
+            let query = qb.build();
+            let txn = instance.begin_txn(false).unwrap();
+            let results = instance
+                .query_aggregate_batch(&txn, &query, &batch)
+                .unwrap();
+            assert!(
+                instance
+                    .query_aggregate_batch(&txn, &query, &[])
+                    .unwrap()
+                    .is_empty()
+            );
+            instance.abort_txn(txn);
+
+            assert_eq!(results.len(), batch.len());
+            assert_eq!(results[1], Some(IsarValue::Bool(long_value == Some(3))));
+            for ((aggregation, property_index), result) in batch.into_iter().zip(results) {
+                let expected = aggregate(&instance, long_value, aggregation, property_index);
+                // Compares the debug output because an empty average may be NaN
+                assert_eq!(
+                    format!("{:?}", result),
+                    format!("{:?}", expected),
+                    "{:?} of {:?} with {:?}",
+                    aggregation,
+                    property_index,
+                    long_value
+                );
+            }
+        }
+        SQLiteInstance::close(instance, true);
+    }
+
+    #[test]
+    fn test_query_bind() {
+        for (name, indexes) in [
+            ("query_bind", vec![]),
+            ("query_bind_index", vec![long_index()]),
+        ] {
+            let instance = open(name, indexes);
+            let objects = (1..=9).map(|id| (id, vec![long(id % 3)])).collect_vec();
+            put(&instance, &objects);
+            let mut qb = instance.query(0).unwrap();
+            qb.set_filter(Filter::new_or(vec![
+                Filter::new_not(Filter::new_parameter(0, 1, ConditionType::Between, true)),
+                Filter::new_parameter(1, 1, ConditionType::Equal, true),
+            ]));
+            qb.add_sort(0, Sort::Asc, true);
+            let mut query = qb.build();
+
+            // Unbound parameters cannot be run
+            let unbound = |query: &SQLiteQuery| {
+                let txn = instance.begin_txn(true).unwrap();
+                assert!(matches!(
+                    instance.query_cursor(&txn, query, None, None),
+                    Err(IsarError::IllegalArgument {})
+                ));
+                assert_eq!(
+                    instance.query_aggregate(&txn, query, Aggregation::Count, None),
+                    Err(IsarError::IllegalArgument {})
+                );
+                assert_eq!(
+                    instance.query_delete(&txn, query, None, None),
+                    Err(IsarError::IllegalArgument {})
+                );
+                assert!(matches!(
+                    instance.query_page(query, None),
+                    Err(IsarError::IllegalArgument {})
+                ));
+                assert!(instance.explain(&txn, query).is_err());
+                instance.abort_txn(txn);
+            };
+            unbound(&query);
+            assert_eq!(
+                instance.query_bind(&mut query, &[vec![long(1), long(2)]]),
+                Err(IsarError::IllegalArgument {})
+            );
+            unbound(&query);
+
+            let bindings = [
+                (vec![long(1), long(2)], long(1), vec![1, 3, 4, 6, 7, 9]),
+                (vec![long(0), long(1)], None, vec![2, 5, 8]),
+                (vec![long(0), long(0)], long(0), (1..=9).collect_vec()),
+                (vec![None, long(2)], long(5), vec![]),
+            ];
+            for (between, equal, expected) in bindings {
+                instance
+                    .query_bind(&mut query, &[between, vec![equal]])
+                    .unwrap();
+                assert_eq!(query_ids(&instance, &query), expected);
+                let txn = instance.begin_txn(false).unwrap();
+                let count = instance
+                    .query_aggregate(&txn, &query, Aggregation::Count, None)
+                    .unwrap();
+                instance.abort_txn(txn);
+                assert_eq!(count, long(expected.len() as i64));
+            }
+
+            // Queries without parameters cannot be bound
+            let mut query = instance.query(0).unwrap().build();
+            assert_eq!(
+                instance.query_bind(&mut query, &[vec![long(1)]]),
+                Err(IsarError::IllegalArgument {})
+            );
+            SQLiteInstance::close(instance, true);
+        }
+    }
+}
@@ -1,16 +1,20 @@
 use std::vec;
 
+use super::sql::{index_column_sql, index_filter_sql, list_index_table};
 use super::sql_filter::filter_sql;
-use super::sqlite_collection::SQLiteCollection;
+use super::sqlite_collection::{SQLiteCollection, SQLiteProperty};
 use super::sqlite_query::{QueryParam, SQLiteQuery};
+use crate::core::error::{IsarError, Result};
 use crate::core::filter::Filter;
 use crate::core::query_builder::{IsarQueryBuilder, Sort};
+use crate::core::value::IsarValue;
 use itertools::Itertools;
 
 pub struct SQLiteQueryBuilder<'a> {
     all_collections: &'a [SQLiteCollection],
     collection_index: u16,
     filter: Option<Filter>,
+    ranges: Vec<(Option<&'a str>, String, Vec<QueryParam>)>,
     sort: Vec<(&'a str, Sort, bool)>,
     distinct: Vec<(&'a str, bool)>,
 }
@@ -24,6 +28,7 @@ impl SQLiteQueryBuilder<'_> {
             all_collections,
             collection_index,
             filter: None,
+            ranges: Vec::new(),
             sort: Vec::new(),
             distinct: Vec::new(),
         }
@@ -31,56 +36,78 @@ impl SQLiteQueryBuilder<'_> {
 }
 
 impl<'a> SQLiteQueryBuilder<'a> {
-    fn build_query(self) -> (String, Vec<QueryParam>) {
+    /// Builds the INDEXED BY clause and the conditions of the query. Also returns the number of
+    /// leading conditions and params that select the ranges of the query.
+    fn build_filter(&mut self) -> (String, Vec<String>, Vec<QueryParam>, (usize, usize)) {
         let mut filter_params = vec![];
-
-        let mut sql = String::new();
-        if let Some(filter) = self.filter {
-            sql.push_str(" WHERE ");
-            let get_property = &|collection_index, property_index| {
-                self.all_collections
-                    .get(collection_index as usize)?
-                    .get_property(property_index)
-            };
-            let (filter_sql, params) = filter_sql(self.collection_index, &get_property, filter);
-            sql.push_str(&filter_sql);
-            filter_params = params;
+        let mut conditions = vec![];
+
+        let mut index_sql = String::new();
+        if let Some(index_name) = self.forced_index() {
+            let collection = &self.all_collections[self.collection_index as usize];
+            index_sql.push_str(&format!(" INDEXED BY {}_{}", collection.name, index_name));
+            // SQLite only uses a partial index if its filter is a term of the query
+            let filter = collection
+                .indexes
+                .iter()
+                .find(|i| i.name == index_name)
+                .and_then(|i| i.filter.as_ref());
+            if let Some(filter) = filter {
+                conditions.push(index_filter_sql(&collection.properties, filter));
+            }
         }
-        if !self.sort.is_empty() {
-            sql.push_str(" ORDER BY ");
-            sql.push_str(
-                &self
-                    .sort
-                    .iter()
-                    .map(|(prop, sort, case_sensitive)| {
-                        format!(
-                            "{} COLLATE {}{}",
-                            prop,
-                            if *case_sensitive { "NOCASE" } else { "BINARY" },
-                            if *sort == Sort::Asc { "" } else { " DESC" }
-                        )
-                    })
-                    .join(", "),
-            );
+        let ranges = std::mem::take(&mut self.ranges);
+        if !ranges.is_empty() {
+            let range_sql = ranges.iter().map(|(_, sql, _)| sql).join(" OR ");
+            if ranges.len() > 1 {
+                conditions.push(format!("({})", range_sql));
+            } else {
+                conditions.push(range_sql);
+            }
+            for (_, _, params) in ranges {
+                filter_params.extend(params);
+            }
         }
-        if !self.distinct.is_empty() {
-            sql.push_str(" GROUP BY ");
-            sql.push_str(
-                &self
-                    .distinct
-                    .iter()
-                    .map(|(prop, case_sensitive)| {
-                        format!(
-                            "{} COLLATE {}",
-                            prop,
-                            if *case_sensitive { "NOCASE" } else { "BINARY" }
-                        )
-                    })
-                    .join(", "),
-            );
+        let covered = (conditions.len(), filter_params.len());
+        if let Some(filter) = self.filter.take() {
+            let (filter_sql, params) =
+                build_filter_sql(self.all_collections, self.collection_index, filter);
+            conditions.push(filter_sql);
+            filter_params.extend(params);
         }
 
-        (sql, filter_params)
+        (index_sql, conditions, filter_params, covered)
+    }
+}
+
+pub(crate) fn build_filter_sql(
+    all_collections: &[SQLiteCollection],
+    collection_index: u16,
+    filter: Filter,
+) -> (String, Vec<QueryParam>) {
+    let get_property = &|collection_index, property_index| {
+        all_collections
+            .get(collection_index as usize)?
+            .get_property(property_index)
+    };
+    // indexes starting with an embedded property or a JSON path
+    let indexes = all_collections[collection_index as usize]
+        .indexes
+        .iter()
+        .filter(|i| i.properties[0].contains('.'))
+        .collect_vec();
+    filter_sql(collection_index, &get_property, &indexes, filter)
+}
+
+impl<'a> SQLiteQueryBuilder<'a> {
+    /// The index all ranges belong to. Id and list index ranges use the table itself.
+    fn forced_index(&self) -> Option<&'a str> {
+        let (first, _, _) = self.ranges.first()?;
+        if self.ranges.iter().all(|(index, _, _)| index == first) {
+            *first
+        } else {
+            None
+        }
     }
 }
 
@@ -106,12 +133,174 @@ impl<'a> IsarQueryBuilder for SQLiteQueryBuilder<'a> {
         ));
     }
 
-    fn build(self) -> Self::Query {
-        let collection_index = self.collection_index;
-        let has_sort_distinct = !self.sort.is_empty() || !self.distinct.is_empty();
-        let (sql, filter_params) = self.build_query();
-        SQLiteQuery::new(collection_index, sql, has_sort_distinct, filter_params)
+    fn add_id_range(&mut self, lower: i64, upper: i64) {
+        let sql = format!("{} BETWEEN ? AND ?", SQLiteProperty::ID_NAME);
+        let params = vec![
+            QueryParam::Value(IsarValue::Integer(lower)),
+            QueryParam::Value(IsarValue::Integer(upper)),
+        ];
+        self.ranges.push((None, sql, params));
+    }
+
+    fn add_index_range(
+        &mut self,
+        index_name: &str,
+        lower: Vec<Option<IsarValue>>,
+        upper: Vec<Option<IsarValue>>,
+        include_lower: bool,
+        include_upper: bool,
+    ) -> Result<()> {
+        let collection = &self.all_collections[self.collection_index as usize];
+        let index = collection
+            .indexes
+            .iter()
+            .find(|i| i.name == index_name)
+            .ok_or(IsarError::IllegalArgument {})?;
+        let properties = index
+            .properties
+            .iter()
+            .map(|p| index_column_sql(index, p))
+            .collect_vec();
+        if lower.len() > properties.len() || upper.len() > properties.len() {
+            return Err(IsarError::IllegalArgument {});
+        }
+        if index.hash {
+            let full_key = lower.len() == properties.len();
+            if !full_key || lower != upper || !include_lower || !include_upper {
+                return Err(IsarError::IllegalArgument {});
+            }
+        }
+
+        // case-insensitive indexes have to be compared using their collation
+        let collate = if index.case_sensitive {
+            ""
+        } else {
+            " COLLATE NOCASE"
+        };
+        let mut params = vec![];
+        let lower_sql = index_bound_sql(
+            &properties,
+            &lower,
+            include_lower,
+            false,
+            collate,
+            &mut params,
+        );
+        let upper_sql = index_bound_sql(
+            &properties,
+            &upper,
+            include_upper,
+            true,
+            collate,
+            &mut params,
+        );
+        if collection.is_list_index(index) {
+            let sql = format!(
+                "{} IN (SELECT _id FROM {} WHERE {} AND {})",
+                SQLiteProperty::ID_NAME,
+                list_index_table(&collection.name, &index.name),
+                lower_sql,
+                upper_sql
+            );
+            self.ranges.push((None, sql, params));
+        } else if let Some(filter) = &index.filter {
+            // partial indexes only contain the objects matching their filter
+            let filter_sql = index_filter_sql(&collection.properties, filter);
+            let sql = format!("({} AND {} AND {})", lower_sql, upper_sql, filter_sql);
+            self.ranges.push((Some(index.name.as_str()), sql, params));
+        } else {
+            let sql = format!("({} AND {})", lower_sql, upper_sql);
+            self.ranges.push((Some(index.name.as_str()), sql, params));
+        }
+        Ok(())
+    }
+
+    fn build(mut self) -> Self::Query {
+        let parameters = self.filter.as_ref().filter(|f| f.has_parameters()).cloned();
+        let (index_sql, conditions, filter_params, covered) = self.build_filter();
+        let sort = self
+            .sort
+            .iter()
+            .map(|(prop, sort, case_sensitive)| (prop.to_string(), *sort, *case_sensitive))
+            .collect();
+        let distinct = self
+            .distinct
+            .iter()
+            .map(|(prop, case_sensitive)| (prop.to_string(), *case_sensitive))
+            .collect();
+        let query = SQLiteQuery::new(
+            self.collection_index,
+            index_sql,
+            conditions,
+            sort,
+            distinct,
+            filter_params,
+            covered,
+        );
+        if let Some(filter) = parameters {
+            query.with_parameters(filter)
+        } else {
+            query
+        }
+    }
+}
+
+/// Lexicographic bound of the index properties. Null is smaller than any other value.
+fn index_bound_sql(
+    properties: &[String],
+    values: &[Option<IsarValue>],
+    include: bool,
+    upper: bool,
+    collate: &str,
+    params: &mut Vec<QueryParam>,
+) -> String {
+    let (property, value) = if let Some(value) = values.first() {
+        (&properties[0], value)
+    } else {
+        return "TRUE".to_string();
+    };
+
+    let mut push_value = |sql: String| {
+        if let Some(value) = value {
+            params.push(QueryParam::Value(value.clone()));
+        }
+        sql
+    };
+    let last = values.len() == 1;
+    let strict_sql = match (upper, value) {
+        (false, None) if include && last => return "TRUE".to_string(),
+        (false, None) => format!("{} IS NOT NULL", property),
+        (false, Some(_)) if include && last => push_value(format!("{} >= ?{}", property, collate)),
+        (false, Some(_)) => push_value(format!("{} > ?{}", property, collate)),
+        (true, None) if include && last => format!("{} IS NULL", property),
+        (true, None) => "FALSE".to_string(),
+        (true, Some(_)) if include && last => push_value(format!(
+            "({} <= ?{} OR {} IS NULL)",
+            property, collate, property
+        )),
+        (true, Some(_)) => push_value(format!(
+            "({} < ?{} OR {} IS NULL)",
+            property, collate, property
+        )),
+    };
+    if last {
+        return strict_sql;
     }
+
+    let equal_sql = if value.is_some() {
+        push_value(format!("{} = ?{}", property, collate))
+    } else {
+        format!("{} IS NULL", property)
+    };
+    let rest_sql = index_bound_sql(
+        &properties[1..],
+        &values[1..],
+        include,
+        upper,
+        collate,
+        params,
+    );
+    format!("({} OR ({} AND {}))", strict_sql, equal_sql, rest_sql)
 }
 
 #[cfg(test)]
@@ -119,11 +308,19 @@ mod test {
     use super::*;
     use crate::core::data_type::DataType;
     use crate::core::filter::{ConditionType::*, Filter::*};
-    use crate::core::value::IsarValue;
-    use crate::sqlite::sqlite_collection::SQLiteProperty;
+    use crate::core::schema::IndexSchema;
+    use crate::sqlite::sql::{keyset_sql, query_sql};
     use crate::sqlite::sqlite_query::JsonCondition;
     use crate::sqlite::sqlite_query::QueryParam::*;
 
+    impl SQLiteQueryBuilder<'_> {
+        fn build_query(mut self) -> (String, Vec<QueryParam>) {
+            let (index_sql, conditions, filter_params, _) = self.build_filter();
+            let sql = query_sql(&index_sql, &conditions, &self.sort, &self.distinct);
+            (sql, filter_params)
+        }
+    }
+
     fn debug_col() -> SQLiteCollection {
         SQLiteCollection::new(
             "col".to_string(),
@@ -163,12 +360,12 @@ mod test {
         let cols = vec![debug_col()];
 
         let mut qb = SQLiteQueryBuilder::new(&cols, 0);
-        qb.add_sort(0, Sort::Asc, false);
+        qb.add_sort(0, Sort::Asc, true);
         let sql = qb.build_query();
         assert_sql!(sql, "ORDER BY _rowid_ COLLATE BINARY",);
 
         let mut qb = SQLiteQueryBuilder::new(&cols, 0);
-        qb.add_sort(2, Sort::Desc, true);
+        qb.add_sort(2, Sort::Desc, false);
         let sql = qb.build_query();
         assert_sql!(sql, "ORDER BY prop2 COLLATE NOCASE DESC",);
     }
@@ -178,9 +375,9 @@ mod test {
         let cols = vec![debug_col()];
 
         let mut qb = SQLiteQueryBuilder::new(&cols, 0);
-        qb.add_sort(0, Sort::Asc, false);
-        qb.add_sort(1, Sort::Desc, false);
-        qb.add_sort(2, Sort::Asc, true);
+        qb.add_sort(0, Sort::Asc, true);
+        qb.add_sort(1, Sort::Desc, true);
+        qb.add_sort(2, Sort::Asc, false);
         let sql = qb.build_query();
         assert_sql!(
             sql,
@@ -193,12 +390,12 @@ mod test {
         let cols = vec![debug_col()];
 
         let mut qb = SQLiteQueryBuilder::new(&cols, 0);
-        qb.add_distinct(0, false);
+        qb.add_distinct(0, true);
         let sql = qb.build_query();
         assert_sql!(sql, "GROUP BY _rowid_ COLLATE BINARY",);
 
         let mut qb = SQLiteQueryBuilder::new(&cols, 0);
-        qb.add_distinct(2, true);
+        qb.add_distinct(2, false);
         let sql = qb.build_query();
         assert_sql!(sql, "GROUP BY prop2 COLLATE NOCASE",);
     }
@@ -208,9 +405,9 @@ mod test {
         let cols = vec![debug_col()];
 
         let mut qb = SQLiteQueryBuilder::new(&cols, 0);
-        qb.add_distinct(0, false);
-        qb.add_distinct(1, false);
-        qb.add_distinct(2, true);
+        qb.add_distinct(0, true);
+        qb.add_distinct(1, true);
+        qb.add_distinct(2, false);
         let sql = qb.build_query();
         assert_sql!(
             sql,
@@ -218,6 +415,46 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_keyset() {
+        let sort = vec![
+            ("prop1".to_string(), Sort::Asc, true),
+            ("prop2".to_string(), Sort::Desc, false),
+            ("_rowid_".to_string(), Sort::Asc, true),
+        ];
+        let one = IsarValue::Integer(1);
+        let id = IsarValue::Integer(5);
+
+        let mut params = vec![];
+        let sql = keyset_sql(
+            &sort,
+            &[Some(one.clone()), None, Some(id.clone())],
+            &mut params,
+        );
+        assert_eq!(
+            sql,
+            "(prop1 > ? COLLATE BINARY OR (prop1 = ? COLLATE BINARY AND \
+            (FALSE OR (prop2 IS NULL AND _rowid_ > ? COLLATE BINARY))))"
+        );
+        assert_eq!(
+            params,
+            vec![Value(one.clone()), Value(one.clone()), Value(id.clone())]
+        );
+
+        let mut params = vec![];
+        let sql = keyset_sql(
+            &sort[1..],
+            &[Some(one.clone()), Some(id.clone())],
+            &mut params,
+        );
+        assert_eq!(
+            sql,
+            "((prop2 < ? COLLATE NOCASE OR prop2 IS NULL) OR \
+            (prop2 = ? COLLATE NOCASE AND _rowid_ > ? COLLATE BINARY))"
+        );
+        assert_eq!(params, vec![Value(one.clone()), Value(one), Value(id)]);
+    }
+
     #[test]
     fn test_filter_null() {
         let cond = Filter::new_condition(1, IsNull, vec![], false);
@@ -423,6 +660,21 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_filter_string_starts_with_case_insensitive() {
+        let value = IsarValue::String("ab%c".to_string());
+        let cond = Filter::new_condition(1, StringStartsWith, vec![Some(value.clone())], false);
+
+        let sql = qb_filter(cond);
+        assert_sql!(
+            sql,
+            "WHERE (prop1 >= ? COLLATE NOCASE AND prop1 <= ? COLLATE NOCASE AND LOWER(prop1) LIKE LOWER(?) ESCAPE '\\')",
+            Value(IsarValue::String("ab%c".to_string())),
+            Value(IsarValue::String(format!("ab%c{}", IsarValue::MAX_STRING))),
+            Value(IsarValue::String("ab\\%c%".to_string()))
+        );
+    }
+
     #[test]
     fn test_filter_string_starts_with_non_string() {
         let value = IsarValue::Integer(123);
@@ -640,4 +892,318 @@ mod test {
             })
         );
     }
+
+    fn embedded_index_cols() -> Vec<SQLiteCollection> {
+        vec![
+            SQLiteCollection::new(
+                "col".to_string(),
+                Some("id".to_string()),
+                vec![SQLiteProperty::new("address", DataType::Object, Some(1))],
+                vec![IndexSchema::new(
+                    "zip",
+                    vec!["address.zip"],
+                    false,
+                    false,
+                    true,
+                )],
+            ),
+            SQLiteCollection::new(
+                "address".to_string(),
+                None,
+                vec![
+                    SQLiteProperty::new("zip", DataType::Long, None),
+                    SQLiteProperty::new("street", DataType::String, None),
+                ],
+                vec![],
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_embedded_filter_indexed() {
+        let cols = embedded_index_cols();
+        let mut qb = SQLiteQueryBuilder::new(&cols, 0);
+        let cond = Filter::new_condition(1, Greater, vec![Some(IsarValue::Integer(5))], true);
+        qb.set_filter(Filter::new_embedded(1, cond));
+        let sql = qb.build_query();
+        assert_sql!(
+            sql,
+            "WHERE (json_extract(address, '$.zip') > ? AND isar_filter_json(address, ?))",
+            Value(IsarValue::Integer(5)),
+            JsonCondition(JsonCondition {
+                path: vec!["zip".to_string()],
+                condition_type: Greater,
+                values: vec![Some(IsarValue::Integer(5))],
+                case_sensitive: true,
+            })
+        );
+
+        // only the JSON filter can be used for properties that are not indexed
+        let mut qb = SQLiteQueryBuilder::new(&cols, 0);
+        let cond = Filter::new_condition(2, IsNull, vec![], true);
+        qb.set_filter(Filter::new_embedded(1, cond));
+        let sql = qb.build_query();
+        assert_sql!(
+            sql,
+            "WHERE isar_filter_json(address, ?)",
+            JsonCondition(JsonCondition {
+                path: vec!["street".to_string()],
+                condition_type: IsNull,
+                values: vec![],
+                case_sensitive: true,
+            })
+        );
+    }
+
+    #[test]
+    fn test_json_filter_indexed() {
+        let cols = vec![SQLiteCollection::new(
+            "col".to_string(),
+            Some("id".to_string()),
+            vec![SQLiteProperty::new("data", DataType::Json, None)],
+            vec![IndexSchema::new_json(
+                "age",
+                "data.age",
+                DataType::Long,
+                false,
+                true,
+            )],
+        )];
+        let index_sql = "CASE WHEN json_valid(data) AND json_type(data, '$.age') IN ('integer') THEN json_extract(data, '$.age') END";
+        let cond = |condition_type, values: Vec<Option<IsarValue>>| JsonCondition {
+            path: vec!["age".to_string()],
+            condition_type,
+            values,
+            case_sensitive: true,
+        };
+
+        let mut qb = SQLiteQueryBuilder::new(&cols, 0);
+        let values = vec![Some(IsarValue::Integer(5))];
+        qb.set_filter(Filter::new_json(
+            1,
+            vec!["age".to_string()],
+            Greater,
+            values.clone(),
+            true,
+        ));
+        let sql = qb.build_query();
+        assert_sql!(
+            sql,
+            format!(
+                "WHERE (({} > ? OR {} IS NULL) AND isar_filter_json(data, ?))",
+                index_sql, index_sql
+            )
+            .as_str(),
+            Value(IsarValue::Integer(5)),
+            JsonCondition(cond(Greater, values))
+        );
+
+        // values of another type than the index type cannot use the index
+        let mut qb = SQLiteQueryBuilder::new(&cols, 0);
+        let values = vec![Some(IsarValue::String("5".to_string()))];
+        qb.set_filter(Filter::new_json(
+            1,
+            vec!["age".to_string()],
+            Equal,
+            values.clone(),
+            true,
+        ));
+        let sql = qb.build_query();
+        assert_sql!(
+            sql,
+            "WHERE isar_filter_json(data, ?)",
+            JsonCondition(cond(Equal, values))
+        );
+    }
+
+    #[test]
+    fn test_index_range_embedded() {
+        let cols = embedded_index_cols();
+        let mut qb = SQLiteQueryBuilder::new(&cols, 0);
+        let value = vec![Some(IsarValue::Integer(5))];
+        qb.add_index_range("zip", value.clone(), value, true, true)
+            .unwrap();
+        let sql = qb.build_query();
+        assert_sql!(
+            sql,
+            "INDEXED BY col_zip WHERE (json_extract(address, '$.zip') >= ? AND (json_extract(address, '$.zip') <= ? OR json_extract(address, '$.zip') IS NULL))",
+            Value(IsarValue::Integer(5)),
+            Value(IsarValue::Integer(5))
+        );
+    }
+
+    #[test]
+    fn test_index_range_partial() {
+        let deleted = Filter::new_condition(2, Equal, vec![Some(IsarValue::Bool(false))], true);
+        let cols = vec![SQLiteCollection::new(
+            "col".to_string(),
+            Some("id".to_string()),
+            vec![
+                SQLiteProperty::new("prop1", DataType::Long, None),
+                SQLiteProperty::new("deleted", DataType::Bool, None),
+            ],
+            vec![IndexSchema::new("idx", vec!["prop1"], true, false, true).with_filter(deleted)],
+        )];
+        let mut qb = SQLiteQueryBuilder::new(&cols, 0);
+        let value = vec![Some(IsarValue::Integer(5))];
+        qb.add_index_range("idx", value.clone(), value, true, true)
+            .unwrap();
+        let sql = qb.build_query();
+        assert_sql!(
+            sql,
+            "INDEXED BY col_idx WHERE deleted = 0 AND (prop1 >= ? AND (prop1 <= ? OR prop1 IS NULL) AND deleted = 0)",
+            Value(IsarValue::Integer(5)),
+            Value(IsarValue::Integer(5))
+        );
+    }
+
+    fn index_col() -> SQLiteCollection {
+        SQLiteCollection::new(
+            "col".to_string(),
+            Some("id".to_string()),
+            vec![
+                SQLiteProperty::new("prop1", DataType::Long, None),
+                SQLiteProperty::new("prop2", DataType::String, None),
+            ],
+            vec![
+                IndexSchema::new("idx", vec!["prop1", "prop2"], false, false, true),
+                IndexSchema::new("hashed", vec!["prop2"], false, true, true),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_id_range() {
+        let cols = vec![index_col()];
+        let mut qb = SQLiteQueryBuilder::new(&cols, 0);
+        qb.add_id_range(1, 5);
+        qb.set_filter(Filter::new_condition(1, IsNull, vec![], true));
+        let sql = qb.build_query();
+        assert_sql!(
+            sql,
+            "WHERE _rowid_ BETWEEN ? AND ? AND prop1 IS NULL",
+            Value(IsarValue::Integer(1)),
+            Value(IsarValue::Integer(5))
+        );
+    }
+
+    #[test]
+    fn test_index_range() {
+        let cols = vec![index_col()];
+        let mut qb = SQLiteQueryBuilder::new(&cols, 0);
+        let lower = vec![Some(IsarValue::Integer(1))];
+        let upper = vec![Some(IsarValue::Integer(5))];
+        qb.add_index_range("idx", lower, upper, true, false)
+            .unwrap();
+        let sql = qb.build_query();
+        assert_sql!(
+            sql,
+            "INDEXED BY col_idx WHERE (prop1 >= ? AND (prop1 < ? OR prop1 IS NULL))",
+            Value(IsarValue::Integer(1)),
+            Value(IsarValue::Integer(5))
+        );
+    }
+
+    #[test]
+    fn test_index_range_case_insensitive() {
+        let cols = vec![SQLiteCollection::new(
+            "col".to_string(),
+            Some("id".to_string()),
+            vec![SQLiteProperty::new("prop1", DataType::String, None)],
+            vec![IndexSchema::new("idx", vec!["prop1"], false, false, false)],
+        )];
+        let mut qb = SQLiteQueryBuilder::new(&cols, 0);
+        let value = vec![Some(IsarValue::String("A".to_string()))];
+        qb.add_index_range("idx", value.clone(), value, true, true)
+            .unwrap();
+        let sql = qb.build_query();
+        assert_sql!(
+            sql,
+            "INDEXED BY col_idx WHERE (prop1 >= ? COLLATE NOCASE AND (prop1 <= ? COLLATE NOCASE OR prop1 IS NULL))",
+            Value(IsarValue::String("A".to_string())),
+            Value(IsarValue::String("A".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_index_range_composite() {
+        let cols = vec![index_col()];
+        let mut qb = SQLiteQueryBuilder::new(&cols, 0);
+        let lower = vec![None, Some(IsarValue::String("a".to_string()))];
+        qb.add_index_range("idx", lower, vec![], false, true)
+            .unwrap();
+        let sql = qb.build_query();
+        assert_sql!(
+            sql,
+            "INDEXED BY col_idx WHERE ((prop1 IS NOT NULL OR (prop1 IS NULL AND prop2 > ?)) AND TRUE)",
+            Value(IsarValue::String("a".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_multiple_ranges() {
+        let cols = vec![index_col()];
+        let mut qb = SQLiteQueryBuilder::new(&cols, 0);
+        let value = vec![Some(IsarValue::String("a".to_string()))];
+        qb.add_index_range("hashed", value.clone(), value, true, true)
+            .unwrap();
+        qb.add_id_range(1, 5);
+        let sql = qb.build_query();
+        assert_sql!(
+            sql,
+            "WHERE ((prop2 >= ? AND (prop2 <= ? OR prop2 IS NULL)) OR _rowid_ BETWEEN ? AND ?)",
+            Value(IsarValue::String("a".to_string())),
+            Value(IsarValue::String("a".to_string())),
+            Value(IsarValue::Integer(1)),
+            Value(IsarValue::Integer(5))
+        );
+    }
+
+    #[test]
+    fn test_index_range_invalid() {
+        let cols = vec![index_col()];
+        let mut qb = SQLiteQueryBuilder::new(&cols, 0);
+        let result = qb.add_index_range("unknown", vec![], vec![], true, true);
+        assert!(matches!(result, Err(IsarError::IllegalArgument {})));
+        let result = qb.add_index_range("hashed", vec![], vec![], true, true);
+        assert!(matches!(result, Err(IsarError::IllegalArgument {})));
+    }
+
+    #[test]
+    fn test_list_index() {
+        let mut list_prop = SQLiteProperty::new("tags", DataType::StringList, None);
+        list_prop.index_table = Some("_isar_col_tags".to_string());
+        let cols = vec![SQLiteCollection::new(
+            "col".to_string(),
+            Some("id".to_string()),
+            vec![list_prop],
+            vec![IndexSchema::new("tags", vec!["tags"], false, false, true)],
+        )];
+
+        let mut qb = SQLiteQueryBuilder::new(&cols, 0);
+        let value = vec![Some(IsarValue::String("a".to_string()))];
+        qb.add_index_range("tags", value.clone(), value, true, true)
+            .unwrap();
+        let sql = qb.build_query();
+        assert_sql!(
+            sql,
+            "WHERE _rowid_ IN (SELECT _id FROM _isar_col_tags WHERE tags >= ? AND (tags <= ? OR tags IS NULL))",
+            Value(IsarValue::String("a".to_string())),
+            Value(IsarValue::String("a".to_string()))
+        );
+
+        let mut qb = SQLiteQueryBuilder::new(&cols, 0);
+        qb.set_filter(Filter::new_condition(
+            1,
+            Equal,
+            vec![Some(IsarValue::String("a".to_string()))],
+            true,
+        ));
+        let sql = qb.build_query();
+        assert_sql!(
+            sql,
+            "WHERE _rowid_ IN (SELECT _id FROM _isar_col_tags WHERE tags = ?)",
+            Value(IsarValue::String("a".to_string()))
+        );
+    }
 }
@@ -1,5 +1,5 @@
-use super::sqlite3::SQLite3;
 use super::sqlite_query::SQLiteQuery;
+use super::sqlite3::SQLite3;
 use crate::core::error::IsarError;
 use crate::core::watcher::CollectionWatchers;
 use crate::core::{error::Result, watcher::ChangeSet};
@@ -8,24 +8,41 @@ use std::rc::Rc;
 use std::sync::Arc;
 
 pub struct SQLiteTxn {
+    pub(crate) instance_id: u32,
     write: bool,
     sqlite: Rc<SQLite3>,
     active: Cell<bool>,
     change_set: Rc<RefCell<ChangeSet>>,
+    /// Releases the instance for the next transaction. Transactions that are dropped without
+    /// being committed or aborted, for example after a failed insert, are released as well.
+    release: Option<Box<dyn FnOnce()>>,
 }
 
 impl SQLiteTxn {
-    pub(crate) fn new(sqlite: Rc<SQLite3>, write: bool) -> Result<SQLiteTxn> {
+    pub(crate) fn new(instance_id: u32, sqlite: Rc<SQLite3>, write: bool) -> Result<SQLiteTxn> {
         sqlite.prepare("BEGIN")?.step()?;
         let txn = SQLiteTxn {
+            instance_id,
             write,
             sqlite: sqlite,
             active: Cell::new(true),
             change_set: Rc::new(RefCell::new(ChangeSet::new())),
+            release: None,
         };
         Ok(txn)
     }
 
+    pub(crate) fn with_release(mut self, release: impl FnOnce() + 'static) -> SQLiteTxn {
+        self.release = Some(Box::new(release));
+        self
+    }
+
+    fn release(&mut self) {
+        if let Some(release) = self.release.take() {
+            release();
+        }
+    }
+
     pub(crate) fn get_sqlite(&self, write: bool) -> Result<&SQLite3> {
         if !self.active.get() {
             return Err(IsarError::TransactionClosed {});
@@ -36,10 +53,6 @@ impl SQLiteTxn {
         Ok(&self.sqlite)
     }
 
-    pub(crate) fn is_write(&self) -> bool {
-        self.write
-    }
-
     pub(crate) fn guard<T, F>(&self, job: F) -> Result<T>
     where
         F: FnOnce() -> Result<T>,
@@ -71,18 +84,20 @@ impl SQLiteTxn {
         self.sqlite.clear_update_hook();
     }
 
-    pub(crate) fn commit(self) -> Result<()> {
+    pub(crate) fn commit(mut self) -> Result<()> {
         if !self.active.get() {
             return Err(IsarError::TransactionClosed {});
         }
         self.sqlite.prepare("COMMIT")?.step()?;
         self.sqlite.clear_update_hook();
+        self.active.replace(false);
+        self.release();
         self.change_set.borrow_mut().notify_watchers();
         Ok(())
     }
 
     pub(crate) fn abort(&self) {
-        if self.active.get() {
+        if self.active.replace(false) {
             self.sqlite.clear_update_hook();
             let stmt = self.sqlite.prepare("ROLLBACK");
             if let Ok(mut stmt) = stmt {
@@ -91,3 +106,10 @@ impl SQLiteTxn {
         }
     }
 }
+
+impl Drop for SQLiteTxn {
+    fn drop(&mut self) {
+        self.abort();
+        self.release();
+    }
+}
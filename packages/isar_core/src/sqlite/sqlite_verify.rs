@@ -1,8 +1,9 @@
+use super::sql::{conflict_trigger_name, index_filter_sql, list_index_table, sql_data_type};
 use super::sqlite_collection::{SQLiteCollection, SQLiteProperty};
-use super::{sql::sql_data_type, sqlite3::SQLite3};
+use super::sqlite3::SQLite3;
 use crate::core::error::{IsarError, Result};
 
-pub(crate) fn verify_sqlite(sqlite: &SQLite3, cols: &[SQLiteCollection]) -> Result<()> {
+pub(crate) fn verify_sqlite(sqlite: &SQLite3, cols: &[SQLiteCollection], deep: bool) -> Result<()> {
     let mut table_names = vec![];
 
     for col in cols {
@@ -16,7 +17,9 @@ pub(crate) fn verify_sqlite(sqlite: &SQLite3, cols: &[SQLiteCollection]) -> Resu
     actual_table_names.sort();
 
     if table_names != actual_table_names {
-        return Err(IsarError::DbCorrupted {});
+        return Err(IsarError::DbCorrupted {
+            message: "Table names do not match the schema.".to_string(),
+        });
     }
 
     for table in table_names {
@@ -27,7 +30,9 @@ pub(crate) fn verify_sqlite(sqlite: &SQLite3, cols: &[SQLiteCollection]) -> Resu
         columns.retain(|(n, t)| n != SQLiteProperty::ID_NAME && t != "INTEGER");
 
         if columns_id_len != columns.len() + 1 || columns.len() != collection.properties.len() {
-            return Err(IsarError::DbCorrupted {});
+            return Err(IsarError::DbCorrupted {
+                message: format!("Columns of table {} do not match the schema.", table),
+            });
         }
 
         for (column, sql_type) in columns {
@@ -35,7 +40,12 @@ pub(crate) fn verify_sqlite(sqlite: &SQLite3, cols: &[SQLiteCollection]) -> Resu
             let target_col_index = if let Some(name) = target_col_name {
                 let index = cols.iter().position(|c| c.name == name).map(|i| i as u16);
                 if index.is_none() {
-                    return Err(IsarError::DbCorrupted {});
+                    return Err(IsarError::DbCorrupted {
+                        message: format!(
+                            "Column {} of table {} has an unknown target.",
+                            column, table
+                        ),
+                    });
                 }
                 index
             } else {
@@ -45,32 +55,94 @@ pub(crate) fn verify_sqlite(sqlite: &SQLite3, cols: &[SQLiteCollection]) -> Resu
             let prop = collection.properties.iter().find(|p| p.name == column);
 
             if let Some(prop) = prop {
-                if prop.data_type != data_type {
-                    return Err(IsarError::DbCorrupted {});
-                }
-                if prop.collection_index != target_col_index {
-                    return Err(IsarError::DbCorrupted {});
+                if prop.data_type != data_type || prop.collection_index != target_col_index {
+                    return Err(IsarError::DbCorrupted {
+                        message: format!(
+                            "Column {} of table {} has the wrong type.",
+                            column, table
+                        ),
+                    });
                 }
             } else {
-                return Err(IsarError::DbCorrupted {});
+                return Err(IsarError::DbCorrupted {
+                    message: format!("Column {} of table {} is unknown.", column, table),
+                });
             }
         }
 
         let indexes = sqlite.get_table_indexes(&table)?;
+        let triggers = sqlite.get_table_triggers(&table)?;
         if indexes.len() != collection.indexes.len() {
-            return Err(IsarError::DbCorrupted {});
+            return Err(IsarError::DbCorrupted {
+                message: format!("Indexes of table {} do not match the schema.", table),
+            });
         }
 
-        for (index, unique, cols) in indexes {
-            let name = index.strip_prefix(&format!("{}_", table));
+        for (index_name, unique, cols, case_sensitive, json_type) in indexes {
+            let name = index_name.strip_prefix(&format!("{}_", table));
             let index = collection.indexes.iter().find(|i| {
-                Some(i.name.as_str()) == name && i.unique == unique && i.properties == cols
+                Some(i.name.as_str()) == name
+                    && i.unique == unique
+                    && i.properties == cols
+                    && i.case_sensitive == case_sensitive
+                    && i.json_type == json_type
+            });
+            let filter = index.and_then(|i| i.filter.as_ref());
+            let filter_sql = filter.map(|f| index_filter_sql(&collection.properties, f));
+            let matches_filter = sqlite.get_index_filter(&index_name)? == filter_sql;
+            let matches_conflict = index.is_some_and(|i| {
+                ["insert", "update"].iter().all(|event| {
+                    conflict_trigger_name(&table, &i.name, event, i.on_conflict)
+                        .is_none_or(|trigger| triggers.contains(&trigger))
+                })
             });
 
-            if index.is_none() {
-                return Err(IsarError::DbCorrupted {});
+            if index.is_none() || !matches_filter || !matches_conflict {
+                return Err(IsarError::DbCorrupted {
+                    message: format!(
+                        "Index {} of table {} does not match the schema.",
+                        index_name, table
+                    ),
+                });
+            }
+        }
+    }
+
+    if deep {
+        for col in cols {
+            for index in col.indexes.iter().filter(|i| col.is_list_index(i)) {
+                let sql = format!(
+                    "SELECT (SELECT COUNT(*) FROM {}) = (SELECT COUNT(*) FROM {} t, json_each(t.{}))",
+                    list_index_table(&col.name, &index.name),
+                    col.name,
+                    index.properties[0]
+                );
+                let mut stmt = sqlite.prepare(&sql)?;
+                stmt.step()?;
+                if stmt.get_int(0) != 1 {
+                    return Err(IsarError::DbCorrupted {
+                        message: format!(
+                            "Index {} of table {} does not match the table contents.",
+                            index.name, col.name
+                        ),
+                    });
+                }
             }
         }
+
+        let mut stmt = sqlite.prepare("PRAGMA integrity_check")?;
+        let mut errors = vec![];
+        while stmt.step()? {
+            let result = stmt.get_text(0);
+            if result != "ok" {
+                errors.push(result.to_string());
+            }
+        }
+        if !errors.is_empty() {
+            return Err(IsarError::DbCorrupted {
+                message: errors.join("\n"),
+            });
+        }
     }
 
     Ok(())
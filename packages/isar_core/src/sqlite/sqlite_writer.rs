@@ -6,7 +6,11 @@ use serde_json::{Map, Number, Value};
 use std::iter::empty;
 
 impl<'a> SQLiteInsert<'a> {
-    fn property_index(&self, index: u32) -> u32 {
+    /// Also marks the property as written for upserts.
+    fn property_index(&mut self, index: u32) -> u32 {
+        if let Some(written) = self.written.get_mut(index as usize) {
+            *written = true;
+        }
         (self.batch_size - self.batch_remaining) * (self.collection.properties.len() as u32 + 1)
             + index
     }
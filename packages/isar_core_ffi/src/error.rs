@@ -1,8 +1,10 @@
+use crate::{IsarI64, i64_to_isar};
 use isar_core::core::error::IsarError;
 use std::cell::RefCell;
 
 thread_local! {
     pub static ERROR: RefCell<Option<String>> = RefCell::new(None);
+    pub static UNIQUE_VIOLATION: RefCell<Option<(String, i64)>> = RefCell::new(None);
 }
 
 #[macro_export]
@@ -17,6 +19,7 @@ macro_rules! isar_try {
             if let Err(err) = l() {
                 if let Some(code) = crate::error::error_code(&err) {
                     crate::error::ERROR.replace(None);
+                    crate::error::UNIQUE_VIOLATION.replace(crate::error::unique_violation(&err));
                     code
                 } else {
                     crate::error::ERROR.replace(Some(err.to_string()));
@@ -38,11 +41,20 @@ pub const fn error_code(err: &IsarError) -> Option<u8> {
         IsarError::InstanceMismatch {} => ERROR_INSTANCE_MISMATCH,
         IsarError::EncryptionError {} => ERROR_ENCRYPTION,
         IsarError::DbFull {} => ERROR_DB_FULL,
+        IsarError::UniqueViolation { .. } => ERROR_UNIQUE_VIOLATION,
         _ => return None,
     };
     Some(code)
 }
 
+pub fn unique_violation(err: &IsarError) -> Option<(String, i64)> {
+    if let IsarError::UniqueViolation { index, existing_id } = err {
+        Some((index.clone(), *existing_id))
+    } else {
+        None
+    }
+}
+
 pub const ERROR_PATH: u8 = 1;
 pub const ERROR_WRITE_TXN_REQUIRED: u8 = 2;
 pub const ERROR_VERSION: u8 = 3;
@@ -50,6 +62,7 @@ pub const ERROR_OBJECT_LIMIT_REACHED: u8 = 4;
 pub const ERROR_INSTANCE_MISMATCH: u8 = 5;
 pub const ERROR_ENCRYPTION: u8 = 6;
 pub const ERROR_DB_FULL: u8 = 7;
+pub const ERROR_UNIQUE_VIOLATION: u8 = 8;
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn isar_get_error(value: *mut *const u8) -> u32 {
@@ -63,6 +76,25 @@ pub unsafe extern "C" fn isar_get_error(value: *mut *const u8) -> u32 {
     })
 }
 
+/// Returns the name of the index of the last `ERROR_UNIQUE_VIOLATION`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn isar_get_unique_violation_index(value: *mut *const u8) -> u32 {
+    UNIQUE_VIOLATION.with_borrow(|e| {
+        if let Some((index, _)) = e.as_ref() {
+            *value = index.as_ptr();
+            index.len() as u32
+        } else {
+            0
+        }
+    })
+}
+
+/// Returns the id of the existing object of the last `ERROR_UNIQUE_VIOLATION`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn isar_get_unique_violation_id() -> IsarI64 {
+    UNIQUE_VIOLATION.with_borrow(|e| i64_to_isar(e.as_ref().map_or(i64::MIN, |(_, id)| *id)))
+}
+
 #[macro_export]
 macro_rules! isar_pause_isolate {
     { $($token:tt)* } => {{
@@ -227,3 +227,22 @@ pub unsafe extern "C" fn isar_filter_not(filter: *mut Filter) -> *const Filter {
     let filter = Filter::new_not(*Box::from_raw(filter));
     Box::into_raw(Box::new(filter))
 }
+
+/// Turns the condition into a parameter that is bound using isar_query_bind. The values of the
+/// condition are ignored. Other filters are returned unchanged.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn isar_filter_parameter(
+    parameter_index: u16,
+    filter: *mut Filter,
+) -> *const Filter {
+    let filter = match *Box::from_raw(filter) {
+        Filter::Condition(condition) => Filter::new_parameter(
+            parameter_index,
+            condition.property_index,
+            condition.condition_type,
+            condition.case_sensitive,
+        ),
+        filter => filter,
+    };
+    Box::into_raw(Box::new(filter))
+}
@@ -1,4 +1,4 @@
-use crate::{isar_to_i64, CIsarInstance, CIsarTxn, CIsarWriter, IsarI64};
+use crate::{CIsarInstance, CIsarTxn, CIsarWriter, IsarI64, i64_to_isar, isar_to_i64};
 use isar_core::core::error::IsarError;
 use isar_core::core::insert::IsarInsert;
 use isar_core::core::instance::IsarInstance;
@@ -30,6 +30,35 @@ pub unsafe extern "C" fn isar_insert(
     }
 }
 
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn isar_upsert(
+    isar: &'static CIsarInstance,
+    txn: *mut CIsarTxn,
+    collection_index: u16,
+    index_name: *mut String,
+    count: u32,
+    insert: *mut *const CIsarWriter,
+) -> u8 {
+    isar_try! {
+        let txn = *Box::from_raw(txn);
+        let index_name = *Box::from_raw(index_name);
+        let new_insert = match (isar, txn) {
+            #[cfg(feature = "native")]
+            (CIsarInstance::Native(isar), CIsarTxn::Native(txn)) => {
+                let insert = isar.upsert(txn, collection_index, &index_name, count)?;
+                CIsarWriter::Native(insert)
+            }
+            #[cfg(feature = "sqlite")]
+            (CIsarInstance::SQLite(isar), CIsarTxn::SQLite(txn)) => {
+                let insert = isar.upsert(txn, collection_index, &index_name, count)?;
+                CIsarWriter::SQLite(insert)
+            }
+            _ => return Err(IsarError::IllegalArgument {}),
+        };
+        *insert = Box::into_raw(Box::new(new_insert));
+    }
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn isar_insert_save(insert: &mut CIsarWriter<'static>, id: IsarI64) -> u8 {
     let id = isar_to_i64(id);
@@ -44,6 +73,29 @@ pub unsafe extern "C" fn isar_insert_save(insert: &mut CIsarWriter<'static>, id:
     }
 }
 
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn isar_upsert_save(
+    insert: &mut CIsarWriter<'static>,
+    id: IsarI64,
+    saved_id: *mut IsarI64,
+    saved: *mut bool,
+) -> u8 {
+    let id = isar_to_i64(id);
+    isar_try! {
+        let new_id = match insert {
+            #[cfg(feature = "native")]
+            CIsarWriter::Native(insert) => insert.save(id)?,
+            #[cfg(feature = "sqlite")]
+            CIsarWriter::SQLite(insert) => insert.save(id)?,
+            _ => return Err(IsarError::IllegalArgument {}),
+        };
+        *saved = new_id.is_some();
+        if let Some(new_id) = new_id {
+            *saved_id = i64_to_isar(new_id);
+        }
+    }
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn isar_insert_finish(
     insert: *mut CIsarWriter,
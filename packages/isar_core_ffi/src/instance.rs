@@ -397,18 +397,61 @@ pub unsafe extern "C" fn isar_copy(isar: &'static CIsarInstance, path: *mut Stri
 }
 
 #[unsafe(no_mangle)]
-pub unsafe extern "C" fn isar_verify(isar: &'static CIsarInstance, txn: &'static CIsarTxn) -> u8 {
+pub unsafe extern "C" fn isar_verify(
+    isar: &'static CIsarInstance,
+    txn: &'static CIsarTxn,
+    deep: bool,
+) -> u8 {
     isar_try! {
         return match (isar, txn) {
             #[cfg(feature = "native")]
-            (CIsarInstance::Native(isar), CIsarTxn::Native(txn)) => isar.verify(txn),
+            (CIsarInstance::Native(isar), CIsarTxn::Native(txn)) => isar.verify(txn, deep),
             #[cfg(feature = "sqlite")]
-            (CIsarInstance::SQLite(isar), CIsarTxn::SQLite(txn)) => isar.verify(txn),
+            (CIsarInstance::SQLite(isar), CIsarTxn::SQLite(txn)) => isar.verify(txn, deep),
             _ => Err(IsarError::IllegalArgument {}),
         }
     }
 }
 
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn isar_rebuild_index(
+    isar: &'static CIsarInstance,
+    txn: &'static CIsarTxn,
+    collection_index: u16,
+    index_name: *mut String,
+) -> u8 {
+    isar_try! {
+        let index_name = *Box::from_raw(index_name);
+        match (isar, txn) {
+            #[cfg(feature = "native")]
+            (CIsarInstance::Native(isar), CIsarTxn::Native(txn)) => {
+                isar.rebuild_index(txn, collection_index, &index_name)?
+            }
+            #[cfg(feature = "sqlite")]
+            (CIsarInstance::SQLite(isar), CIsarTxn::SQLite(txn)) => {
+                isar.rebuild_index(txn, collection_index, &index_name)?
+            }
+            _ => return Err(IsarError::IllegalArgument {}),
+        };
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn isar_rebuild_indexes(
+    isar: &'static CIsarInstance,
+    txn: &'static CIsarTxn,
+) -> u8 {
+    isar_try! {
+        match (isar, txn) {
+            #[cfg(feature = "native")]
+            (CIsarInstance::Native(isar), CIsarTxn::Native(txn)) => isar.rebuild_indexes(txn)?,
+            #[cfg(feature = "sqlite")]
+            (CIsarInstance::SQLite(isar), CIsarTxn::SQLite(txn)) => isar.rebuild_indexes(txn)?,
+            _ => return Err(IsarError::IllegalArgument {}),
+        };
+    }
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn isar_close(isar: *mut CIsarInstance, delete: bool) -> u8 {
     isar_pause_isolate! {
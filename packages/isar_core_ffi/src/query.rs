@@ -1,7 +1,10 @@
-use crate::{CIsarInstance, CIsarQuery, CIsarQueryBuilder, CIsarQueryCursor, CIsarTxn};
-use isar_core::core::error::IsarError;
+use crate::{
+    CIsarInstance, CIsarQuery, CIsarQueryBuilder, CIsarQueryCursor, CIsarTxn, IsarI64, isar_to_i64,
+};
+use core::slice;
+use isar_core::core::error::{IsarError, Result};
 use isar_core::core::filter::Filter;
-use isar_core::core::instance::{Aggregation, IsarInstance};
+use isar_core::core::instance::{Aggregation, IsarInstance, Percentile};
 use isar_core::core::query_builder::{IsarQueryBuilder, Sort};
 use isar_core::core::value::IsarValue;
 use std::ptr;
@@ -71,6 +74,86 @@ pub unsafe extern "C" fn isar_query_add_distinct(
     }
 }
 
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn isar_query_add_id_range(
+    builder: &'static mut CIsarQueryBuilder,
+    lower: IsarI64,
+    upper: IsarI64,
+) {
+    let lower = isar_to_i64(lower);
+    let upper = isar_to_i64(upper);
+    match builder {
+        #[cfg(feature = "native")]
+        CIsarQueryBuilder::Native(builder) => builder.add_id_range(lower, upper),
+        #[cfg(feature = "sqlite")]
+        CIsarQueryBuilder::SQLite(builder) => builder.add_id_range(lower, upper),
+    }
+}
+
+unsafe fn values_from_raw(values: *mut *mut IsarValue, length: u32) -> Vec<Option<IsarValue>> {
+    if values.is_null() {
+        return vec![];
+    }
+    slice::from_raw_parts(values, length as usize)
+        .iter()
+        .map(|v| {
+            if v.is_null() {
+                None
+            } else {
+                Some(*Box::from_raw(*v))
+            }
+        })
+        .collect()
+}
+
+/// The values have to be freed using isar_values_free.
+fn values_into_raw(values: Vec<Option<IsarValue>>) -> *mut *mut IsarValue {
+    let values = values
+        .into_iter()
+        .map(|value| match value {
+            Some(value) => Box::into_raw(Box::new(value)),
+            None => ptr::null_mut(),
+        })
+        .collect::<Vec<_>>();
+    Box::into_raw(values.into_boxed_slice()) as *mut *mut IsarValue
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn isar_query_add_index_range(
+    builder: &'static mut CIsarQueryBuilder,
+    index_name: *mut String,
+    lower: *mut *mut IsarValue,
+    lower_length: u32,
+    upper: *mut *mut IsarValue,
+    upper_length: u32,
+    include_lower: bool,
+    include_upper: bool,
+) -> u8 {
+    let index_name = *Box::from_raw(index_name);
+    let lower = values_from_raw(lower, lower_length);
+    let upper = values_from_raw(upper, upper_length);
+    isar_try! {
+        match builder {
+            #[cfg(feature = "native")]
+            CIsarQueryBuilder::Native(builder) => builder.add_index_range(
+                &index_name,
+                lower,
+                upper,
+                include_lower,
+                include_upper,
+            )?,
+            #[cfg(feature = "sqlite")]
+            CIsarQueryBuilder::SQLite(builder) => builder.add_index_range(
+                &index_name,
+                lower,
+                upper,
+                include_lower,
+                include_upper,
+            )?,
+        }
+    }
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn isar_query_build(builder: *mut CIsarQueryBuilder) -> *mut CIsarQuery {
     let builder = *Box::from_raw(builder);
@@ -88,6 +171,42 @@ pub unsafe extern "C" fn isar_query_build(builder: *mut CIsarQueryBuilder) -> *m
     }
 }
 
+/// Binds the values of all parameters. `value_counts` contains the number of values of each
+/// parameter and `values` the values of all parameters in order.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn isar_query_bind(
+    isar: &'static CIsarInstance,
+    query: &'static mut CIsarQuery,
+    values: *mut *mut IsarValue,
+    value_counts: *const u32,
+    length: u32,
+) -> u8 {
+    let value_counts = if length == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(value_counts, length as usize)
+    };
+    let mut values = values_from_raw(values, value_counts.iter().sum()).into_iter();
+    let parameters = value_counts
+        .iter()
+        .map(|count| values.by_ref().take(*count as usize).collect())
+        .collect::<Vec<_>>();
+
+    isar_try! {
+        match (isar, query) {
+            #[cfg(feature = "native")]
+            (CIsarInstance::Native(isar), CIsarQuery::Native(query)) => {
+                isar.query_bind(query, &parameters)?;
+            }
+            #[cfg(feature = "sqlite")]
+            (CIsarInstance::SQLite(isar), CIsarQuery::SQLite(query)) => {
+                isar.query_bind(query, &parameters)?;
+            }
+            _ => return Err(IsarError::IllegalArgument {}),
+        }
+    }
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn isar_query_cursor(
     isar: &'static CIsarInstance,
@@ -118,13 +237,174 @@ pub unsafe extern "C" fn isar_query_cursor(
     }
 }
 
+/// Only the id and the given properties are read. All other properties are read as null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn isar_query_cursor_properties(
+    isar: &'static CIsarInstance,
+    txn: &'static CIsarTxn,
+    query: &'static CIsarQuery,
+    properties: *const u16,
+    properties_length: u32,
+    cursor: *mut *const CIsarQueryCursor,
+    offset: u32,
+    limit: u32,
+) -> u8 {
+    let offset = if offset == 0 { None } else { Some(offset) };
+    let limit = if limit == 0 { None } else { Some(limit) };
+    let properties = if properties_length == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(properties, properties_length as usize)
+    };
+
+    isar_try! {
+        let new_cursor = match (isar, txn, query) {
+            #[cfg(feature = "native")]
+            (CIsarInstance::Native(isar), CIsarTxn::Native(txn), CIsarQuery::Native(query)) => {
+                let cursor = isar.query_properties_cursor(txn, query, properties, offset, limit)?;
+                CIsarQueryCursor::Native(cursor)
+            }
+            #[cfg(feature = "sqlite")]
+            (CIsarInstance::SQLite(isar), CIsarTxn::SQLite(txn), CIsarQuery::SQLite(query)) => {
+                let cursor = isar.query_properties_cursor(txn, query, properties, offset, limit)?;
+                CIsarQueryCursor::SQLite(cursor)
+            }
+            _ => return Err(IsarError::IllegalArgument {}),
+        };
+        *cursor = Box::into_raw(Box::new(new_cursor));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn isar_query_page(
+    isar: &'static CIsarInstance,
+    query: &'static CIsarQuery,
+    token: *mut String,
+    page_query: *mut *const CIsarQuery,
+) -> u8 {
+    let token = if token.is_null() {
+        None
+    } else {
+        Some(*Box::from_raw(token))
+    };
+
+    isar_try! {
+        let new_query = match (isar, query) {
+            #[cfg(feature = "native")]
+            (CIsarInstance::Native(isar), CIsarQuery::Native(query)) => {
+                CIsarQuery::Native(isar.query_page(query, token.as_deref())?)
+            }
+            #[cfg(feature = "sqlite")]
+            (CIsarInstance::SQLite(isar), CIsarQuery::SQLite(query)) => {
+                CIsarQuery::SQLite(isar.query_page(query, token.as_deref())?)
+            }
+            _ => return Err(IsarError::IllegalArgument {}),
+        };
+        *page_query = Box::into_raw(Box::new(new_query));
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn isar_query_page_token(
+    isar: &'static CIsarInstance,
+    txn: &'static CIsarTxn,
+    query: &'static CIsarQuery,
+    id: IsarI64,
+    token: *mut *mut u8,
+    token_length: *mut u32,
+) -> u8 {
+    let id = isar_to_i64(id);
+    isar_try! {
+        let new_token = match (isar, txn, query) {
+            #[cfg(feature = "native")]
+            (CIsarInstance::Native(isar), CIsarTxn::Native(txn), CIsarQuery::Native(query)) => {
+                isar.query_page_token(txn, query, id)?
+            }
+            #[cfg(feature = "sqlite")]
+            (CIsarInstance::SQLite(isar), CIsarTxn::SQLite(txn), CIsarQuery::SQLite(query)) => {
+                isar.query_page_token(txn, query, id)?
+            }
+            _ => return Err(IsarError::IllegalArgument {}),
+        };
+        // the token has to be freed using isar_buffer_free
+        if let Some(new_token) = new_token {
+            *token_length = new_token.len() as u32;
+            *token = Box::into_raw(new_token.into_boxed_str()) as *mut u8;
+        } else {
+            *token_length = 0;
+            *token = ptr::null_mut();
+        }
+    }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn isar_query_explain(
+    isar: &'static CIsarInstance,
+    txn: &'static CIsarTxn,
+    query: &'static CIsarQuery,
+    json: *mut *mut u8,
+    json_length: *mut u32,
+) -> u8 {
+    isar_try! {
+        let plan = match (isar, txn, query) {
+            #[cfg(feature = "native")]
+            (CIsarInstance::Native(isar), CIsarTxn::Native(txn), CIsarQuery::Native(query)) => {
+                isar.explain(txn, query)?
+            }
+            #[cfg(feature = "sqlite")]
+            (CIsarInstance::SQLite(isar), CIsarTxn::SQLite(txn), CIsarQuery::SQLite(query)) => {
+                isar.explain(txn, query)?
+            }
+            _ => return Err(IsarError::IllegalArgument {}),
+        };
+        let plan_json = serde_json::to_string(&plan).map_err(|e| IsarError::JsonError {
+            message: e.to_string(),
+        })?;
+        // the json has to be freed using isar_buffer_free
+        *json_length = plan_json.len() as u32;
+        *json = Box::into_raw(plan_json.into_boxed_str()) as *mut u8;
+    }
+}
+
 pub const AGGREGATION_COUNT: u8 = 0;
 pub const AGGREGATION_IS_EMPTY: u8 = 1;
 pub const AGGREGATION_MIN: u8 = 2;
 pub const AGGREGATION_MAX: u8 = 3;
 pub const AGGREGATION_SUM: u8 = 4;
 pub const AGGREGATION_AVERAGE: u8 = 5;
+pub const AGGREGATION_COUNT_DISTINCT: u8 = 6;
+pub const AGGREGATION_MEDIAN: u8 = 7;
+pub const AGGREGATION_PERCENTILE: u8 = 8;
+pub const AGGREGATION_STD_DEV: u8 = 9;
+pub const AGGREGATION_VARIANCE: u8 = 10;
+pub const AGGREGATION_ELEMENT_COUNT: u8 = 11;
+pub const AGGREGATION_MIN_LENGTH: u8 = 12;
+pub const AGGREGATION_MAX_LENGTH: u8 = 13;
+pub const AGGREGATION_AVERAGE_LENGTH: u8 = 14;
+
+fn aggregation_from_u8(aggregation: u8, percentile: f64) -> Result<Option<Aggregation>> {
+    let aggregation = match aggregation {
+        AGGREGATION_COUNT => Aggregation::Count,
+        AGGREGATION_IS_EMPTY => Aggregation::IsEmpty,
+        AGGREGATION_MIN => Aggregation::Min,
+        AGGREGATION_MAX => Aggregation::Max,
+        AGGREGATION_SUM => Aggregation::Sum,
+        AGGREGATION_AVERAGE => Aggregation::Average,
+        AGGREGATION_COUNT_DISTINCT => Aggregation::CountDistinct,
+        AGGREGATION_MEDIAN => Aggregation::Median,
+        AGGREGATION_PERCENTILE => Aggregation::Percentile(Percentile::new(percentile)?),
+        AGGREGATION_STD_DEV => Aggregation::StdDev,
+        AGGREGATION_VARIANCE => Aggregation::Variance,
+        AGGREGATION_ELEMENT_COUNT => Aggregation::ElementCount,
+        AGGREGATION_MIN_LENGTH => Aggregation::MinLength,
+        AGGREGATION_MAX_LENGTH => Aggregation::MaxLength,
+        AGGREGATION_AVERAGE_LENGTH => Aggregation::AverageLength,
+        _ => return Ok(None),
+    };
+    Ok(Some(aggregation))
+}
 
+/// Percentiles require [isar_query_aggregate_percentile].
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn isar_query_aggregate(
     isar: &'static CIsarInstance,
@@ -134,19 +414,40 @@ pub unsafe extern "C" fn isar_query_aggregate(
     property_index: u16,
     value: *mut *const IsarValue,
 ) -> u8 {
-    let aggregation = match aggregation {
-        AGGREGATION_COUNT => Aggregation::Count,
-        AGGREGATION_IS_EMPTY => Aggregation::IsEmpty,
-        AGGREGATION_MIN => Aggregation::Min,
-        AGGREGATION_MAX => Aggregation::Max,
-        AGGREGATION_SUM => Aggregation::Sum,
-        AGGREGATION_AVERAGE => Aggregation::Average,
-        _ => {
+    let aggregation = match aggregation_from_u8(aggregation, f64::NAN) {
+        Ok(Some(aggregation)) => Ok(aggregation),
+        Ok(None) => {
             *value = ptr::null();
             return 0;
         }
+        Err(err) => Err(err),
     };
+    query_aggregate(isar, txn, query, aggregation, property_index, value)
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn isar_query_aggregate_percentile(
+    isar: &'static CIsarInstance,
+    txn: &'static CIsarTxn,
+    query: &'static CIsarQuery,
+    property_index: u16,
+    percentile: f64,
+    value: *mut *const IsarValue,
+) -> u8 {
+    let aggregation = Percentile::new(percentile).map(Aggregation::Percentile);
+    query_aggregate(isar, txn, query, aggregation, property_index, value)
+}
+
+unsafe fn query_aggregate(
+    isar: &'static CIsarInstance,
+    txn: &'static CIsarTxn,
+    query: &'static CIsarQuery,
+    aggregation: Result<Aggregation>,
+    property_index: u16,
+    value: *mut *const IsarValue,
+) -> u8 {
     isar_try! {
+        let aggregation = aggregation?;
         let new_value = match (isar, txn, query) {
             #[cfg(feature = "native")]
             (CIsarInstance::Native(isar), CIsarTxn::Native(txn), CIsarQuery::Native(query)) => {
@@ -166,6 +467,96 @@ pub unsafe extern "C" fn isar_query_aggregate(
     }
 }
 
+/// Evaluates `length` aggregations with a single iteration of the query. The percentiles are
+/// only used by percentile aggregations and may be null. The values have to be freed using
+/// isar_values_free.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn isar_query_aggregate_batch(
+    isar: &'static CIsarInstance,
+    txn: &'static CIsarTxn,
+    query: &'static CIsarQuery,
+    aggregations: *const u8,
+    property_indexes: *const u16,
+    percentiles: *const f64,
+    length: u32,
+    values: *mut *mut *mut IsarValue,
+) -> u8 {
+    isar_try! {
+        let mut batch = vec![];
+        for i in 0..length as usize {
+            let percentile = if percentiles.is_null() {
+                f64::NAN
+            } else {
+                *percentiles.add(i)
+            };
+            let aggregation = aggregation_from_u8(*aggregations.add(i), percentile)?
+                .ok_or(IsarError::IllegalArgument {})?;
+            batch.push((aggregation, Some(*property_indexes.add(i))));
+        }
+        let results = match (isar, txn, query) {
+            #[cfg(feature = "native")]
+            (CIsarInstance::Native(isar), CIsarTxn::Native(txn), CIsarQuery::Native(query)) => {
+                isar.query_aggregate_batch(txn, query, &batch)?
+            }
+            #[cfg(feature = "sqlite")]
+            (CIsarInstance::SQLite(isar), CIsarTxn::SQLite(txn), CIsarQuery::SQLite(query)) => {
+                isar.query_aggregate_batch(txn, query, &batch)?
+            }
+            _ => return Err(IsarError::IllegalArgument {}),
+        };
+        *values = values_into_raw(results);
+    }
+}
+
+/// Returns `count` groups of `group_length + 1` values: the values of the group properties
+/// followed by the result of the group. The values have to be freed using isar_values_free.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn isar_query_aggregate_groups(
+    isar: &'static CIsarInstance,
+    txn: &'static CIsarTxn,
+    query: &'static CIsarQuery,
+    group_properties: *const u16,
+    group_case_sensitive: *const bool,
+    group_length: u32,
+    aggregation: u8,
+    property_index: u16,
+    percentile: f64,
+    values: *mut *mut *mut IsarValue,
+    count: *mut u32,
+) -> u8 {
+    let group_by = if group_length == 0 {
+        vec![]
+    } else {
+        let properties = slice::from_raw_parts(group_properties, group_length as usize);
+        let case_sensitive = slice::from_raw_parts(group_case_sensitive, group_length as usize);
+        properties
+            .iter()
+            .copied()
+            .zip(case_sensitive.iter().copied())
+            .collect()
+    };
+    isar_try! {
+        let aggregation = aggregation_from_u8(aggregation, percentile)?.ok_or(IsarError::IllegalArgument {})?;
+        let groups = match (isar, txn, query) {
+            #[cfg(feature = "native")]
+            (CIsarInstance::Native(isar), CIsarTxn::Native(txn), CIsarQuery::Native(query)) => {
+                isar.query_aggregate_groups(txn, query, &group_by, aggregation, Some(property_index))?
+            }
+            #[cfg(feature = "sqlite")]
+            (CIsarInstance::SQLite(isar), CIsarTxn::SQLite(txn), CIsarQuery::SQLite(query)) => {
+                isar.query_aggregate_groups(txn, query, &group_by, aggregation, Some(property_index))?
+            }
+            _ => return Err(IsarError::IllegalArgument {}),
+        };
+        *count = groups.len() as u32;
+        let new_values = groups
+            .into_iter()
+            .flat_map(|group| group.values.into_iter().chain([group.result]))
+            .collect();
+        *values = values_into_raw(new_values);
+    }
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn isar_query_delete(
     isar: &'static CIsarInstance,
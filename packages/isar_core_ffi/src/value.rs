@@ -1,6 +1,6 @@
 use crate::{IsarI64, i64_to_isar, isar_to_i64};
 use isar_core::core::value::IsarValue;
-use std::ptr;
+use std::{ptr, slice};
 
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn isar_value_bool(value: bool) -> *const IsarValue {
@@ -67,3 +67,13 @@ pub unsafe extern "C" fn isar_value_free(value: *mut IsarValue) {
         drop(Box::from_raw(value));
     }
 }
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn isar_values_free(values: *mut *mut IsarValue, length: u32) {
+    if !values.is_null() {
+        let values = Box::from_raw(slice::from_raw_parts_mut(values, length as usize));
+        for value in values.iter() {
+            isar_value_free(*value);
+        }
+    }
+}